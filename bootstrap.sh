@@ -7,7 +7,7 @@ set -e
 echo "Installing Caelestia Hyprland dotfiles..."
 
 # Install minimal dependencies
-sudo dnf install -y git curl gcc
+sudo dnf install -y git curl gcc unzip
 
 # Install Rust
 if ! command -v cargo &> /dev/null; then
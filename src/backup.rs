@@ -0,0 +1,260 @@
+//! Backups of the configs the installer replaces with symlinks. A run moves
+//! what it replaces into a staging directory, which is packed into
+//! `~/.local/share/caelestia-installer/backups/<timestamp>.tar.gz` once the
+//! configs are linked, so earlier backups are never overwritten.
+//! `caelestia-installer restore` lists the archives and puts one back.
+
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+use crate::{log, ui};
+
+/// Lists the paths an archive holds, relative to the home directory.
+const MANIFEST: &str = "MANIFEST";
+
+/// Names this run's archive.
+static STAMP: LazyLock<u64> = LazyLock::new(|| {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+});
+
+pub fn backups_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.local/share"))
+        .join("caelestia-installer/backups")
+}
+
+/// The archive this run's backups go to.
+pub fn archive_path() -> PathBuf {
+    backups_dir().join(format!("{}.tar.gz", *STAMP))
+}
+
+/// Where the backups of `archive` are collected before it is packed.
+fn staging_dir(archive: &Path) -> PathBuf {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    archive.with_file_name(name.trim_end_matches(".tar.gz"))
+}
+
+fn home() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("Could not find the home directory"))
+}
+
+/// `path` relative to the home directory, as it is stored in archives.
+fn relative(path: &Path) -> Result<PathBuf> {
+    match path.strip_prefix(home()?) {
+        Ok(relative) => Ok(relative.to_path_buf()),
+        Err(_) => bail!("{:?} is outside the home directory", path),
+    }
+}
+
+fn read_manifest(staging: &Path) -> Vec<String> {
+    fs::read_to_string(staging.join(MANIFEST))
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Moves `path` into this run's backup and returns the archive it will be
+/// packed into.
+pub fn stash(path: &Path) -> Result<PathBuf> {
+    let archive = archive_path();
+    let staging = staging_dir(&archive);
+    let relative = relative(path)?;
+    let staged = staging.join(&relative);
+
+    if let Some(parent) = staged.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(path, &staged)?;
+
+    let mut manifest = read_manifest(&staging);
+    manifest.push(relative.to_string_lossy().into_owned());
+    fs::write(staging.join(MANIFEST), manifest.join("\n") + "\n")?;
+
+    log::log(&format!("Backed up {:?} to {:?}", path, staged));
+    Ok(archive)
+}
+
+/// Puts `path` back if this run backed it up, returning whether it did.
+pub fn restore_from_run(path: &Path) -> Result<bool> {
+    let archive = archive_path();
+    let relative = relative(path)?;
+    let staged = staging_dir(&archive).join(&relative).exists();
+    let packed = archive.exists() && contents(&archive)?.contains(&relative.to_string_lossy().into_owned());
+    if !staged && !packed {
+        return Ok(false);
+    }
+    restore_path(&archive, path)?;
+    Ok(true)
+}
+
+/// Packs what this run stashed into its archive, merging with an archive
+/// packed earlier in the same run.
+pub fn finish() -> Result<Option<PathBuf>> {
+    let archive = archive_path();
+    let staging = staging_dir(&archive);
+    if !staging.exists() {
+        return Ok(None);
+    }
+
+    if archive.exists() {
+        tar(&["-xzf", &archive.to_string_lossy(), "-C", &staging.to_string_lossy()])?;
+    }
+    let mut manifest = read_manifest(&staging);
+    manifest.sort();
+    manifest.dedup();
+
+    let mut args = vec![
+        "-czf".to_string(),
+        archive.to_string_lossy().into_owned(),
+        "-C".to_string(),
+        staging.to_string_lossy().into_owned(),
+        MANIFEST.to_string(),
+    ];
+    args.extend(manifest.iter().cloned());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    tar(&args)?;
+    fs::remove_dir_all(&staging)?;
+
+    ui::success(&format!("Backed up {} replaced configs to {:?}", manifest.len(), archive));
+    Ok(Some(archive))
+}
+
+/// Puts `path` back as it was saved in `archive`, replacing what is there.
+pub fn restore_path(archive: &Path, path: &Path) -> Result<()> {
+    let relative = relative(path)?;
+    let staged = staging_dir(archive).join(&relative);
+
+    if path.is_symlink() {
+        fs::remove_file(path)?;
+    }
+    if staged.exists() {
+        fs::rename(&staged, path)?;
+    } else if archive.exists() {
+        tar(&[
+            "-xzf",
+            &archive.to_string_lossy(),
+            "-C",
+            &home()?.to_string_lossy(),
+            &relative.to_string_lossy(),
+        ])?;
+    } else {
+        bail!("the backup {:?} is missing", archive);
+    }
+    log::log(&format!("Restored {:?} from {:?}", path, archive));
+    Ok(())
+}
+
+fn tar(args: &[&str]) -> Result<()> {
+    log::log_command(&format!("tar {}", args.join(" ")));
+    let output = Command::new("tar").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("tar failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// The backup archives, newest first.
+pub fn list() -> Vec<PathBuf> {
+    let mut archives: Vec<PathBuf> = fs::read_dir(backups_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.to_string_lossy().ends_with(".tar.gz"))
+                .collect()
+        })
+        .unwrap_or_default();
+    archives.sort();
+    archives.reverse();
+    archives
+}
+
+/// The paths `archive` holds, relative to the home directory.
+fn contents(archive: &Path) -> Result<Vec<String>> {
+    let output = Command::new("tar")
+        .args(["-xzOf", &archive.to_string_lossy(), MANIFEST])
+        .output()?;
+    if !output.status.success() {
+        bail!("{:?} is not a backup made by the installer", archive);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Finds an archive by path, file name or timestamp.
+fn find(name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if path.exists() {
+        return Ok(path.to_path_buf());
+    }
+    let file_name = if name.ends_with(".tar.gz") {
+        name.to_string()
+    } else {
+        format!("{}.tar.gz", name)
+    };
+    let archive = backups_dir().join(file_name);
+    if !archive.exists() {
+        bail!("No backup named {}, run 'caelestia-installer restore' to list them", name);
+    }
+    Ok(archive)
+}
+
+/// Lists the backups, or restores `name`. What the restored configs replace
+/// is backed up in turn, so a restore can be undone the same way.
+pub fn restore(name: Option<&str>, dry_run: bool) -> Result<()> {
+    let Some(name) = name else {
+        let archives = list();
+        if archives.is_empty() {
+            ui::info(&format!("No backups in {:?}", backups_dir()));
+            return Ok(());
+        }
+        ui::info("Backups, newest first:");
+        for archive in &archives {
+            let stamp = archive.file_name().unwrap_or_default().to_string_lossy();
+            let stamp = stamp.trim_end_matches(".tar.gz");
+            let paths = contents(archive).map(|paths| paths.join(", ")).unwrap_or_default();
+            ui::block(&format!("  {}  {}", stamp, paths));
+        }
+        ui::info("Restore one with: caelestia-installer restore <TIMESTAMP>");
+        return Ok(());
+    };
+
+    let archive = find(name)?;
+    let paths = contents(&archive)?;
+    let home = home()?;
+    ui::info(&format!("Restoring {} configs from {:?}...", paths.len(), archive));
+
+    for relative in &paths {
+        let path = home.join(relative);
+        if dry_run {
+            ui::success(&format!("Would restore {:?} (dry-run)", path));
+            continue;
+        }
+
+        if path.is_symlink() {
+            fs::remove_file(&path)?;
+        } else if path.exists() {
+            stash(&path)?;
+        }
+        restore_path(&archive, &path)?;
+        ui::success(&format!("Restored {:?}", path));
+    }
+
+    if !dry_run {
+        finish()?;
+    }
+    Ok(())
+}
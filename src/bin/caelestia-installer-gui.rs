@@ -0,0 +1,314 @@
+//! Graphical front-end driving the same install steps as the CLI. Started
+//! from a launcher it has no terminal for sudo to ask the password in, so it
+//! is also sudo's askpass helper, asking in a dialog of its own.
+
+use iced::widget::{button, checkbox, column, progress_bar, row, scrollable, text, text_input, Column};
+use iced::{time, Element, Length, Subscription};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use caelestia_installer::{checks, install, log, packages, sudo, ui};
+
+/// How many log lines are kept on screen.
+const MAX_LOG_LINES: usize = 500;
+
+/// Set in the environment sudo runs the askpass helper with, so this
+/// program knows to ask for the password instead.
+const ASKPASS_ENV: &str = "CAELESTIA_INSTALLER_ASKPASS";
+
+pub fn main() -> iced::Result {
+    if std::env::var_os(ASKPASS_ENV).is_some() {
+        let prompt = std::env::args().nth(1).unwrap_or_else(|| "Password:".to_string());
+        return iced::application("Caelestia Installer", Askpass::update, Askpass::view)
+            .window_size((420.0, 140.0))
+            .run_with(move || (Askpass { prompt, password: String::new() }, text_input::focus("password")));
+    }
+
+    if let Err(e) = checks::refuse_root() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    match std::env::current_exe() {
+        Ok(program) => {
+            std::env::set_var(ASKPASS_ENV, "1");
+            sudo::set_askpass(&program);
+        }
+        Err(e) => eprintln!("Could not find the installer's path for sudo to ask the password with: {}", e),
+    }
+    iced::application("Caelestia Installer", Gui::update, Gui::view)
+        .subscription(Gui::subscription)
+        .run()
+}
+
+#[derive(Debug, Clone)]
+enum AskpassMessage {
+    Input(String),
+    Submit,
+    Cancel,
+}
+
+/// The password dialog sudo runs with its prompt. The password goes to
+/// stdout, where sudo reads it; cancelling exits with an error, which sudo
+/// takes as no password.
+struct Askpass {
+    prompt: String,
+    password: String,
+}
+
+impl Askpass {
+    fn update(&mut self, message: AskpassMessage) {
+        match message {
+            AskpassMessage::Input(password) => self.password = password,
+            AskpassMessage::Submit => {
+                println!("{}", self.password);
+                std::process::exit(0);
+            }
+            AskpassMessage::Cancel => std::process::exit(1),
+        }
+    }
+
+    fn view(&self) -> Element<'_, AskpassMessage> {
+        column![
+            text(format!("The installer needs administrator access. {}", self.prompt)),
+            text_input("Password", &self.password)
+                .id("password")
+                .secure(true)
+                .on_input(AskpassMessage::Input)
+                .on_submit(AskpassMessage::Submit),
+            row![
+                button("Cancel").on_press(AskpassMessage::Cancel),
+                button("OK").on_press(AskpassMessage::Submit),
+            ]
+            .spacing(8),
+        ]
+        .spacing(12)
+        .padding(16)
+        .into()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    TogglePackage(usize, bool),
+    SelectAll(bool),
+    ToggleDryRun(bool),
+    Install,
+    Tick,
+    Answer(bool),
+}
+
+#[derive(PartialEq)]
+enum Phase {
+    Selecting,
+    Installing,
+    Finished(Result<(), String>),
+}
+
+/// State written by the install thread and picked up on every tick.
+#[derive(Default)]
+struct Shared {
+    events: Vec<ui::Event>,
+    question: Option<String>,
+    finished: Option<Result<(), String>>,
+}
+
+struct Gui {
+    packages: Vec<(&'static str, bool)>,
+    dry_run: bool,
+    phase: Phase,
+    step: (usize, usize, String),
+    lines: Vec<String>,
+    question: Option<String>,
+    answers: Option<Sender<bool>>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Default for Gui {
+    fn default() -> Self {
+        Self {
+            packages: packages::GROUPS
+                .iter()
+                .flat_map(|group| group.packages.iter().map(|p| (*p, group.id != "apps")))
+                .collect(),
+            dry_run: false,
+            phase: Phase::Selecting,
+            step: (0, 0, String::new()),
+            lines: Vec::new(),
+            question: None,
+            answers: None,
+            shared: Arc::new(Mutex::new(Shared::default())),
+        }
+    }
+}
+
+impl Gui {
+    fn update(&mut self, message: Message) {
+        match message {
+            Message::TogglePackage(index, checked) => self.packages[index].1 = checked,
+            Message::SelectAll(checked) => {
+                for package in &mut self.packages {
+                    package.1 = checked;
+                }
+            }
+            Message::ToggleDryRun(checked) => self.dry_run = checked,
+            Message::Install => self.start(),
+            Message::Tick => self.poll(),
+            Message::Answer(answer) => {
+                self.question = None;
+                if let Some(ref answers) = self.answers {
+                    let _ = answers.send(answer);
+                }
+            }
+        }
+    }
+
+    /// Starts the installation on a worker thread, routing its output and
+    /// prompts through `Shared`.
+    fn start(&mut self) {
+        let options = install::Options {
+            dry_run: self.dry_run,
+            packages: self
+                .packages
+                .iter()
+                .filter(|(_, checked)| *checked)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        let shared = self.shared.clone();
+        ui::set_event_handler(move |event| shared.lock().unwrap().events.push(event));
+
+        let (answers, questions) = mpsc::channel();
+        self.answers = Some(answers);
+        let shared = self.shared.clone();
+        ui::set_prompt_handler(move |question| {
+            shared.lock().unwrap().question = Some(question.to_string());
+            questions.recv().unwrap_or(false)
+        });
+
+        let shared = self.shared.clone();
+        std::thread::spawn(move || {
+            let result = log::init()
+                .and_then(|_| install::Plan::new(options).run())
+                .map_err(|e| e.to_string());
+            shared.lock().unwrap().finished = Some(result);
+        });
+
+        self.phase = Phase::Installing;
+    }
+
+    fn poll(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+
+        for event in shared.events.drain(..) {
+            let line = match event {
+                ui::Event::Step {
+                    current,
+                    total,
+                    message,
+                    ..
+                } => {
+                    self.step = (current, total, message.clone());
+                    format!("[{}/{}] {}", current, total, message)
+                }
+                ui::Event::Info(message) => format!("→ {}", message),
+                ui::Event::Success(message) => format!("✓ {}", message),
+                ui::Event::Warning(message) => format!("! {}", message),
+                ui::Event::Error(message) => format!("✗ {}", message),
+                ui::Event::Output(line) => format!("  {}", line),
+                ui::Event::StepFinished { .. } => continue,
+            };
+            self.lines.push(line);
+        }
+
+        let overflow = self.lines.len().saturating_sub(MAX_LOG_LINES);
+        self.lines.drain(..overflow);
+
+        if let Some(question) = shared.question.take() {
+            self.question = Some(question);
+        }
+
+        if let Some(result) = shared.finished.take() {
+            self.step.0 = self.step.1;
+            self.phase = Phase::Finished(result);
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        match self.phase {
+            Phase::Selecting => self.view_selection(),
+            _ => self.view_progress(),
+        }
+    }
+
+    fn view_selection(&self) -> Element<'_, Message> {
+        let packages = Column::with_children(self.packages.iter().enumerate().map(
+            |(index, (name, checked))| {
+                checkbox(*name, *checked)
+                    .on_toggle(move |checked| Message::TogglePackage(index, checked))
+                    .into()
+            },
+        ))
+        .spacing(4);
+
+        column![
+            text("Caelestia Hyprland dotfiles for Fedora").size(24),
+            text("Packages to install"),
+            row![
+                button("Select all").on_press(Message::SelectAll(true)),
+                button("Select none").on_press(Message::SelectAll(false)),
+            ]
+            .spacing(8),
+            scrollable(packages).height(Length::Fill),
+            checkbox("Dry run (show what would happen)", self.dry_run)
+                .on_toggle(Message::ToggleDryRun),
+            button("Install").on_press(Message::Install),
+        ]
+        .spacing(12)
+        .padding(16)
+        .into()
+    }
+
+    fn view_progress(&self) -> Element<'_, Message> {
+        let (current, total, ref message) = self.step;
+
+        let status = match self.phase {
+            Phase::Finished(Ok(())) => text("Installation complete! Please reboot to apply all changes."),
+            Phase::Finished(Err(ref e)) => text(format!("Installation failed: {}", e)),
+            _ => text(message.clone()),
+        };
+
+        let log = Column::with_children(self.lines.iter().map(|line| text(line.clone()).into()));
+
+        let mut content = column![
+            progress_bar(0.0..=total as f32, current as f32),
+            status,
+            scrollable(log).height(Length::Fill).anchor_bottom(),
+        ]
+        .spacing(12)
+        .padding(16);
+
+        if let Some(ref question) = self.question {
+            content = content.push(
+                row![
+                    text(question.clone()),
+                    button("Yes").on_press(Message::Answer(true)),
+                    button("No").on_press(Message::Answer(false)),
+                ]
+                .spacing(8),
+            );
+        }
+
+        content.into()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        if self.phase == Phase::Installing {
+            time::every(Duration::from_millis(100)).map(|_| Message::Tick)
+        } else {
+            Subscription::none()
+        }
+    }
+}
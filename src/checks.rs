@@ -1,13 +1,16 @@
 use anyhow::{bail, Result};
 use std::fs;
-use std::process::Command;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::{log, ui};
+use crate::packages::{self, Backend};
+use crate::{download, log, offline, prefix, sudo, ui};
 
 pub fn run_all(dry_run: bool) -> Result<()> {
     check_fedora()?;
     check_network(dry_run)?;
-    check_sudo(dry_run)?;
+    sudo::validate(dry_run)?;
     Ok(())
 }
 
@@ -23,59 +26,382 @@ fn check_fedora() -> Result<()> {
 
     ui::success("Running on Fedora");
     log::log("Fedora detected");
+    check_release()?;
+
+    if packages::backend() == Backend::RpmOstree {
+        let variant = os_release
+            .lines()
+            .find_map(|line| line.strip_prefix("VARIANT_ID="))
+            .unwrap_or("ostree");
+        ui::info(&format!(
+            "Image-based Fedora ({}): packages will be layered with rpm-ostree and sources built in a toolbox",
+            variant
+        ));
+        if let Some(prefix) = prefix::configured() {
+            ui::info(&format!("Built components go to {}", prefix.display()));
+        }
+        log::log(&format!("rpm-ostree system, variant {}", variant));
+        if which::which("toolbox").is_err() {
+            bail!("toolbox is needed to build the source components, but it is not installed");
+        }
+    }
     Ok(())
 }
 
+/// Fails when run as root. Everything the installer sets up lives in the
+/// home directory, which would be /root instead of the user's, and it asks
+/// for sudo itself when it needs it.
+pub fn refuse_root() -> Result<()> {
+    // /proc/self belongs to the effective user
+    let root = fs::metadata("/proc/self").map(|m| m.uid() == 0).unwrap_or(false);
+    if !root {
+        return Ok(());
+    }
+    match std::env::var("SUDO_USER").ok().filter(|user| user != "root") {
+        Some(user) => bail!(
+            "Do not run the installer with sudo, the dotfiles would be installed for root instead of {}. \
+             Run it again as {} without sudo, it asks for the password when it needs it.",
+            user,
+            user
+        ),
+        None => bail!(
+            "The installer cannot run as root, it sets up the desktop in the home directory of the user running it. \
+             Log in as the user the desktop is for and run it from there."
+        ),
+    }
+}
+
+/// Approximate download sizes used for time estimates.
+pub const PACKAGES_DOWNLOAD_MB: f64 = 1200.0;
+pub const FONTS_DOWNLOAD_MB: f64 = 160.0;
+
+/// Bytes requested from each probe URL (via an HTTP range request).
+const PROBE_BYTES: u64 = 2 * 1024 * 1024;
+/// How long a probe may take, slow mirrors counting as unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+/// A release asset the fonts step downloads from GitHub.
+const GITHUB_PROBE_URL: &str = "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/JetBrainsMono.zip";
+
+static BANDWIDTH: Mutex<Option<f64>> = Mutex::new(None);
+
+/// Measured download speed in bytes per second, if the network probe ran.
+pub fn measured_bandwidth() -> Option<f64> {
+    *BANDWIDTH.lock().unwrap()
+}
+
 fn check_network(dry_run: bool) -> Result<()> {
-    ui::info("Checking network connectivity...");
+    if let Some(dir) = offline::cache_dir() {
+        ui::success(&format!("Installing offline from {:?}, not checking the network", dir));
+        return Ok(());
+    }
+    ui::info("Checking network connectivity and speed...");
 
     if dry_run {
         ui::success("Network check (dry-run: skipped)");
         return Ok(());
     }
 
-    let cmd = "ping -c 1 -W 5 fedoraproject.org";
-    log::log_command(cmd);
+    let probes = [
+        ("Fedora mirrors", fedora_probe_url()),
+        ("GitHub", Ok(GITHUB_PROBE_URL.to_string())),
+    ];
 
-    let output = Command::new("ping")
-        .args(["-c", "1", "-W", "5", "fedoraproject.org"])
-        .output();
-
-    match output {
-        Ok(o) if o.status.success() => {
-            ui::success("Network connectivity OK");
-            log::log("Network check passed");
-            Ok(())
-        }
-        _ => {
-            log::log_error("Network check failed");
-            bail!("No network connectivity. Please check your internet connection.");
+    let mut speeds = Vec::new();
+    for (name, url) in probes {
+        match url.and_then(|url| probe_speed(&url)) {
+            Ok(speed) => {
+                ui::success(&format!("{}: {}/s", name, format_bytes(speed)));
+                log::log(&format!("Network probe {} -> {:.0} B/s", name, speed));
+                speeds.push(speed);
+            }
+            Err(e) => {
+                ui::warning(&format!("{}: could not measure the speed ({})", name, e));
+                log::log_error(&format!("Network probe {} failed: {}", name, e));
+            }
         }
     }
+
+    if speeds.is_empty() {
+        log::log_error("Network check failed");
+        bail!("No network connectivity. Please check your internet connection.");
+    }
+    if speeds.len() < 2 {
+        ui::info("Download estimates only use the speed that could be measured");
+    }
+
+    // The slowest source bounds how long the install will spend downloading
+    let bandwidth = speeds.iter().cloned().fold(f64::INFINITY, f64::min);
+    *BANDWIDTH.lock().unwrap() = Some(bandwidth);
+
+    ui::info(&format!(
+        "Estimated download time: packages ~{}, fonts ~{}",
+        ui::format_duration(estimate(PACKAGES_DOWNLOAD_MB, bandwidth)),
+        ui::format_duration(estimate(FONTS_DOWNLOAD_MB, bandwidth))
+    ));
+    log::log("Network check passed");
+    Ok(())
 }
 
-fn check_sudo(dry_run: bool) -> Result<()> {
-    ui::info("Checking sudo access...");
+/// Downloads the first `PROBE_BYTES` of `url` and returns the speed in bytes/s.
+fn probe_speed(url: &str) -> Result<f64> {
+    log::log(&format!("Probing the download speed of {}", url));
+    download::probe(url, PROBE_BYTES, PROBE_TIMEOUT)
+}
 
-    if dry_run {
-        ui::success("Sudo check (dry-run: skipped)");
-        return Ok(());
+/// The package list of the Fedora repo on the mirror dnf would pick, found
+/// through the metalink and the mirror's repomd.xml. Unlike install images,
+/// every release and rawhide has one, and it is several megabytes.
+fn fedora_probe_url() -> Result<String> {
+    let repo = match release() {
+        Some(Release::Version(v)) => format!("fedora-{}", v),
+        Some(Release::Rawhide) => "rawhide".to_string(),
+        None => bail!("could not read the Fedora release"),
+    };
+    let metalink = format!(
+        "https://mirrors.fedoraproject.org/metalink?repo={}&arch={}",
+        repo,
+        std::env::consts::ARCH
+    );
+    let Some(repomd) = metalink_repomd(&download::text(&metalink, PROBE_TIMEOUT)?) else {
+        bail!("the metalink lists no HTTPS mirror");
+    };
+    let Some(primary) = repomd_primary(&download::text(&repomd, PROBE_TIMEOUT)?) else {
+        bail!("{} lists no package list", repomd);
+    };
+    let base = repomd.trim_end_matches("repodata/repomd.xml");
+    Ok(format!("{}{}", base, primary))
+}
+
+/// The first HTTPS mirror's repomd.xml in a metalink.
+fn metalink_repomd(metalink: &str) -> Option<String> {
+    metalink
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("<url") && line.contains("protocol=\"https\""))
+        .filter_map(|line| {
+            let url = line.split_once('>')?.1.split_once('<')?.0;
+            url.ends_with("/repodata/repomd.xml").then(|| url.to_string())
+        })
+        .next()
+}
+
+/// Where repomd.xml says the primary package list is, relative to the repo.
+fn repomd_primary(repomd: &str) -> Option<String> {
+    let primary = &repomd[repomd.find("<data type=\"primary\">")?..];
+    let href = &primary[primary.find("<location href=\"")? + "<location href=\"".len()..];
+    Some(href[..href.find('"')?].to_string())
+}
+
+fn estimate(megabytes: f64, bandwidth: f64) -> Duration {
+    Duration::from_secs_f64(megabytes * 1024.0 * 1024.0 / bandwidth)
+}
+
+pub(crate) fn format_bytes(bytes: f64) -> String {
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} GB", bytes / 1024.0 / 1024.0 / 1024.0)
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB", bytes / 1024.0 / 1024.0)
+    } else {
+        format!("{:.0} KB", bytes / 1024.0)
     }
+}
 
-    let cmd = "sudo -v";
-    log::log_command(cmd);
+pub fn fedora_version() -> Option<String> {
+    let os_release = fs::read_to_string("/etc/os-release").ok()?;
+    os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("VERSION_ID="))
+        .map(|v| v.trim_matches('"').to_string())
+}
+
+/// Oldest Fedora release the installer supports. Older releases lack the
+/// Qt and Hyprland versions the shell needs and the COPRs no longer build
+/// for them.
+pub const MIN_FEDORA: u32 = 40;
+
+/// A Fedora release, as read from `/etc/os-release`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Release {
+    Version(u32),
+    Rawhide,
+}
+
+impl Release {
+    /// Whether this is `version` or newer. Rawhide is newer than every
+    /// numbered release.
+    pub fn at_least(self, version: u32) -> bool {
+        match self {
+            Release::Version(v) => v >= version,
+            Release::Rawhide => true,
+        }
+    }
+
+    /// The release as COPR chroots and RPM Fusion name it: the version
+    /// number, or `rawhide`.
+    pub fn name(self) -> String {
+        match self {
+            Release::Version(v) => v.to_string(),
+            Release::Rawhide => "rawhide".to_string(),
+        }
+    }
+}
 
-    let output = Command::new("sudo").arg("-v").status();
+/// The running release. Rawhide's VERSION_ID is the number of the next
+/// release, so it is recognised by its VERSION and support product fields.
+pub fn release() -> Option<Release> {
+    let os_release = fs::read_to_string("/etc/os-release").ok()?;
+    parse_release(&os_release)
+}
 
-    match output {
-        Ok(s) if s.success() => {
-            ui::success("Sudo access granted");
-            log::log("Sudo access verified");
-            Ok(())
+/// The release `os_release` describes, or `None` when it is not Fedora.
+fn parse_release(os_release: &str) -> Option<Release> {
+    if !os_release.lines().any(|line| line.replace('"', "") == "ID=fedora") {
+        return None;
+    }
+    let rawhide = os_release.lines().any(|line| {
+        line.replace('"', "") == "REDHAT_SUPPORT_PRODUCT_VERSION=rawhide"
+            || (line.starts_with("VERSION=") && line.contains("Rawhide"))
+    });
+    if rawhide {
+        return Some(Release::Rawhide);
+    }
+    os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("VERSION_ID="))
+        .and_then(|v| v.trim_matches('"').parse().ok())
+        .map(Release::Version)
+}
+
+/// Fails on releases older than [`MIN_FEDORA`] before anything is installed.
+fn check_release() -> Result<()> {
+    match release() {
+        Some(Release::Version(v)) if v < MIN_FEDORA => {
+            log::log_error(&format!("Unsupported Fedora release {}", v));
+            bail!(
+                "Fedora {} is not supported, upgrade to Fedora {} or newer first",
+                v,
+                MIN_FEDORA
+            );
+        }
+        Some(Release::Version(v)) => {
+            ui::success(&format!("Fedora {}", v));
+        }
+        Some(Release::Rawhide) => {
+            ui::warning("Fedora Rawhide is not tested, packages may be missing or broken");
         }
-        _ => {
-            log::log_error("Sudo access denied");
-            bail!("Could not get sudo access. Please run as a user with sudo privileges.");
+        None => {
+            ui::warning("Could not read the Fedora release, assuming it is supported");
         }
     }
+    log::log(&format!("Fedora release: {:?}", release()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{metalink_repomd, parse_release, repomd_primary, Release};
+
+    #[test]
+    fn finds_the_first_https_mirror_in_a_metalink() {
+        let metalink = r#"<?xml version="1.0" encoding="utf-8"?>
+<metalink version="3.0" xmlns="http://www.metalinker.org/">
+ <files>
+  <file name="repomd.xml">
+   <resources maxconnections="1">
+    <url protocol="rsync" type="rsync" location="DE" preference="100">rsync://mirror.example.de/fedora/linux/releases/41/Everything/x86_64/os/repodata/repomd.xml</url>
+    <url protocol="https" type="https" location="DE" preference="100">https://mirror.example.de/fedora/linux/releases/41/Everything/x86_64/os/repodata/repomd.xml</url>
+    <url protocol="http" type="http" location="DE" preference="99">http://mirror.example.de/fedora/linux/releases/41/Everything/x86_64/os/repodata/repomd.xml</url>
+   </resources>
+  </file>
+ </files>
+</metalink>"#;
+        assert_eq!(
+            metalink_repomd(metalink).as_deref(),
+            Some("https://mirror.example.de/fedora/linux/releases/41/Everything/x86_64/os/repodata/repomd.xml")
+        );
+        assert_eq!(metalink_repomd("<metalink></metalink>"), None);
+    }
+
+    #[test]
+    fn finds_the_primary_package_list_in_repomd() {
+        let repomd = r#"<repomd xmlns="http://linux.duke.edu/metadata/repo">
+  <revision>1729000000</revision>
+  <data type="filelists">
+    <location href="repodata/aaa-filelists.xml.zst"/>
+  </data>
+  <data type="primary">
+    <checksum type="sha256">bbb</checksum>
+    <location href="repodata/bbb-primary.xml.zst"/>
+    <size>23000000</size>
+  </data>
+</repomd>"#;
+        assert_eq!(repomd_primary(repomd).as_deref(), Some("repodata/bbb-primary.xml.zst"));
+        assert_eq!(repomd_primary("<repomd></repomd>"), None);
+    }
+
+    const WORKSTATION: &str = r#"NAME="Fedora Linux"
+VERSION="41 (Workstation Edition)"
+RELEASE_TYPE=stable
+ID=fedora
+VERSION_ID=41
+VERSION_CODENAME=""
+PLATFORM_ID="platform:f41"
+PRETTY_NAME="Fedora Linux 41 (Workstation Edition)"
+REDHAT_SUPPORT_PRODUCT="Fedora"
+REDHAT_SUPPORT_PRODUCT_VERSION=41
+VARIANT="Workstation Edition"
+VARIANT_ID=workstation
+"#;
+
+    const SILVERBLUE: &str = r#"NAME="Fedora Linux"
+VERSION="42 (Silverblue)"
+RELEASE_TYPE=stable
+ID=fedora
+VERSION_ID=42
+PLATFORM_ID="platform:f42"
+PRETTY_NAME="Fedora Linux 42 (Silverblue)"
+REDHAT_SUPPORT_PRODUCT="Fedora"
+REDHAT_SUPPORT_PRODUCT_VERSION=42
+VARIANT="Silverblue"
+VARIANT_ID=silverblue
+OSTREE_VERSION='42.20250415.0'
+"#;
+
+    const RAWHIDE: &str = r#"NAME="Fedora Linux"
+VERSION="43 (Workstation Edition Prerelease)"
+RELEASE_TYPE=development
+ID=fedora
+VERSION_ID=43
+PLATFORM_ID="platform:f43"
+REDHAT_SUPPORT_PRODUCT="Fedora"
+REDHAT_SUPPORT_PRODUCT_VERSION=rawhide
+VARIANT_ID=workstation
+"#;
+
+    const DEBIAN: &str = r#"PRETTY_NAME="Debian GNU/Linux 12 (bookworm)"
+NAME="Debian GNU/Linux"
+VERSION_ID="12"
+VERSION="12 (bookworm)"
+VERSION_CODENAME=bookworm
+ID=debian
+"#;
+
+    #[test]
+    fn reads_the_release_of_fedora_editions() {
+        assert_eq!(parse_release(WORKSTATION), Some(Release::Version(41)));
+        assert_eq!(parse_release(SILVERBLUE), Some(Release::Version(42)));
+        assert_eq!(parse_release(RAWHIDE), Some(Release::Rawhide));
+    }
+
+    #[test]
+    fn reads_no_release_from_other_distributions() {
+        assert_eq!(parse_release(DEBIAN), None);
+        assert_eq!(parse_release(""), None);
+    }
+
+    #[test]
+    fn reads_no_release_without_a_version_id() {
+        let os_release = WORKSTATION.replace("VERSION_ID=41\n", "");
+        assert_eq!(parse_release(&os_release), None);
+    }
 }
@@ -0,0 +1,83 @@
+//! Expected SHA-256 checksums of the files the installer downloads, read
+//! from the checksum files their upstreams publish next to them. Downloads
+//! missing from the table are not verified: the Material Symbols font has no
+//! published checksum, and prebuilt artifacts carry their own.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use crate::{download, log, system};
+
+/// Download URL prefixes and the checksum file covering them, where
+/// `{url}` stands for the URL of the download itself.
+const TABLE: &[(&str, &str)] = &[
+    (
+        "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/",
+        "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/SHA-256.txt",
+    ),
+    ("https://github.com/starship/starship/releases/", "{url}.sha256"),
+    ("https://static.rust-lang.org/rustup/dist/", "{url}.sha256"),
+];
+
+/// Checksum files already fetched, by URL.
+static FETCHED: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The checksum `url` must match, `None` when it is not in the table or
+/// verification is turned off with `--skip-verify`.
+pub fn sha256(url: &str) -> Result<Option<String>> {
+    if download::skip_verify() {
+        return Ok(None);
+    }
+    let Some((_, source)) = TABLE.iter().find(|(prefix, _)| url.starts_with(prefix)) else {
+        log::log(&format!("No checksum known for {}, not verifying it", url));
+        return Ok(None);
+    };
+
+    let source = source.replace("{url}", url);
+    let content = fetch(&source)?;
+    let name = url.rsplit('/').next().unwrap_or(url);
+    match find(&content, name) {
+        Some(sum) => Ok(Some(sum)),
+        None => bail!("{} lists no checksum for {}", source, name),
+    }
+}
+
+fn fetch(source: &str) -> Result<String> {
+    if let Some(content) = FETCHED.lock().unwrap().as_ref().and_then(|fetched| fetched.get(source)) {
+        return Ok(content.clone());
+    }
+
+    let file_name = source.rsplit('/').next().unwrap_or("checksums");
+    let path = system::build_root().join("checksums").join(file_name);
+    download::download(source, &path, None)?;
+    let content = fs::read_to_string(&path)?;
+    fs::remove_file(&path).ok();
+
+    FETCHED
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(source.to_string(), content.clone());
+    Ok(content)
+}
+
+/// Finds the checksum of `name` in `sha256sum` output, or the bare checksum
+/// of a file covering a single download.
+fn find(content: &str, name: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let sum = fields.next()?;
+        if sum.len() != 64 || !sum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        match fields.next() {
+            None => Some(sum.to_lowercase()),
+            Some(file) => {
+                let file = file.trim_start_matches('*');
+                (file.rsplit('/').next() == Some(name)).then(|| sum.to_lowercase())
+            }
+        }
+    })
+}
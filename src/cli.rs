@@ -1,10 +1,58 @@
-use anyhow::{bail, Result};
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{journal, localrpm, log, prefix, summary, toolbox, ui, versions};
+
+pub const CLI_REPO: &str = "https://github.com/caelestia-dots/cli.git";
+
+/// Name of the package `--cli-rpm` builds.
+const RPM_NAME: &str = "caelestia-cli";
+
+/// Where the RPM puts caelestia-cli and the Python packages it needs, out of
+/// the system's site-packages so it clashes with no other package.
+const RPM_PREFIX: &str = "/usr/lib/caelestia-cli";
+
+const SPEC_TEMPLATE: &str = r#"Name:           caelestia-cli
+Version:        {version}
+Release:        1%{?dist}
+Summary:        Command line interface of the caelestia dotfiles
+License:        GPL-3.0-only
+URL:            https://github.com/caelestia-dots/cli
+Requires:       python(abi) = {python}
+# The bundled Python packages bring their own libraries
+AutoReqProv:    no
+
+%global debug_package %{nil}
+%global _build_id_links none
+
+%description
+caelestia-cli, packaged locally by caelestia-installer from commit {commit}.
+
+%install
+pip3 install --root %{buildroot} --prefix {prefix} --ignore-installed --no-warn-script-location {source}
+install -D -m 755 {wrapper} %{buildroot}/usr/bin/caelestia
+{completions_install}
+%files
+{prefix}
+/usr/bin/caelestia
+{completions_file}
+"#;
+
+/// Whether caelestia-cli is packaged as an RPM and installed with dnf.
+static RPM: Mutex<bool> = Mutex::new(false);
+
+pub fn set_rpm(rpm: bool) {
+    *RPM.lock().unwrap() = rpm;
+}
 
-use crate::{log, ui};
-
-const CLI_REPO: &str = "https://github.com/caelestia-dots/cli.git";
+/// Whether to install caelestia-cli as an RPM: asked for, or installed that
+/// way before so `update` keeps it so.
+fn rpm() -> bool {
+    *RPM.lock().unwrap() || localrpm::installed(RPM_NAME)
+}
 
 pub fn install_cli(dry_run: bool) -> Result<()> {
     ui::info("Installing caelestia-cli...");
@@ -17,38 +65,100 @@ pub fn install_cli(dry_run: bool) -> Result<()> {
     // Check if already installed
     if which::which("caelestia").is_ok() {
         ui::success("caelestia-cli already installed");
+        summary::skipped();
         return Ok(());
     }
 
-    let cli_dir = PathBuf::from("/tmp/caelestia-cli");
+    install_from_source()
+}
+
+/// Installs the latest upstream caelestia-cli over the current one.
+pub fn reinstall(dry_run: bool) -> Result<()> {
+    ui::info("Reinstalling caelestia-cli...");
 
-    // Clone repo
-    if cli_dir.exists() {
-        std::fs::remove_dir_all(&cli_dir).ok();
+    if dry_run {
+        ui::success("Would reinstall caelestia-cli (dry-run)");
+        return Ok(());
     }
 
-    let cmd = format!("git clone {} {:?}", CLI_REPO, cli_dir);
-    log::log_command(&cmd);
+    install_from_source()
+}
+
+/// Commit of caelestia-cli that was last installed, if known.
+pub fn installed_commit() -> Option<String> {
+    fs::read_to_string(commit_path())
+        .ok()
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+}
 
-    let output = Command::new("git")
-        .args(["clone", CLI_REPO, cli_dir.to_str().unwrap()])
-        .output()?;
+fn commit_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer/cli-commit")
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        log::log_error(&stderr);
-        bail!("Failed to clone caelestia-cli");
+/// The virtual environment caelestia-cli is installed into, keeping its
+/// Python packages out of the system's, where they could clash with dnf's.
+pub fn venv_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.local/share"))
+        .join("caelestia-cli")
+}
+
+fn install_from_source() -> Result<()> {
+    let cli_dir = crate::system::build_root().join("caelestia-cli");
+
+    ui::info("Fetching caelestia-cli...");
+    versions::sync_source("cli", CLI_REPO, &cli_dir, false)
+        .map_err(|e| anyhow!("Failed to clone caelestia-cli: {}", e))?;
+
+    if rpm() && toolbox::active() {
+        ui::warning("caelestia-cli is not packaged as an RPM in the toolbox, installing it into a Python environment");
+        install_venv(&cli_dir)?;
+    } else if rpm() {
+        install_rpm(&cli_dir)?;
+    } else {
+        install_venv(&cli_dir)?;
     }
 
-    ui::success("Cloned caelestia-cli");
+    // Remember the commit so `update` can tell when upstream moved on
+    let head = Command::new("git")
+        .args(["-C", cli_dir.to_str().unwrap(), "rev-parse", "HEAD"])
+        .output()?;
+    if head.status.success() {
+        let path = commit_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &head.stdout)?;
+    }
+    Ok(())
+}
+
+/// Installs caelestia-cli from `cli_dir` into its Python environment, with a
+/// wrapper in the prefix's bin.
+fn install_venv(cli_dir: &Path) -> Result<()> {
+    let venv = venv_dir();
+    let pip = venv.join("bin/pip");
+    if !pip.exists() {
+        ui::info(&format!("Creating a Python environment in {}...", venv.display()));
+        log::log_command(&toolbox::command_line(&format!("python3 -m venv {}", venv.display())));
+        let output = toolbox::command("python3").args(["-m", "venv"]).arg(&venv).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Failed to create the Python environment for caelestia-cli: {}", stderr.trim());
+        }
+    }
+    let pip = pip.to_string_lossy();
 
     // Install hatch-vcs (required by pyproject.toml)
     ui::info("Installing build dependencies...");
-    let cmd = "pip3 install --break-system-packages hatch-vcs";
-    log::log_command(cmd);
+    log::log_command(&toolbox::command_line(&format!("{} install hatch-vcs", pip)));
 
-    let output = Command::new("pip3")
-        .args(["install", "--break-system-packages", "hatch-vcs"])
+    let output = toolbox::command(&pip)
+        .args(["install", "hatch-vcs"])
         .output()?;
 
     if !output.status.success() {
@@ -57,11 +167,11 @@ pub fn install_cli(dry_run: bool) -> Result<()> {
 
     // Install directly with pip (simpler than building wheel)
     ui::info("Installing caelestia-cli...");
-    let cmd = "pip3 install --break-system-packages /tmp/caelestia-cli";
-    log::log_command(cmd);
+    log::log_command(&toolbox::command_line(&format!("{} install {}", pip, cli_dir.display())));
 
-    let output = Command::new("pip3")
-        .args(["install", "--break-system-packages", "/tmp/caelestia-cli"])
+    let output = toolbox::command(&pip)
+        .arg("install")
+        .arg(cli_dir)
         .output()?;
 
     if !output.status.success() {
@@ -69,13 +179,22 @@ pub fn install_cli(dry_run: bool) -> Result<()> {
         log::log_error(&stderr);
         bail!("Failed to install caelestia-cli");
     }
+    remove_system_install();
 
-    // Create wrapper script in /usr/local/bin (pip doesn't always add to PATH)
+    // Create wrapper script in the prefix's bin, as the environment's bin is
+    // not on PATH
     ui::info("Creating caelestia wrapper script...");
-    let wrapper = "#!/bin/bash\nexec python3 -m caelestia \"$@\"\n";
+    let wrapper = format!(
+        "#!/bin/bash\nexec \"{}\" -m caelestia \"$@\"\n",
+        venv.join("bin/python").display()
+    );
+    let wrapper_path = prefix::bin_dir().join("caelestia");
+    if prefix::is_user() {
+        std::fs::create_dir_all(prefix::bin_dir())?;
+    }
 
-    let output = Command::new("sudo")
-        .args(["tee", "/usr/local/bin/caelestia"])
+    let output = prefix::command("tee")
+        .arg(&wrapper_path)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::null())
         .spawn();
@@ -88,29 +207,185 @@ pub fn install_cli(dry_run: bool) -> Result<()> {
         let _ = child.wait();
     }
 
-    let _ = Command::new("sudo")
-        .args(["chmod", "+x", "/usr/local/bin/caelestia"])
+    let _ = prefix::command("chmod")
+        .arg("+x")
+        .arg(&wrapper_path)
         .output();
 
     ui::success("Installed caelestia-cli");
     log::log("caelestia-cli installation complete");
+    summary::artifact("caelestia-cli installed");
 
     // Copy fish completions
-    install_fish_completions(&cli_dir)?;
+    install_fish_completions(cli_dir)
+}
+
+/// Packages caelestia-cli from `cli_dir` with rpmbuild, bundling the Python
+/// packages it needs, and installs the package with dnf so `dnf remove` and
+/// later updates handle it like any other.
+fn install_rpm(cli_dir: &Path) -> Result<()> {
+    localrpm::ensure_rpmbuild()?;
+    let (version, commit) = localrpm::snapshot_version(cli_dir);
 
+    let python = Command::new("python3")
+        .args(["-c", "import sysconfig; print(sysconfig.get_python_version())"])
+        .output()?;
+    let python = String::from_utf8_lossy(&python.stdout).trim().to_string();
+    if python.is_empty() {
+        bail!("Could not tell the Python version caelestia-cli is packaged for");
+    }
+
+    let topdir = localrpm::topdir(RPM_NAME);
+    fs::remove_dir_all(&topdir).ok();
+    fs::create_dir_all(&topdir)?;
+
+    let wrapper = topdir.join("caelestia");
+    fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/bash\nexport PYTHONPATH=\"{}${{PYTHONPATH:+:$PYTHONPATH}}\"\nexec python3 -m caelestia \"$@\"\n",
+            site_packages(RPM_PREFIX)?
+        ),
+    )?;
+
+    let completions = cli_dir.join("completions/caelestia.fish");
+    let (completions_install, completions_file) = if completions.exists() {
+        (
+            format!(
+                "install -D -m 644 {} %{{buildroot}}/usr/share/fish/vendor_completions.d/caelestia.fish\n",
+                completions.display()
+            ),
+            "/usr/share/fish/vendor_completions.d/caelestia.fish".to_string(),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+    let spec = SPEC_TEMPLATE
+        .replace("{version}", &version)
+        .replace("{python}", &python)
+        .replace("{commit}", &commit)
+        .replace("{prefix}", RPM_PREFIX)
+        .replace("{source}", &cli_dir.to_string_lossy())
+        .replace("{wrapper}", &wrapper.to_string_lossy())
+        .replace("{completions_install}", &completions_install)
+        .replace("{completions_file}", &completions_file);
+
+    let rpm = localrpm::build(RPM_NAME, &spec, &topdir)?;
+    localrpm::install(RPM_NAME, &rpm)?;
+
+    // A wrapper earlier in PATH would shadow the package's
+    remove_venv()?;
+    remove_system_install();
+    ui::success(&format!("Installed caelestia-cli {} as the {} package", version, RPM_NAME));
+    summary::artifact("caelestia-cli installed");
+    Ok(())
+}
+
+/// The site-packages directories pip installs into under `prefix`, joined
+/// for PYTHONPATH: `lib` for pure Python and `lib64` for compiled packages.
+fn site_packages(prefix: &str) -> Result<String> {
+    let script = format!(
+        "import sysconfig; v = {{'base': '{0}', 'platbase': '{0}'}}; print(sysconfig.get_path('purelib', vars=v) + ':' + sysconfig.get_path('platlib', vars=v))",
+        prefix
+    );
+    let output = Command::new("python3").args(["-c", &script]).output()?;
+    let site = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || site.is_empty() {
+        bail!("Could not tell where pip installs under {}", prefix);
+    }
+    Ok(site)
+}
+
+/// Removes a caelestia-cli that earlier versions of the installer put into
+/// the system's Python with `--break-system-packages`, now shadowed by the
+/// environment.
+fn remove_system_install() {
+    let installed = toolbox::command("pip3")
+        .args(["show", "caelestia"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        return;
+    }
+    ui::info("Removing the caelestia-cli installed into the system's Python...");
+    let mut args = vec!["pip3", "uninstall", "-y", "--break-system-packages", "caelestia"];
+    log::log_command(&toolbox::command_line(&args.join(" ")));
+    let mut output = toolbox::command(args[0]).args(&args[1..]).output();
+    if !output.as_ref().is_ok_and(|output| output.status.success()) && !prefix::is_user() {
+        // System-wide installs went to /usr/local, owned by root
+        args.insert(0, "sudo");
+        log::log_command(&toolbox::command_line(&args.join(" ")));
+        output = toolbox::command(args[0]).args(&args[1..]).output();
+    }
+    if !output.is_ok_and(|output| output.status.success()) {
+        ui::warning("Could not remove it, run: pip3 uninstall --break-system-packages caelestia");
+    }
+}
+
+/// Removes caelestia-cli: the package when it was installed as one, or else
+/// the wrapper, its Python environment and the fish completions.
+pub fn uninstall() -> Result<()> {
+    ui::info("Removing caelestia-cli...");
+    if localrpm::installed(RPM_NAME) {
+        if crate::privileged::run(&["dnf", "remove", "-y", RPM_NAME]).is_err() {
+            bail!("Failed to remove the {} package", RPM_NAME);
+        }
+    } else {
+        remove_venv()?;
+        let completions = completions_path();
+        if completions.exists() {
+            log::log_command(&prefix::command_line(&format!("rm -f {}", completions.display())));
+            let output = prefix::command("rm").arg("-f").arg(&completions).output()?;
+            if !output.status.success() {
+                bail!("Failed to remove {}", completions.display());
+            }
+        }
+    }
+    fs::remove_file(commit_path()).ok();
+    ui::success("Removed caelestia-cli");
+    log::log("caelestia-cli removed");
     Ok(())
 }
 
-fn install_fish_completions(cli_dir: &PathBuf) -> Result<()> {
+/// Removes the Python environment and the wrapper that runs it.
+fn remove_venv() -> Result<()> {
+    let wrapper = prefix::bin_dir().join("caelestia");
+    let venv = venv_dir();
+    if fs::read_to_string(&wrapper).is_ok_and(|content| content.contains(&*venv.to_string_lossy())) {
+        log::log_command(&prefix::command_line(&format!("rm -f {}", wrapper.display())));
+        let output = prefix::command("rm").arg("-f").arg(&wrapper).output()?;
+        if !output.status.success() {
+            bail!("Failed to remove {}", wrapper.display());
+        }
+    }
+    if venv.exists() {
+        log::log_command(&format!("rm -rf {}", venv.display()));
+        fs::remove_dir_all(&venv)?;
+    }
+    Ok(())
+}
+
+fn completions_path() -> PathBuf {
+    if prefix::is_user() {
+        let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+        config_dir.join("fish/completions/caelestia.fish")
+    } else {
+        PathBuf::from("/usr/share/fish/vendor_completions.d/caelestia.fish")
+    }
+}
+
+fn install_fish_completions(cli_dir: &Path) -> Result<()> {
     let completions_src = cli_dir.join("completions/caelestia.fish");
-    let completions_dst = PathBuf::from("/usr/share/fish/vendor_completions.d/caelestia.fish");
+    let completions_dst = completions_path();
 
     if completions_src.exists() {
         ui::info("Installing fish completions...");
+        if let Some(parent) = completions_dst.parent().filter(|_| prefix::is_user()) {
+            std::fs::create_dir_all(parent)?;
+        }
 
-        let output = Command::new("sudo")
+        let output = prefix::command("cp")
             .args([
-                "cp",
                 completions_src.to_str().unwrap(),
                 completions_dst.to_str().unwrap(),
             ])
@@ -143,7 +418,7 @@ pub fn init_scheme(dry_run: bool) -> Result<()> {
 
     // Copy default scheme to current if source exists
     if scheme_src.exists() {
-        std::fs::copy(&scheme_src, &scheme_dst)?;
+        journal::write_file(&scheme_dst, std::fs::read(&scheme_src)?)?;
         ui::success("Initialized default color scheme");
         log::log("Color scheme initialized");
     } else {
@@ -159,10 +434,10 @@ pub fn init_scheme(dry_run: bool) -> Result<()> {
     let hypr_user = caelestia_conf.join("hypr-user.conf");
 
     if !hypr_vars.exists() {
-        std::fs::write(&hypr_vars, "# User Hyprland variables\n")?;
+        journal::write_file(&hypr_vars, "# User Hyprland variables\n")?;
     }
     if !hypr_user.exists() {
-        std::fs::write(&hypr_user, "# User Hyprland config\n# Add your custom Hyprland settings here\n")?;
+        journal::write_file(&hypr_user, "# User Hyprland config\n# Add your custom Hyprland settings here\n")?;
     }
 
     ui::success("Created caelestia config directory");
@@ -178,7 +453,7 @@ pub fn init_scheme(dry_run: bool) -> Result<()> {
             .replace("success_symbol = \"[◎](bold italic bright-yellow)\"", "success_symbol = \"[✔](bold italic bright-green)\"")
             .replace("error_symbol = \"[○](italic purple)\"", "error_symbol = \"[✘](italic bold red)\"");
         
-        std::fs::write(starship_config, new_content)?;
+        journal::write_file(&starship_config, new_content)?;
         ui::success("Customized Starship prompt symbols");
     }
 
@@ -0,0 +1,56 @@
+//! Clipboard history with cliphist. Hyprland stores every copied text and
+//! image, and `Super+V` picks an entry through fuzzel.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{hyprconf, journal, log, summary, ui};
+
+/// Marks the lines the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: clipboard";
+
+const CLIPBOARD_CONFIG: &str = "exec-once = wl-paste --type text --watch cliphist store
+exec-once = wl-paste --type image --watch cliphist store
+bind = SUPER, V, exec, cliphist list | fuzzel --dmenu | cliphist decode | wl-copy
+";
+
+/// Adds the cliphist watchers and the picker keybind to
+/// `~/.config/caelestia/hypr-user.conf`.
+pub fn setup(dry_run: bool) -> Result<()> {
+    ui::info("Setting up clipboard history...");
+
+    let hypr_user = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia/hypr-user.conf");
+
+    if dry_run {
+        ui::success(&format!("Would add cliphist to {:?} (dry-run)", hypr_user));
+        return Ok(());
+    }
+
+    let missing: Vec<&str> = ["cliphist", "wl-paste", "fuzzel"]
+        .into_iter()
+        .filter(|program| which::which(program).is_err())
+        .collect();
+    if !missing.is_empty() {
+        bail!("{} not found after installing packages", missing.join(", "));
+    }
+
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if existing.contains(HYPR_MARKER) {
+        ui::success("Clipboard history already set up");
+        return Ok(());
+    }
+
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, format!("{}\n{}\n{}", existing, HYPR_MARKER, CLIPBOARD_CONFIG))?;
+    hyprconf::check(&hypr_user);
+
+    ui::success("Clipboard history starts with Hyprland, Super+V opens it");
+    log::log(&format!("cliphist config written to {:?}", hypr_user));
+    summary::artifact("cliphist");
+    Ok(())
+}
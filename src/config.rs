@@ -0,0 +1,245 @@
+//! `install.toml`, describing an installation so it can be repeated
+//! unattended on other machines. Every key is optional and command-line
+//! flags take precedence.
+//!
+//! ```toml
+//! noconfirm = true
+//! rollback_on_failure = true
+//! prebuilt = true
+//! rpmfusion = true
+//! command_timeout = 1200
+//! wallpapers_dir = "~/Pictures/Backgrounds"
+//! display_manager = "sddm"
+//! shell = "zsh"
+//! login_shell = true
+//! rust_toolchain = "1.82.0"
+//! components = ["fonts", "cava", "keybinds", "rust"]
+//!
+//! [packages]
+//! profile = "minimal"
+//! with = ["theming"]
+//! add = ["htop"]
+//! exclude = ["fuzzel"]
+//!
+//! [steps]
+//! skip = ["shell-config"]
+//!
+//! [repos]
+//! dotfiles_repo = "https://github.com/me/caelestia.git"
+//! dotfiles_branch = "main"
+//! shell_branch = "main"
+//! quickshell_copr = "errornointernet/quickshell"
+//!
+//! [repos.copr_keys]
+//! "solopasha/hyprland" = "1234567890ABCDEF1234567890ABCDEF12345678"
+//!
+//! [keybinds]
+//! main_mod = "ALT"
+//! terminal = "kitty"
+//! browser = "chromium-browser"
+//! file_manager = "nautilus"
+//! launcher = "fuzzel"
+//! import = "~/hyprland.conf.old"
+//!
+//! [default_apps]
+//! browser = "chromium-browser.desktop"
+//! image_viewer = "imv.desktop"
+//!
+//! [fish]
+//! plugins = ["jorgebucaran/autopair.fish", "franciscolourenco/done"]
+//!
+//! [input]
+//! layout = "de"
+//! variant = "nodeadkeys"
+//! natural_scroll = true
+//! lock_after = 10
+//!
+//! [flatpak]
+//! apps = ["com.spotify.Client"]
+//!
+//! [hooks.pre]
+//! packages = "sudo dnf config-manager setopt fastestmirror=True"
+//!
+//! [hooks.post]
+//! symlinks = "~/dotfiles/overrides.sh"
+//!
+//! [[step]]
+//! id = "tailscale"
+//! title = "Installing Tailscale..."
+//! after = ["packages"]
+//! run = "sudo dnf install -y tailscale && sudo systemctl enable --now tailscaled"
+//! check = "systemctl is-enabled tailscaled"
+//! rollback = "sudo systemctl disable --now tailscaled"
+//! resource = "dnf"
+//! ```
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub noconfirm: Option<bool>,
+    pub rollback_on_failure: Option<bool>,
+    pub continue_on_error: Option<bool>,
+    /// Whether to set up the display manager; named after the only one
+    /// there used to be.
+    pub greetd: Option<bool>,
+    pub display_manager: Option<String>,
+    pub shell: Option<String>,
+    pub login_shell: Option<bool>,
+    pub starship_script: Option<bool>,
+    pub rpm: Option<bool>,
+    pub force_source: Option<bool>,
+    pub container_build: Option<bool>,
+    pub remove_build_deps: Option<bool>,
+    pub remove_de_portals: Option<bool>,
+    pub keep_build_artifacts: Option<bool>,
+    pub cli_rpm: Option<bool>,
+    /// Used when the rust component is selected.
+    pub rust_toolchain: Option<String>,
+    pub prefix: Option<String>,
+    pub build_dir: Option<String>,
+    pub jobs: Option<usize>,
+    pub command_timeout: Option<u64>,
+    pub memory_guard: Option<bool>,
+    pub limit_rate: Option<String>,
+    pub skip_verify: Option<bool>,
+    pub track_latest: Option<bool>,
+    pub rpmfusion: Option<bool>,
+    pub prebuilt: Option<bool>,
+    pub prebuilt_url: Option<String>,
+    pub wallpapers: Option<bool>,
+    pub wallpapers_dir: Option<String>,
+    /// Ids of the optional components to install, replacing the defaults.
+    pub components: Option<Vec<String>>,
+    pub packages: Packages,
+    pub steps: Steps,
+    pub repos: Repos,
+    pub keybinds: Keybinds,
+    pub default_apps: DefaultApps,
+    pub fish: Fish,
+    pub input: Input,
+    pub flatpak: Flatpak,
+    pub hooks: Hooks,
+    /// Extra steps, declared as `[[step]]` tables.
+    #[serde(rename = "step")]
+    pub custom_steps: Vec<CustomStep>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Packages {
+    /// minimal, default or full.
+    pub profile: Option<String>,
+    /// Package groups added to or left out of the profile.
+    pub with: Vec<String>,
+    pub without: Vec<String>,
+    pub add: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Steps {
+    pub only: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Repos {
+    pub dotfiles_repo: Option<String>,
+    pub shell_repo: Option<String>,
+    pub dotfiles_branch: Option<String>,
+    pub shell_branch: Option<String>,
+    /// Extra COPR to look for a quickshell package in.
+    pub quickshell_copr: Option<String>,
+    /// Signing key fingerprints of COPRs, by `owner/project`, trusted
+    /// without asking.
+    pub copr_keys: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keybinds {
+    pub main_mod: Option<String>,
+    pub terminal: Option<String>,
+    pub browser: Option<String>,
+    pub file_manager: Option<String>,
+    pub launcher: Option<String>,
+    pub import: Option<String>,
+}
+
+/// Desktop entries to open folders, links, images and `terminal:` links
+/// with, such as `thunar.desktop`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultApps {
+    pub file_manager: Option<String>,
+    pub browser: Option<String>,
+    pub image_viewer: Option<String>,
+    pub terminal: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fish {
+    /// Plugins installed with fisher, such as `jorgebucaran/autopair.fish`.
+    pub plugins: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Input {
+    pub layout: Option<String>,
+    pub variant: Option<String>,
+    pub natural_scroll: Option<bool>,
+    /// Minutes without input before the session is locked.
+    pub lock_after: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flatpak {
+    /// Flathub app ids, such as `org.mozilla.firefox`.
+    pub apps: Vec<String>,
+}
+
+/// Shell commands run before or after the step they are keyed by.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    pub pre: BTreeMap<String, String>,
+    pub post: BTreeMap<String, String>,
+}
+
+/// A step running `run` with bash. `check` exiting 0 means it is done and
+/// skipped; `rollback` undoes it when a later step fails with
+/// `rollback_on_failure`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CustomStep {
+    pub id: String,
+    pub title: Option<String>,
+    /// Ids of the steps it runs after; the last step when empty.
+    pub after: Vec<String>,
+    pub run: String,
+    pub check: Option<String>,
+    pub rollback: Option<String>,
+    /// light, dnf, build or network, for how it may overlap other steps.
+    pub resource: Option<String>,
+    /// Whether it may prompt, in which case it runs alone.
+    pub interactive: bool,
+}
+
+pub fn load(path: &Path) -> Result<Config> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => bail!("Could not read {:?}: {}", path, e),
+    };
+    toml::from_str(&content).map_err(|e| anyhow!("Invalid config file {:?}: {}", path, e))
+}
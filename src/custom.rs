@@ -0,0 +1,141 @@
+//! Steps declared in the config file with `[[step]]`, run alongside the
+//! built-in ones: a shell command placed after another step, with optional
+//! commands that tell whether it is done and that undo it.
+
+use anyhow::{bail, Result};
+use std::process::Command;
+
+use crate::config;
+use crate::engine::Resource;
+use crate::install::{Options, Step};
+use crate::{log, runner, ui};
+
+pub struct CommandStep {
+    id: &'static str,
+    title: &'static str,
+    after: Vec<&'static str>,
+    resource: Resource,
+    interactive: bool,
+    run: String,
+    check: Option<String>,
+    rollback: Option<String>,
+}
+
+impl CommandStep {
+    /// Builds the step declared by `declared`. Its id and title live as long
+    /// as the built-in ones, for the rest of the run.
+    pub fn new(declared: &config::CustomStep) -> Result<Self> {
+        let id = declared.id.trim();
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            bail!("Invalid step id '{}': use letters, digits, '-' and '_'", declared.id);
+        }
+        if declared.run.trim().is_empty() {
+            bail!("Step {} in the config file has no command to run", id);
+        }
+        let resource = match declared.resource.as_deref() {
+            None | Some("light") => Resource::Light,
+            Some("dnf") => Resource::Dnf,
+            Some("build") => Resource::Build,
+            Some("network") => Resource::Network,
+            Some(other) => bail!(
+                "Invalid resource '{}' for step {} (use light, dnf, build or network)",
+                other,
+                id
+            ),
+        };
+        let title = declared.title.clone().unwrap_or_else(|| format!("Running {}...", id));
+
+        Ok(Self {
+            id: Box::leak(id.to_string().into_boxed_str()),
+            title: Box::leak(title.into_boxed_str()),
+            after: declared
+                .after
+                .iter()
+                .map(|dep| &*Box::leak(dep.clone().into_boxed_str()))
+                .collect(),
+            resource,
+            interactive: declared.interactive,
+            run: declared.run.clone(),
+            check: declared.check.clone(),
+            rollback: declared.rollback.clone(),
+        })
+    }
+
+    fn shell(&self, line: &str, options: &Options) -> Command {
+        let mut command = Command::new("bash");
+        command
+            .args(["-c", line])
+            .env("CAELESTIA_STEP", self.id)
+            .env("CAELESTIA_DRY_RUN", if options.dry_run { "1" } else { "0" })
+            .env("CAELESTIA_NONINTERACTIVE", if options.noconfirm { "1" } else { "0" });
+        command
+    }
+
+    fn run_command(&self, line: &str, options: &Options) -> Result<()> {
+        log::log_command(line);
+        if options.dry_run {
+            ui::success(&format!("Would run: {} (dry-run)", line));
+            return Ok(());
+        }
+        let output = runner::run(&mut self.shell(line, options))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            let reason = match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            };
+            bail!("{}", reason);
+        }
+        Ok(())
+    }
+}
+
+impl Step for CommandStep {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn title(&self) -> &'static str {
+        self.title
+    }
+
+    fn after(&self) -> Option<&[&'static str]> {
+        // Without `after` the step follows the one before it in the plan
+        (!self.after.is_empty()).then_some(self.after.as_slice())
+    }
+
+    fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    fn check(&self, options: &Options) -> bool {
+        let Some(ref check) = self.check else {
+            return false;
+        };
+        // Not log_command: the check is no part of a dry run's plan
+        log::debug(&format!("Checking {}: bash -c {:?}", self.id, check));
+        runner::run(&mut self.shell(check, options)).is_ok_and(|output| output.status.success())
+    }
+
+    fn checked(&self) -> bool {
+        self.check.is_some()
+    }
+
+    fn apply(&self, options: &Options) -> Result<()> {
+        self.run_command(&self.run, options)?;
+        ui::success(&format!("{} done", self.id));
+        Ok(())
+    }
+
+    fn rollback(&self, options: &Options) -> Result<()> {
+        match self.rollback {
+            Some(ref rollback) => self.run_command(rollback, options),
+            None => Ok(()),
+        }
+    }
+}
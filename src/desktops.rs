@@ -0,0 +1,125 @@
+//! Other desktops installed next to Hyprland, such as GNOME on Fedora
+//! Workstation or Plasma on the KDE spin. Their portals also answer the
+//! Hyprland session's requests, where GNOME's and KDE's fail screenshots and
+//! screen sharing or stall apps for the portal timeout; the portals step
+//! keeps them out of the session. Their display managers are replaced by the
+//! display-manager step.
+
+use anyhow::Result;
+use std::process::Command;
+
+use crate::packages::{self, Backend};
+use crate::{journal, log, portals, privileged, summary, ui};
+
+struct Desktop {
+    name: &'static str,
+    /// Package that is installed with the desktop.
+    package: &'static str,
+    /// Its portal backend, if it has one of its own.
+    portal: Option<&'static str>,
+    /// The display manager it comes with.
+    display_manager: &'static str,
+}
+
+const DESKTOPS: &[Desktop] = &[
+    Desktop { name: "GNOME", package: "gnome-shell", portal: Some("xdg-desktop-portal-gnome"), display_manager: "gdm" },
+    Desktop { name: "KDE Plasma", package: "plasma-workspace", portal: Some("xdg-desktop-portal-kde"), display_manager: "sddm" },
+    Desktop { name: "Xfce", package: "xfce4-session", portal: None, display_manager: "lightdm" },
+    Desktop { name: "Cinnamon", package: "cinnamon", portal: Some("xdg-desktop-portal-xapp"), display_manager: "lightdm" },
+    Desktop { name: "MATE", package: "mate-session-manager", portal: None, display_manager: "lightdm" },
+    Desktop { name: "LXQt", package: "lxqt-session", portal: Some("xdg-desktop-portal-lxqt"), display_manager: "sddm" },
+    Desktop { name: "Budgie", package: "budgie-desktop", portal: None, display_manager: "lightdm" },
+];
+
+fn installed(package: &str) -> bool {
+    Command::new("rpm")
+        .args(["-q", package])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// The desktops installed besides Hyprland.
+fn installed_desktops() -> Vec<&'static Desktop> {
+    DESKTOPS.iter().filter(|desktop| installed(desktop.package)).collect()
+}
+
+/// Portals of desktops that are no longer installed, left behind when the
+/// desktop was removed. Removing them takes nothing else with them.
+fn leftover_portals() -> Vec<&'static str> {
+    let mut leftover: Vec<&str> = DESKTOPS
+        .iter()
+        .filter_map(|desktop| desktop.portal)
+        .filter(|portal| installed(portal))
+        .filter(|portal| {
+            !DESKTOPS
+                .iter()
+                .any(|desktop| desktop.portal == Some(*portal) && installed(desktop.package))
+        })
+        .collect();
+    leftover.dedup();
+    leftover
+}
+
+/// Whether the Hyprland session is kept from other desktops' portals, or
+/// there are none.
+pub fn configured() -> bool {
+    portals::conf_path().exists()
+        || DESKTOPS
+            .iter()
+            .filter_map(|desktop| desktop.portal)
+            .all(|portal| !installed(portal))
+}
+
+/// Explains what the installed desktops conflict with and, with
+/// `remove_portals`, removes the portals of desktops that are gone.
+pub fn setup(remove_portals: bool, dry_run: bool, noconfirm: bool) -> Result<()> {
+    let desktops = installed_desktops();
+    let leftover = leftover_portals();
+    if desktops.is_empty() && leftover.is_empty() {
+        ui::success("No other desktop installed");
+        summary::skipped();
+        return Ok(());
+    }
+
+    for desktop in &desktops {
+        log::log(&format!("{} is installed ({})", desktop.name, desktop.package));
+        ui::warning(&format!("{} is installed next to Hyprland", desktop.name));
+        if let Some(portal) = desktop.portal {
+            ui::info(&format!(
+                "  {} would also answer the Hyprland session, the portals step keeps it out",
+                portal
+            ));
+        }
+        if journal::service_enabled(desktop.display_manager) {
+            ui::info(&format!(
+                "  {} is its display manager, the display-manager step offers to replace it",
+                desktop.display_manager
+            ));
+        }
+    }
+
+    if leftover.is_empty() {
+        return Ok(());
+    }
+    ui::warning(&format!("Portals of removed desktops are still installed: {}", leftover.join(", ")));
+    if packages::backend() == Backend::RpmOstree {
+        ui::info("They are part of the image, portals.conf keeps them out of the Hyprland session");
+        return Ok(());
+    }
+    let remove = remove_portals || (!noconfirm && ui::confirm(&format!("Remove {}?", leftover.join(", "))));
+    if !remove {
+        ui::info("Keeping them, portals.conf keeps them out of the Hyprland session");
+        return Ok(());
+    }
+
+    let mut args = vec!["dnf", "remove", "-y"];
+    args.extend(&leftover);
+    privileged::run(&args)?;
+    if dry_run {
+        ui::success(&format!("Would remove {} (dry-run)", leftover.join(", ")));
+    } else {
+        ui::success(&format!("Removed {}", leftover.join(", ")));
+        summary::artifact(&format!("removed {}", leftover.join(", ")));
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! The display manager starting Hyprland: greetd with tuigreet, or SDDM.
+//! Each backend lives in its own module; what they share is here.
+
+use anyhow::{bail, Result};
+
+use crate::{greetd, journal, log, privileged, sddm, summary, ui};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DisplayManager {
+    #[default]
+    Greetd,
+    Sddm,
+    /// Leave the display manager alone and start Hyprland by hand.
+    None,
+}
+
+/// Display managers Fedora ships, by service name.
+const KNOWN_SERVICES: &[&str] = &["gdm", "sddm", "lightdm", "lxdm", "greetd", "xdm", "slim"];
+
+pub const ALL: &[DisplayManager] = &[DisplayManager::Greetd, DisplayManager::Sddm, DisplayManager::None];
+
+impl DisplayManager {
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayManager::Greetd => "greetd",
+            DisplayManager::Sddm => "sddm",
+            DisplayManager::None => "none",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayManager::Greetd => "greetd with tuigreet",
+            DisplayManager::Sddm => "SDDM",
+            DisplayManager::None => "none, start Hyprland from the console",
+        }
+    }
+
+    pub fn packages(self) -> &'static [&'static str] {
+        match self {
+            DisplayManager::Greetd => &["greetd", "tuigreet"],
+            DisplayManager::Sddm => sddm::PACKAGES,
+            DisplayManager::None => &[],
+        }
+    }
+}
+
+pub fn parse_display_manager(name: &str) -> Result<DisplayManager> {
+    match ALL.iter().find(|dm| dm.name() == name) {
+        Some(dm) => Ok(*dm),
+        None => bail!("unknown display manager {:?}, expected greetd, sddm or none", name),
+    }
+}
+
+/// Sets up `dm`, asking first unless `confirmed` says whether to. With
+/// `uwsm`, greetd starts the UWSM session.
+pub fn setup(dm: DisplayManager, confirmed: Option<bool>, uwsm: bool, dry_run: bool, noconfirm: bool) -> Result<()> {
+    if dm == DisplayManager::None {
+        ui::info("Leaving the display manager alone, start Hyprland with 'Hyprland'");
+        summary::skipped();
+        return Ok(());
+    }
+    let wanted = confirmed
+        .unwrap_or_else(|| noconfirm || ui::prompt(&format!("Set up {} as display manager?", dm.label())));
+    if !wanted {
+        summary::skipped();
+        return Ok(());
+    }
+
+    match dm {
+        DisplayManager::Greetd => greetd::setup_all(uwsm, dry_run, noconfirm),
+        DisplayManager::Sddm => sddm::setup_all(dry_run, noconfirm),
+        DisplayManager::None => Ok(()),
+    }
+}
+
+/// Whether `dm` has its config written and its service enabled.
+pub fn configured(dm: DisplayManager, uwsm: bool) -> bool {
+    match dm {
+        DisplayManager::Greetd => greetd::configured(uwsm),
+        DisplayManager::Sddm => sddm::configured(),
+        DisplayManager::None => true,
+    }
+}
+
+/// The display managers enabled besides `service`: the one behind the
+/// `display-manager.service` alias, and any other known one.
+fn enabled_others(service: &str) -> Vec<String> {
+    let mut enabled = Vec::new();
+    if let Ok(target) = std::fs::read_link("/etc/systemd/system/display-manager.service") {
+        if let Some(name) = target.file_stem() {
+            enabled.push(name.to_string_lossy().into_owned());
+        }
+    }
+    for known in KNOWN_SERVICES {
+        if !enabled.iter().any(|name| name == known) && journal::service_enabled(known) {
+            enabled.push(known.to_string());
+        }
+    }
+    enabled.retain(|name| name != service);
+    enabled
+}
+
+/// Disables the other display managers before `service` is enabled, as two
+/// of them fight over the same VT and leave a black screen at boot. Returns
+/// whether `service` can be enabled. The disabled ones are journaled, so a
+/// rollback enables them again.
+pub(crate) fn disable_others(service: &str, noconfirm: bool) -> Result<bool> {
+    let others = enabled_others(service);
+    if others.is_empty() {
+        return Ok(true);
+    }
+    log::log(&format!("Enabled display managers: {}", others.join(", ")));
+
+    let list = others.join(", ");
+    ui::warning(&format!("{} is enabled and would conflict with {}", list, service));
+    if !noconfirm && !ui::prompt(&format!("Disable {} and use {} instead?", list, service)) {
+        ui::warning(&format!("Keeping {}, {} is installed but not enabled", list, service));
+        return Ok(false);
+    }
+
+    for other in &others {
+        privileged::disable_service(other)?;
+    }
+    ui::success(&format!("Disabled {}, rollback re-enables it", list));
+    Ok(true)
+}
+
+/// Boots into the graphical target, so the display manager starts.
+pub(crate) fn set_graphical_target() -> Result<()> {
+    if let Err(e) = privileged::set_default_target("graphical.target") {
+        log::log_error(&e.to_string());
+        ui::warning("Could not set default target (may need to run manually)");
+    }
+    Ok(())
+}
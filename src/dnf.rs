@@ -0,0 +1,220 @@
+//! The differences between dnf 4 and dnf5, which replaced it as `dnf` in
+//! Fedora 41: the package carrying the `copr` command and the layout of the
+//! transaction summary. Parsing is kept in plain functions over the captured
+//! output so both formats can be checked without running dnf.
+
+use anyhow::{bail, Result};
+use std::sync::Mutex;
+
+use crate::{log, net, privileged, toolbox, ui};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Version {
+    Dnf4,
+    Dnf5,
+}
+
+impl Version {
+    /// The package providing `dnf copr`.
+    pub fn copr_plugin(self) -> &'static str {
+        match self {
+            Version::Dnf4 => "dnf-plugins-core",
+            Version::Dnf5 => "dnf5-plugins",
+        }
+    }
+}
+
+static VERSION: Mutex<Option<Version>> = Mutex::new(None);
+
+/// The dnf of the build environment, detected once. Defaults to dnf5 when
+/// `dnf --version` cannot be run, as on every supported release.
+pub fn version() -> Version {
+    let mut cached = VERSION.lock().unwrap();
+    if let Some(version) = *cached {
+        return version;
+    }
+    let version = toolbox::command("dnf")
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|o| parse_version(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or(Version::Dnf5);
+    log::log(&format!("dnf version: {:?}", version));
+    *cached = Some(version);
+    version
+}
+
+/// Reads `dnf --version`: dnf5 starts with `dnf5 version 5.x`, dnf 4 with
+/// its bare version number.
+pub fn parse_version(output: &str) -> Option<Version> {
+    let first = output.lines().next()?.trim();
+    if first.starts_with("dnf5") || first.starts_with('5') {
+        Some(Version::Dnf5)
+    } else if first.starts_with('4') {
+        Some(Version::Dnf4)
+    } else {
+        None
+    }
+}
+
+/// Arguments of `dnf install`, after `sudo`. Both versions take
+/// `--allowerasing`, which lets COPR packages replace Fedora's.
+pub fn install_args(allow_erasing: bool) -> Vec<String> {
+    let mut args = vec!["dnf".to_string(), "install".to_string(), "-y".to_string()];
+    if allow_erasing {
+        args.push("--allowerasing".to_string());
+    }
+    args.extend(net::dnf_args());
+    args
+}
+
+/// Installs the plugin package `dnf copr` needs when it is missing, which
+/// minimal installs and containers leave out.
+pub fn ensure_copr_plugin(dry_run: bool) -> Result<()> {
+    let available = toolbox::command("dnf")
+        .args(["copr", "--help"])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    if available {
+        return Ok(());
+    }
+
+    let plugin = version().copr_plugin();
+    ui::info(&format!("Installing {} for dnf copr...", plugin));
+    let mut args = install_args(false);
+    args.push(plugin.to_string());
+    if dry_run {
+        log::log_command(&toolbox::command_line(&format!("sudo {}", args.join(" "))));
+        return Ok(());
+    }
+
+    if let Err(e) = privileged::run_in_build_env(&args) {
+        bail!("Failed to install {}, which dnf copr needs: {}", plugin, e);
+    }
+    Ok(())
+}
+
+/// The packages a transaction summary lists under "Skipping packages ...",
+/// in either version's layout. dnf 4 puts a hint in parentheses below the
+/// heading and dnf5 does not; both then list one indented package per line.
+pub fn skipped_packages(output: &str) -> Vec<String> {
+    let mut skipped = Vec::new();
+    let mut in_section = false;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Skipping packages") {
+            in_section = true;
+            continue;
+        }
+        if !in_section || trimmed.starts_with('(') {
+            continue;
+        }
+        if trimmed.is_empty() || !line.starts_with(' ') {
+            in_section = false;
+            continue;
+        }
+        if let Some(name) = trimmed.split_whitespace().next() {
+            if !skipped.iter().any(|s| s == name) {
+                skipped.push(name.to_string());
+            }
+        }
+    }
+    skipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_version, skipped_packages, Version};
+
+    const DNF4_VERSION: &str = "\
+4.21.1
+  Installed: dnf-0:4.21.1-1.fc40.noarch at Tue 02 Jul 2024 08:14:55 GMT
+  Built    : Fedora Project at Wed 19 Jun 2024 12:00:00 GMT
+
+  Installed: rpm-0:4.19.1.1-1.fc40.x86_64 at Thu 02 May 2024 10:01:32 GMT
+  Built    : Fedora Project at Wed 06 Mar 2024 12:00:00 GMT
+";
+
+    const DNF5_VERSION: &str = "\
+dnf5 version 5.2.8.1
+dnf5 plugin API version 2.0
+libdnf5 version 5.2.8.1
+libdnf5 plugin API version 2.0
+
+Loaded dnf5 plugins:
+  name: builddep
+  version: 1.0.0
+";
+
+    const DNF4_SKIPPED: &str = "\
+Last metadata expiration check: 0:12:41 ago on Tue 15 Oct 2024 09:30:12.
+Dependencies resolved.
+================================================================================
+ Package              Arch     Version            Repository               Size
+================================================================================
+Installing:
+ foot                 x86_64   1.18.1-1.fc40      updates                 600 k
+ fuzzel               x86_64   1.10.2-2.fc40      fedora                  156 k
+Skipping packages with conflicts:
+(add '--best --allowerasing' to command line to force their upgrade):
+ qt6-qtbase           x86_64   6.7.2-3.fc40       updates                 3.8 M
+Skipping packages with broken dependencies:
+ hyprland-qtutils     x86_64   0.1.2-1.fc40       copr:copr.fedorainfracloud.org:solopasha:hyprland  88 k
+
+Transaction Summary
+================================================================================
+Install  2 Packages
+Skip     2 Packages
+";
+
+    const DNF5_SKIPPED: &str = "\
+Updating and loading repositories:
+Repositories loaded.
+Package                    Arch   Version              Repository          Size
+Installing:
+ foot                      x86_64 1.20.2-1.fc41        updates          1.2 MiB
+ fuzzel                    x86_64 1.11.1-1.fc41        updates        360.1 KiB
+Skipping packages with broken dependencies:
+ hyprland-qtutils          x86_64 0.1.3-1.fc41         copr:copr.fedorainfracloud.org:solopasha:hyprland 254.0 KiB
+ hyprland-qtutils          i686   0.1.3-1.fc41         copr:copr.fedorainfracloud.org:solopasha:hyprland 260.5 KiB
+
+Transaction Summary:
+ Installing:         2 packages
+ Skipping:           2 packages
+";
+
+    const DNF5_NOTHING_SKIPPED: &str = "\
+Updating and loading repositories:
+Repositories loaded.
+Package                    Arch   Version              Repository          Size
+Installing:
+ foot                      x86_64 1.20.2-1.fc41        updates          1.2 MiB
+
+Transaction Summary:
+ Installing:         1 package
+";
+
+    #[test]
+    fn reads_the_version_of_either_dnf() {
+        assert_eq!(parse_version(DNF4_VERSION), Some(Version::Dnf4));
+        assert_eq!(parse_version(DNF5_VERSION), Some(Version::Dnf5));
+        assert_eq!(parse_version(""), None);
+        assert_eq!(parse_version("bash: dnf: command not found"), None);
+    }
+
+    #[test]
+    fn lists_the_packages_dnf4_skipped() {
+        assert_eq!(skipped_packages(DNF4_SKIPPED), ["qt6-qtbase", "hyprland-qtutils"]);
+    }
+
+    #[test]
+    fn lists_the_packages_dnf5_skipped_once() {
+        assert_eq!(skipped_packages(DNF5_SKIPPED), ["hyprland-qtutils"]);
+    }
+
+    #[test]
+    fn lists_nothing_when_nothing_was_skipped() {
+        assert!(skipped_packages(DNF5_NOTHING_SKIPPED).is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! `caelestia-installer doctor`: checks an installed setup piece by piece,
+//! printing what was found and, for each problem, how to fix it.
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{dotfiles, log, portals, prefix, selinux, shell, ui};
+
+/// Font families the shell, foot and the prompt render with.
+const FONTS: &[&str] = &["Material Symbols Rounded", "CaskaydiaCove Nerd Font", "JetBrainsMono Nerd Font"];
+
+/// Qt's own QML directories, searched without QML_IMPORT_PATH.
+const SYSTEM_QML_DIRS: &[&str] = &["/usr/lib64/qt6/qml", "/usr/lib/qt6/qml"];
+
+/// What is wrong, and what to run about it.
+struct Problem {
+    detail: String,
+    fix: String,
+}
+
+impl Problem {
+    fn new(detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Problem {
+            detail: detail.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+type Check = fn() -> Result<String, Problem>;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("Quickshell", check_quickshell),
+    ("QML modules", check_qml_modules),
+    ("Fonts", check_fonts),
+    ("Config symlinks", check_symlinks),
+    ("Display manager", check_display_manager),
+    ("Portals", check_portals),
+    ("Login shell", check_login_shell),
+    ("caelestia CLI", check_cli),
+    ("SELinux", check_selinux),
+];
+
+/// Runs every check, failing if any of them did.
+pub fn run() -> Result<()> {
+    let failed = report();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, CHECKS.len());
+    }
+    ui::success("Everything looks fine");
+    Ok(())
+}
+
+/// Prints the result of every check and returns how many failed.
+pub fn report() -> usize {
+    ui::block(&format!("\n{}", "Diagnostics:".cyan().bold()));
+
+    let mut failed = 0;
+    for (name, check) in CHECKS {
+        match check() {
+            Ok(detail) => {
+                ui::success(&format!("{}: {}", name, detail));
+                log::log(&format!("Doctor: {} ok: {}", name, detail));
+            }
+            Err(problem) => {
+                failed += 1;
+                ui::error(&format!("{}: {}", name, problem.detail));
+                ui::info(&format!("  Fix: {}", problem.fix));
+                log::log(&format!("Doctor: {} failed: {}", name, problem.detail));
+            }
+        }
+    }
+    failed
+}
+
+/// Finds `program` on PATH or in the prefix's bin directory.
+fn find_program(program: &str) -> Option<PathBuf> {
+    which::which(program)
+        .ok()
+        .or_else(|| Some(prefix::bin_dir().join(program)).filter(|path| path.exists()))
+}
+
+fn check_quickshell() -> Result<String, Problem> {
+    match find_program("quickshell") {
+        Some(path) => Ok(format!("found at {}", path.display())),
+        None => Err(Problem::new(
+            "quickshell is not installed",
+            "caelestia-installer --only quickshell",
+        )),
+    }
+}
+
+/// Looks for the Caelestia QML module in Qt's directories and the prefix.
+fn check_qml_modules() -> Result<String, Problem> {
+    let mut dirs: Vec<PathBuf> = SYSTEM_QML_DIRS.iter().map(PathBuf::from).collect();
+    dirs.push(prefix::qml_dir());
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".local/lib64/qt6/qml"));
+    }
+
+    let Some(dir) = dirs.iter().find(|dir| dir.join("Caelestia").is_dir()) else {
+        return Err(Problem::new(
+            "the Caelestia QML module is not installed",
+            "caelestia-installer --only shell-build",
+        ));
+    };
+
+    let on_import_path = SYSTEM_QML_DIRS.iter().any(|system| dir == Path::new(system))
+        || std::env::var("QML_IMPORT_PATH")
+            .map(|paths| paths.split(':').any(|path| Path::new(path) == dir))
+            .unwrap_or(false);
+    if !on_import_path {
+        return Err(Problem::new(
+            format!("found in {}, which is not on QML_IMPORT_PATH", dir.display()),
+            "caelestia-installer --only environment, then log out and back in",
+        ));
+    }
+    Ok(format!("found in {}", dir.join("Caelestia").display()))
+}
+
+fn check_fonts() -> Result<String, Problem> {
+    let output = Command::new("fc-list")
+        .args([":", "family"])
+        .output()
+        .map_err(|_| Problem::new("fc-list is not available", "sudo dnf install fontconfig"))?;
+    let families = String::from_utf8_lossy(&output.stdout);
+
+    let missing: Vec<&str> = FONTS
+        .iter()
+        .copied()
+        .filter(|font| !families.contains(font))
+        .collect();
+    if missing.is_empty() {
+        Ok(format!("{} installed", FONTS.join(", ")))
+    } else {
+        Err(Problem::new(
+            format!("missing {}", missing.join(", ")),
+            "caelestia-installer --only fonts, then fc-cache -fv",
+        ))
+    }
+}
+
+/// Checks that every config is linked into the dotfiles checkout and that
+/// the link target exists.
+fn check_symlinks() -> Result<String, Problem> {
+    let dotfiles_dir = dotfiles::dotfiles_dir();
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+
+    let mut broken = Vec::new();
+    for (src, dst) in dotfiles::LINKS {
+        let destination = config_dir.join(dst);
+        let linked = std::fs::read_link(&destination)
+            .map(|target| target == dotfiles_dir.join(src))
+            .unwrap_or(false);
+        if !linked || !destination.exists() {
+            broken.push(*dst);
+        }
+    }
+
+    if broken.is_empty() {
+        Ok(format!("{} configs linked", dotfiles::LINKS.len()))
+    } else {
+        Err(Problem::new(
+            format!("not linked to {}: {}", dotfiles_dir.display(), broken.join(", ")),
+            "caelestia-installer --only dotfiles,symlinks",
+        ))
+    }
+}
+
+/// The display manager is optional, so this only fails when one is
+/// installed but none is enabled.
+fn check_display_manager() -> Result<String, Problem> {
+    let installed: Vec<&str> = ["greetd", "sddm"]
+        .into_iter()
+        .filter(|dm| which::which(dm).is_ok())
+        .collect();
+    if installed.is_empty() {
+        return Ok("none installed (optional)".to_string());
+    }
+    let enabled = installed.iter().find(|dm| {
+        Command::new("systemctl")
+            .args(["is-enabled", dm])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    });
+    match enabled {
+        Some(dm) => Ok(format!("{} enabled", dm)),
+        None => Err(Problem::new(
+            format!("{} installed but the service is not enabled", installed.join(" and ")),
+            format!("caelestia-installer --only display-manager --display-manager {}", installed[0]),
+        )),
+    }
+}
+
+fn check_portals() -> Result<String, Problem> {
+    if !portals::conf_path().exists() {
+        return Err(Problem::new(
+            format!("{} is missing", portals::conf_path().display()),
+            "caelestia-installer --only portals",
+        ));
+    }
+    portals::verify().map_err(|problem| {
+        Problem::new(problem, "sudo dnf install xdg-desktop-portal-hyprland xdg-desktop-portal-gtk, then log in again")
+    })
+}
+
+fn check_login_shell() -> Result<String, Problem> {
+    match shell::current_shell() {
+        Some(shell) if ["/fish", "/zsh", "/bash"].iter().any(|name| shell.ends_with(name)) => Ok(shell),
+        Some(shell) => Err(Problem::new(
+            format!("login shell is {}, which the installer does not set up", shell),
+            "caelestia-installer --only shell-config --login-shell",
+        )),
+        None => Err(Problem::new(
+            "could not read the login shell",
+            "check 'getent passwd $USER'",
+        )),
+    }
+}
+
+/// Runs the wrapper, which also catches a broken Python install behind it.
+fn check_cli() -> Result<String, Problem> {
+    let Some(path) = find_program("caelestia") else {
+        return Err(Problem::new(
+            "the caelestia wrapper is not installed",
+            "caelestia-installer --only cli",
+        ));
+    };
+    let works = Command::new(&path)
+        .arg("--help")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    if works {
+        Ok(format!("found at {}", path.display()))
+    } else {
+        Err(Problem::new(
+            format!("{} fails to run", path.display()),
+            "caelestia-installer --only cli",
+        ))
+    }
+}
+
+/// Wrong labels on installed files, then denials of the desktop's programs
+/// since boot.
+fn check_selinux() -> Result<String, Problem> {
+    if !selinux::enabled() {
+        return Ok("disabled".to_string());
+    }
+    let mislabelled = selinux::mislabelled();
+    if !mislabelled.is_empty() {
+        for report in &mislabelled {
+            log::log(&format!("Doctor: {}", report));
+        }
+        return Err(Problem::new(
+            format!("{} installed files have the wrong label: {}", mislabelled.len(), mislabelled.join("; ")),
+            "caelestia-installer --only selinux",
+        ));
+    }
+    match selinux::recent_denials() {
+        None => Ok("labels verified, reading AVC denials needs 'sudo -v' first".to_string()),
+        Some(denials) if denials.is_empty() => Ok("labels verified, no AVC denials".to_string()),
+        Some(denials) => {
+            for denial in &denials {
+                log::log(&format!("Doctor: {}", denial));
+            }
+            Err(Problem::new(
+                format!("{} AVC denials since boot, the first: {}", denials.len(), denials[0]),
+                "sudo ausearch -m AVC -ts boot -i | audit2why",
+            ))
+        }
+    }
+}
@@ -1,23 +1,54 @@
 use anyhow::{bail, Result};
 use std::fs;
 use std::os::unix::fs::symlink;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::{log, ui};
+use crate::{backup, interrupt, journal, localrpm, log, manifest, merge, prebuilt, preview, prefix, runner, summary, toolbox, ui, versions};
 
-const DOTFILES_REPO: &str = "https://github.com/caelestia-dots/caelestia.git";
-const SHELL_REPO: &str = "https://github.com/caelestia-dots/shell.git";
+pub const DOTFILES_REPO: &str = "https://github.com/caelestia-dots/caelestia.git";
+pub const SHELL_REPO: &str = "https://github.com/caelestia-dots/shell.git";
 
-pub fn clone_repos(dry_run: bool) -> Result<()> {
+/// Entries of the dotfiles repo linked into ~/.config, as (source, destination).
+pub const LINKS: &[(&str, &str)] = &[
+    ("hypr", "hypr"),
+    ("foot", "foot"),
+    ("fish", "fish"),
+    ("fastfetch", "fastfetch"),
+    ("btop", "btop"),
+    ("uwsm", "uwsm"),
+    ("starship.toml", "starship.toml"),
+];
+
+/// Checkout of the dotfiles repo the configs are linked from.
+pub fn dotfiles_dir() -> PathBuf {
     let local_share = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("~/.local/share"));
-    let dotfiles_dir = local_share.join("caelestia");
+    local_share.join("caelestia")
+}
 
+/// Checkout of caelestia-shell, built in place.
+pub fn shell_dir() -> PathBuf {
     let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
-    let shell_dir = config_dir.join("quickshell/caelestia");
+    config_dir.join("quickshell/caelestia")
+}
 
-    clone_repo(DOTFILES_REPO, &dotfiles_dir, dry_run)?;
-    clone_repo(SHELL_REPO, &shell_dir, dry_run)?;
+/// Clones (or pulls) the dotfiles and shell repos, from the given URLs and
+/// branches or the upstream repos and their default branches.
+pub fn clone_repos(
+    dotfiles_repo: Option<&str>,
+    dotfiles_branch: Option<&str>,
+    shell_repo: Option<&str>,
+    shell_branch: Option<&str>,
+    dry_run: bool,
+    noconfirm: bool,
+) -> Result<()> {
+    let dotfiles_dir = dotfiles_dir();
+    let shell_dir = shell_dir();
+
+    let pinned = revision("dotfiles", dotfiles_repo, dotfiles_branch)?;
+    clone_repo(dotfiles_repo.unwrap_or(DOTFILES_REPO), &dotfiles_dir, dotfiles_branch, pinned, dry_run, noconfirm)?;
+    let pinned = revision("shell", shell_repo, shell_branch)?;
+    clone_repo(shell_repo.unwrap_or(SHELL_REPO), &shell_dir, shell_branch, pinned, dry_run, noconfirm)?;
 
     // Patch deprecated gesture syntax in cloned dotfiles
     patch_gestures(&dotfiles_dir, dry_run)?;
@@ -42,8 +73,30 @@ fn patch_gestures(_dotfiles_dir: &PathBuf, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
-fn clone_repo(url: &str, dest: &PathBuf, dry_run: bool) -> Result<()> {
-    ui::info(&format!("Cloning {} to {:?}", url, dest));
+/// The locked revision of `repo`, unless another repository or a branch
+/// was asked for.
+fn revision(repo: &str, url: Option<&str>, branch: Option<&str>) -> Result<Option<String>> {
+    match (url, branch) {
+        (None, None) => versions::pinned(repo),
+        _ => Ok(None),
+    }
+}
+
+/// Clones `url` to `dest`, or updates the checkout already there, keeping
+/// the user's edits to it.
+pub fn clone_repo(
+    url: &str,
+    dest: &PathBuf,
+    branch: Option<&str>,
+    revision: Option<String>,
+    dry_run: bool,
+    noconfirm: bool,
+) -> Result<()> {
+    match (branch, &revision) {
+        (Some(branch), _) => ui::info(&format!("Cloning {} ({}) to {:?}", url, branch, dest)),
+        (None, Some(revision)) => ui::info(&format!("Cloning {} at {} to {:?}", url, revision, dest)),
+        (None, None) => ui::info(&format!("Cloning {} to {:?}", url, dest)),
+    }
 
     if dry_run {
         ui::success(&format!("Would clone to {:?} (dry-run)", dest));
@@ -51,37 +104,38 @@ fn clone_repo(url: &str, dest: &PathBuf, dry_run: bool) -> Result<()> {
     }
 
     if dest.exists() {
-        ui::warning(&format!("{:?} already exists, pulling latest...", dest));
-        let cmd = format!("git -C {:?} pull", dest);
-        log::log_command(&cmd);
-
-        let output = Command::new("git")
-            .args(["-C", dest.to_str().unwrap(), "pull"])
-            .output()?;
-
-        if !output.status.success() {
-            ui::warning("Pull failed, continuing anyway");
-        }
-        return Ok(());
+        switch_remote(dest, url)?;
+        return merge::around_local_changes(dest, noconfirm, || update_checkout(dest, branch, revision, noconfirm));
     }
 
     if let Some(parent) = dest.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    let cmd = format!("git clone {} {:?}", url, dest);
-    log::log_command(&cmd);
-
-    let output = Command::new("git")
-        .args(["clone", url, dest.to_str().unwrap()])
-        .output()?;
+    let mut args = vec!["clone", url, dest.to_str().unwrap()];
+    if let Some(branch) = branch {
+        args.extend(["--branch", branch]);
+    }
+    log::log_command(&format!("git {}", args.join(" ")));
+
+    interrupt::remove_if_interrupted(dest);
+    let output = runner::run(Command::new("git").args(&args));
+    interrupt::keep(dest);
+    if !output.as_ref().is_ok_and(|o| o.status.success()) {
+        // A stopped clone leaves a directory the next run would take as done
+        fs::remove_dir_all(dest).ok();
+    }
+    let output = output?;
 
     log::log_output(&String::from_utf8_lossy(&output.stdout));
 
     if output.status.success() {
         ui::success(&format!("Cloned to {:?}", dest));
         log::log(&format!("Cloned {} to {:?}", url, dest));
-        Ok(())
+        match revision {
+            Some(revision) => versions::checkout(dest, &revision),
+            None => Ok(()),
+        }
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
         log::log_error(&stderr);
@@ -89,9 +143,71 @@ fn clone_repo(url: &str, dest: &PathBuf, dry_run: bool) -> Result<()> {
     }
 }
 
+/// Moves an existing checkout to `revision`, or to the latest commit of
+/// `branch` or its current branch.
+fn update_checkout(dest: &Path, branch: Option<&str>, revision: Option<String>, noconfirm: bool) -> Result<()> {
+    if let Some(revision) = revision {
+        ui::warning(&format!("{:?} already exists, checking out {}...", dest, revision));
+        return versions::checkout(dest, &revision);
+    }
+    if branch.is_none() {
+        versions::reattach(dest)?;
+    }
+    ui::warning(&format!("{:?} already exists, pulling latest...", dest));
+
+    if let Some(branch) = branch {
+        let cmd = format!("git -C {:?} checkout {}", dest, branch);
+        log::log_command(&cmd);
+
+        let output = Command::new("git")
+            .args(["-C", dest.to_str().unwrap(), "checkout", branch])
+            .output()?;
+        if !output.status.success() {
+            log::log_error(&String::from_utf8_lossy(&output.stderr));
+            ui::warning(&format!("Could not switch to branch {}, staying on the current one", branch));
+        }
+    }
+
+    if let Err(e) = merge::pull(dest, noconfirm) {
+        ui::warning(&format!("Pull failed, continuing anyway: {}", e));
+    }
+    Ok(())
+}
+
+/// Points the origin of an existing checkout at `url` when it was cloned
+/// from somewhere else, such as upstream before switching to a fork.
+fn switch_remote(dest: &Path, url: &str) -> Result<()> {
+    let dest_str = dest.to_string_lossy();
+    let output = Command::new("git")
+        .args(["-C", &dest_str, "remote", "get-url", "origin"])
+        .output()?;
+    let origin = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if origin == url {
+        return Ok(());
+    }
+
+    ui::warning(&format!("{:?} was cloned from {}, switching it to {}", dest, origin, url));
+    for args in [
+        vec!["-C", &dest_str, "remote", "set-url", "origin", url],
+        vec!["-C", &dest_str, "fetch", "origin"],
+    ] {
+        log::log_command(&format!("git {}", args.join(" ")));
+        let output = Command::new("git").args(&args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Could not switch {:?} to {}: {}", dest, url, stderr.trim());
+        }
+    }
+    Ok(())
+}
+
 pub fn build_shell(dry_run: bool) -> Result<()> {
-    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
-    let shell_dir = config_dir.join("quickshell/caelestia");
+    let shell_dir = shell_dir();
+
+    if prebuilt::install("caelestia-shell", dry_run)? {
+        return Ok(());
+    }
 
     ui::info("Building caelestia-shell...");
 
@@ -113,79 +229,60 @@ pub fn build_shell(dry_run: bool) -> Result<()> {
 
     // CMake configure
     ui::info("Configuring caelestia-shell...");
+    let install_prefix = format!("-DCMAKE_INSTALL_PREFIX={}", prefix::prefix().display());
+    let qml_dir = format!("-DINSTALL_QMLDIR={}", prefix::qml_dir().display());
+    let lib_dir = format!("-DINSTALL_LIBDIR={}", prefix::lib_dir().display());
     let cmake_cmd = format!(
-        "cmake -B {:?} -S {:?} -G Ninja -DCMAKE_BUILD_TYPE=Release -DCMAKE_INSTALL_PREFIX=/usr -DINSTALL_QMLDIR=/usr/lib64/qt6/qml -DINSTALL_LIBDIR=/usr/lib64/caelestia",
-        build_dir, shell_dir
+        "cmake -B {:?} -S {:?} -G Ninja -DCMAKE_BUILD_TYPE=Release {} {} {}",
+        build_dir, shell_dir, install_prefix, qml_dir, lib_dir
     );
-    log::log_command(&cmake_cmd);
-
-    let output = Command::new("cmake")
-        .args([
-            "-B",
-            build_dir.to_str().unwrap(),
-            "-S",
-            shell_dir.to_str().unwrap(),
-            "-G",
-            "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
-            "-DCMAKE_INSTALL_PREFIX=/usr",
-            "-DINSTALL_QMLDIR=/usr/lib64/qt6/qml",
-            "-DINSTALL_LIBDIR=/usr/lib64/caelestia",
-        ])
-        .output()?;
+    log::log_command(&toolbox::command_line(&cmake_cmd));
+
+    // Output is streamed into the log as it is produced
+    let output = runner::run(toolbox::command("cmake").args([
+        "-B",
+        build_dir.to_str().unwrap(),
+        "-S",
+        shell_dir.to_str().unwrap(),
+        "-G",
+        "Ninja",
+        "-DCMAKE_BUILD_TYPE=Release",
+        &install_prefix,
+        &qml_dir,
+        &lib_dir,
+    ]))?;
 
-    // Always log both stdout and stderr for debugging
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    log::log("=== SHELL CMAKE CONFIGURE STDOUT ===");
-    log::log_output(&stdout);
-    log::log("=== SHELL CMAKE CONFIGURE STDERR ===");
-    log::log_error(&stderr);
 
     if !output.status.success() {
         ui::error("CMake configure failed:");
         // Print both stdout and stderr - cmake errors often go to stdout
         if !stdout.is_empty() {
-            println!("STDOUT:\n{}", stdout);
+            ui::block(&format!("STDOUT:\n{}", stdout));
         }
         if !stderr.is_empty() {
-            println!("STDERR:\n{}", stderr);
+            ui::block(&format!("STDERR:\n{}", stderr));
         }
         bail!("CMake configure failed. Check ~/.cache/caelestia-installer/install.log for details.");
     }
 
     // Ninja build
     ui::info("Compiling caelestia-shell...");
-    let jobs = crate::system::get_ninja_jobs();
-    let mut build_args = vec!["--build", build_dir.to_str().unwrap()];
-    let jobs_str;
-    if jobs > 0 {
-        build_args.push("-j");
-        jobs_str = jobs.to_string();
-        build_args.push(&jobs_str);
-    }
-
-    let output = Command::new("cmake")
-        .args(&build_args)
-        .output()?;
+    let output = crate::system::cmake_build(build_dir.to_str().unwrap())?;
 
-    // Always log both stdout and stderr for debugging
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    log::log("=== SHELL BUILD STDOUT ===");
-    log::log_output(&stdout);
-    log::log("=== SHELL BUILD STDERR ===");
-    log::log_error(&stderr);
 
     if !output.status.success() {
         ui::error("Shell build failed:");
         // Print both - ninja/cmake errors can be in either stream
         if !stdout.is_empty() {
             let start = stdout.len().saturating_sub(2000);
-            println!("STDOUT (last 2000 chars):\n{}", &stdout[start..]);
+            ui::block(&format!("STDOUT (last 2000 chars):\n{}", &stdout[start..]));
         }
         if !stderr.is_empty() {
-            println!("STDERR:\n{}", stderr);
+            ui::block(&format!("STDERR:\n{}", stderr));
         }
         crate::system::check_oom_event();
         bail!("Shell build failed. Check ~/.cache/caelestia-installer/install.log for details.");
@@ -193,25 +290,42 @@ pub fn build_shell(dry_run: bool) -> Result<()> {
 
     ui::success("Built caelestia-shell");
 
-    // Install (requires sudo)
+    if localrpm::wanted("caelestia-shell") {
+        localrpm::CmakeProject {
+            name: "caelestia-shell",
+            summary: "Caelestia desktop shell for Quickshell",
+            license: "GPL-3.0-only",
+            url: "https://github.com/caelestia-dots/shell",
+            source: &shell_dir,
+            build_dir: &build_dir,
+        }
+        .package()?;
+        summary::artifact("caelestia-shell built");
+        return Ok(());
+    }
+
+    // Install (requires sudo unless installing to a user prefix)
     ui::info("Installing caelestia-shell...");
-    let install_cmd = format!("sudo cmake --install {:?}", build_dir);
+    let install_cmd = prefix::command_line(&format!("cmake --install {:?}", build_dir));
     log::log_command(&install_cmd);
 
-    let output = Command::new("sudo")
-        .args(["cmake", "--install", build_dir.to_str().unwrap()])
+    let output = prefix::command("cmake")
+        .args(["--install", build_dir.to_str().unwrap()])
         .output()?;
 
     if output.status.success() {
         ui::success("Installed caelestia-shell");
         log::log("Shell installation complete");
+        summary::artifact("caelestia-shell built");
 
         // Verification
         ui::info("Verifying installation...");
         // Verification (Check lib64 first, then lib)
-        if std::path::Path::new("/usr/lib64/qt6/qml/Caelestia").exists() {
+        let qml_module = prefix::qml_dir().join("Caelestia");
+        if qml_module.exists() {
             let _ = Command::new("ls")
-                .args(["-R", "/usr/lib64/qt6/qml/Caelestia"])
+                .arg("-R")
+                .arg(&qml_module)
                 .status();
         } else if std::path::Path::new("/usr/lib/qt6/qml/Caelestia").exists() {
             let _ = Command::new("ls")
@@ -228,67 +342,102 @@ pub fn build_shell(dry_run: bool) -> Result<()> {
 }
 
 pub fn symlink_configs(dry_run: bool) -> Result<()> {
-    let local_share = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("~/.local/share"));
-    let dotfiles_dir = local_share.join("caelestia");
-
+    let dotfiles_dir = dotfiles_dir();
     let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
 
-    let links = [
-        ("hypr", "hypr"),
-        ("foot", "foot"),
-        ("fish", "fish"),
-        ("fastfetch", "fastfetch"),
-        ("btop", "btop"),
-        ("uwsm", "uwsm"),
-    ];
-
-    for (src, dst) in links {
+    let mut linked = 0;
+    for (src, dst) in LINKS {
         let source = dotfiles_dir.join(src);
         let destination = config_dir.join(dst);
 
-        create_symlink(&source, &destination, dry_run)?;
+        if create_symlink(&source, &destination, dry_run)? {
+            linked += 1;
+        }
+    }
+    if !dry_run {
+        backup::finish()?;
     }
 
-    // Starship config
-    let starship_src = dotfiles_dir.join("starship.toml");
-    let starship_dst = config_dir.join("starship.toml");
-    create_symlink(&starship_src, &starship_dst, dry_run)?;
+    summary::artifact(&format!("{} files linked", linked));
+    Ok(())
+}
+
+/// Whether every config the dotfiles ship is linked into the checkout.
+pub fn configs_linked() -> bool {
+    let dotfiles_dir = dotfiles_dir();
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+
+    dotfiles_dir.exists()
+        && LINKS
+            .iter()
+            .filter(|(src, _)| dotfiles_dir.join(src).exists())
+            .all(|(src, dst)| fs::read_link(config_dir.join(dst)).is_ok_and(|target| target == dotfiles_dir.join(src)))
+}
+
+/// Removes the config symlinks pointing into the dotfiles checkout and puts
+/// back what `create_symlink` backed up in this run.
+pub fn unlink_configs() -> Result<()> {
+    let dotfiles_dir = dotfiles_dir();
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+
+    for (_, dst) in LINKS {
+        let destination = config_dir.join(dst);
+        let points_into_dotfiles = fs::read_link(&destination)
+            .map(|target| target.starts_with(&dotfiles_dir))
+            .unwrap_or(false);
+
+        if !points_into_dotfiles {
+            continue;
+        }
+
+        fs::remove_file(&destination)?;
+        log::log(&format!("Removed symlink {:?}", destination));
+
+        if backup::restore_from_run(&destination)? {
+            ui::info(&format!("Restored {:?} from backup", destination));
+        }
+    }
 
     Ok(())
 }
 
-fn create_symlink(source: &PathBuf, destination: &PathBuf, dry_run: bool) -> Result<()> {
+/// Links `destination` to `source`, backing up what is there, and records
+/// the link in the manifest. Returns whether the link was (or, in dry-run,
+/// would be) created.
+pub(crate) fn create_symlink(source: &PathBuf, destination: &PathBuf, dry_run: bool) -> Result<bool> {
     ui::info(&format!("Linking {:?} -> {:?}", destination, source));
 
     if dry_run {
+        preview::symlink(destination, source);
         ui::success(&format!("Would link {:?} (dry-run)", destination));
-        return Ok(());
+        return Ok(true);
     }
 
-    // Remove existing symlink or directory
-    if destination.exists() || destination.is_symlink() {
-        if destination.is_dir() && !destination.is_symlink() {
-            // Backup existing directory
-            let backup = destination.with_extension("bak");
-            ui::warning(&format!("Backing up {:?} to {:?}", destination, backup));
-            fs::rename(destination, &backup)?;
-        } else {
-            fs::remove_file(destination).ok();
-            fs::remove_dir_all(destination).ok();
-        }
+    if !source.exists() {
+        ui::warning(&format!("Source {:?} does not exist, skipping", source));
+        return Ok(false);
     }
 
-    if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent)?;
+    // Replace an existing symlink, and back up anything else
+    let mut backup = None;
+    if destination.is_symlink() {
+        fs::remove_file(destination)?;
+    } else if destination.exists() {
+        ui::info(&format!("Backing up {:?}", destination));
+        backup = Some(backup::stash(destination)?);
     }
 
-    if source.exists() {
-        symlink(source, destination)?;
-        ui::success(&format!("Linked {:?}", destination));
-        log::log(&format!("Created symlink {:?} -> {:?}", destination, source));
-    } else {
-        ui::warning(&format!("Source {:?} does not exist, skipping", source));
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
     }
 
-    Ok(())
+    symlink(source, destination)?;
+    journal::record(journal::Entry::Symlink {
+        path: destination.clone(),
+        backup,
+    });
+    manifest::record_link(destination, source);
+    ui::success(&format!("Linked {:?}", destination));
+    log::log(&format!("Created symlink {:?} -> {:?}", destination, source));
+    Ok(true)
 }
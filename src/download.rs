@@ -0,0 +1,356 @@
+//! Downloads made by the installer itself rather than by dnf: fonts, install
+//! scripts, COPR keys and other release assets, and the network speed probe.
+//! Transfers show a progress bar, honour `--limit-rate`, are retried with
+//! backoff and can be checked against a SHA-256 checksum.
+
+use anyhow::{anyhow, bail, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{log, net, offline, ui};
+
+/// Attempts per download before giving up.
+const ATTEMPTS: u32 = 4;
+/// Wait before the first retry, doubled after each failed attempt.
+const BACKOFF: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Attempts per speed probe, fewer than downloads so an unreachable mirror
+/// does not hold up the checks.
+const PROBE_ATTEMPTS: u32 = 2;
+
+static SKIP_VERIFY: Mutex<bool> = Mutex::new(false);
+
+/// Turns off checksum verification, for `--skip-verify`.
+pub fn set_skip_verify(skip: bool) {
+    *SKIP_VERIFY.lock().unwrap() = skip;
+}
+
+pub fn skip_verify() -> bool {
+    *SKIP_VERIFY.lock().unwrap()
+}
+
+/// A download that kept arriving with the wrong content.
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub url: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} does not match its checksum: expected {} but got {}",
+            self.url, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// Why an attempt failed, and whether trying again can help.
+enum Failure {
+    Retry(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Downloads `url` to `dest`, verifying it against `sha256` (lowercase hex)
+/// when given. The file is written next to `dest` and only moved into place
+/// once complete and verified. A mismatch fails the download unless
+/// verification was turned off.
+pub fn download(url: &str, dest: &Path, sha256: Option<&str>) -> Result<()> {
+    log::log(&format!("Downloading {} to {:?}", url, dest));
+    let sha256 = sha256.filter(|_| !skip_verify());
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Some(cached) = offline::cached(url)? {
+        return copy_cached(url, &cached, dest, sha256);
+    }
+    let partial = partial_path(dest);
+
+    let client = client(None)?;
+
+    let mut delay = BACKOFF;
+    for attempt in 1..=ATTEMPTS {
+        let error = match fetch(&client, url, &partial, sha256) {
+            Ok(()) => {
+                fs::rename(&partial, dest)?;
+                log::log(&format!("Downloaded {}", url));
+                offline::store(url, dest)?;
+                return Ok(());
+            }
+            Err(Failure::Fatal(e)) => {
+                fs::remove_file(&partial).ok();
+                log::log_error(&e.to_string());
+                return Err(e);
+            }
+            Err(Failure::Retry(e)) => e,
+        };
+
+        fs::remove_file(&partial).ok();
+        log::log_error(&format!("Attempt {}/{} failed: {}", attempt, ATTEMPTS, error));
+        if attempt == ATTEMPTS {
+            if error.is::<ChecksumMismatch>() {
+                return Err(error);
+            }
+            bail!("Could not download {}: {}", url, error);
+        }
+
+        ui::warning(&format!("Download failed ({}), retrying in {}s...", error, delay.as_secs()));
+        thread::sleep(delay);
+        delay *= 2;
+    }
+    unreachable!()
+}
+
+/// Downloads up to `bytes` of `url` within `timeout`, keeping nothing, and
+/// returns the speed in bytes per second.
+pub fn probe(url: &str, bytes: u64, timeout: Duration) -> Result<f64> {
+    if offline::enabled() {
+        bail!("Installing offline, not probing {}", url);
+    }
+    let client = client(Some(timeout))?;
+
+    let mut delay = BACKOFF;
+    for attempt in 1..=PROBE_ATTEMPTS {
+        let error = match probe_once(&client, url, bytes) {
+            Ok(speed) => return Ok(speed),
+            Err(e) => e,
+        };
+        log::log_error(&format!("Probe attempt {}/{} of {} failed: {}", attempt, PROBE_ATTEMPTS, url, error));
+        if attempt == PROBE_ATTEMPTS {
+            return Err(error);
+        }
+        thread::sleep(delay);
+        delay *= 2;
+    }
+    unreachable!()
+}
+
+/// Fetches the small text document at `url`, such as a metalink, within
+/// `timeout`.
+pub fn text(url: &str, timeout: Duration) -> Result<String> {
+    if offline::enabled() {
+        bail!("Installing offline, not fetching {}", url);
+    }
+    log::log(&format!("Fetching {}", url));
+    let response = client(Some(timeout))?.get(url).send()?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("{} answered {}", url, status);
+    }
+    Ok(response.text()?)
+}
+
+fn probe_once(client: &reqwest::blocking::Client, url: &str, bytes: u64) -> Result<f64> {
+    let started = Instant::now();
+    let mut response = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes=0-{}", bytes.saturating_sub(1)))
+        .send()?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("server answered {}", status);
+    }
+
+    let limit = net::limit_bytes();
+    let mut received: u64 = 0;
+    let mut buffer = [0u8; 64 * 1024];
+    while received < bytes {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        received += read as u64;
+        throttle(limit, received, started);
+    }
+
+    let secs = started.elapsed().as_secs_f64();
+    if received == 0 || secs <= 0.0 {
+        bail!("no data received");
+    }
+    Ok(received as f64 / secs)
+}
+
+fn client(timeout: Option<Duration>) -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .user_agent(concat!("caelestia-installer/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(timeout)
+        .build()?)
+}
+
+/// Sleeps as long as `received` bytes are ahead of the `--limit-rate`.
+fn throttle(limit: Option<u64>, received: u64, started: Instant) {
+    if let Some(limit) = limit.filter(|limit| *limit > 0) {
+        let expected = Duration::from_secs_f64(received as f64 / limit as f64);
+        if let Some(ahead) = expected.checked_sub(started.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
+
+/// Copies the cached download of `url` into place, checking it like a
+/// fresh download.
+fn copy_cached(url: &str, cached: &Path, dest: &Path, sha256: Option<&str>) -> Result<()> {
+    if let Some(expected) = sha256 {
+        let actual: String = Sha256::digest(fs::read(cached)?)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(ChecksumMismatch {
+                url: url.to_string(),
+                expected: expected.to_string(),
+                actual,
+            }
+            .into());
+        }
+    }
+    fs::copy(cached, dest)?;
+    log::log(&format!("Copied {} from the offline cache", url));
+    Ok(())
+}
+
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+fn fetch(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    partial: &Path,
+    sha256: Option<&str>,
+) -> Result<(), Failure> {
+    let mut response = client.get(url).send().map_err(|e| Failure::Retry(e.into()))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error = anyhow!("server answered {}", status);
+        // Client errors other than rate limiting will not go away on retry.
+        return Err(if status.is_client_error() && status.as_u16() != 429 {
+            Failure::Fatal(anyhow!("Could not download {}: {}", url, error))
+        } else {
+            Failure::Retry(error)
+        });
+    }
+
+    let name = url.rsplit('/').next().unwrap_or(url);
+    let bar = ui::progress_bar(name, response.content_length());
+    let mut file = File::create(partial).map_err(|e| Failure::Fatal(e.into()))?;
+    let mut hasher = Sha256::new();
+    let limit = net::limit_bytes();
+    let started = Instant::now();
+    let mut received: u64 = 0;
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = response.read(&mut buffer).map_err(|e| Failure::Retry(e.into()))?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).map_err(|e| Failure::Fatal(e.into()))?;
+        hasher.update(&buffer[..read]);
+        received += read as u64;
+        bar.set_position(received);
+        throttle(limit, received, started);
+    }
+    bar.finish_and_clear();
+    file.flush().map_err(|e| Failure::Fatal(e.into()))?;
+
+    if let Some(expected) = sha256 {
+        let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(Failure::Retry(
+                ChecksumMismatch {
+                    url: url.to_string(),
+                    expected: expected.to_string(),
+                    actual,
+                }
+                .into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the files of the zip `archive` whose names match `pattern` (which
+/// may contain one `*`) directly into `dest_dir`, returning how many there were.
+pub fn extract(archive: &Path, dest_dir: &Path, pattern: &str) -> Result<usize> {
+    log::log(&format!("Extracting {} from {:?} to {:?}", pattern, archive, dest_dir));
+
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    fs::create_dir_all(dest_dir)?;
+
+    let mut extracted = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        // Only the file name is kept, which also keeps entries from escaping dest_dir.
+        let Some(name) = entry
+            .enclosed_name()
+            .and_then(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        else {
+            continue;
+        };
+        if !matches(pattern, &name) {
+            continue;
+        }
+
+        let mut out = File::create(dest_dir.join(&name))?;
+        std::io::copy(&mut entry, &mut out)?;
+        extracted += 1;
+    }
+
+    log::log(&format!("Extracted {} files", extracted));
+    Ok(extracted)
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+        None => name == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn matches_names_exactly_without_a_wildcard() {
+        assert!(matches("JetBrainsMonoNerdFont-Regular.ttf", "JetBrainsMonoNerdFont-Regular.ttf"));
+        assert!(!matches("JetBrainsMonoNerdFont-Regular.ttf", "JetBrainsMonoNerdFont-Bold.ttf"));
+    }
+
+    #[test]
+    fn matches_a_prefix_and_suffix_around_the_wildcard() {
+        assert!(matches("CaskaydiaCove*.ttf", "CaskaydiaCoveNerdFont-Regular.ttf"));
+        assert!(matches("*.ttf", "a.ttf"));
+        assert!(matches("*", "LICENSE"));
+        assert!(!matches("CaskaydiaCove*.ttf", "CaskaydiaCoveNerdFont-Regular.otf"));
+        assert!(!matches("CaskaydiaCove*.ttf", "README.md"));
+    }
+
+    #[test]
+    fn does_not_let_prefix_and_suffix_overlap() {
+        assert!(!matches("ab*ba", "aba"));
+        assert!(matches("ab*ba", "abba"));
+    }
+}
@@ -0,0 +1,424 @@
+//! Runs the steps of a plan on a tokio runtime, overlapping steps whose
+//! dependencies are met as long as the resources they need are available.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Builder;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+
+use crate::install::{Options, Step};
+use crate::{checks, hooks, interrupt, log, state, summary, system, ui};
+
+/// What a step mostly spends its time on, used to bound concurrency.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resource {
+    /// Quick local work, never throttled.
+    Light,
+    /// dnf transactions, which hold the rpm lock and run one at a time.
+    Dnf,
+    /// Compilation, bounded by the memory-aware job budget.
+    Build,
+    /// Downloads, bounded by the measured bandwidth.
+    Network,
+}
+
+/// What to do about a failed step.
+enum Recovery {
+    Retry,
+    /// Carry on as if the step had succeeded.
+    Skip,
+    /// Carry on without the step and the ones depending on it.
+    Continue,
+    Abort,
+}
+
+/// Steps the user asked to skip while the run was going, from `--tui`.
+static SKIP_REQUESTS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Skips step `id` when it comes up, as if it were already done.
+pub fn request_skip(id: &'static str) {
+    SKIP_REQUESTS.lock().unwrap().push(id);
+}
+
+/// Below this bandwidth (bytes/s) downloads are not run in parallel.
+const SLOW_NETWORK: f64 = 1024.0 * 1024.0;
+
+struct Budget {
+    dnf: Arc<Semaphore>,
+    build: Arc<Semaphore>,
+    network: Arc<Semaphore>,
+}
+
+impl Budget {
+    fn new() -> Self {
+        // Concurrent builds split the job budget between them, so running
+        // more of them only shortens the phases where a build cannot use
+        // every core (configuring, linking, installing).
+        let build_slots = system::concurrent_builds();
+        system::set_build_slots(build_slots);
+
+        let network_slots = match checks::measured_bandwidth() {
+            Some(bandwidth) if bandwidth < SLOW_NETWORK => 1,
+            _ => 3,
+        };
+
+        log::log(&format!(
+            "Concurrency budget: {} build(s), {} download(s)",
+            build_slots, network_slots
+        ));
+
+        Self {
+            dnf: Arc::new(Semaphore::new(1)),
+            build: Arc::new(Semaphore::new(build_slots)),
+            network: Arc::new(Semaphore::new(network_slots)),
+        }
+    }
+
+    /// Takes a slot for `resource` if one is free right now.
+    fn try_acquire(&self, resource: Resource) -> Option<Option<OwnedSemaphorePermit>> {
+        let semaphore = match resource {
+            Resource::Light => return Some(None),
+            Resource::Dnf => &self.dnf,
+            Resource::Build => &self.build,
+            Resource::Network => &self.network,
+        };
+        semaphore.clone().try_acquire_owned().ok().map(Some)
+    }
+}
+
+/// Checks that the steps form a DAG: unique ids, dependencies naming known
+/// steps, and no cycles. Returns the step indexes in a valid execution order.
+pub fn validate(steps: &[Arc<dyn Step>]) -> Result<Vec<usize>> {
+    let mut ids = HashSet::new();
+    for step in steps {
+        if !ids.insert(step.id()) {
+            bail!("Duplicate step id: {}", step.id());
+        }
+    }
+
+    let deps: Vec<Vec<&'static str>> = (0..steps.len()).map(|i| dependencies(steps, i)).collect();
+    for (step, deps) in steps.iter().zip(&deps) {
+        if let Some(unknown) = deps.iter().find(|dep| !ids.contains(*dep)) {
+            bail!("Step {} depends on unknown step {}", step.id(), unknown);
+        }
+    }
+
+    // Kahn's algorithm, preferring plan order among ready steps
+    let mut order = Vec::new();
+    let mut placed: HashSet<&'static str> = HashSet::new();
+    while order.len() < steps.len() {
+        let next = (0..steps.len()).find(|&i| {
+            !placed.contains(steps[i].id()) && deps[i].iter().all(|dep| placed.contains(dep))
+        });
+        match next {
+            Some(i) => {
+                placed.insert(steps[i].id());
+                order.push(i);
+            }
+            None => {
+                let cycle: Vec<_> = steps
+                    .iter()
+                    .map(|step| step.id())
+                    .filter(|id| !placed.contains(id))
+                    .collect();
+                bail!("Dependency cycle between steps: {}", cycle.join(", "));
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+/// Runs `steps` in dependency order, starting every step whose dependencies
+/// have finished and whose resource has a free slot. Steps in `skipped` are
+/// not run and count as satisfied dependencies. When a step fails the user
+/// may retry or skip it; otherwise no new steps are started and the ones
+/// already running are waited for. With `continue_on_error` the steps not
+/// depending on the failed one still run, and all failures are reported at
+/// the end. The steps that applied their changes are added to `applied` in
+/// the order they finished, so a failed run can be rolled back.
+pub fn run(
+    steps: &[Arc<dyn Step>],
+    skipped: &HashSet<&'static str>,
+    options: Arc<Options>,
+    progress: &mut ui::Progress,
+    applied: &mut Vec<&'static str>,
+) -> Result<()> {
+    let order = validate(steps)?;
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+
+    runtime.block_on(async {
+        // Budget::new probes memory, so build it after the checks step has
+        // measured the bandwidth.
+        let mut budget: Option<Budget> = None;
+        let mut pending: Vec<usize> = order
+            .into_iter()
+            .filter(|&i| !skipped.contains(steps[i].id()))
+            .collect();
+        let mut done: HashSet<&'static str> = skipped.clone();
+        let mut running = JoinSet::new();
+        let mut exclusive: Option<&'static str> = None;
+        let mut failure = None;
+        let mut failed: Vec<&'static str> = Vec::new();
+
+        loop {
+            if failure.is_none() && exclusive.is_none() && !interrupt::interrupted() {
+                let mut index = 0;
+                while index < pending.len() {
+                    let position = pending[index];
+                    let step = &steps[position];
+
+                    let alone = runs_alone(step.as_ref(), &options);
+                    let ready = dependencies(steps, position)
+                        .iter()
+                        .all(|dep| done.contains(dep));
+                    // Steps that must run alone wait until nothing else runs
+                    let ready = ready && (!alone || running.is_empty());
+
+                    if !ready {
+                        index += 1;
+                        continue;
+                    }
+
+                    let permit = if step.resource() == Resource::Light {
+                        Some(None)
+                    } else {
+                        budget.get_or_insert_with(Budget::new).try_acquire(step.resource())
+                    };
+                    let Some(permit) = permit else {
+                        index += 1;
+                        continue;
+                    };
+
+                    pending.remove(index);
+                    progress.start(step.id(), step.title());
+
+                    let step = step.clone();
+                    let options = options.clone();
+                    running.spawn_blocking(move || {
+                        let _permit = permit;
+                        let id = step.id();
+                        (id, run_step(step.as_ref(), &options))
+                    });
+
+                    if alone {
+                        exclusive = Some(steps[position].id());
+                        break;
+                    }
+                }
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (id, result) = joined.map_err(|e| anyhow!("step task panicked: {}", e))?;
+            if exclusive == Some(id) {
+                exclusive = None;
+            }
+
+            let recovery = match result {
+                Ok(_) => None,
+                Err(ref e) => Some(recover(id, e, &options)),
+            };
+            if let Some(Recovery::Retry) = recovery {
+                log::log(&format!("Retrying {}", id));
+                if let Some(position) = steps.iter().position(|step| step.id() == id) {
+                    pending.insert(0, position);
+                }
+                continue;
+            }
+
+            let took = progress.finish(id, result.is_ok());
+            if !options.dry_run {
+                state::record(id, result.is_ok(), took);
+            }
+            match (result, recovery) {
+                (Ok(changed), _) => {
+                    if changed {
+                        applied.push(id);
+                    }
+                    done.insert(id);
+                }
+                (Err(_), Some(Recovery::Skip)) => {
+                    done.insert(id);
+                }
+                (Err(e), Some(Recovery::Continue)) => {
+                    log::log_error(&format!("{} failed, continuing: {}", id, e));
+                    failed.push(id);
+                }
+                (Err(e), _) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
+
+        if !failed.is_empty() {
+            let blocked: Vec<_> = pending.iter().map(|&i| steps[i].id()).collect();
+            for id in &blocked {
+                summary::skip_step(id);
+            }
+            let mut message = format!("{} step(s) failed: {}", failed.len(), failed.join(", "));
+            if !blocked.is_empty() {
+                message += &format!("; not run because of them: {}", blocked.join(", "));
+            }
+            bail!(message);
+        }
+
+        if !pending.is_empty() {
+            let stuck: Vec<_> = pending.iter().map(|&i| steps[i].id()).collect();
+            bail!("Could not schedule steps: {}", stuck.join(", "));
+        }
+
+        Ok(())
+    })
+}
+
+/// Decides what to do about the failure of step `id`: continue with
+/// `--continue-on-error`, otherwise ask whoever can answer.
+fn recover(id: &str, error: &anyhow::Error, options: &Options) -> Recovery {
+    // The step failed because its commands were stopped
+    if interrupt::interrupted() {
+        return Recovery::Abort;
+    }
+    if options.continue_on_error {
+        ui::error(&format!("{} failed: {}", id, error));
+        return Recovery::Continue;
+    }
+    if options.noconfirm {
+        return Recovery::Abort;
+    }
+
+    ui::error(&format!("{} failed: {}", id, error));
+    match ui::select(
+        &format!("What should happen with {}?", id),
+        &["Retry", "Skip it and continue", "Abort"],
+    ) {
+        Some(0) => Recovery::Retry,
+        Some(1) => {
+            ui::warning(&format!("Skipping {}, steps depending on it may fail", id));
+            Recovery::Skip
+        }
+        _ => Recovery::Abort,
+    }
+}
+
+/// Steps that did not declare dependencies run after the one before them.
+pub fn dependencies(steps: &[Arc<dyn Step>], position: usize) -> Vec<&'static str> {
+    match steps[position].after() {
+        Some(after) => after.to_vec(),
+        None => position
+            .checked_sub(1)
+            .map(|previous| vec![steps[previous].id()])
+            .unwrap_or_default(),
+    }
+}
+
+/// Steps that may prompt the user run alone, keeping the questions readable.
+fn runs_alone(step: &dyn Step, options: &Options) -> bool {
+    step.interactive() && !options.noconfirm
+}
+
+/// Runs `step` unless it is skipped, returning whether it applied its changes.
+fn run_step(step: &dyn Step, options: &Options) -> Result<bool> {
+    ui::enter_step(step.id());
+    log::begin_section(step.id());
+    summary::begin(step.id());
+
+    let result = if SKIP_REQUESTS.lock().unwrap().contains(&step.id()) {
+        ui::info(&format!("Skipping {} as asked", step.id()));
+        summary::skipped();
+        Ok(false)
+    } else if step.check(options) {
+        ui::success(&format!("{} already done, skipping", step.id()));
+        summary::skipped();
+        Ok(false)
+    } else {
+        hooks::run(hooks::When::Pre, step, options)
+            .and_then(|_| step.apply(options))
+            .and_then(|_| hooks::run(hooks::When::Post, step, options))
+            .map(|_| true)
+    };
+
+    match result {
+        Ok(_) => summary::finish(),
+        Err(_) => summary::fail(),
+    }
+    log::end_section(step.id(), result.is_ok());
+    ui::leave_step();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::install::Step;
+    use std::sync::Arc;
+
+    struct TestStep {
+        id: &'static str,
+        after: Option<&'static [&'static str]>,
+    }
+
+    impl Step for TestStep {
+        fn id(&self) -> &'static str {
+            self.id
+        }
+
+        fn title(&self) -> &'static str {
+            self.id
+        }
+
+        fn after(&self) -> Option<&[&'static str]> {
+            self.after
+        }
+
+        fn apply(&self, _options: &crate::install::Options) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn steps(steps: &[(&'static str, Option<&'static [&'static str]>)]) -> Vec<Arc<dyn Step>> {
+        steps
+            .iter()
+            .map(|&(id, after)| Arc::new(TestStep { id, after }) as Arc<dyn Step>)
+            .collect()
+    }
+
+    fn order(steps: &[Arc<dyn Step>]) -> Vec<&'static str> {
+        validate(steps).unwrap().into_iter().map(|i| steps[i].id()).collect()
+    }
+
+    #[test]
+    fn runs_steps_without_dependencies_in_plan_order() {
+        let steps = steps(&[("a", None), ("b", None), ("c", None)]);
+        assert_eq!(order(&steps), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn orders_steps_after_their_dependencies() {
+        let steps = steps(&[("a", Some(&["c"])), ("b", Some(&[])), ("c", Some(&["b"]))]);
+        assert_eq!(order(&steps), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn reports_the_steps_of_a_cycle() {
+        let steps = steps(&[("a", Some(&[])), ("b", Some(&["c"])), ("c", Some(&["b"]))]);
+        let error = validate(&steps).unwrap_err().to_string();
+        assert_eq!(error, "Dependency cycle between steps: b, c");
+    }
+
+    #[test]
+    fn rejects_unknown_dependencies_and_duplicate_ids() {
+        let unknown = steps(&[("a", Some(&["missing"]))]);
+        assert!(validate(&unknown).unwrap_err().to_string().contains("unknown step missing"));
+
+        let duplicate = steps(&[("a", None), ("a", None)]);
+        assert!(validate(&duplicate).unwrap_err().to_string().contains("Duplicate step id: a"));
+    }
+}
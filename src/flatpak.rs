@@ -0,0 +1,112 @@
+//! Flatpak with the Flathub remote, which Fedora does not enable by default,
+//! so Discover and `flatpak install` find apps. Apps listed in the config
+//! file are installed from it, and Flatpak GTK apps get the same theme as
+//! the host.
+
+use anyhow::{bail, Result};
+use std::process::Command;
+
+use crate::{log, offline, runner, summary, ui};
+
+const FLATHUB_URL: &str = "https://dl.flathub.org/repo/flathub.flatpakrepo";
+
+/// Flatpaks cannot see the host's GTK themes, so the theme comes from
+/// Flathub and is selected through an override.
+const GTK_THEME_REF: &str = "org.gtk.Gtk3theme.adw-gtk3-dark";
+const GTK_THEME: &str = "adw-gtk3-dark";
+
+/// Adds Flathub, installs `apps` from it and applies the theme overrides.
+pub fn setup(apps: &[String], dry_run: bool) -> Result<()> {
+    if offline::enabled() {
+        ui::warning("Skipping Flathub, it cannot be reached when installing offline");
+        summary::skipped();
+        return Ok(());
+    }
+    if which::which("flatpak").is_err() && !dry_run {
+        bail!("flatpak not found after installing packages");
+    }
+
+    add_flathub(dry_run)?;
+
+    let mut refs: Vec<&str> = apps.iter().map(String::as_str).collect();
+    refs.push(GTK_THEME_REF);
+    install(&refs, dry_run)?;
+
+    apply_overrides(dry_run)
+}
+
+fn flatpak(args: &[&str], sudo: bool) -> Result<std::process::Output> {
+    let (program, args) = if sudo {
+        ("sudo", [&["flatpak"], args].concat())
+    } else {
+        ("flatpak", args.to_vec())
+    };
+    log::log_command(&format!("{} {}", program, args.join(" ")));
+    runner::run(Command::new(program).args(&args))
+}
+
+fn add_flathub(dry_run: bool) -> Result<()> {
+    ui::info("Adding the Flathub remote...");
+
+    if dry_run {
+        ui::success("Would add Flathub (dry-run)");
+        return Ok(());
+    }
+
+    let output = flatpak(&["remote-add", "--if-not-exists", "flathub", FLATHUB_URL], true)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to add the Flathub remote: {}", stderr.trim());
+    }
+    // Fedora may ship the remote disabled, which --if-not-exists leaves alone
+    let _ = flatpak(&["remote-modify", "--enable", "flathub"], true);
+
+    ui::success("Flathub enabled");
+    Ok(())
+}
+
+fn install(refs: &[&str], dry_run: bool) -> Result<()> {
+    ui::info(&format!("Installing {} from Flathub...", refs.join(", ")));
+
+    if dry_run {
+        ui::success("Would install the Flatpaks (dry-run)");
+        return Ok(());
+    }
+
+    let mut args = vec!["install", "--noninteractive", "--or-update", "flathub"];
+    args.extend_from_slice(refs);
+    let output = flatpak(&args, true)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install Flatpaks: {}", stderr.trim());
+    }
+
+    ui::success(&format!("Installed {} Flatpaks", refs.len()));
+    summary::artifact("flatpaks");
+    Ok(())
+}
+
+/// Selects the GTK theme for the user's Flatpaks and lets GTK 4 apps read
+/// the host's libadwaita settings.
+fn apply_overrides(dry_run: bool) -> Result<()> {
+    if dry_run {
+        ui::success(&format!("Would set GTK_THEME={} for Flatpaks (dry-run)", GTK_THEME));
+        return Ok(());
+    }
+
+    let env = format!("--env=GTK_THEME={}", GTK_THEME);
+    let output = flatpak(
+        &["override", "--user", &env, "--filesystem=xdg-config/gtk-3.0:ro", "--filesystem=xdg-config/gtk-4.0:ro"],
+        false,
+    )?;
+    if !output.status.success() {
+        log::log_error(&String::from_utf8_lossy(&output.stderr));
+        ui::warning("Could not apply the GTK theme override to Flatpaks");
+        return Ok(());
+    }
+
+    ui::success("Flatpak apps use the GTK theme");
+    Ok(())
+}
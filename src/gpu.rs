@@ -0,0 +1,195 @@
+//! Graphics card detection and NVIDIA setup. Most Hyprland problems on
+//! Fedora come from NVIDIA cards running nouveau or missing the environment
+//! Hyprland needs, so those get the proprietary driver from RPM Fusion and
+//! the recommended variables in the user's Hyprland config.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{hyprconf, journal, log, packages, repos, runner, summary, ui, vm};
+
+/// Marks the lines the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: nvidia";
+
+/// Recommended by the Hyprland wiki for the proprietary driver.
+const NVIDIA_ENV: &str = "env = LIBVA_DRIVER_NAME,nvidia
+env = __GLX_VENDOR_LIBRARY_NAME,nvidia
+env = NVD_BACKEND,direct
+env = ELECTRON_OZONE_PLATFORM_HINT,auto
+
+cursor {
+    no_hardware_cursors = true
+}
+";
+
+const NVIDIA_PACKAGES: &[&str] = &["akmod-nvidia", "xorg-x11-drv-nvidia-cuda", "libva-nvidia-driver"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Vendor {
+    Nvidia,
+    Amd,
+    Intel,
+}
+
+impl Vendor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Vendor::Nvidia => "NVIDIA",
+            Vendor::Amd => "AMD",
+            Vendor::Intel => "Intel",
+        }
+    }
+}
+
+/// Vendors of the display controllers `lspci` lists, in bus order.
+pub fn detect() -> Vec<Vendor> {
+    let Ok(output) = Command::new("lspci").output() else {
+        return Vec::new();
+    };
+
+    let mut vendors = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let is_gpu = ["VGA compatible controller", "3D controller", "Display controller"]
+            .iter()
+            .any(|class| line.contains(class));
+        if !is_gpu {
+            continue;
+        }
+
+        let vendor = if line.contains("NVIDIA") {
+            Vendor::Nvidia
+        } else if line.contains("AMD") || line.contains("ATI") {
+            Vendor::Amd
+        } else if line.contains("Intel") {
+            Vendor::Intel
+        } else {
+            continue;
+        };
+        if !vendors.contains(&vendor) {
+            vendors.push(vendor);
+        }
+    }
+    vendors
+}
+
+/// Whether the proprietary NVIDIA driver is installed.
+fn driver_installed() -> bool {
+    Command::new("rpm")
+        .args(["-q", "akmod-nvidia"])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Offers the NVIDIA driver and writes the Hyprland environment it needs.
+/// Other cards work with the drivers Fedora ships.
+pub fn setup(dry_run: bool, noconfirm: bool) -> Result<()> {
+    let vendors = detect();
+    if vendors.is_empty() {
+        if let Some(hypervisor) = vm::detect() {
+            ui::info(&format!("Virtual graphics on {}, no driver setup needed", hypervisor.name()));
+            summary::skipped();
+            return Ok(());
+        }
+        ui::warning("Could not detect the graphics card (is pciutils installed?)");
+        summary::skipped();
+        return Ok(());
+    }
+
+    let names: Vec<&str> = vendors.iter().map(|v| v.name()).collect();
+    ui::info(&format!("Detected graphics: {}", names.join(", ")));
+    log::log(&format!("GPUs: {:?}", vendors));
+
+    if !vendors.contains(&Vendor::Nvidia) {
+        ui::success("No extra driver setup needed");
+        summary::skipped();
+        return Ok(());
+    }
+
+    if driver_installed() {
+        ui::success("NVIDIA driver already installed");
+    } else if noconfirm || ui::prompt("Enable RPM Fusion and install the NVIDIA driver (akmod-nvidia)?") {
+        repos::enable_rpmfusion(dry_run)?;
+        install_driver(dry_run)?;
+    } else {
+        ui::warning("Keeping nouveau, Hyprland may be slow or unstable on this card");
+        summary::skipped();
+        return Ok(());
+    }
+
+    // On hybrid laptops the integrated GPU drives the session, and forcing
+    // the NVIDIA libraries for everything would break it.
+    if vendors.len() > 1 {
+        ui::info("Hybrid graphics detected, leaving the Hyprland environment to the integrated GPU");
+        return Ok(());
+    }
+    write_env(dry_run)
+}
+
+fn install_driver(dry_run: bool) -> Result<()> {
+    ui::info("Installing the NVIDIA driver...");
+
+    log::log_command(&packages::install_command_line(&NVIDIA_PACKAGES.join(" ")));
+
+    if dry_run {
+        ui::success("Would install the NVIDIA driver (dry-run)");
+        return Ok(());
+    }
+
+    let output = runner::run(&mut packages::install_command(NVIDIA_PACKAGES))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install the NVIDIA driver");
+    }
+
+    ui::success("NVIDIA driver installed, the kernel module builds in the background");
+    ui::info("Wait a few minutes before rebooting, until 'modinfo -F version nvidia' shows a version");
+    summary::artifact("akmod-nvidia");
+
+    if secure_boot_enabled() {
+        ui::warning("Secure Boot is enabled: the NVIDIA module must be signed before it loads");
+        ui::info("See https://rpmfusion.org/Howto/Secure%20Boot");
+    }
+    Ok(())
+}
+
+fn secure_boot_enabled() -> bool {
+    Command::new("mokutil")
+        .arg("--sb-state")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).contains("SecureBoot enabled"))
+        .unwrap_or(false)
+}
+
+/// Adds the NVIDIA environment to `~/.config/caelestia/hypr-user.conf`.
+fn write_env(dry_run: bool) -> Result<()> {
+    let hypr_user = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia/hypr-user.conf");
+
+    if dry_run {
+        ui::success(&format!("Would add the NVIDIA environment to {:?} (dry-run)", hypr_user));
+        return Ok(());
+    }
+
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if existing.contains(HYPR_MARKER) {
+        ui::success("NVIDIA environment already set up");
+        return Ok(());
+    }
+
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, format!("{}\n{}\n{}", existing, HYPR_MARKER, NVIDIA_ENV))?;
+    hyprconf::check(&hypr_user);
+
+    ui::success("Added the NVIDIA environment to the Hyprland config");
+    log::log(&format!("NVIDIA environment written to {:?}", hypr_user));
+    summary::artifact("nvidia env");
+    Ok(())
+}
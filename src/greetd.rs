@@ -1,21 +1,43 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
+use std::path::Path;
 use std::process::Command;
 
-use crate::{log, ui};
+use crate::display_manager::{disable_others, set_graphical_target};
+use crate::{journal, log, privileged, summary, ui, uwsm};
+
+const GREETER_HOME: &str = "/var/lib/greeter";
+
+const CONFIG_PATH: &str = "/etc/greetd/config.toml";
 
 const GREETD_CONFIG: &str = r#"[terminal]
 vt = 1
 
 [default_session]
-command = "tuigreet --time --remember --remember-session --sessions /usr/share/wayland-sessions"
+command = "tuigreet --time --remember --remember-session --sessions /usr/share/wayland-sessions{cmd}"
 user = "greeter"
 "#;
 
-pub fn setup_all(dry_run: bool) -> Result<()> {
+/// The greetd config. With `uwsm`, tuigreet starts the UWSM session until
+/// another one was picked and remembered.
+fn greetd_config(uwsm: bool) -> String {
+    let cmd = if uwsm {
+        format!(" --cmd '{}'", uwsm::START_COMMAND)
+    } else {
+        String::new()
+    };
+    GREETD_CONFIG.replace("{cmd}", &cmd)
+}
+
+/// Whether greetd runs tuigreet and is enabled.
+pub fn configured(uwsm: bool) -> bool {
+    std::fs::read_to_string(CONFIG_PATH).is_ok_and(|config| config == greetd_config(uwsm)) && journal::service_enabled("greetd")
+}
+
+pub fn setup_all(uwsm: bool, dry_run: bool, noconfirm: bool) -> Result<()> {
     create_greeter_user(dry_run)?;
     create_cache_dir(dry_run)?;
-    write_config(dry_run)?;
-    configure_services(dry_run)?;
+    write_config(uwsm, dry_run)?;
+    configure_services(dry_run, noconfirm)?;
     Ok(())
 }
 
@@ -33,40 +55,29 @@ fn create_greeter_user(dry_run: bool) -> Result<()> {
         if output.status.success() {
             ui::success("Greeter user already exists");
             // Ensure home directory exists anyway
-            let _ = Command::new("sudo")
-                .args(["mkdir", "-p", "/var/lib/greeter"])
-                .output();
-            let _ = Command::new("sudo")
-                .args(["chown", "greeter:greeter", "/var/lib/greeter"])
-                .output();
-            return Ok(());
+            return create_greeter_home();
         }
     }
 
     // Create user with home directory
-    let cmd = "sudo useradd -r -d /var/lib/greeter -s /usr/sbin/nologin greeter";
-    log::log_command(cmd);
-
-    let output = Command::new("sudo")
-        .args(["useradd", "-r", "-d", "/var/lib/greeter", "-s", "/usr/sbin/nologin", "greeter"])
-        .output()?;
-
-    if output.status.success() {
-        ui::success("Created greeter user");
-        log::log("Greeter user created");
-    } else {
-        ui::warning("Could not create greeter user (may already exist)");
+    match privileged::run(&["useradd", "-r", "-d", GREETER_HOME, "-s", "/usr/sbin/nologin", "greeter"]) {
+        Ok(()) => {
+            ui::success("Created greeter user");
+            log::log("Greeter user created");
+        }
+        Err(e) => {
+            log::log_error(&e.to_string());
+            ui::warning("Could not create greeter user (may already exist)");
+        }
     }
 
-    // Create home directory
-    let _ = Command::new("sudo")
-        .args(["mkdir", "-p", "/var/lib/greeter"])
-        .output();
-    let _ = Command::new("sudo")
-        .args(["chown", "greeter:greeter", "/var/lib/greeter"])
-        .output();
+    create_greeter_home()
+}
 
-    Ok(())
+fn create_greeter_home() -> Result<()> {
+    let home = Path::new(GREETER_HOME);
+    privileged::create_dir(home)?;
+    privileged::set_owner(home, "greeter:greeter")
 }
 
 fn create_cache_dir(dry_run: bool) -> Result<()> {
@@ -77,20 +88,10 @@ fn create_cache_dir(dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
-    // Create directory
-    let _ = Command::new("sudo")
-        .args(["mkdir", "-p", "/var/cache/tuigreet"])
-        .output();
-
-    // Set ownership
-    let _ = Command::new("sudo")
-        .args(["chown", "greeter:greeter", "/var/cache/tuigreet"])
-        .output();
-
-    // Set permissions
-    let _ = Command::new("sudo")
-        .args(["chmod", "0755", "/var/cache/tuigreet"])
-        .output();
+    let cache = Path::new("/var/cache/tuigreet");
+    privileged::create_dir(cache)?;
+    privileged::set_owner(cache, "greeter:greeter")?;
+    privileged::set_mode(cache, "0755")?;
 
     ui::success("Created tuigreet cache directory");
     log::log("Cache directory created at /var/cache/tuigreet");
@@ -98,98 +99,47 @@ fn create_cache_dir(dry_run: bool) -> Result<()> {
     Ok(())
 }
 
-fn write_config(dry_run: bool) -> Result<()> {
-    let config_path = "/etc/greetd/config.toml";
-
+fn write_config(uwsm: bool, dry_run: bool) -> Result<()> {
     ui::info("Writing greetd configuration...");
 
+    privileged::write_file(Path::new(CONFIG_PATH), greetd_config(uwsm))?;
     if dry_run {
         ui::success("Would write greetd config (dry-run)");
         return Ok(());
     }
-
-    // Write via sudo tee
-    let cmd = format!("sudo tee {}", config_path);
-    log::log_command(&cmd);
-
-    // Ensure directory exists
-    let _ = Command::new("sudo")
-        .args(["mkdir", "-p", "/etc/greetd"])
-        .output();
-
-    let output = Command::new("sudo")
-        .args(["tee", config_path])
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::null())
-        .spawn();
-
-    match output {
-        Ok(mut child) => {
-            use std::io::Write;
-            if let Some(ref mut stdin) = child.stdin {
-                stdin.write_all(GREETD_CONFIG.as_bytes())?;
-            }
-            let status = child.wait()?;
-            if status.success() {
-                ui::success("Wrote greetd config");
-                log::log("Greetd config written");
-            } else {
-                log::log_error("Failed to write greetd config");
-                bail!("Failed to write greetd configuration");
-            }
-        }
-        Err(e) => {
-            log::log_error(&format!("Failed to spawn tee: {}", e));
-            bail!("Failed to write greetd configuration");
-        }
-    }
+    ui::success("Wrote greetd config");
+    log::log("Greetd config written");
 
     Ok(())
 }
 
-fn configure_services(dry_run: bool) -> Result<()> {
+fn configure_services(dry_run: bool, noconfirm: bool) -> Result<()> {
     ui::info("Configuring greetd services...");
 
     if dry_run {
+        privileged::disable_service("getty@tty1")?;
+        privileged::enable_service("greetd")?;
         ui::success("Would configure services (dry-run)");
         return Ok(());
     }
 
+    // GDM on Workstation, SDDM on the KDE spin
+    if !disable_others("greetd", noconfirm)? {
+        summary::skipped();
+        return Ok(());
+    }
+
     // Disable getty on tty1
-    run_systemctl("disable", "getty@tty1")?;
+    privileged::disable_service("getty@tty1")?;
 
     // Enable greetd
-    run_systemctl("enable", "greetd")?;
+    privileged::enable_service("greetd")?;
 
     // Set graphical target
-    let cmd = "sudo systemctl set-default graphical.target";
-    log::log_command(cmd);
-
-    let output = Command::new("sudo")
-        .args(["systemctl", "set-default", "graphical.target"])
-        .output()?;
-
-    if !output.status.success() {
-        ui::warning("Could not set default target (may need to run manually)");
-    }
+    set_graphical_target()?;
 
     ui::success("Greetd services configured");
     log::log("Greetd service configuration complete");
 
     Ok(())
 }
-
-fn run_systemctl(action: &str, service: &str) -> Result<()> {
-    let cmd = format!("sudo systemctl {} {}", action, service);
-    log::log_command(&cmd);
-
-    let output = Command::new("sudo")
-        .args(["systemctl", action, service])
-        .output()?;
-
-    if !output.status.success() {
-        ui::warning(&format!("systemctl {} {} may have failed", action, service));
-    }
-
-    Ok(())
-}
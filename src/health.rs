@@ -0,0 +1,148 @@
+//! The last step of an install: starts each part of the desktop the way the
+//! session will, so a broken config or a missing library shows up before the
+//! reboot rather than as a black screen after it.
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use crate::shell::Shell;
+use crate::{log, prefix, ui};
+
+/// How long Quickshell has to fail loading the shell. Still running by then
+/// means it loaded.
+const QUICKSHELL_SECS: &str = "8";
+
+/// What `timeout` exits with when it had to stop the command.
+const TIMED_OUT: i32 = 124;
+
+type Check = fn(Shell) -> Result<String, String>;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("Hyprland config", check_hyprland),
+    ("Quickshell", check_quickshell),
+    ("caelestia CLI", check_cli),
+    ("Shell", check_shell),
+];
+
+/// Runs every check, warning about each part that errors. Failures do not
+/// fail the install: everything is in place, and the warnings tell what to
+/// look at before rebooting.
+pub fn run(shell: Shell, dry_run: bool) -> Result<()> {
+    if dry_run {
+        ui::success("Would start Hyprland, Quickshell, the caelestia CLI and the shell to check them (dry-run)");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (name, check) in CHECKS {
+        match check(shell) {
+            Ok(detail) => {
+                ui::success(&format!("{}: {}", name, detail));
+                log::log(&format!("Health: {} ok: {}", name, detail));
+            }
+            Err(problem) => {
+                failed += 1;
+                ui::warning(&format!("{}: {}", name, problem));
+                log::log_error(&format!("Health: {} failed: {}", name, problem));
+            }
+        }
+    }
+    if failed > 0 {
+        ui::warning(&format!(
+            "{} of {} parts failed to start, the session may not come up after a reboot",
+            failed,
+            CHECKS.len()
+        ));
+        ui::info("Run caelestia-installer doctor for fixes");
+    }
+    Ok(())
+}
+
+/// Runs `command`, logging its line first.
+fn output(command: &mut Command) -> Result<Output, String> {
+    let line = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    log::log_command(&line);
+    command
+        .output()
+        .map_err(|e| format!("could not run {}: {}", command.get_program().to_string_lossy(), e))
+}
+
+/// The last few lines of what a failed command printed.
+fn reason(output: &Output) -> String {
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    match lines.len() {
+        0 => output.status.to_string(),
+        len => lines[len.saturating_sub(3)..].join(" / "),
+    }
+}
+
+fn find_program(program: &str) -> Option<PathBuf> {
+    which::which(program)
+        .ok()
+        .or_else(|| Some(prefix::bin_dir().join(program)).filter(|path| path.exists()))
+}
+
+fn check_hyprland(_: Shell) -> Result<String, String> {
+    let config = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("hypr/hyprland.conf");
+    if !config.exists() {
+        return Err(format!("{} does not exist", config.display()));
+    }
+    let output = output(Command::new("Hyprland").arg("--verify-config").arg("--config").arg(&config))?;
+    if !output.status.success() {
+        return Err(reason(&output));
+    }
+    Ok(format!("{} parses", config.display()))
+}
+
+/// Starts the shell without a display for a few seconds. Quickshell exits
+/// right away when the config or a QML module fails to load.
+fn check_quickshell(_: Shell) -> Result<String, String> {
+    let Some(quickshell) = find_program("quickshell") else {
+        return Err("quickshell is not installed".to_string());
+    };
+    let output = output(
+        Command::new("timeout")
+            .arg(QUICKSHELL_SECS)
+            .arg(&quickshell)
+            .args(["-c", "caelestia"])
+            .env("QT_QPA_PLATFORM", "offscreen"),
+    )?;
+    if output.status.code() == Some(TIMED_OUT) {
+        return Ok(format!("the caelestia shell loads ({})", quickshell.display()));
+    }
+    Err(format!("exited while loading the shell: {}", reason(&output)))
+}
+
+fn check_cli(_: Shell) -> Result<String, String> {
+    let Some(caelestia) = find_program("caelestia") else {
+        return Err("caelestia is not installed".to_string());
+    };
+    let output = output(Command::new(&caelestia).arg("--version"))?;
+    if !output.status.success() {
+        return Err(reason(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Starts the configured shell, which reads its config (fish reads
+/// config.fish even for `-c`). Errors in it are printed but do not change
+/// the exit status, so any output counts.
+fn check_shell(shell: Shell) -> Result<String, String> {
+    let output = output(Command::new(shell.name()).args(["-c", "true"]))?;
+    if !output.status.success() || !output.stderr.is_empty() {
+        return Err(format!("{} reports: {}", shell.name(), reason(&output)));
+    }
+    Ok(format!("{} starts cleanly", shell.name()))
+}
@@ -0,0 +1,87 @@
+//! User hooks run around steps, for site-specific additions without a fork:
+//! `~/.config/caelestia-installer/hooks/pre-<step>.sh` and `post-<step>.sh`,
+//! and commands under `[hooks.pre]` and `[hooks.post]` in the config file.
+//! They run with bash and get the step in the environment; a failing hook
+//! fails its step.
+
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::install::{Options, Step};
+use crate::{log, runner, ui};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum When {
+    Pre,
+    Post,
+}
+
+impl When {
+    fn name(self) -> &'static str {
+        match self {
+            When::Pre => "pre",
+            When::Post => "post",
+        }
+    }
+}
+
+pub fn hooks_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia-installer/hooks")
+}
+
+/// Runs the `when` hooks of `step`: the script, then the config command.
+/// Hooks also run in a dry run, with `CAELESTIA_DRY_RUN=1` so they can do
+/// the same.
+pub fn run(when: When, step: &dyn Step, options: &Options) -> Result<()> {
+    let script = hooks_dir().join(format!("{}-{}.sh", when.name(), step.id()));
+    if script.exists() {
+        let mut command = Command::new("bash");
+        command.arg(&script);
+        let line = format!("bash {}", script.display());
+        run_hook(command, &script.display().to_string(), &line, when, step, options)?;
+    }
+
+    let configured = match when {
+        When::Pre => options.hooks.pre.get(step.id()),
+        When::Post => options.hooks.post.get(step.id()),
+    };
+    if let Some(line) = configured {
+        let mut command = Command::new("bash");
+        command.args(["-c", line]);
+        run_hook(command, line, &format!("bash -c {:?}", line), when, step, options)?;
+    }
+    Ok(())
+}
+
+fn run_hook(
+    mut command: Command,
+    name: &str,
+    line: &str,
+    when: When,
+    step: &dyn Step,
+    options: &Options,
+) -> Result<()> {
+    ui::info(&format!("Running {} hook of {}: {}", when.name(), step.id(), name));
+    log::log_command(line);
+
+    command
+        .env("CAELESTIA_STEP", step.id())
+        .env("CAELESTIA_STEP_TITLE", step.title().trim_end_matches("..."))
+        .env("CAELESTIA_HOOK", when.name())
+        .env("CAELESTIA_DRY_RUN", if options.dry_run { "1" } else { "0" })
+        .env("CAELESTIA_NONINTERACTIVE", if options.noconfirm { "1" } else { "0" });
+    let output = runner::run(&mut command)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        let reason = match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => stderr.to_string(),
+        };
+        bail!("The {} hook of {} failed: {}", when.name(), step.id(), reason);
+    }
+    Ok(())
+}
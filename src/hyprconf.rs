@@ -0,0 +1,110 @@
+//! A minimal parser for Hyprland's config syntax, run on the files the
+//! installer edits so a line Hyprland would reject is reported with its line
+//! number right after the edit instead of as an error bar at login.
+
+use std::fs;
+use std::path::Path;
+
+use crate::{log, ui};
+
+/// A line Hyprland would reject.
+pub struct Issue {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Checks `path` and warns about every line Hyprland would reject. A missing
+/// file has nothing to check.
+pub fn check(path: &Path) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let issues = parse(&content);
+    if issues.is_empty() {
+        log::debug(&format!("{} parses", path.display()));
+        return;
+    }
+    for issue in &issues {
+        ui::warning(&format!("{}:{}: {}", path.display(), issue.line, issue.message));
+    }
+    ui::info("Hyprland will show these errors at login until the lines are fixed");
+}
+
+/// Finds the lines of `content` that Hyprland would reject: unbalanced
+/// braces, lines that are not `key = value` and binds missing fields.
+/// Sourced files are left alone, some only exist once later steps ran.
+pub fn parse(content: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+
+    for (index, raw) in content.lines().enumerate() {
+        let line = index + 1;
+        let text = strip_comment(raw);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        if text == "}" {
+            if open.pop().is_none() {
+                issues.push(Issue { line, message: "`}` closes nothing".to_string() });
+            }
+            continue;
+        }
+        if let Some(name) = text.strip_suffix('{') {
+            let name = name.trim();
+            if name.is_empty() || name.contains('=') || name.contains(char::is_whitespace) {
+                issues.push(Issue { line, message: format!("invalid section name {:?}", name) });
+            }
+            open.push(line);
+            continue;
+        }
+
+        let Some((key, value)) = text.split_once('=') else {
+            issues.push(Issue { line, message: format!("expected `key = value`, found {:?}", text) });
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            issues.push(Issue { line, message: format!("invalid key {:?}", key) });
+            continue;
+        }
+
+        if is_bind(key) && value.split(',').count() < 3 {
+            issues.push(Issue {
+                line,
+                message: format!("{} needs modifiers, a key and a dispatcher", key),
+            });
+        } else if (key == "exec" || key == "exec-once") && value.is_empty() {
+            issues.push(Issue { line, message: format!("{} has no command", key) });
+        }
+    }
+
+    for line in open {
+        issues.push(Issue { line, message: "`{` is never closed".to_string() });
+    }
+    issues
+}
+
+/// Cuts a `#` comment off `line`. `##` is an escaped `#`, as in colors.
+pub(crate) fn strip_comment(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '#' {
+            if chars.peek() != Some(&'#') {
+                break;
+            }
+            chars.next();
+        }
+        text.push(c);
+    }
+    text
+}
+
+/// Whether `key` is `bind` or one of its flagged forms such as `binde` or
+/// `bindm`.
+fn is_bind(key: &str) -> bool {
+    key.strip_prefix("bind")
+        .is_some_and(|flags| flags.chars().all(|flag| "lrcgoenmtisdp".contains(flag)))
+}
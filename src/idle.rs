@@ -0,0 +1,135 @@
+//! Screen locking and idle handling with hyprlock and hypridle. The user
+//! keybinds lock with `Super+L`, and hypridle locks the session and turns
+//! the screens off after a while without input.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{hyprconf, journal, log, summary, ui};
+
+/// Minutes without input before the session is locked, unless configured.
+pub const DEFAULT_LOCK_AFTER: u32 = 5;
+
+/// Marks the line the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: idle";
+
+const HYPRLOCK_CONFIG: &str = r#"# Generated by caelestia-installer
+general {
+    hide_cursor = true
+}
+
+background {
+    path = screenshot
+    blur_passes = 3
+}
+
+input-field {
+    size = 250, 50
+    position = 0, -80
+    halign = center
+    valign = center
+    placeholder_text = Password
+}
+
+label {
+    text = $TIME
+    font_size = 64
+    position = 0, 80
+    halign = center
+    valign = center
+}
+"#;
+
+/// hypridle's config, locking after `lock_after` minutes and turning the
+/// screens off 30 seconds later.
+fn hypridle_config(lock_after: u32) -> String {
+    let lock_secs = lock_after * 60;
+    format!(
+        r#"# Generated by caelestia-installer
+general {{
+    lock_cmd = pidof hyprlock || hyprlock
+    before_sleep_cmd = loginctl lock-session
+    after_sleep_cmd = hyprctl dispatch dpms on
+}}
+
+listener {{
+    timeout = {}
+    on-timeout = loginctl lock-session
+}}
+
+listener {{
+    timeout = {}
+    on-timeout = hyprctl dispatch dpms off
+    on-resume = hyprctl dispatch dpms on
+}}
+"#,
+        lock_secs,
+        lock_secs + 30
+    )
+}
+
+/// Writes the hypridle and hyprlock configs, unless the dotfiles or the user
+/// already have them, and starts hypridle with Hyprland.
+pub fn setup(lock_after: u32, dry_run: bool) -> Result<()> {
+    ui::info("Setting up idle locking...");
+
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+    let hypr_dir = config_dir.join("hypr");
+
+    if dry_run {
+        ui::success(&format!(
+            "Would configure hypridle to lock after {} minutes (dry-run)",
+            lock_after
+        ));
+        return Ok(());
+    }
+
+    let missing: Vec<&str> = ["hypridle", "hyprlock"]
+        .into_iter()
+        .filter(|program| which::which(program).is_err())
+        .collect();
+    if !missing.is_empty() {
+        bail!("{} not found after installing packages", missing.join(" and "));
+    }
+
+    fs::create_dir_all(&hypr_dir)?;
+    let hypridle = hypr_dir.join("hypridle.conf");
+    if hypridle.exists() {
+        ui::success("hypridle.conf already exists, keeping it");
+    } else {
+        journal::write_file(&hypridle, hypridle_config(lock_after))?;
+        ui::success(&format!("Created hypridle.conf, locking after {} minutes", lock_after));
+        log::log(&format!("hypridle config written to {:?}", hypridle));
+    }
+
+    let hyprlock = hypr_dir.join("hyprlock.conf");
+    if hyprlock.exists() {
+        ui::success("hyprlock.conf already exists, keeping it");
+    } else {
+        journal::write_file(&hyprlock, HYPRLOCK_CONFIG)?;
+        ui::success("Created hyprlock.conf");
+        log::log(&format!("hyprlock config written to {:?}", hyprlock));
+    }
+
+    autostart(&config_dir)
+}
+
+/// Starts hypridle with Hyprland from `~/.config/caelestia/hypr-user.conf`.
+fn autostart(config_dir: &std::path::Path) -> Result<()> {
+    let hypr_user = config_dir.join("caelestia/hypr-user.conf");
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if existing.contains(HYPR_MARKER) || existing.contains("exec-once = hypridle") {
+        return Ok(());
+    }
+
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, format!("{}\n{}\nexec-once = hypridle\n", existing, HYPR_MARKER))?;
+    hyprconf::check(&hypr_user);
+
+    ui::success("hypridle starts with Hyprland");
+    summary::artifact("hypridle");
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Keyboard layout and touchpad settings. Hyprland ignores the system
+//! keymap and starts with a US layout, so the layout set with `localectl` is
+//! carried over into the user's Hyprland config.
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{hyprconf, journal, log, summary, ui};
+
+/// Frame the block the installer writes, so a later run can replace it.
+const BEGIN_MARKER: &str = "# caelestia-installer: input";
+const END_MARKER: &str = "# caelestia-installer: input end";
+
+/// Layout and variant as `localectl` reports them for X11, falling back to
+/// the console keymap (e.g. `de-nodeadkeys`).
+pub fn detect_layout() -> Option<(String, Option<String>)> {
+    let output = Command::new("localectl").arg("status").output().ok()?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(name))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty() && value != "n/a")
+    };
+
+    if let Some(layout) = field("X11 Layout:") {
+        return Some((layout, field("X11 Variant:")));
+    }
+    let keymap = field("VC Keymap:")?;
+    match keymap.split_once('-') {
+        Some((layout, variant)) => Some((layout.to_string(), Some(variant.to_string()))),
+        None => Some((keymap, None)),
+    }
+}
+
+/// Whether the kernel lists a touchpad among the input devices.
+fn has_touchpad() -> bool {
+    fs::read_to_string("/proc/bus/input/devices")
+        .map(|devices| devices.lines().any(|line| line.starts_with("N:") && line.contains("Touchpad")))
+        .unwrap_or(false)
+}
+
+fn describe(layout: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) => format!("{} ({})", layout, variant),
+        None => layout.to_string(),
+    }
+}
+
+/// Writes the keyboard layout and touchpad settings to
+/// `~/.config/caelestia/hypr-user.conf`. `layout` and `natural_scroll` are
+/// asked for when not given.
+pub fn setup(
+    layout: Option<&str>,
+    variant: Option<&str>,
+    natural_scroll: Option<bool>,
+    dry_run: bool,
+    noconfirm: bool,
+) -> Result<()> {
+    let (layout, variant) = match layout {
+        Some(layout) => (layout.to_string(), variant.map(str::to_string)),
+        None => choose_layout(noconfirm),
+    };
+    ui::info(&format!("Keyboard layout: {}", describe(&layout, variant.as_deref())));
+    log::log(&format!("Keyboard layout: {} variant: {:?}", layout, variant));
+
+    let natural_scroll = match natural_scroll {
+        Some(natural_scroll) => natural_scroll,
+        None if has_touchpad() && !noconfirm => ui::prompt("Use natural (reversed) scrolling on the touchpad?"),
+        None => false,
+    };
+
+    if layout == "us" && variant.is_none() && !natural_scroll {
+        ui::success("US layout and default touchpad settings, nothing to change");
+        summary::skipped();
+        return Ok(());
+    }
+    write_config(&layout, variant.as_deref(), natural_scroll, dry_run)
+}
+
+/// Offers the detected layout, letting the user type another one.
+fn choose_layout(noconfirm: bool) -> (String, Option<String>) {
+    let detected = detect_layout().unwrap_or_else(|| ("us".to_string(), None));
+    if noconfirm {
+        return detected;
+    }
+
+    let current = describe(&detected.0, detected.1.as_deref());
+    if ui::prompt(&format!("Use the keyboard layout {} in Hyprland?", current)) {
+        return detected;
+    }
+    let Some(layout) = ui::input("Keyboard layout (e.g. de, fr, gb)", &detected.0) else {
+        return detected;
+    };
+    let variant = ui::input("Layout variant (empty for none)", "").filter(|variant| !variant.is_empty());
+    (layout, variant)
+}
+
+fn write_config(layout: &str, variant: Option<&str>, natural_scroll: bool, dry_run: bool) -> Result<()> {
+    let hypr_user = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia/hypr-user.conf");
+
+    if dry_run {
+        ui::success(&format!("Would write the input settings to {:?} (dry-run)", hypr_user));
+        return Ok(());
+    }
+
+    let mut block = format!("{}\ninput {{\n    kb_layout = {}\n", BEGIN_MARKER, layout);
+    if let Some(variant) = variant {
+        block += &format!("    kb_variant = {}\n", variant);
+    }
+    if natural_scroll {
+        block += "\n    touchpad {\n        natural_scroll = true\n    }\n";
+    }
+    block += &format!("}}\n{}\n", END_MARKER);
+
+    // Replace the block of a previous run, keeping the rest of the file
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    let content = match (existing.find(BEGIN_MARKER), existing.find(END_MARKER)) {
+        (Some(begin), Some(end)) if begin < end => {
+            let end = end + END_MARKER.len();
+            let rest = existing[end..].strip_prefix('\n').unwrap_or(&existing[end..]);
+            format!("{}{}{}", &existing[..begin], block, rest)
+        }
+        _ => format!("{}\n{}", existing, block),
+    };
+    if content == existing {
+        ui::success("Input settings already set up");
+        return Ok(());
+    }
+
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, content)?;
+    hyprconf::check(&hypr_user);
+
+    ui::success("Wrote the keyboard layout and touchpad settings to the Hyprland config");
+    log::log(&format!("Input settings written to {:?}", hypr_user));
+    summary::artifact("input config");
+    Ok(())
+}
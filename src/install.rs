@@ -0,0 +1,1084 @@
+//! The install pipeline as a reusable API.
+//!
+//! A [`Plan`] is built from [`Options`] and holds the [`Step`]s of an
+//! installation, which form a dependency graph through [`Step::after`]. Front-ends run it and observe progress through the
+//! callbacks registered with `ui::set_event_handler` and
+//! `ui::set_prompt_handler`; without them everything prints to the terminal.
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::engine::{self, Resource};
+use crate::shell::Shell;
+use crate::timings::Timings;
+use crate::display_manager::{self, DisplayManager};
+use crate::{checks, cli, clipboard, config, custom, desktops, dotfiles, flatpak, gpu, health, idle, input, journal, keybinds, log, mime, packages, portals, prefix, preview, privileged, repos, selinux, shell, state, system, theming, ui, uwsm, vm, wallpapers};
+
+/// Settings shared by every front-end driving an installation.
+#[derive(Clone)]
+pub struct Options {
+    pub dry_run: bool,
+    pub noconfirm: bool,
+    /// Keep running the steps that do not depend on a failed one, and report
+    /// every failure at the end.
+    pub continue_on_error: bool,
+    /// Undo the steps that ran when the run fails, newest first.
+    pub rollback_on_failure: bool,
+    /// Packages installed with dnf, defaults to the default profile's.
+    pub packages: Vec<String>,
+    /// Packages never installed, even when a component asks for them.
+    pub exclude_packages: Vec<String>,
+    /// Whether to enable RPM Fusion, `None` asking when the repos are added.
+    pub rpmfusion: Option<bool>,
+    /// Display manager to set up, when its component is selected.
+    pub display_manager: DisplayManager,
+    /// Whether to set it up, `None` asking when the step is reached.
+    pub display_manager_wanted: Option<bool>,
+    /// Whether greetd starts the UWSM session, which the plan sets from
+    /// whether the uwsm step runs.
+    pub uwsm_session: bool,
+    /// Ids of the [`COMPONENTS`] to install.
+    pub components: Vec<String>,
+    /// Repos to clone instead of upstream, such as forks.
+    pub dotfiles_repo: Option<String>,
+    pub shell_repo: Option<String>,
+    /// Branches to check out instead of the repos' default ones.
+    pub dotfiles_branch: Option<String>,
+    pub shell_branch: Option<String>,
+    /// Interactive shell to configure.
+    pub shell: Shell,
+    /// Whether to make it the login shell, `None` asking when the step is reached.
+    pub login_shell: Option<bool>,
+    /// Whether Starship may be installed with its install script when neither
+    /// a package nor the release binary can be installed.
+    pub starship_script: bool,
+    /// Extra COPR to look for a quickshell package in, such as
+    /// `errornointernet/quickshell`.
+    pub quickshell_copr: Option<String>,
+    /// Rust toolchain for the rust component, rustup's `stable` by default
+    /// and `fedora` for Fedora's packages.
+    pub rust_toolchain: Option<String>,
+    /// Whether to remove the portals of desktops that are no longer
+    /// installed without asking.
+    pub remove_de_portals: bool,
+    /// Whether to remove the build dependencies without asking once
+    /// everything is built.
+    pub remove_build_deps: bool,
+    /// Fish plugins installed with fisher, such as `jorgebucaran/autopair.fish`.
+    pub fish_plugins: Vec<String>,
+    /// Main key and programs of the user keybinds, asked for when unset.
+    pub keybinds: keybinds::Choices,
+    /// Default applications chosen in the config file.
+    pub default_apps: mime::Defaults,
+    /// Keyboard layout and variant for Hyprland, `None` asking which to use.
+    pub keyboard_layout: Option<String>,
+    pub keyboard_variant: Option<String>,
+    /// Whether touchpads scroll naturally, `None` asking when there is one.
+    pub natural_scroll: Option<bool>,
+    /// Minutes without input before hypridle locks the session.
+    pub lock_after: u32,
+    /// Flatpak apps installed from Flathub.
+    pub flatpak_apps: Vec<String>,
+    /// Where to put the wallpapers, `~/Pictures/Wallpapers` by default.
+    pub wallpapers_dir: Option<PathBuf>,
+    /// Commands from the config file run around steps.
+    pub hooks: config::Hooks,
+}
+
+/// An optional part of the installation, made of steps and/or packages.
+pub struct Component {
+    pub id: &'static str,
+    pub label: &'static str,
+    /// Steps that only run when the component is selected.
+    pub steps: &'static [&'static str],
+    /// Packages that are only installed when the component is selected.
+    pub packages: &'static [&'static str],
+    /// Whether the component is selected unless the user says otherwise.
+    pub default: bool,
+}
+
+pub const COMPONENTS: &[Component] = &[
+    Component {
+        id: "display-manager",
+        label: "Display manager (greetd/tuigreet or SDDM)",
+        steps: &["display-manager"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "shell-config",
+        label: "Shell configuration (fish, zsh or bash)",
+        steps: &["shell-config"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "fonts",
+        label: "Nerd fonts (CaskaydiaCove, JetBrains Mono)",
+        steps: &["fonts"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "thunar",
+        label: "Thunar file manager",
+        steps: &[],
+        packages: &["Thunar"],
+        default: false,
+    },
+    Component {
+        id: "discover",
+        label: "Discover software center",
+        steps: &[],
+        packages: &["plasma-discover"],
+        default: false,
+    },
+    Component {
+        id: "flatpak",
+        label: "Flathub and Flatpak apps",
+        steps: &["flatpak"],
+        packages: &["flatpak"],
+        default: false,
+    },
+    Component {
+        id: "cava",
+        label: "Cava audio visualizer",
+        steps: &["cava"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "keybinds",
+        label: "User keybinds file",
+        steps: &["keybinds"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "uwsm",
+        label: "UWSM session, running Hyprland as a systemd user session",
+        steps: &["uwsm"],
+        packages: &[],
+        default: true,
+    },
+    Component {
+        id: "mime",
+        label: "Default applications for folders, links, images and terminals",
+        steps: &["mime"],
+        packages: &["xdg-utils"],
+        default: true,
+    },
+    Component {
+        id: "idle",
+        label: "Screen locking (hyprlock, hypridle)",
+        steps: &["idle"],
+        packages: &["hypridle", "hyprlock"],
+        default: true,
+    },
+    Component {
+        id: "clipboard",
+        label: "Clipboard history (cliphist, Super+V)",
+        steps: &["clipboard"],
+        packages: &["cliphist"],
+        default: true,
+    },
+    Component {
+        id: "theming",
+        label: "GTK, Qt and cursor theming (qt6ct, Kvantum)",
+        steps: &["theming"],
+        packages: &["qt6ct", "kvantum", "adwaita-cursor-theme"],
+        default: true,
+    },
+    Component {
+        id: "rust",
+        label: "Rust toolchain (rustup, not needed by the desktop)",
+        steps: &["rust"],
+        packages: &[],
+        default: false,
+    },
+    Component {
+        id: "wallpapers",
+        label: "Wallpaper collection",
+        steps: &["wallpapers"],
+        packages: &[],
+        default: true,
+    },
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            noconfirm: false,
+            continue_on_error: false,
+            rollback_on_failure: false,
+            packages: packages::default_packages(),
+            exclude_packages: Vec::new(),
+            rpmfusion: None,
+            display_manager: DisplayManager::default(),
+            display_manager_wanted: None,
+            uwsm_session: false,
+            components: COMPONENTS
+                .iter()
+                .filter(|c| c.default)
+                .map(|c| c.id.to_string())
+                .collect(),
+            dotfiles_repo: None,
+            shell_repo: None,
+            dotfiles_branch: None,
+            shell_branch: None,
+            shell: Shell::default(),
+            login_shell: None,
+            starship_script: false,
+            quickshell_copr: None,
+            rust_toolchain: None,
+            remove_de_portals: false,
+            remove_build_deps: false,
+            fish_plugins: Vec::new(),
+            keybinds: keybinds::Choices::default(),
+            default_apps: mime::Defaults::default(),
+            keyboard_layout: None,
+            keyboard_variant: None,
+            natural_scroll: None,
+            lock_after: idle::DEFAULT_LOCK_AFTER,
+            flatpak_apps: Vec::new(),
+            wallpapers_dir: None,
+            hooks: config::Hooks::default(),
+        }
+    }
+}
+
+/// One unit of the installation.
+pub trait Step: Send + Sync {
+    /// Stable identifier, used for timings and the summary.
+    fn id(&self) -> &'static str;
+
+    /// Message shown when the step starts.
+    fn title(&self) -> &'static str;
+
+    /// Ids of the steps that must finish first, `None` meaning the step
+    /// before this one in the plan.
+    fn after(&self) -> Option<&[&'static str]> {
+        None
+    }
+
+    /// What the step mostly spends its time on.
+    fn resource(&self) -> Resource {
+        Resource::Light
+    }
+
+    /// Whether the step may prompt the user, in which case it runs alone.
+    fn interactive(&self) -> bool {
+        false
+    }
+
+    /// Returns true when the step's result is already in place, in which case
+    /// it is skipped. Must not modify the system.
+    fn check(&self, _options: &Options) -> bool {
+        false
+    }
+
+    /// Whether [`Step::check`] can tell the step is done, rather than
+    /// always running it.
+    fn checked(&self) -> bool {
+        false
+    }
+
+    fn apply(&self, options: &Options) -> Result<()>;
+
+    /// Undoes what `apply` did, where that is possible.
+    fn rollback(&self, _options: &Options) -> Result<()> {
+        Ok(())
+    }
+}
+
+type Action = fn(&Options) -> Result<()>;
+
+/// A step made of plain functions, which is all the built-in steps need.
+pub struct FnStep {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub after: &'static [&'static str],
+    pub resource: Resource,
+    pub interactive: bool,
+    pub check: Option<fn(&Options) -> bool>,
+    pub apply: Action,
+    pub rollback: Option<Action>,
+}
+
+impl Step for FnStep {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn title(&self) -> &'static str {
+        self.title
+    }
+
+    fn after(&self) -> Option<&[&'static str]> {
+        Some(self.after)
+    }
+
+    fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    fn check(&self, options: &Options) -> bool {
+        self.check.is_some_and(|check| check(options))
+    }
+
+    fn checked(&self) -> bool {
+        self.check.is_some()
+    }
+
+    fn apply(&self, options: &Options) -> Result<()> {
+        (self.apply)(options)
+    }
+
+    fn rollback(&self, options: &Options) -> Result<()> {
+        match self.rollback {
+            Some(rollback) => rollback(options),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A step as `list-steps` shows it, before anything runs.
+pub struct Listing {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub after: Vec<&'static str>,
+    /// Left out by the selected components or the step flags.
+    pub left_out: bool,
+    /// Its check finds the result already in place, so it would be skipped.
+    pub done: bool,
+    /// Whether the step has a check at all.
+    pub checked: bool,
+    /// How long it is expected to take on this machine.
+    pub estimate: Duration,
+}
+
+/// The steps of an installation together with the options they run with.
+pub struct Plan {
+    options: Arc<Options>,
+    steps: Vec<Arc<dyn Step>>,
+    /// Steps left out of this run, treated as already done.
+    skipped: HashSet<&'static str>,
+    /// Checkpoints this run builds on; empty for a fresh installation.
+    checkpoint: state::State,
+}
+
+impl Plan {
+    /// Builds the standard installation, leaving out the steps and packages
+    /// of unselected components.
+    pub fn new(mut options: Options) -> Self {
+        let mut skipped = HashSet::new();
+        for component in COMPONENTS {
+            if options.components.iter().any(|id| id == component.id) {
+                for package in component.packages {
+                    if !options.packages.iter().any(|p| p == package) {
+                        options.packages.push(package.to_string());
+                    }
+                }
+            } else {
+                skipped.extend(component.steps.iter().copied());
+                options.packages.retain(|p| !component.packages.contains(&p.as_str()));
+            }
+        }
+        let mut extra = options.shell.packages().to_vec();
+        if options.components.iter().any(|id| id == "display-manager") {
+            extra.extend(options.display_manager.packages());
+        }
+        for package in extra {
+            if !options.packages.iter().any(|p| p == package) {
+                options.packages.push(package.to_string());
+            }
+        }
+        let exclude = std::mem::take(&mut options.exclude_packages);
+        options.packages.retain(|p| !exclude.contains(p));
+        options.exclude_packages = exclude;
+        options.uwsm_session = !skipped.contains("uwsm");
+
+        Self {
+            options: Arc::new(options),
+            steps: default_steps(),
+            skipped,
+            checkpoint: state::State::default(),
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn steps(&self) -> &[Arc<dyn Step>] {
+        &self.steps
+    }
+
+    pub fn step_ids(&self) -> Vec<&'static str> {
+        self.steps.iter().map(|step| step.id()).collect()
+    }
+
+    /// Ids of the steps that will run, in dependency order.
+    pub fn selected_ids(&self) -> Result<Vec<&'static str>> {
+        Ok(self
+            .order()?
+            .into_iter()
+            .filter(|id| !self.skipped.contains(id))
+            .collect())
+    }
+
+    /// Fails when a config hook names a step that does not exist.
+    pub fn check_hooks(&self) -> Result<()> {
+        let known = self.step_ids();
+        let hooks = &self.options.hooks;
+        for (when, id) in hooks.pre.keys().map(|id| ("pre", id)).chain(hooks.post.keys().map(|id| ("post", id))) {
+            if !known.contains(&id.as_str()) {
+                bail!("Unknown step '{}' in [hooks.{}] (available: {})", id, when, known.join(", "));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_ids(&self, ids: &[String]) -> Result<()> {
+        let known = self.step_ids();
+        if let Some(unknown) = ids.iter().find(|id| !known.contains(&id.as_str())) {
+            bail!("Unknown step '{}' (available: {})", unknown, known.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Leaves out the steps matching `exclude`, assuming they are done.
+    fn exclude(&mut self, exclude: impl Fn(&'static str) -> bool) {
+        let ids = self.step_ids();
+        self.skipped.extend(ids.into_iter().filter(|id| exclude(id)));
+        // A partial run adds to the checkpoints of earlier runs
+        self.checkpoint = state::load();
+    }
+
+    /// Limits the run to the given steps; the rest are assumed to be done.
+    pub fn only(&mut self, ids: &[String]) -> Result<()> {
+        self.check_ids(ids)?;
+        self.exclude(|id| !ids.iter().any(|wanted| wanted == id));
+        Ok(())
+    }
+
+    /// Leaves the given steps out of the run.
+    pub fn skip(&mut self, ids: &[String]) -> Result<()> {
+        self.check_ids(ids)?;
+        self.exclude(|id| ids.iter().any(|unwanted| unwanted == id));
+        if ids.iter().any(|id| id == "uwsm") {
+            // Without it Hyprland is started directly
+            Arc::make_mut(&mut self.options).uwsm_session = false;
+        }
+        Ok(())
+    }
+
+    /// Starts the run at step `first` of the dependency order.
+    pub fn from(&mut self, first: &str) -> Result<()> {
+        self.check_ids(&[first.to_string()])?;
+        let order = self.order()?;
+        let start = order.iter().position(|id| *id == first).unwrap_or(0);
+        self.exclude(|id| order[..start].contains(&id));
+        Ok(())
+    }
+
+    /// Ends the run after step `last` of the dependency order.
+    pub fn until(&mut self, last: &str) -> Result<()> {
+        self.check_ids(&[last.to_string()])?;
+        let order = self.order()?;
+        let end = order.iter().position(|id| *id == last).map_or(order.len(), |i| i + 1);
+        self.exclude(|id| order[end..].contains(&id));
+        Ok(())
+    }
+
+    /// Ids of every step in dependency order.
+    fn order(&self) -> Result<Vec<&'static str>> {
+        Ok(engine::validate(&self.steps)?
+            .into_iter()
+            .map(|i| self.steps[i].id())
+            .collect())
+    }
+
+    /// Every step in dependency order with what it waits for and whether it
+    /// would run. Runs the checks, which do not modify the system.
+    pub fn list(&self) -> Result<Vec<Listing>> {
+        let timings = Timings::load();
+        Ok(engine::validate(&self.steps)?
+            .into_iter()
+            .map(|position| {
+                let step = &self.steps[position];
+                let left_out = self.skipped.contains(step.id());
+                Listing {
+                    id: step.id(),
+                    title: step.title(),
+                    after: engine::dependencies(&self.steps, position),
+                    left_out,
+                    done: !left_out && step.check(&self.options),
+                    checked: step.checked(),
+                    estimate: timings.estimate(step.id()),
+                }
+            })
+            .collect())
+    }
+
+    /// Skips the steps a previous run completed, continuing from where it
+    /// stopped. Returns how many steps are skipped.
+    pub fn resume(&mut self) -> usize {
+        let previous = state::load();
+        let mut resumed = 0;
+        for step in &self.steps {
+            if previous.completed.iter().any(|done| done == step.id()) && self.skipped.insert(step.id()) {
+                resumed += 1;
+            }
+        }
+
+        if previous.completed.is_empty() {
+            ui::warning("No previous run to resume, starting from the beginning");
+        } else {
+            let from = previous
+                .failed
+                .as_deref()
+                .map(|id| format!(", continuing from {}", id))
+                .unwrap_or_default();
+            ui::info(&format!("Resuming: {} step(s) already completed{}", resumed, from));
+            log::log(&format!("Resuming after {}", previous.completed.join(", ")));
+        }
+
+        self.checkpoint = previous;
+        resumed
+    }
+
+    /// Warns about selected steps whose left-out dependencies do not look done.
+    fn check_partial(&self) {
+        for (position, step) in self.steps.iter().enumerate() {
+            if self.skipped.contains(step.id()) {
+                continue;
+            }
+            for dep in engine::dependencies(&self.steps, position) {
+                let Some(dep_step) = self.steps.iter().find(|s| s.id() == dep) else {
+                    continue;
+                };
+                if self.skipped.contains(dep) && !dep_step.check(&self.options) {
+                    ui::warning(&format!(
+                        "{} depends on {}, which is not selected and may not be done",
+                        step.id(),
+                        dep
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Adds the steps declared in the config file, each after the last of the
+    /// steps it names, so they run in the order they are declared in.
+    pub fn add_custom_steps(&mut self, declared: &[config::CustomStep]) -> Result<()> {
+        for declared in declared {
+            let step = custom::CommandStep::new(declared)?;
+            if self.step_ids().contains(&step.id()) {
+                bail!("Step '{}' in the config file is already a step", step.id());
+            }
+            let known = self.step_ids();
+            if let Some(unknown) = declared.after.iter().find(|id| !known.contains(&id.as_str())) {
+                bail!(
+                    "Step '{}' in the config file runs after unknown step '{}' (available: {})",
+                    step.id(),
+                    unknown,
+                    known.join(", ")
+                );
+            }
+            let after = self.steps.iter().rev().map(|s| s.id()).find(|id| declared.after.iter().any(|a| a == id));
+            log::log(&format!("Custom step {} after {}", step.id(), after.unwrap_or("the last step")));
+            self.insert_after(after, Arc::new(step));
+        }
+        Ok(())
+    }
+
+    /// Adds a step after the one with id `after`, or at the end.
+    pub fn insert_after(&mut self, after: Option<&str>, step: Arc<dyn Step>) {
+        let index = after
+            .and_then(|id| self.steps.iter().position(|s| s.id() == id))
+            .map(|i| i + 1)
+            .unwrap_or(self.steps.len());
+        self.steps.insert(index, step);
+    }
+
+    /// Runs every step once its dependencies are done, overlapping
+    /// independent steps, and stops starting new ones after a failure. With
+    /// `rollback_on_failure` a failed run then rolls back the steps that ran
+    /// and the changes they journaled.
+    pub fn run(&self) -> Result<()> {
+        let selected = self.selected_ids()?;
+        if !self.skipped.is_empty() {
+            let left_out: Vec<_> = self
+                .order()?
+                .into_iter()
+                .filter(|id| self.skipped.contains(id))
+                .collect();
+            if left_out.len() > selected.len() {
+                ui::info(&format!("Running only: {}", selected.join(", ")));
+            } else {
+                ui::info(&format!("Skipping: {}", left_out.join(", ")));
+            }
+            self.check_partial();
+        }
+
+        privileged::set_dry_run(self.options.dry_run);
+        if self.options.dry_run {
+            preview::begin();
+        } else {
+            state::begin(self.checkpoint.clone());
+            journal::begin();
+        }
+
+        let mut progress = ui::Progress::new(&selected);
+        let mut applied = Vec::new();
+        let result = engine::run(&self.steps, &self.skipped, self.options.clone(), &mut progress, &mut applied);
+        drop(progress);
+        if let Err(e) = result {
+            if self.options.rollback_on_failure && !self.options.dry_run {
+                ui::warning("Installation failed, rolling back its changes...");
+                self.rollback(&applied);
+                if let Err(rollback_error) = journal::rollback_run() {
+                    ui::warning(&format!("Rollback incomplete: {}", rollback_error));
+                }
+            }
+            return Err(e);
+        }
+        if self.options.dry_run {
+            preview::print();
+        }
+
+        log::log("Installation completed successfully");
+        Ok(())
+    }
+
+    /// Rolls back the steps with the ids in `applied` in reverse order,
+    /// continuing past individual failures.
+    pub fn rollback(&self, applied: &[&'static str]) {
+        for id in applied.iter().rev() {
+            let Some(step) = self.steps.iter().find(|step| step.id() == *id) else {
+                continue;
+            };
+            ui::info(&format!("Rolling back {}...", step.id()));
+            if let Err(e) = step.rollback(&self.options) {
+                ui::warning(&format!("Could not roll back {}: {}", step.id(), e));
+            }
+        }
+    }
+}
+
+fn default_steps() -> Vec<Arc<dyn Step>> {
+    let steps = [
+        FnStep {
+            id: "checks",
+            title: "Running pre-flight checks...",
+            after: &[],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| checks::run_all(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "repos",
+            title: "Adding COPR repositories...",
+            after: &["checks"],
+            resource: Resource::Dnf,
+            interactive: true,
+            check: None,
+            apply: |o| repos::add_all(o.rpmfusion, o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "memory",
+            title: "Checking memory for builds...",
+            after: &["checks"],
+            resource: Resource::Dnf,
+            interactive: true,
+            check: None,
+            apply: |o| system::ensure_memory(o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "packages",
+            title: "Installing packages...",
+            after: &["repos", "memory"],
+            resource: Resource::Dnf,
+            // Enabling the Starship COPR may ask to trust its key
+            interactive: true,
+            check: Some(|o| packages::all_installed(&o.packages)),
+            apply: |o| {
+                packages::install_all(&o.packages, o.dry_run, o.noconfirm)?;
+                packages::install_starship(o.starship_script, o.dry_run, o.noconfirm)
+            },
+            rollback: None,
+        },
+        FnStep {
+            id: "qt-utils",
+            title: "Installing Hyprland Qt utils...",
+            after: &["packages"],
+            resource: Resource::Build,
+            interactive: false,
+            check: Some(|_| {
+                prefix::prefix().join("lib64/libhyprland-qt-support.so").exists()
+                    && which::which("hyprland-dialog").is_ok()
+            }),
+            apply: |o| {
+                packages::install_hyprland_qt_support(o.dry_run)?;
+                packages::install_hyprland_qtutils(o.dry_run)
+            },
+            rollback: None,
+        },
+        FnStep {
+            id: "quickshell",
+            title: "Building Quickshell...",
+            after: &["packages"],
+            resource: Resource::Build,
+            interactive: false,
+            check: Some(|_| which::which("quickshell").is_ok()),
+            apply: |o| packages::install_quickshell(o.quickshell_copr.as_deref(), o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "cava",
+            title: "Installing Cava...",
+            after: &["packages"],
+            resource: Resource::Build,
+            interactive: false,
+            check: Some(|_| packages::cava_installed()),
+            apply: |o| packages::install_cava(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "rust",
+            title: "Installing Rust toolchain...",
+            after: &["packages"],
+            resource: Resource::Dnf,
+            interactive: false,
+            check: Some(|_| packages::rust_installed()),
+            apply: |o| packages::install_rust(o.rust_toolchain.as_deref(), o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "fonts",
+            title: "Installing Fonts...",
+            after: &["checks"],
+            resource: Resource::Network,
+            interactive: false,
+            check: None,
+            apply: |o| packages::install_fonts(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "dotfiles",
+            title: "Cloning dotfiles...",
+            after: &["checks"],
+            resource: Resource::Network,
+            interactive: false,
+            check: None,
+            apply: |o| {
+                dotfiles::clone_repos(
+                    o.dotfiles_repo.as_deref(),
+                    o.dotfiles_branch.as_deref(),
+                    o.shell_repo.as_deref(),
+                    o.shell_branch.as_deref(),
+                    o.dry_run,
+                    o.noconfirm,
+                )
+            },
+            rollback: None,
+        },
+        FnStep {
+            id: "cli",
+            title: "Installing caelestia-cli...",
+            after: &["packages"],
+            resource: Resource::Network,
+            interactive: false,
+            check: Some(|_| which::which("caelestia").is_ok()),
+            apply: |o| cli::install_cli(o.dry_run),
+            rollback: Some(|_| cli::uninstall()),
+        },
+        FnStep {
+            id: "symlinks",
+            title: "Symlinking configurations...",
+            after: &["dotfiles"],
+            resource: Resource::Light,
+            interactive: false,
+            check: Some(|_| dotfiles::configs_linked()),
+            apply: |o| dotfiles::symlink_configs(o.dry_run),
+            rollback: Some(|_| dotfiles::unlink_configs()),
+        },
+        // Initialize color scheme after symlinks so ~/.config/hypr exists
+        FnStep {
+            id: "scheme",
+            title: "Initializing color scheme...",
+            after: &["symlinks"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| cli::init_scheme(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "gpu",
+            title: "Setting up graphics drivers...",
+            after: &["packages", "scheme"],
+            resource: Resource::Dnf,
+            interactive: true,
+            check: None,
+            apply: |o| gpu::setup(o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "vm",
+            title: "Setting up the virtual machine...",
+            after: &["packages", "scheme"],
+            resource: Resource::Dnf,
+            interactive: false,
+            check: None,
+            apply: |o| vm::setup(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "input",
+            title: "Setting up the keyboard and touchpad...",
+            after: &["scheme"],
+            resource: Resource::Light,
+            interactive: true,
+            check: None,
+            apply: |o| {
+                input::setup(
+                    o.keyboard_layout.as_deref(),
+                    o.keyboard_variant.as_deref(),
+                    o.natural_scroll,
+                    o.dry_run,
+                    o.noconfirm,
+                )
+            },
+            rollback: None,
+        },
+        FnStep {
+            id: "environment",
+            title: "Setting up the install prefix environment...",
+            after: &["scheme"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| prefix::setup_environment(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "shell-build",
+            title: "Building caelestia-shell...",
+            after: &["quickshell", "qt-utils", "cava", "dotfiles"],
+            resource: Resource::Build,
+            interactive: false,
+            check: None,
+            apply: |o| dotfiles::build_shell(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "shell-config",
+            title: "Setting up the shell...",
+            after: &["packages", "symlinks"],
+            resource: Resource::Light,
+            interactive: true,
+            check: Some(|o| shell::configured(o.shell, o.login_shell, &o.fish_plugins)),
+            apply: |o| shell::setup_all(o.shell, o.login_shell, &o.fish_plugins, o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "idle",
+            title: "Setting up screen locking...",
+            after: &["packages", "symlinks"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| idle::setup(o.lock_after, o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "clipboard",
+            title: "Setting up clipboard history...",
+            after: &["packages", "scheme"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| clipboard::setup(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "flatpak",
+            title: "Setting up Flathub...",
+            after: &["packages", "theming"],
+            resource: Resource::Network,
+            interactive: false,
+            check: None,
+            apply: |o| flatpak::setup(&o.flatpak_apps, o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "theming",
+            title: "Applying themes...",
+            after: &["packages", "scheme"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| theming::setup(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "wallpapers",
+            title: "Downloading wallpapers...",
+            after: &["cli", "scheme"],
+            resource: Resource::Network,
+            interactive: false,
+            check: None,
+            apply: |o| wallpapers::setup(o.wallpapers_dir.as_deref(), o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "keybinds",
+            title: "Setting up Hyprland keybinds...",
+            // After packages, so the apps it launches are installed
+            after: &["packages", "symlinks"],
+            resource: Resource::Light,
+            interactive: true,
+            check: None,
+            apply: |o| {
+                keybinds::setup_keybinds(&o.keybinds, o.dry_run, o.noconfirm)
+            },
+            rollback: Some(|_| keybinds::remove_keybinds()),
+        },
+        FnStep {
+            id: "mime",
+            title: "Setting default applications...",
+            // After keybinds, whose terminal and browser it follows
+            after: &["keybinds"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| mime::setup(&o.default_apps, &o.keybinds, o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "desktops",
+            title: "Checking for other desktops...",
+            after: &["packages"],
+            resource: Resource::Dnf,
+            interactive: true,
+            check: Some(|_| desktops::configured()),
+            apply: |o| desktops::setup(o.remove_de_portals, o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "portals",
+            title: "Setting up desktop portals...",
+            after: &["packages", "desktops"],
+            resource: Resource::Light,
+            interactive: false,
+            check: Some(|_| portals::configured()),
+            apply: |o| portals::setup(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "uwsm",
+            title: "Setting up the UWSM session...",
+            after: &["packages"],
+            resource: Resource::Dnf,
+            interactive: false,
+            check: Some(|_| uwsm::available()),
+            apply: |o| uwsm::setup(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "display-manager",
+            title: "Setting up display manager...",
+            // After uwsm, so greetd starts its session
+            after: &["packages", "uwsm"],
+            resource: Resource::Light,
+            interactive: true,
+            check: Some(|o| display_manager::configured(o.display_manager, o.uwsm_session)),
+            apply: |o| {
+                display_manager::setup(o.display_manager, o.display_manager_wanted, o.uwsm_session, o.dry_run, o.noconfirm)
+            },
+            rollback: None,
+        },
+        // Last, once every system file is in place
+        FnStep {
+            id: "selinux",
+            title: "Restoring SELinux labels...",
+            after: &["cava", "cli", "shell-build", "display-manager"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| selinux::restore(o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "build-deps",
+            title: "Cleaning up build dependencies...",
+            after: &["qt-utils", "cli", "shell-build"],
+            resource: Resource::Dnf,
+            interactive: true,
+            check: None,
+            apply: |o| packages::remove_build_deps(o.remove_build_deps, o.dry_run, o.noconfirm),
+            rollback: None,
+        },
+        FnStep {
+            id: "health",
+            title: "Checking that the desktop starts...",
+            after: &["selinux", "input", "keybinds", "shell-config", "build-deps"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| health::run(o.shell, o.dry_run),
+            rollback: None,
+        },
+        FnStep {
+            id: "cleanup",
+            title: "Removing build artifacts...",
+            after: &["build-deps", "selinux"],
+            resource: Resource::Light,
+            interactive: false,
+            check: None,
+            apply: |o| system::clean_build_artifacts(o.dry_run),
+            rollback: None,
+        },
+    ];
+
+    steps
+        .into_iter()
+        .map(|step| Arc::new(step) as Arc<dyn Step>)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_steps, COMPONENTS};
+    use crate::engine;
+
+    #[test]
+    fn the_default_steps_form_a_dag() {
+        let steps = default_steps();
+        assert_eq!(engine::validate(&steps).unwrap().len(), steps.len());
+    }
+
+    #[test]
+    fn components_name_existing_steps() {
+        let ids: Vec<_> = default_steps().iter().map(|step| step.id()).collect();
+        for component in COMPONENTS {
+            for step in component.steps {
+                assert!(ids.contains(step), "{} names unknown step {}", component.id, step);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Ctrl-C and SIGTERM. The signal handler only sets a flag; a watcher thread
+//! then stops the running commands, removes half-done clones, undoes this
+//! run's journaled changes with `--rollback-on-failure`, and exits after
+//! saying which steps were cut short.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::{journal, log, summary, sudo, system, ui};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static ROLLBACK: Mutex<bool> = Mutex::new(false);
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static PARTIAL: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+extern "C" fn handle(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs the handlers. With `rollback` the changes this run journaled are
+/// undone before exiting, as after a failure.
+pub fn install(rollback: bool) {
+    *ROLLBACK.lock().unwrap() = rollback;
+    let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only stores to an atomic, which is signal safe
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+
+    thread::spawn(|| loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            clean_up();
+            std::process::exit(130);
+        }
+        thread::sleep(Duration::from_millis(100));
+    });
+}
+
+/// Whether the run was interrupted and is being cleaned up.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Stops `pid` when interrupted, until [`forget_child`].
+pub fn track_child(pid: u32) {
+    CHILDREN.lock().unwrap().push(pid);
+}
+
+pub fn forget_child(pid: u32) {
+    CHILDREN.lock().unwrap().retain(|&child| child != pid);
+}
+
+/// Removes `path` when interrupted, until [`keep`]. For directories that
+/// are only usable once complete, such as a clone.
+pub fn remove_if_interrupted(path: &Path) {
+    PARTIAL.lock().unwrap().push(path.to_path_buf());
+}
+
+pub fn keep(path: &Path) {
+    PARTIAL.lock().unwrap().retain(|partial| partial != path);
+}
+
+fn clean_up() {
+    ui::warning("Interrupted, cleaning up...");
+    log::warn("Interrupted by a signal");
+
+    let children: Vec<u32> = CHILDREN.lock().unwrap().drain(..).collect();
+    for pid in &children {
+        log::log(&format!("Stopping process {}", pid));
+        // SAFETY: kill has no memory effects; a pid that already exited is
+        // reported as ESRCH
+        unsafe {
+            libc::kill(*pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    if !children.is_empty() {
+        // Let them exit before removing what they were writing
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    for path in PARTIAL.lock().unwrap().drain(..) {
+        log::log(&format!("Removing incomplete {:?}", path));
+        std::fs::remove_dir_all(&path).ok();
+    }
+
+    system::remove_swapfile();
+    sudo::stop_keepalive();
+
+    if *ROLLBACK.lock().unwrap() {
+        ui::warning("Rolling back the changes of this run...");
+        if let Err(e) = journal::rollback_run() {
+            ui::warning(&format!("Rollback incomplete: {}", e));
+        }
+    }
+
+    let stopped = summary::running();
+    if stopped.is_empty() {
+        ui::error("Installation interrupted");
+    } else {
+        ui::error(&format!("Installation interrupted during: {}", stopped.join(", ")));
+    }
+    if !*ROLLBACK.lock().unwrap() {
+        ui::info("Finished steps are kept, continue with: caelestia-installer --resume");
+    }
+    if let Some(path) = log::get_log_path() {
+        ui::info(&format!("Log: {}", path.display()));
+    }
+    log::log("Exiting after the interrupt");
+}
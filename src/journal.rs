@@ -0,0 +1,267 @@
+//! Journal of the changes the installer makes to the system (symlinks,
+//! config files, services, the login shell), kept in
+//! `~/.local/state/caelestia-installer/journal.json` so they can be undone in
+//! reverse order with `--rollback`. With `--rollback-on-failure` a run that
+//! fails undoes its own changes right away. Entries accumulate across runs
+//! until rolled back.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{backup, log, privileged, state, ui};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Entry {
+    /// A symlink was created at `path`, and what was there saved in the
+    /// `backup` archive.
+    Symlink { path: PathBuf, backup: Option<PathBuf> },
+    /// A file in the home directory was written. Its previous content was
+    /// saved to `backup`, or it did not exist.
+    File { path: PathBuf, backup: Option<PathBuf> },
+    /// Like `File`, for a root-owned file written through sudo.
+    SystemFile { path: PathBuf, backup: Option<PathBuf> },
+    /// A system service was enabled or disabled.
+    Service { name: String, was_enabled: bool },
+    /// The default systemd target was changed from `previous`.
+    DefaultTarget { previous: String },
+    /// The login shell was changed from `previous`.
+    Shell { previous: String },
+}
+
+static JOURNAL: Mutex<Option<Vec<Entry>>> = Mutex::new(None);
+/// Entries before this index are from earlier runs.
+static RUN_START: Mutex<usize> = Mutex::new(0);
+
+fn dir() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer")
+}
+
+pub fn journal_path() -> PathBuf {
+    dir().join("journal.json")
+}
+
+/// Where the previous contents of changed files are kept.
+fn backup_dir() -> PathBuf {
+    dir().join("journal")
+}
+
+pub fn load() -> Vec<Entry> {
+    fs::read_to_string(journal_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Starts recording changes, after those of earlier runs.
+pub fn begin() {
+    let entries = load();
+    *RUN_START.lock().unwrap() = entries.len();
+    *JOURNAL.lock().unwrap() = Some(entries);
+}
+
+/// The changes recorded since [`begin`].
+pub fn this_run() -> Vec<Entry> {
+    let start = *RUN_START.lock().unwrap();
+    match *JOURNAL.lock().unwrap() {
+        Some(ref entries) => entries.get(start..).unwrap_or_default().to_vec(),
+        None => Vec::new(),
+    }
+}
+
+pub fn record(entry: Entry) {
+    let mut guard = JOURNAL.lock().unwrap();
+    let Some(entries) = guard.as_mut() else {
+        return;
+    };
+    log::log(&format!("Journal: {:?}", entry));
+    entries.push(entry);
+    if let Err(e) = save(entries) {
+        log::log(&format!("Could not save the journal: {}", e));
+    }
+}
+
+fn save(entries: &[Entry]) -> Result<()> {
+    fs::create_dir_all(dir())?;
+    fs::write(journal_path(), serde_json::to_string_pretty(entries)?)?;
+    Ok(())
+}
+
+fn recording() -> bool {
+    JOURNAL.lock().unwrap().is_some()
+}
+
+/// Copies `content` to a new file in the backup directory.
+fn store_backup(path: &Path, content: &[u8]) -> Result<PathBuf> {
+    fs::create_dir_all(backup_dir())?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let count = fs::read_dir(backup_dir())?.count();
+    let backup = backup_dir().join(format!("{:04}-{}", count, name));
+    fs::write(&backup, content)?;
+    Ok(backup)
+}
+
+/// Writes a file in the home directory, journaling its previous content.
+pub fn write_file(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    if recording() {
+        let backup = match fs::read(path) {
+            Ok(previous) => Some(store_backup(path, &previous)?),
+            Err(_) => None,
+        };
+        record(Entry::File {
+            path: path.to_path_buf(),
+            backup,
+        });
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Journals a root-owned file that is about to be written through sudo.
+pub fn before_system_write(path: &Path) -> Result<()> {
+    if !recording() {
+        return Ok(());
+    }
+    let backup = match fs::read(path) {
+        Ok(previous) => Some(store_backup(path, &previous)?),
+        Err(_) => None,
+    };
+    record(Entry::SystemFile {
+        path: path.to_path_buf(),
+        backup,
+    });
+    Ok(())
+}
+
+/// Whether systemd has `service` enabled.
+pub fn service_enabled(service: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-enabled", service])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Undoes the journaled changes of every run, newest first, for
+/// `--rollback`. Entries that cannot be undone stay in the journal so the
+/// rollback can be retried.
+pub fn rollback(dry_run: bool) -> Result<()> {
+    undo_entries(Vec::new(), load(), dry_run)
+}
+
+/// Undoes only the changes recorded since [`begin`], for a run that failed
+/// or was interrupted. Earlier runs' changes stay in the journal for
+/// `--rollback`.
+pub fn rollback_run() -> Result<()> {
+    let start = *RUN_START.lock().unwrap();
+    let mut earlier = JOURNAL.lock().unwrap().clone().unwrap_or_default();
+    let entries = earlier.split_off(start.min(earlier.len()));
+    undo_entries(earlier, entries, false)
+}
+
+/// Undoes `entries` newest first and saves what is left after `kept`.
+fn undo_entries(kept: Vec<Entry>, entries: Vec<Entry>, dry_run: bool) -> Result<()> {
+    if entries.is_empty() {
+        ui::info("Nothing to roll back");
+        return Ok(());
+    }
+
+    ui::info(&format!("Rolling back {} changes...", entries.len()));
+    let mut remaining = Vec::new();
+    for entry in entries.into_iter().rev() {
+        if dry_run {
+            ui::info(&format!("Would undo {} (dry-run)", describe(&entry)));
+            continue;
+        }
+        match undo(&entry) {
+            Ok(()) => {
+                ui::success(&format!("Undid {}", describe(&entry)));
+                log::log(&format!("Rolled back {:?}", entry));
+            }
+            Err(e) => {
+                ui::warning(&format!("Could not undo {}: {}", describe(&entry), e));
+                remaining.push(entry);
+            }
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    remaining.reverse();
+    let failed = remaining.len();
+    let mut left = kept;
+    left.append(&mut remaining);
+    *JOURNAL.lock().unwrap() = None;
+    // The checkpoints no longer hold, so --resume must not skip anything
+    fs::remove_file(state::state_path()).ok();
+    if left.is_empty() {
+        fs::remove_file(journal_path()).ok();
+        fs::remove_dir_all(backup_dir()).ok();
+    } else {
+        save(&left)?;
+    }
+    if failed > 0 {
+        bail!("{} changes could not be rolled back, see {:?}", failed, journal_path());
+    }
+    ui::success("Rollback complete");
+    Ok(())
+}
+
+fn describe(entry: &Entry) -> String {
+    match entry {
+        Entry::Symlink { path, .. } => format!("symlink {:?}", path),
+        Entry::File { path, .. } | Entry::SystemFile { path, .. } => format!("changes to {:?}", path),
+        Entry::Service { name, was_enabled } => format!(
+            "{} {}",
+            if *was_enabled { "disabling" } else { "enabling" },
+            name
+        ),
+        Entry::DefaultTarget { previous } => format!("default target (was {})", previous),
+        Entry::Shell { previous } => format!("login shell (was {})", previous),
+    }
+}
+
+fn undo(entry: &Entry) -> Result<()> {
+    match entry {
+        Entry::Symlink { path, backup } => {
+            match backup {
+                Some(archive) => backup::restore_path(archive, path)?,
+                None if path.is_symlink() => fs::remove_file(path)?,
+                None => {}
+            }
+        }
+        Entry::File { path, backup } => match backup {
+            Some(backup) => {
+                fs::copy(backup, path)?;
+            }
+            None => {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        },
+        Entry::SystemFile { path, backup } => match backup {
+            Some(backup) => privileged::restore_file(path, &fs::read(backup)?)?,
+            None => privileged::remove(path)?,
+        },
+        Entry::Service { name, was_enabled } => {
+            let action = if *was_enabled { "enable" } else { "disable" };
+            privileged::run(&["systemctl", action, name])?;
+        }
+        Entry::DefaultTarget { previous } => privileged::run(&["systemctl", "set-default", previous])?,
+        Entry::Shell { previous } => {
+            log::log_command(&format!("chsh -s {}", previous));
+            if !Command::new("chsh").args(["-s", previous]).status()?.success() {
+                bail!("chsh failed");
+            }
+        }
+    }
+    Ok(())
+}
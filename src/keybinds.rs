@@ -1,27 +1,33 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::{log, ui};
+use crate::{backup, dotfiles, hyprconf, journal, log, manifest, packages, preview, runner, summary, ui};
 
-const KEYBINDS_CONTENT: &str = r#"# Caelestia User Keybinds
+/// How deep `source` lines are followed, against files sourcing each other.
+const MAX_SOURCE_DEPTH: usize = 8;
+
+/// The keybinds file, with `{main_mod}`, `{terminal}`, `{browser}`,
+/// `{file_manager}` and `{launcher}` filled in by [`render`].
+const KEYBINDS_TEMPLATE: &str = r#"# Caelestia User Keybinds
 # Edit this file to customize your keybindings
 # This file is sourced by the main hyprland.conf
 
-$mainMod = SUPER
+$mainMod = {main_mod}
 
 # Applications
-bind = $mainMod, Return, exec, foot
-bind = $mainMod, Space, exec, quickshell -c caelestia launcher
-bind = $mainMod, E, exec, foot -e yazi
-bind = $mainMod, B, exec, firefox
+bind = $mainMod, Return, exec, {terminal}
+bind = $mainMod, Space, exec, {launcher}
+bind = $mainMod, E, exec, {file_manager}
+bind = $mainMod, B, exec, {browser}
 
 # Window management
 bind = $mainMod, W, killactive
 bind = $mainMod, F, fullscreen
 bind = $mainMod, T, togglefloating
 bind = $mainMod, P, pseudo
-bind = $mainMod, J, togglesplit
+bind = $mainMod SHIFT, T, togglesplit
 
 # Focus
 bind = $mainMod, left, movefocus, l
@@ -105,37 +111,695 @@ bind = $mainMod, L, exec, hyprlock
 bind = $mainMod SHIFT, E, exit
 "#;
 
-pub fn setup_keybinds(dry_run: bool) -> Result<()> {
+/// The main modifier and the programs the keybinds launch. Those left `None`
+/// are asked for, or take their default with `--noconfirm`.
+#[derive(Clone, Default)]
+pub struct Choices {
+    pub main_mod: Option<String>,
+    pub terminal: Option<String>,
+    pub browser: Option<String>,
+    pub file_manager: Option<String>,
+    pub launcher: Option<String>,
+    /// A previous Hyprland config whose binds are added to keybinds.conf.
+    pub import: Option<PathBuf>,
+}
+
+/// [`Choices`] with every value filled in.
+struct Resolved {
+    main_mod: String,
+    terminal: String,
+    browser: String,
+    file_manager: String,
+    launcher: String,
+}
+
+/// Browsers looked for on PATH, in order of preference.
+const BROWSERS: &[&str] = &[
+    "firefox",
+    "chromium-browser",
+    "google-chrome-stable",
+    "brave-browser",
+    "librewolf",
+    "epiphany",
+    "qutebrowser",
+];
+
+/// Browsers installed from Flathub, run through flatpak.
+const FLATPAK_BROWSERS: &[&str] = &[
+    "org.mozilla.firefox",
+    "org.chromium.Chromium",
+    "com.google.Chrome",
+    "com.brave.Browser",
+];
+
+/// Graphical file managers looked for on PATH, in order of preference.
+const FILE_MANAGERS: &[&str] = &["thunar", "nautilus", "dolphin", "nemo", "pcmanfm-qt", "pcmanfm"];
+
+/// File managers that run in the terminal.
+const TERMINAL_FILE_MANAGERS: &[&str] = &["yazi", "ranger", "lf", "nnn"];
+
+/// Opens the browser set as default through its desktop entry, whichever
+/// it is. Hyprland runs exec lines with `sh -c`.
+const DEFAULT_BROWSER: &str = r#"gtk-launch "$(xdg-settings get default-web-browser)""#;
+
+/// Opens the home directory in the default file manager.
+const DEFAULT_FILE_MANAGER: &str = "xdg-open ~";
+
+/// The first installed browser, as the command to run it.
+pub(crate) fn detect_browser() -> Option<String> {
+    if let Some(browser) = BROWSERS.iter().find(|browser| which::which(browser).is_ok()) {
+        return Some(browser.to_string());
+    }
+    ["/var/lib/flatpak/exports/bin", "~/.local/share/flatpak/exports/bin"]
+        .iter()
+        .map(|dir| expand_home(dir, Path::new("/")))
+        .find_map(|dir| FLATPAK_BROWSERS.iter().find(|id| dir.join(id).exists()))
+        .map(|id| format!("flatpak run {}", id))
+}
+
+/// The first installed file manager, terminal ones opened in `terminal`.
+fn detect_file_manager(terminal: &str) -> Option<String> {
+    if let Some(manager) = FILE_MANAGERS.iter().find(|manager| which::which(manager).is_ok()) {
+        return Some(manager.to_string());
+    }
+    TERMINAL_FILE_MANAGERS
+        .iter()
+        .find(|manager| which::which(manager).is_ok())
+        .map(|manager| format!("{} -e {}", terminal, manager))
+}
+
+/// Offers to install `package` when no `kind` was found, returning the
+/// command to run it once installed.
+fn offer_install(kind: &str, package: &str, program: &str, dry_run: bool) -> Option<String> {
+    if !ui::prompt(&format!("No {} found. Install {}?", kind, package)) {
+        return None;
+    }
+    log::log_command(&packages::install_command_line(package));
+    if dry_run {
+        ui::success(&format!("Would install {} (dry-run)", package));
+        return Some(program.to_string());
+    }
+    match runner::run(&mut packages::install_command(&[package])) {
+        Ok(output) if output.status.success() => {
+            ui::success(&format!("Installed {}", package));
+            summary::packages_added(vec![package.to_string()]);
+            Some(program.to_string())
+        }
+        Ok(output) => {
+            log::log_error(&String::from_utf8_lossy(&output.stderr));
+            ui::warning(&format!("Could not install {}", package));
+            None
+        }
+        Err(e) => {
+            ui::warning(&format!("Could not install {}: {}", package, e));
+            None
+        }
+    }
+}
+
+impl Choices {
+    /// Fills in the values not chosen yet: the installed browser and file
+    /// manager, or the defaults xdg knows of when there is none.
+    fn defaults(&self) -> Resolved {
+        let terminal = self.terminal.clone().unwrap_or_else(|| "foot".to_string());
+        Resolved {
+            main_mod: self.main_mod.clone().unwrap_or_else(|| "SUPER".to_string()),
+            file_manager: self
+                .file_manager
+                .clone()
+                .or_else(|| detect_file_manager(&terminal))
+                .unwrap_or_else(|| DEFAULT_FILE_MANAGER.to_string()),
+            terminal,
+            browser: self
+                .browser
+                .clone()
+                .or_else(detect_browser)
+                .unwrap_or_else(|| DEFAULT_BROWSER.to_string()),
+            launcher: self
+                .launcher
+                .clone()
+                .unwrap_or_else(|| "quickshell -c caelestia launcher".to_string()),
+        }
+    }
+
+    /// Asks for the values not given in the config file, after offering the
+    /// defaults as they are. Without a browser or file manager installed,
+    /// offers to install Firefox or Thunar first.
+    fn ask(&self, dry_run: bool, noconfirm: bool) -> Resolved {
+        let mut choices = self.clone();
+        if !noconfirm {
+            if choices.browser.is_none() && detect_browser().is_none() {
+                choices.browser = offer_install("web browser", "firefox", "firefox", dry_run);
+            }
+            if choices.file_manager.is_none() && detect_file_manager("foot").is_none() {
+                choices.file_manager = offer_install("file manager", "Thunar", "thunar", dry_run);
+            }
+        }
+        let defaults = choices.defaults();
+        let unset = [&self.main_mod, &self.terminal, &self.browser, &self.file_manager, &self.launcher]
+            .iter()
+            .any(|value| value.is_none());
+        if noconfirm || !unset {
+            return defaults;
+        }
+        let summary = format!(
+            "{} as main key, {}, {}, {}",
+            defaults.main_mod, defaults.terminal, defaults.browser, defaults.file_manager
+        );
+        if ui::prompt(&format!("Use the default keybinds ({})?", summary)) {
+            return defaults;
+        }
+
+        let ask = |chosen: &Option<String>, message: &str, default: &str| match chosen {
+            Some(value) => value.clone(),
+            None => ui::input(message, default)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| default.to_string()),
+        };
+        let main_mod = ask(&choices.main_mod, "Main modifier key (SUPER, ALT, CTRL)", &defaults.main_mod).to_uppercase();
+        let terminal = ask(&choices.terminal, "Terminal", &defaults.terminal);
+        // Terminal file managers follow the terminal unless one was chosen
+        let file_manager_default = match choices.file_manager {
+            Some(_) => defaults.file_manager.clone(),
+            None => detect_file_manager(&terminal).unwrap_or_else(|| DEFAULT_FILE_MANAGER.to_string()),
+        };
+        Resolved {
+            main_mod,
+            browser: ask(&choices.browser, "Browser", &defaults.browser),
+            file_manager: ask(&choices.file_manager, "File manager", &file_manager_default),
+            launcher: ask(&choices.launcher, "App launcher", &defaults.launcher),
+            terminal,
+        }
+    }
+}
+
+/// The keybinds file for `resolved`.
+fn render(resolved: &Resolved) -> String {
+    KEYBINDS_TEMPLATE
+        .replace("{main_mod}", &resolved.main_mod)
+        .replace("{terminal}", &resolved.terminal)
+        .replace("{browser}", &resolved.browser)
+        .replace("{file_manager}", &resolved.file_manager)
+        .replace("{launcher}", &resolved.launcher)
+}
+
+/// Writes the user keybinds file for `choices`, asking for what they leave
+/// open, imports the binds of a previous config when asked to, then looks
+/// for keys bound twice and writes a cheat sheet.
+pub fn setup_keybinds(choices: &Choices, dry_run: bool, noconfirm: bool) -> Result<()> {
     let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
     let hypr_dir = config_dir.join("hypr");
     let keybinds_path = hypr_dir.join("keybinds.conf");
 
     ui::info("Setting up user keybinds...");
 
+    // Don't overwrite existing keybinds
+    if keybinds_path.exists() {
+        ui::warning("keybinds.conf already exists, skipping");
+        if let Some(ref import) = choices.import {
+            import_binds(import, &hypr_dir, dry_run, noconfirm)?;
+        } else {
+            summary::skipped();
+        }
+        check_conflicts(&hypr_dir, dry_run, noconfirm)?;
+        return write_cheat_sheet(&hypr_dir, dry_run);
+    }
+
+    let resolved = choices.ask(dry_run, noconfirm);
+    log::log(&format!(
+        "Keybinds: main mod {}, terminal {}, browser {}, file manager {}, launcher {}",
+        resolved.main_mod, resolved.terminal, resolved.browser, resolved.file_manager, resolved.launcher
+    ));
+    let content = render(&resolved);
+
     if dry_run {
+        preview::file(&keybinds_path, content.as_bytes());
+        add_source_line(&hypr_dir, dry_run)?;
         ui::success("Would create keybinds.conf (dry-run)");
+        if let Some(ref import) = choices.import {
+            ui::success(&format!("Would import the keybinds of {} (dry-run)", import.display()));
+        }
         return Ok(());
     }
 
     fs::create_dir_all(&hypr_dir)?;
+    journal::write_file(&keybinds_path, content)?;
+    hyprconf::check(&keybinds_path);
+    ui::success("Created keybinds.conf");
+    log::log("Created user keybinds file");
 
-    // Don't overwrite existing keybinds
-    if keybinds_path.exists() {
-        ui::warning("keybinds.conf already exists, skipping");
+    // Add source line to hyprland.conf if it exists and doesn't have it
+    add_source_line(&hypr_dir, dry_run)?;
+
+    if let Some(ref import) = choices.import {
+        import_binds(import, &hypr_dir, dry_run, noconfirm)?;
+    }
+    check_conflicts(&hypr_dir, dry_run, noconfirm)?;
+    write_cheat_sheet(&hypr_dir, dry_run)
+}
+
+/// A bind line and where it is.
+struct Bind {
+    file: PathBuf,
+    line: usize,
+    text: String,
+    /// The comment heading the group of lines the bind is in.
+    section: String,
+    /// What the bind does, in words.
+    description: String,
+    /// The dispatcher and its arguments, for telling whether two binds do
+    /// the same.
+    action: String,
+    /// The line with its variables substituted, to copy into another file.
+    resolved: String,
+}
+
+/// What a bind is triggered by: its submap, modifiers and key.
+type Trigger = (String, String, String);
+
+/// Collects the binds of `path` and the files it sources, in the order
+/// Hyprland reads them. `vars` holds the `$variables` defined so far.
+fn load_binds(
+    path: &Path,
+    vars: &mut BTreeMap<String, String>,
+    submap: &mut String,
+    depth: usize,
+    binds: &mut Vec<(Trigger, Bind)>,
+) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let mut section = String::new();
+    for (index, raw) in content.lines().enumerate() {
+        // A comment of its own names the lines below it, unless it is a
+        // commented-out setting
+        if let Some(comment) = raw.trim().strip_prefix('#') {
+            if !comment.starts_with('#') && !comment.contains('=') {
+                section = comment.trim().to_string();
+            }
+            continue;
+        }
+        let text = hyprconf::strip_comment(raw);
+        let text = text.trim();
+        let Some((key, value)) = text.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+
+        if let Some(name) = key.strip_prefix('$') {
+            let value = substitute(value, vars);
+            vars.insert(name.to_string(), value);
+        } else if key == "submap" {
+            *submap = if value == "reset" { String::new() } else { value.to_string() };
+        } else if key == "source" && depth < MAX_SOURCE_DEPTH && !value.contains('*') {
+            let sourced = expand_home(&substitute(value, vars), path.parent().unwrap_or(Path::new("/")));
+            load_binds(&sourced, vars, submap, depth + 1, binds);
+        } else if key == "unbind" {
+            let value = substitute(value, vars);
+            let mut fields = value.splitn(2, ',');
+            let (Some(mods), Some(bound)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let trigger = (submap.clone(), normalize_mods(mods), bound.trim().to_lowercase());
+            binds.retain(|(bound, _)| *bound != trigger);
+        } else if let Some(flags) = key.strip_prefix("bind") {
+            let value = substitute(value, vars);
+            let mut fields = value.splitn(3, ',');
+            let (Some(mods), Some(bound)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let trigger = (submap.clone(), normalize_mods(mods), bound.trim().to_lowercase());
+            let action = fields.next().unwrap_or("");
+            binds.push((
+                trigger,
+                Bind {
+                    file: path.to_path_buf(),
+                    line: index + 1,
+                    text: raw.trim().to_string(),
+                    section: section.clone(),
+                    description: describe(flags, action),
+                    action: action.split(',').map(str::trim).collect::<Vec<_>>().join(", "),
+                    resolved: format!("{} = {}", key, value),
+                },
+            ));
+        }
+    }
+}
+
+/// Puts the dispatcher and arguments of a bind into words. Binds with the
+/// `d` flag carry their own description.
+fn describe(flags: &str, action: &str) -> String {
+    let (first, rest) = action.split_once(',').unwrap_or((action, ""));
+    let (first, rest) = (first.trim(), rest.trim());
+    if flags.contains('d') {
+        return first.to_string();
+    }
+    if flags.contains('m') {
+        return match first {
+            "movewindow" => "Drag to move the window".to_string(),
+            "resizewindow" => "Drag to resize the window".to_string(),
+            other => other.to_string(),
+        };
+    }
+    let direction = match rest {
+        "l" => "left",
+        "r" => "right",
+        "u" => "up",
+        "d" => "down",
+        other => other,
+    };
+    match first {
+        "exec" => format!("Run {}", rest),
+        "killactive" => "Close the window".to_string(),
+        "fullscreen" => "Toggle fullscreen".to_string(),
+        "togglefloating" => "Toggle floating".to_string(),
+        "pseudo" => "Toggle pseudotiling".to_string(),
+        "togglesplit" => "Toggle the split direction".to_string(),
+        "movefocus" => format!("Focus {}", direction),
+        "movewindow" => format!("Move the window {}", direction),
+        "resizeactive" => format!("Resize the window by {}", rest),
+        "workspace" => match rest {
+            "e+1" => "Next workspace".to_string(),
+            "e-1" => "Previous workspace".to_string(),
+            _ => format!("Go to workspace {}", rest),
+        },
+        "movetoworkspace" => format!("Move the window to workspace {}", rest),
+        "exit" => "Exit Hyprland".to_string(),
+        "" => String::new(),
+        other if rest.is_empty() => other.to_string(),
+        other => format!("{} {}", other, rest),
+    }
+}
+
+/// The keys of `trigger` as they are written on a cheat sheet, such as
+/// `Super + Shift + E`.
+fn keys((submap, mods, key): &Trigger) -> String {
+    let mut parts: Vec<String> = mods
+        .split('+')
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            let mut chars = m.chars();
+            chars.next().into_iter().chain(chars.flat_map(char::to_lowercase)).collect()
+        })
+        .collect();
+    let mut chars = key.chars();
+    parts.push(chars.next().into_iter().flat_map(char::to_uppercase).chain(chars).collect());
+    match submap.as_str() {
+        "" => parts.join(" + "),
+        submap => format!("{} (in {})", parts.join(" + "), submap),
+    }
+}
+
+/// Every bind Hyprland would read: hyprland.conf and what it sources, and
+/// keybinds.conf when it is not sourced yet.
+fn all_binds(hypr_dir: &Path) -> Vec<(Trigger, Bind)> {
+    let keybinds_path = hypr_dir.join("keybinds.conf");
+    let hyprland_conf = hypr_dir.join("hyprland.conf");
+    let root = if hyprland_conf.exists() { &hyprland_conf } else { &keybinds_path };
+
+    let mut binds = Vec::new();
+    load_binds(root, &mut BTreeMap::new(), &mut String::new(), 0, &mut binds);
+    // Not sourced (yet), but it will be
+    if !binds.iter().any(|(_, bind)| bind.file == keybinds_path) {
+        load_binds(&keybinds_path, &mut BTreeMap::new(), &mut String::new(), 0, &mut binds);
+    }
+    binds
+}
+
+/// Appends the binds of a previous Hyprland config at `path` (and the files
+/// it sources) to keybinds.conf. Binds caelestia already has are left out;
+/// for keys caelestia binds to something else the user picks which to keep,
+/// and with `noconfirm` caelestia's is kept. Taking the old one adds an
+/// `unbind` line before it, which works for binds in the dotfiles too
+/// since keybinds.conf is sourced last.
+fn import_binds(path: &Path, hypr_dir: &Path, dry_run: bool, noconfirm: bool) -> Result<()> {
+    if !path.exists() {
+        bail!("Cannot import keybinds: {} does not exist", path.display());
+    }
+    let keybinds_path = hypr_dir.join("keybinds.conf");
+    let dotfiles_dir = dotfiles::dotfiles_dir();
+    if fs::canonicalize(path).is_ok_and(|real| real.starts_with(&dotfiles_dir)) {
+        bail!(
+            "Cannot import keybinds: {} is now the caelestia config. Import a copy of the old one, which the symlinks step archived in {}",
+            path.display(),
+            backup::backups_dir().display()
+        );
+    }
+    ui::info(&format!("Importing keybinds from {}...", path.display()));
+
+    let mut imported = Vec::new();
+    load_binds(path, &mut BTreeMap::new(), &mut String::new(), 0, &mut imported);
+    let existing = all_binds(hypr_dir);
+
+    let mut lines = Vec::new();
+    let (mut kept_ours, mut same) = (0, 0);
+    for (trigger, bind) in &imported {
+        if !trigger.0.is_empty() {
+            // Submaps need their enter and reset lines, which are not binds
+            log::log(&format!("Not importing {} from submap {}", bind.text, trigger.0));
+            continue;
+        }
+        let taken: Vec<&Bind> = existing.iter().filter(|(t, _)| t == trigger).map(|(_, b)| b).collect();
+        if taken.iter().any(|ours| ours.action == bind.action) {
+            same += 1;
+            continue;
+        }
+        if let Some(ours) = taken.first() {
+            let keep_old = !noconfirm
+                && !dry_run
+                && ui::select(
+                    &format!("{} is bound by caelestia and your old config", keys(trigger)),
+                    &[
+                        &format!("Keep caelestia's: {}", ours.description),
+                        &format!("Keep yours: {}", bind.description),
+                    ],
+                ) == Some(1);
+            if !keep_old {
+                kept_ours += 1;
+                continue;
+            }
+            let mut fields = bind.resolved.split_once('=').map(|(_, value)| value).unwrap_or("").splitn(3, ',');
+            lines.push(format!(
+                "unbind = {}, {}",
+                fields.next().unwrap_or("").trim(),
+                fields.next().unwrap_or("").trim()
+            ));
+        }
+        lines.push(bind.resolved.clone());
+    }
+
+    if lines.is_empty() {
+        ui::success(&format!(
+            "Nothing to import from {} ({} binds already there, {} keys kept as caelestia binds them)",
+            path.display(),
+            same,
+            kept_ours
+        ));
+        return Ok(());
+    }
+    let count = lines.iter().filter(|line| !line.starts_with("unbind")).count();
+    let content = format!(
+        "{}\n\n# Imported from {}\n{}\n",
+        fs::read_to_string(&keybinds_path).unwrap_or_default().trim_end(),
+        path.display(),
+        lines.join("\n")
+    );
+    if dry_run {
+        preview::file(&keybinds_path, content.as_bytes());
+        ui::success(&format!("Would import {} keybinds from {} (dry-run)", count, path.display()));
         return Ok(());
     }
+    journal::write_file(&keybinds_path, content)?;
+    hyprconf::check(&keybinds_path);
+    ui::success(&format!("Imported {} keybinds from {}", count, path.display()));
+    if kept_ours > 0 {
+        ui::info(&format!("{} keys keep the action caelestia binds them to", kept_ours));
+    }
+    log::log(&format!("Imported {} keybinds from {:?}", count, path));
+    Ok(())
+}
 
-    fs::write(&keybinds_path, KEYBINDS_CONTENT)?;
-    ui::success("Created keybinds.conf");
-    log::log("Created user keybinds file");
+/// Where the cheat sheet generated from the binds is written.
+pub fn cheat_sheet_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia/keybinds-cheatsheet.txt")
+}
 
-    // Add source line to hyprland.conf if it exists and doesn't have it
-    add_source_line(&hypr_dir)?;
+/// The keys and description of every bind in the Hyprland config, in the
+/// order Hyprland reads them.
+pub fn cheat_sheet() -> Vec<(String, String)> {
+    let hypr_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config")).join("hypr");
+    all_binds(&hypr_dir)
+        .iter()
+        .map(|(trigger, bind)| (keys(trigger), bind.description.clone()))
+        .collect()
+}
+
+/// Writes the binds of the Hyprland config, grouped under their comments,
+/// to [`cheat_sheet_path`].
+fn write_cheat_sheet(hypr_dir: &Path, dry_run: bool) -> Result<()> {
+    let path = cheat_sheet_path();
+    if dry_run {
+        ui::success(&format!("Would write the keybind cheat sheet to {} (dry-run)", path.display()));
+        return Ok(());
+    }
+    let binds = all_binds(hypr_dir);
+    if binds.is_empty() {
+        return Ok(());
+    }
+
+    let width = binds.iter().map(|(trigger, _)| keys(trigger).chars().count()).max().unwrap_or(0);
+    let mut content = format!(
+        "Hyprland keybinds\nGenerated by caelestia-installer from {}\n",
+        hypr_dir.join("hyprland.conf").display()
+    );
+    let mut heading = None;
+    for (trigger, bind) in &binds {
+        let group = (&bind.file, &bind.section);
+        if heading != Some(group) {
+            let title = match bind.section.as_str() {
+                "" => file_name(&bind.file),
+                section => format!("{} ({})", section, file_name(&bind.file)),
+            };
+            content += &format!("\n{}\n", title);
+            heading = Some(group);
+        }
+        content += &format!("  {:<width$}  {}\n", keys(trigger), bind.description, width = width);
+    }
 
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&path, content)?;
+    ui::success(&format!("Wrote a cheat sheet of {} keybinds to {}", binds.len(), path.display()));
     Ok(())
 }
 
-fn add_source_line(hypr_dir: &PathBuf) -> Result<()> {
+/// Replaces the `$variables` in `value`, longest names first so `$mainMod`
+/// is not taken for `$main`.
+fn substitute(value: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut names: Vec<&String> = vars.keys().collect();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let mut value = value.to_string();
+    for name in names {
+        value = value.replace(&format!("${}", name), &vars[name]);
+    }
+    value
+}
+
+fn expand_home(path: &str, dir: &Path) -> PathBuf {
+    match path.strip_prefix("~/").or_else(|| path.strip_prefix("$HOME/")) {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => dir.join(path),
+    }
+}
+
+/// Modifiers in one spelling and order, so `SUPER SHIFT` and `shift_super`
+/// compare equal.
+fn normalize_mods(mods: &str) -> String {
+    let mut mods: Vec<&str> = mods
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '+')
+        .filter(|m| !m.is_empty())
+        .map(|m| match m.to_uppercase().as_str() {
+            "SUPER" | "WIN" | "LOGO" | "MOD4" | "META" => "SUPER",
+            "CTRL" | "CONTROL" => "CTRL",
+            "ALT" | "MOD1" => "ALT",
+            "SHIFT" => "SHIFT",
+            _ => "OTHER",
+        })
+        .collect();
+    mods.sort_by_key(|m| ["SUPER", "CTRL", "ALT", "SHIFT", "OTHER"].iter().position(|known| known == m));
+    mods.dedup();
+    mods.join("+")
+}
+
+/// Finds keys bound more than once across hyprland.conf and the files it
+/// sources. Each is reported with the lines binding it; when one of them is
+/// in keybinds.conf, the user can pick the bind to keep and the others in
+/// keybinds.conf are commented out. The dotfiles' own files are never edited.
+fn check_conflicts(hypr_dir: &Path, dry_run: bool, noconfirm: bool) -> Result<()> {
+    let keybinds_path = hypr_dir.join("keybinds.conf");
+    let mut by_trigger: BTreeMap<Trigger, Vec<Bind>> = BTreeMap::new();
+    for (trigger, bind) in all_binds(hypr_dir) {
+        by_trigger.entry(trigger).or_default().push(bind);
+    }
+    let conflicts: Vec<(Trigger, Vec<Bind>)> = by_trigger.into_iter().filter(|(_, binds)| binds.len() > 1).collect();
+    if conflicts.is_empty() {
+        log::debug("No keybind is bound twice");
+        return Ok(());
+    }
+
+    let mut disable: Vec<usize> = Vec::new();
+    for (trigger, binds) in &conflicts {
+        let combo = keys(trigger);
+        ui::warning(&format!("{} is bound {} times:", combo, binds.len()));
+        for bind in binds {
+            ui::info(&format!("  {}:{}: {}", bind.file.display(), bind.line, bind.text));
+        }
+
+        let ours: Vec<&Bind> = binds.iter().filter(|bind| bind.file == keybinds_path).collect();
+        if ours.is_empty() || dry_run || noconfirm {
+            continue;
+        }
+        let items: Vec<String> = binds
+            .iter()
+            .map(|bind| format!("{}:{}: {}", file_name(&bind.file), bind.line, bind.text))
+            .chain(std::iter::once("Keep all of them".to_string()))
+            .collect();
+        let items: Vec<&str> = items.iter().map(String::as_str).collect();
+        let Some(keep) = ui::select(&format!("Which bind should {} keep?", combo), &items) else {
+            continue;
+        };
+        if keep == binds.len() {
+            continue;
+        }
+        disable.extend(
+            binds
+                .iter()
+                .enumerate()
+                .filter(|(index, bind)| *index != keep && bind.file == keybinds_path)
+                .map(|(_, bind)| bind.line),
+        );
+    }
+
+    if disable.is_empty() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(&keybinds_path)?;
+    let content: String = content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| match disable.contains(&(index + 1)) {
+            true => format!("# {} # disabled: key bound elsewhere\n", line),
+            false => format!("{}\n", line),
+        })
+        .collect();
+    journal::write_file(&keybinds_path, content)?;
+    ui::success(&format!("Commented out {} conflicting binds in keybinds.conf", disable.len()));
+    log::log(&format!("Disabled keybinds.conf lines {:?}", disable));
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+const SOURCE_LINE: &str = "source = ~/.config/hypr/keybinds.conf";
+
+/// The source line as the manifest records it.
+pub(crate) fn source_line_entry(hypr_dir: &Path) -> manifest::Line {
+    manifest::Line {
+        file: hypr_dir.join("hyprland.conf"),
+        comment: "User keybinds".to_string(),
+        line: SOURCE_LINE.to_string(),
+    }
+}
+
+fn add_source_line(hypr_dir: &Path, dry_run: bool) -> Result<()> {
     let hyprland_conf = hypr_dir.join("hyprland.conf");
 
     if !hyprland_conf.exists() {
@@ -143,7 +807,11 @@ fn add_source_line(hypr_dir: &PathBuf) -> Result<()> {
     }
 
     let content = fs::read_to_string(&hyprland_conf)?;
-    let source_line = "source = ~/.config/hypr/keybinds.conf";
+    let source_line = SOURCE_LINE;
+    if !dry_run {
+        let entry = source_line_entry(hypr_dir);
+        manifest::record_line(&entry.file, &entry.comment, &entry.line);
+    }
 
     if content.contains(source_line) {
         return Ok(());
@@ -152,10 +820,39 @@ fn add_source_line(hypr_dir: &PathBuf) -> Result<()> {
     ui::info("Adding keybinds source to hyprland.conf...");
 
     let new_content = format!("{}\n\n# User keybinds\n{}\n", content, source_line);
-    fs::write(&hyprland_conf, new_content)?;
+    if dry_run {
+        preview::file(&hyprland_conf, new_content.as_bytes());
+        return Ok(());
+    }
+    journal::write_file(&hyprland_conf, new_content)?;
+    hyprconf::check(&hyprland_conf);
 
     ui::success("Added keybinds source to hyprland.conf");
     log::log("Added source line to hyprland.conf");
 
     Ok(())
 }
+
+/// Removes the keybinds file if it is still the generated one, along with the
+/// source line added to hyprland.conf.
+pub fn remove_keybinds() -> Result<()> {
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+    let hypr_dir = config_dir.join("hypr");
+    let keybinds_path = hypr_dir.join("keybinds.conf");
+
+    if fs::read_to_string(&keybinds_path).is_ok_and(|content| content == render(&Choices::default().defaults())) {
+        fs::remove_file(&keybinds_path)?;
+        log::log("Removed user keybinds file");
+    }
+
+    let hyprland_conf = hypr_dir.join("hyprland.conf");
+    if let Ok(content) = fs::read_to_string(&hyprland_conf) {
+        let block = format!("\n\n# User keybinds\n{}\n", SOURCE_LINE);
+        if content.contains(&block) {
+            fs::write(&hyprland_conf, content.replace(&block, ""))?;
+            log::log("Removed source line from hyprland.conf");
+        }
+    }
+
+    Ok(())
+}
@@ -1,11 +1,60 @@
+pub mod backup;
 pub mod checks;
+pub mod checksums;
 pub mod cli;
+pub mod clipboard;
+pub mod config;
+pub mod custom;
+pub mod desktops;
+pub mod display_manager;
+pub mod dnf;
+pub mod doctor;
 pub mod dotfiles;
+pub mod download;
+pub mod engine;
+pub mod flatpak;
+pub mod gpu;
 pub mod greetd;
+pub mod health;
+pub mod hooks;
+pub mod hyprconf;
+pub mod idle;
+pub mod input;
+pub mod install;
+pub mod interrupt;
+pub mod journal;
 pub mod keybinds;
+pub mod localrpm;
 pub mod log;
+pub mod manifest;
+pub mod merge;
+pub mod mime;
+pub mod net;
+pub mod offline;
 pub mod packages;
+pub mod portals;
+pub mod prebuilt;
+pub mod prefix;
+pub mod preview;
+pub mod privileged;
+pub mod report;
 pub mod repos;
+pub mod runner;
+pub mod sddm;
+pub mod selinux;
 pub mod shell;
+pub mod state;
+pub mod sudo;
+pub mod summary;
 pub mod system;
+pub mod theming;
+pub mod timings;
+pub mod toolbox;
+#[cfg(feature = "tui")]
+pub mod tui;
 pub mod ui;
+pub mod update;
+pub mod uwsm;
+pub mod versions;
+pub mod vm;
+pub mod wallpapers;
@@ -0,0 +1,245 @@
+//! Local RPMs of the components built from source. `--rpm` packages
+//! Quickshell and caelestia-shell, `--cli-rpm` caelestia-cli: a generated
+//! spec is built with rpmbuild and the package installed with dnf, so rpm
+//! knows every file and `dnf remove` or a newer package replaces them cleanly.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{log, packages, runner, summary, system, toolbox, ui};
+
+const SPEC_TEMPLATE: &str = r#"Name:           {name}
+Version:        {version}
+Release:        1%{?dist}
+Summary:        {summary}
+License:        {license}
+URL:            {url}
+# The files were built and installed already, they are only packaged here
+AutoReqProv:    no
+
+%global debug_package %{nil}
+%global _build_id_links none
+%global __os_install_post %{nil}
+%global __arch_install_post %{nil}
+
+%description
+{summary}, packaged locally by caelestia-installer from commit {commit}.
+
+%install
+cp -a {staging}/. %{buildroot}/
+
+%files
+{files}
+"#;
+
+/// Whether `--rpm` packages Quickshell and caelestia-shell.
+static ENABLED: Mutex<bool> = Mutex::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    *ENABLED.lock().unwrap() = enabled;
+}
+
+/// Whether to package `name`: `--rpm` asked for it, or it was installed as a
+/// package before so an update keeps it one. Builds in the toolbox, on
+/// rpm-ostree or with `--container-build`, are left to `cmake --install`.
+pub fn wanted(name: &str) -> bool {
+    if toolbox::active() {
+        if *ENABLED.lock().unwrap() {
+            ui::warning(&format!("Local RPMs are not built in the toolbox, installing {} with cmake", name));
+        }
+        return false;
+    }
+    *ENABLED.lock().unwrap() || installed(name)
+}
+
+pub fn installed(name: &str) -> bool {
+    Command::new("rpm")
+        .args(["-q", name])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Installs rpm-build when rpmbuild is missing.
+pub fn ensure_rpmbuild() -> Result<()> {
+    if which::which("rpmbuild").is_ok() {
+        return Ok(());
+    }
+    ui::info("Installing rpm-build...");
+    log::log_command(&packages::install_command_line("rpm-build"));
+    let output = runner::run(&mut packages::install_command(&["rpm-build"]))?;
+    if !output.status.success() {
+        bail!("Failed to install rpm-build, which building local packages needs");
+    }
+    summary::packages_added(vec!["rpm-build".to_string()]);
+    Ok(())
+}
+
+/// A snapshot version for the checkout in `source`, the latest tag followed
+/// by the commit date and hash so each newer commit upgrades the package,
+/// and the short hash.
+pub fn snapshot_version(source: &Path) -> (String, String) {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(source)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let base = git(&["describe", "--tags", "--abbrev=0"])
+        .map(|tag| tag.trim_start_matches('v').replace('-', "."))
+        .unwrap_or_else(|| "0".to_string());
+    let date = git(&["log", "-1", "--format=%cd", "--date=format:%Y%m%d"]).unwrap_or_else(|| "0".to_string());
+    (format!("{}^{}git{}", base, date, commit), commit)
+}
+
+/// Where the spec of `name` is built.
+pub fn topdir(name: &str) -> PathBuf {
+    system::build_root().join(format!("{}-rpm", name))
+}
+
+/// Builds `spec` with rpmbuild in `topdir` and returns the package.
+pub fn build(name: &str, spec: &str, topdir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(topdir)?;
+    let spec_path = topdir.join(format!("{}.spec", name));
+    fs::write(&spec_path, spec)?;
+
+    ui::info(&format!("Building the {} RPM...", name));
+    let define = format!("_topdir {}", topdir.display());
+    log::log_command(&format!("rpmbuild -bb --define '{}' {}", define, spec_path.display()));
+    let output = runner::run(Command::new("rpmbuild").args(["-bb", "--define", &define]).arg(&spec_path))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to build the {} RPM", name);
+    }
+
+    let rpms = topdir.join("RPMS");
+    let found = fs::read_dir(&rpms)?
+        .flatten()
+        .filter_map(|arch| fs::read_dir(arch.path()).ok())
+        .flat_map(|files| files.flatten().map(|file| file.path()))
+        .find(|path| path.extension().is_some_and(|ext| ext == "rpm"));
+    match found {
+        Some(rpm) => Ok(rpm),
+        None => bail!("rpmbuild made no package in {}", rpms.display()),
+    }
+}
+
+/// Installs the package `rpm` with dnf, which upgrades an older build.
+pub fn install(name: &str, rpm: &Path) -> Result<()> {
+    log::log_command(&packages::install_command_line(&rpm.to_string_lossy()));
+    let output = runner::run(&mut packages::install_command(&[rpm]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install {}", rpm.display());
+    }
+    summary::packages_added(vec![name.to_string()]);
+    log::log(&format!("{} installed with dnf from {}", name, rpm.display()));
+    Ok(())
+}
+
+/// A CMake project built in `build_dir` from the checkout in `source`,
+/// packaged instead of installed with `cmake --install`.
+pub struct CmakeProject<'a> {
+    pub name: &'a str,
+    pub summary: &'a str,
+    pub license: &'a str,
+    pub url: &'a str,
+    pub source: &'a Path,
+    pub build_dir: &'a Path,
+}
+
+impl CmakeProject<'_> {
+    /// Installs the build into a staging directory, packages every file in
+    /// it and installs the package.
+    pub fn package(&self) -> Result<()> {
+        ensure_rpmbuild()?;
+        let topdir = topdir(self.name);
+        fs::remove_dir_all(&topdir).ok();
+        let staging = topdir.join("staging");
+
+        let line = format!("DESTDIR={} cmake --install {}", staging.display(), self.build_dir.display());
+        log::log_command(&line);
+        let output = Command::new("cmake")
+            .arg("--install")
+            .arg(self.build_dir)
+            .env("DESTDIR", &staging)
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Failed to stage {} for packaging", self.name);
+        }
+
+        let files = file_list(self.name, &staging)?;
+        if files.is_empty() {
+            bail!("cmake --install put nothing in {}", staging.display());
+        }
+        let (version, commit) = snapshot_version(self.source);
+        let spec = SPEC_TEMPLATE
+            .replace("{name}", self.name)
+            .replace("{version}", &version)
+            .replace("{summary}", self.summary)
+            .replace("{license}", self.license)
+            .replace("{url}", self.url)
+            .replace("{commit}", &commit)
+            .replace("{staging}", &staging.to_string_lossy())
+            .replace("{files}", &files.join("\n"));
+
+        let rpm = build(self.name, &spec, &topdir)?;
+        install(self.name, &rpm)?;
+        ui::success(&format!("Installed {} {} as a package", self.name, version));
+        Ok(())
+    }
+}
+
+/// The `%files` lines for what is in `staging`: every file and symlink, and
+/// the directories no other package owns, so removing the package leaves
+/// none behind.
+fn file_list(name: &str, staging: &Path) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut pending = vec![staging.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            let Ok(installed) = path.strip_prefix(staging) else {
+                continue;
+            };
+            let installed = Path::new("/").join(installed);
+            let quoted = format!("\"{}\"", installed.display().to_string().replace('%', "%%"));
+            if entry.file_type()?.is_dir() {
+                if owned_by(name, &installed) {
+                    lines.push(format!("%dir {}", quoted));
+                }
+                pending.push(path);
+            } else {
+                lines.push(quoted);
+            }
+        }
+    }
+    lines.sort();
+    Ok(lines)
+}
+
+/// Whether the package should own `dir`: nothing has it yet, or an earlier
+/// build of the package does.
+fn owned_by(name: &str, dir: &Path) -> bool {
+    if !dir.exists() {
+        return true;
+    }
+    Command::new("rpm")
+        .args(["-qf", "--qf", "%{NAME}\n"])
+        .arg(dir)
+        .output()
+        .is_ok_and(|output| {
+            output.status.success() && String::from_utf8_lossy(&output.stdout).lines().all(|owner| owner == name)
+        })
+}
@@ -1,59 +1,192 @@
-use anyhow::Result;
+//! The install log. Each entry carries an RFC 3339 timestamp, a level and
+//! the step it was written for, and each step's entries are framed by
+//! section markers. `--log-level` drops entries below a level; by default
+//! everything, including the output of builds, is kept.
+
+use anyhow::{bail, Result};
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
-use crate::ui;
+use crate::{preview, ui};
 
 static LOG_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
 
-pub fn init() -> Result<PathBuf> {
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("caelestia-installer");
+static LEVEL: Mutex<Level> = Mutex::new(Level::Debug);
 
-    fs::create_dir_all(&cache_dir)?;
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    /// Output of the commands the installer runs.
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
 
-    let log_path = cache_dir.join("install.log");
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+pub fn parse_level(level: &str) -> Result<Level> {
+    match level.to_lowercase().as_str() {
+        "debug" => Ok(Level::Debug),
+        "info" => Ok(Level::Info),
+        "warn" | "warning" => Ok(Level::Warn),
+        "error" => Ok(Level::Error),
+        _ => bail!("unknown log level {:?}, expected debug, info, warn or error", level),
+    }
+}
+
+pub fn set_level(level: Level) {
+    *LEVEL.lock().unwrap() = level;
+}
+
+/// Directory holding the install log and other run artifacts.
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer")
+}
+
+pub fn init() -> Result<PathBuf> {
+    let log_path = open()?;
 
     // Clear previous log
     fs::write(&log_path, "")?;
 
+    Ok(log_path)
+}
+
+/// Logs to the install log after what is already in it, for the
+/// subcommands that run after an install, so `doctor` or `repair` after a
+/// failure keep the log `report-bug` collects.
+pub fn init_append() -> Result<PathBuf> {
+    let log_path = open()?;
+    OpenOptions::new().create(true).append(true).open(&log_path)?;
+    Ok(log_path)
+}
+
+fn open() -> Result<PathBuf> {
+    let cache_dir = cache_dir();
+
+    fs::create_dir_all(&cache_dir)?;
+
+    let log_path = cache_dir.join("install.log");
+
     *LOG_FILE.lock().unwrap() = Some(log_path.clone());
 
     Ok(log_path)
 }
 
-pub fn log(message: &str) {
+/// Appends an entry, `kind` naming what structured entries hold.
+fn write(level: Level, kind: Option<&str>, message: &str) {
+    if level < *LEVEL.lock().unwrap() {
+        return;
+    }
     if let Some(ref path) = *LOG_FILE.lock().unwrap() {
         if let Ok(mut file) = OpenOptions::new().append(true).open(path) {
-            let timestamp = chrono_lite_timestamp();
-            let _ = writeln!(file, "[{}] {}", timestamp, message);
+            let mut line = format!("{} {:<5}", timestamp(), level.name());
+            if let Some(step) = ui::step_label() {
+                line += &format!(" [{}]", step);
+            }
+            if let Some(kind) = kind {
+                line += &format!(" {}:", kind);
+            }
+            let _ = writeln!(file, "{} {}", line, message);
         }
     }
 }
 
+pub fn debug(message: &str) {
+    write(Level::Debug, None, message);
+}
+
+pub fn log(message: &str) {
+    write(Level::Info, None, message);
+}
+
+pub fn warn(message: &str) {
+    write(Level::Warn, None, message);
+}
+
+pub fn log_error(error: &str) {
+    write(Level::Error, None, error.trim_end());
+}
+
 pub fn log_command(command: &str) {
-    log(&format!("CMD: {}", command));
+    preview::command(command);
+    ui::command(command);
+    write(Level::Info, Some("cmd"), command);
 }
 
 pub fn log_output(output: &str) {
     for line in output.lines() {
-        log(&format!("OUT: {}", line));
+        write(Level::Debug, Some("out"), line);
     }
 }
 
-pub fn log_error(error: &str) {
-    log(&format!("ERR: {}", error));
+/// Logs a line a running command printed on `stream` (stdout or stderr).
+pub fn log_stream(stream: &str, line: &str) {
+    write(Level::Debug, Some(stream), line);
 }
 
-fn chrono_lite_timestamp() -> String {
+/// Marks where the entries of step `id` begin.
+pub fn begin_section(id: &str) {
+    write(Level::Info, None, &format!("===== {} =====", id));
+}
+
+/// Marks where the entries of step `id` end, and how it went.
+pub fn end_section(id: &str, succeeded: bool) {
+    let (level, outcome) = if succeeded {
+        (Level::Info, "done")
+    } else {
+        (Level::Error, "failed")
+    };
+    write(level, None, &format!("===== {} {} =====", id, outcome));
+}
+
+/// The current UTC time in RFC 3339 format, e.g. `2025-01-31T18:04:05Z`.
+pub fn timestamp() -> String {
     use std::time::{SystemTime, UNIX_EPOCH};
-    let duration = SystemTime::now()
+    let secs = SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    format!("{}", duration.as_secs())
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let (days, time) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian date
+/// (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
 }
 
 pub fn show_recent_logs(lines: usize) {
@@ -64,7 +197,7 @@ pub fn show_recent_logs(lines: usize) {
 
             ui::info(&format!("Recent log entries (from {:?}):", path));
             for line in &log_lines[start..] {
-                println!("  {}", line);
+                ui::block(&format!("  {}", line));
             }
         }
     }
@@ -1,36 +1,550 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use caelestia_installer::{checks, cli, dotfiles, greetd, keybinds, log, packages, repos, shell, ui};
+use caelestia_installer::display_manager::{self, DisplayManager};
+use caelestia_installer::packages::{self, Profile};
+use caelestia_installer::shell::Shell;
+use caelestia_installer::{backup, checks, cli, config, doctor, download, idle, install, interrupt, journal, keybinds, localrpm, log, manifest, mime, net, offline, prebuilt, prefix, preview, privileged, report, repos, runner, shell, sudo, summary, system, toolbox, ui, update, versions};
 
 #[derive(Parser)]
 #[command(name = "caelestia-installer")]
 #[command(about = "Installer for Caelestia Hyprland dotfiles on Fedora")]
 #[command(version)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Show what would happen without making changes
-    #[arg(long)]
+    #[arg(long, global = true)]
     dry_run: bool,
 
     /// Skip all confirmation prompts
     #[arg(long)]
     noconfirm: bool,
+
+    /// Print progress as JSON lines on stdout, for front-ends
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print without colors; also the case when NO_COLOR is set or the
+    /// output is not a terminal
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Show the install full-screen: steps with live status, command output,
+    /// memory use and time left, with keys to skip steps and answer retries
+    #[arg(long, conflicts_with = "json")]
+    tui: bool,
+
+    /// Show the output of the commands run instead of only build progress;
+    /// twice also shows every command line
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only show step headers, warnings and errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Leave entries below this level out of the install log: debug (the
+    /// default, including command output), info, warn or error
+    #[arg(long, value_name = "LEVEL", value_parser = log::parse_level, global = true)]
+    log_level: Option<log::Level>,
+
+    /// Read settings from this install.toml; flags given on the command line win
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Throttle downloads to this rate in bytes/s (e.g. 500K, 2M)
+    #[arg(long, value_name = "RATE", value_parser = net::parse_rate)]
+    limit_rate: Option<String>,
+
+    /// Do not check downloads against their published SHA-256 checksums
+    #[arg(long)]
+    skip_verify: bool,
+
+    /// Number of parallel build jobs, overriding the memory and load heuristics
+    #[arg(short, long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: Option<usize>,
+
+    /// Seconds a command may print nothing before it is taken as hung and
+    /// you are asked whether to keep waiting (default 600, 0 waits forever)
+    #[arg(long, value_name = "SECS")]
+    command_timeout: Option<u64>,
+
+    /// Install Quickshell, the shell and the caelestia wrapper under this prefix
+    /// (e.g. ~/.local) instead of the system directories
+    #[arg(long, value_name = "DIR", value_parser = prefix::parse_prefix)]
+    prefix: Option<PathBuf>,
+
+    /// Interactive shell to set up: fish, zsh (with starship, zoxide and fzf)
+    /// or bash (left as it is)
+    #[arg(long, value_name = "SHELL", value_parser = shell::parse_shell)]
+    shell: Option<Shell>,
+
+    /// Make the chosen shell the login shell without asking
+    #[arg(long)]
+    login_shell: bool,
+
+    /// Build Quickshell and Cava from source even when a package would do
+    #[arg(long)]
+    force_source: bool,
+
+    /// Also look for a quickshell package in this COPR (e.g.
+    /// errornointernet/quickshell) before building it from source
+    #[arg(long, value_name = "OWNER/PROJECT")]
+    quickshell_copr: Option<String>,
+
+    /// Build Quickshell, Cava and the shell inside a toolbox container holding
+    /// the build dependencies, keeping the -devel packages off the host
+    #[arg(long)]
+    container_build: bool,
+
+    /// Package Quickshell and caelestia-shell as local RPMs installed with
+    /// dnf instead of installing them with cmake --install
+    #[arg(long)]
+    rpm: bool,
+
+    /// Package caelestia-cli as an RPM and install it with dnf, so dnf tracks,
+    /// updates and removes it
+    #[arg(long)]
+    cli_rpm: bool,
+
+    /// Remove the portals of desktops that are no longer installed without
+    /// asking
+    #[arg(long)]
+    remove_de_portals: bool,
+
+    /// Keep the build trees and downloads in the build directory after the
+    /// install, so rebuilds only compile what changed
+    #[arg(long)]
+    keep_build_artifacts: bool,
+
+    /// Remove the -devel packages and build tools the install added once
+    /// everything is built, without asking
+    #[arg(long)]
+    remove_build_deps: bool,
+
+    /// Run Starship's install script when neither Fedora's package, its COPR
+    /// nor the checksummed release binary can be installed
+    #[arg(long)]
+    starship_script: bool,
+
+    /// Enable the RPM Fusion free and nonfree repositories (codecs, NVIDIA)
+    /// without asking
+    #[arg(long)]
+    enable_rpmfusion: bool,
+
+    /// Install these packages on top of the built-in list (comma-separated)
+    #[arg(long, value_name = "PACKAGES", value_delimiter = ',')]
+    extra_packages: Vec<String>,
+
+    /// Do not install these packages, even when a component asks for them
+    /// (comma-separated)
+    #[arg(long, value_name = "PACKAGES", value_delimiter = ',')]
+    exclude_packages: Vec<String>,
+
+    /// Display manager to set up: greetd (with tuigreet, the default), sddm
+    /// or none
+    #[arg(long, value_name = "DM", value_parser = display_manager::parse_display_manager)]
+    display_manager: Option<DisplayManager>,
+
+    /// Do not download the wallpaper collection
+    #[arg(long)]
+    no_wallpapers: bool,
+
+    /// Install the Rust toolchain, which the desktop itself does not need
+    #[arg(long, conflicts_with = "without_rust")]
+    with_rust: bool,
+
+    /// Do not install the Rust toolchain, even when the config lists it
+    #[arg(long)]
+    without_rust: bool,
+
+    /// Rust toolchain to install with rustup (stable, beta, nightly or a
+    /// version such as 1.82.0), or fedora for Fedora's packages. Implies
+    /// --with-rust
+    #[arg(long, value_name = "TOOLCHAIN", conflicts_with = "without_rust")]
+    rust_toolchain: Option<String>,
+
+    /// Put the wallpapers here instead of ~/Pictures/Wallpapers
+    #[arg(long, value_name = "DIR", value_parser = prefix::parse_prefix, conflicts_with = "no_wallpapers")]
+    wallpapers_dir: Option<PathBuf>,
+
+    /// Add the binds of a previous Hyprland config to keybinds.conf
+    #[arg(long, value_name = "FILE", value_parser = prefix::parse_prefix)]
+    import_keybinds: Option<PathBuf>,
+
+    /// Lock the session after this many minutes without input
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u32).range(1..))]
+    lock_after: Option<u32>,
+
+    /// Package profile: minimal (core and build dependencies), default (adds
+    /// theming and utilities) or full (adds desktop apps)
+    #[arg(long, value_name = "PROFILE", value_parser = packages::parse_profile)]
+    profile: Option<Profile>,
+
+    /// Install these package groups on top of the profile (comma-separated)
+    #[arg(long, value_name = "GROUPS", value_delimiter = ',', value_parser = packages::parse_group)]
+    with: Vec<String>,
+
+    /// Leave these package groups out of the profile (comma-separated)
+    #[arg(long, value_name = "GROUPS", value_delimiter = ',', value_parser = packages::parse_group)]
+    without: Vec<String>,
+
+    /// Keyboard layout for Hyprland (e.g. de), instead of asking to confirm
+    /// the one localectl reports
+    #[arg(long, value_name = "LAYOUT")]
+    keyboard_layout: Option<String>,
+
+    /// Variant of the keyboard layout (e.g. nodeadkeys)
+    #[arg(long, value_name = "VARIANT", requires = "keyboard_layout")]
+    keyboard_variant: Option<String>,
+
+    /// Run builds in a systemd scope capped below the available memory, so a
+    /// build that runs out is throttled or killed instead of freezing the desktop
+    #[arg(long)]
+    memory_guard: bool,
+
+    /// Scheduling priority of builds; auto lowers it unless --noconfirm is given
+    #[arg(long, value_name = "PRIORITY", value_enum, default_value_t = BuildPriority::Auto)]
+    build_priority: BuildPriority,
+
+    /// Skip the steps a previous run completed and continue where it stopped
+    #[arg(long)]
+    resume: bool,
+
+    /// Run only these steps (comma-separated), assuming the rest are done
+    #[arg(long, value_name = "STEPS", value_delimiter = ',')]
+    only: Vec<String>,
+
+    /// Skip these steps (comma-separated)
+    #[arg(long, value_name = "STEPS", value_delimiter = ',')]
+    skip: Vec<String>,
+
+    /// Start at this step, assuming the ones before it are done
+    #[arg(long, value_name = "STEP")]
+    from: Option<String>,
+
+    /// Stop after this step
+    #[arg(long, value_name = "STEP")]
+    until: Option<String>,
+
+    /// Install Quickshell and the shell from prebuilt artifacts when one
+    /// matches this system, instead of compiling them
+    #[arg(long)]
+    prebuilt: bool,
+
+    /// Release URL to download prebuilt artifacts from (implies --prebuilt)
+    #[arg(long, value_name = "URL")]
+    prebuilt_url: Option<String>,
+
+    /// Clone the dotfiles from this repository, such as a fork, instead of upstream
+    #[arg(long, value_name = "URL")]
+    dotfiles_repo: Option<String>,
+
+    /// Check out this branch or tag of the dotfiles
+    #[arg(long, value_name = "BRANCH")]
+    dotfiles_branch: Option<String>,
+
+    /// Clone the shell from this repository instead of upstream
+    #[arg(long, value_name = "URL")]
+    shell_repo: Option<String>,
+
+    /// Check out this branch or tag of the shell
+    #[arg(long, value_name = "BRANCH")]
+    shell_branch: Option<String>,
+
+    /// Install without network access, from a cache filled by `prefetch`
+    #[arg(long, requires = "cache_dir")]
+    offline: bool,
+
+    /// Clone and build sources here, kept between runs for incremental
+    /// rebuilds (default: ~/.cache/caelestia-installer/build)
+    #[arg(long, value_name = "DIR", global = true)]
+    build_dir: Option<PathBuf>,
+
+    /// Cache of repositories, downloads and packages for --offline and prefetch
+    #[arg(long, value_name = "DIR", global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// Clone Quickshell, the dotfiles, the shell and the CLI at HEAD instead
+    /// of the revisions in versions.lock
+    #[arg(long, global = true)]
+    track_latest: bool,
+
+    /// Read pinned revisions from this file, overriding the built-in lock
+    #[arg(long, value_name = "FILE", global = true)]
+    lock_file: Option<PathBuf>,
+
+    /// Undo the changes recorded by previous runs (symlinks, config files,
+    /// services, login shell) and exit
+    #[arg(long, conflicts_with_all = ["resume", "only", "skip", "from", "until"])]
+    rollback: bool,
+
+    /// With --dry-run, also write the change plan to FILE as JSON
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    plan: Option<PathBuf>,
+
+    /// Write the commands and files of the install to FILE as a bash script
+    /// instead of running them; implies --dry-run
+    #[arg(long, value_name = "FILE")]
+    emit_script: Option<PathBuf>,
+
+    /// Run every step's check without changing anything and show which parts
+    /// of the setup are in place; exits with 1 when something has drifted
+    #[arg(long, conflicts_with_all = ["rollback", "resume"])]
+    check: bool,
+
+    /// Keep going when a step fails, running everything that does not depend
+    /// on it, and report the failed steps at the end
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Undo this run's changes if the installation fails
+    #[arg(long)]
+    rollback_on_failure: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BuildPriority {
+    Auto,
+    /// Build with low CPU and IO priority, keeping the desktop responsive
+    Low,
+    /// Build with normal priority
+    Normal,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Collect logs, hardware and system state into a tarball for bug reports
+    #[command(alias = "report-bundle")]
+    ReportBug {
+        /// Where to write the archive (defaults to the current directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Pull the latest dotfiles and shell, rebuild the shell and update the CLI
+    Update,
+    /// Check the installed setup and suggest fixes for what is broken
+    Doctor,
+    /// Remove the build directory with the sources kept in it
+    Clean,
+    /// Download everything an --offline install needs into --cache-dir
+    Prefetch,
+    /// List the backups of replaced configs, or restore one
+    Restore {
+        /// Timestamp or path of the backup to restore
+        backup: Option<String>,
+    },
+    /// Check that the config symlinks and the lines added to configs are still in place
+    Verify,
+    /// Recreate the config symlinks and lines that verify finds missing or replaced
+    Repair,
+    /// Show the install steps in order, what each waits for and which are already done
+    ListSteps,
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    ui::init_color(cli.no_color);
+    ui::set_verbosity(match (cli.quiet, cli.verbose) {
+        (true, _) => ui::Verbosity::Quiet,
+        (false, 0) => ui::Verbosity::Normal,
+        (false, 1) => ui::Verbosity::Verbose,
+        (false, _) => ui::Verbosity::Commands,
+    });
+    if cli.json {
+        ui::use_json();
+    }
+    if let Err(e) = checks::refuse_root() {
+        ui::error(&format!("{}", e));
+        std::process::exit(1);
+    }
+    if let Some(level) = cli.log_level {
+        log::set_level(level);
+    }
+    versions::set_lock_file(cli.lock_file.clone());
+    versions::set_track_latest(cli.track_latest);
+
+    system::set_build_root(cli.build_dir.clone());
+    system::set_keep_build_artifacts(cli.keep_build_artifacts);
+
+    if let Some(command) = cli.command.take() {
+        let result = match command {
+            Commands::ReportBug { output } => report::create_bundle(output).map(|_| ()),
+            Commands::Update => log::init_append().and_then(|_| update::run(cli.dry_run, cli.noconfirm)),
+            Commands::Doctor => log::init_append().and_then(|_| doctor::run()),
+            Commands::Clean => log::init_append().and_then(|_| system::clean(cli.dry_run)),
+            Commands::Prefetch => match cli.cache_dir {
+                Some(ref dir) => log::init_append().and_then(|_| offline::prefetch(dir, cli.dry_run)),
+                None => Err(anyhow::anyhow!("prefetch needs --cache-dir DIR to download into")),
+            },
+            Commands::Restore { backup } => {
+                log::init_append().and_then(|_| backup::restore(backup.as_deref(), cli.dry_run))
+            }
+            Commands::Verify => match log::init_append().and_then(|_| manifest::verify()) {
+                Ok(0) => Ok(()),
+                Ok(problems) => Err(anyhow::anyhow!("{} problems found, fix them with: caelestia-installer repair", problems)),
+                Err(e) => Err(e),
+            },
+            Commands::Repair => log::init_append().and_then(|_| manifest::repair(cli.dry_run)),
+            Commands::ListSteps => list_steps(cli),
+        };
+        if let Err(e) = result {
+            ui::error(&format!("{}", e));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if cli.rollback {
+        if let Err(e) = log::init_append().and_then(|_| journal::rollback(cli.dry_run)) {
+            ui::error(&format!("Rollback failed: {}", e));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if cli.check {
+        match check(cli) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                ui::error(&format!("{}", e));
+                std::process::exit(1);
+            }
+        }
+    }
 
-    if let Err(e) = run(cli) {
+    interrupt::install(cli.rollback_on_failure && !cli.dry_run);
+    let result = run(&mut cli);
+    // After run, which merges noconfirm from the config file
+    let ask = !cli.noconfirm && !cli.json;
+    system::remove_swapfile();
+    sudo::stop_keepalive();
+    if let Err(e) = result {
+        summary::print();
         ui::error(&format!("Installation failed: {}", e));
         ui::info("Check the log for details:");
         log::show_recent_logs(20);
-    ui::print_diagnostics();
-    ui::print_troubleshooting();
+        doctor::report();
+        ui::print_troubleshooting();
+        if ask && ui::confirm("Upload the install log, with secrets removed, to paste.rs to share it?") {
+            match report::upload_log() {
+                Ok(url) => ui::success(&format!("Log uploaded: {}", url)),
+                Err(e) => ui::warning(&format!("Could not upload the log: {}", e)),
+            }
+        }
         std::process::exit(1);
     }
 }
 
-fn run(cli: Cli) -> Result<()> {
+/// Fills in the settings not given on the command line from the config file.
+fn apply_config(cli: &mut Cli, config: &config::Config) -> Result<()> {
+    cli.noconfirm |= config.noconfirm.unwrap_or(false);
+    cli.rollback_on_failure |= config.rollback_on_failure.unwrap_or(false);
+    cli.continue_on_error |= config.continue_on_error.unwrap_or(false);
+    cli.skip_verify |= config.skip_verify.unwrap_or(false);
+    cli.starship_script |= config.starship_script.unwrap_or(false);
+    cli.remove_build_deps |= config.remove_build_deps.unwrap_or(false);
+    cli.remove_de_portals |= config.remove_de_portals.unwrap_or(false);
+    cli.keep_build_artifacts |= config.keep_build_artifacts.unwrap_or(false);
+    cli.cli_rpm |= config.cli_rpm.unwrap_or(false);
+    cli.rpm |= config.rpm.unwrap_or(false);
+    cli.force_source |= config.force_source.unwrap_or(false);
+    cli.container_build |= config.container_build.unwrap_or(false);
+    cli.memory_guard |= config.memory_guard.unwrap_or(false);
+    if cli.shell.is_none() {
+        cli.shell = config.shell.as_deref().map(shell::parse_shell).transpose()?;
+    }
+    if cli.profile.is_none() {
+        cli.profile = config.packages.profile.as_deref().map(packages::parse_profile).transpose()?;
+    }
+    for id in config.packages.with.iter().chain(&config.packages.without) {
+        packages::parse_group(id)?;
+    }
+    cli.extra_packages.extend(config.packages.add.iter().cloned());
+    cli.exclude_packages.extend(config.packages.exclude.iter().cloned());
+    cli.with.extend(config.packages.with.iter().cloned());
+    cli.without.extend(config.packages.without.iter().cloned());
+    if cli.display_manager.is_none() {
+        cli.display_manager = config
+            .display_manager
+            .as_deref()
+            .map(display_manager::parse_display_manager)
+            .transpose()?;
+    }
+    cli.no_wallpapers |= config.wallpapers == Some(false);
+    if cli.wallpapers_dir.is_none() {
+        cli.wallpapers_dir = config.wallpapers_dir.as_deref().map(prefix::parse_prefix).transpose()?;
+    }
+    if cli.import_keybinds.is_none() {
+        cli.import_keybinds = config.keybinds.import.as_deref().map(prefix::parse_prefix).transpose()?;
+    }
+    if cli.lock_after.is_none() {
+        if config.input.lock_after == Some(0) {
+            anyhow::bail!("lock_after must be at least 1");
+        }
+        cli.lock_after = config.input.lock_after;
+    }
+    if cli.keyboard_layout.is_none() {
+        cli.keyboard_layout = config.input.layout.clone();
+        cli.keyboard_variant = cli.keyboard_variant.take().or_else(|| config.input.variant.clone());
+    }
+    cli.track_latest |= config.track_latest.unwrap_or(false);
+    cli.prebuilt |= config.prebuilt.unwrap_or(false);
+    if cli.prebuilt_url.is_none() {
+        cli.prebuilt_url = config.prebuilt_url.clone();
+    }
+    if cli.limit_rate.is_none() {
+        cli.limit_rate = config.limit_rate.as_deref().map(net::parse_rate).transpose()?;
+    }
+    if cli.command_timeout.is_none() {
+        cli.command_timeout = config.command_timeout;
+    }
+    if cli.jobs.is_none() {
+        if config.jobs == Some(0) {
+            anyhow::bail!("jobs must be at least 1");
+        }
+        cli.jobs = config.jobs;
+    }
+    if cli.build_dir.is_none() {
+        cli.build_dir = config.build_dir.as_ref().map(PathBuf::from);
+    }
+    if cli.prefix.is_none() {
+        cli.prefix = config.prefix.as_deref().map(prefix::parse_prefix).transpose()?;
+    }
+    if cli.only.is_empty() {
+        cli.only = config.steps.only.clone();
+    }
+    cli.skip.extend(config.steps.skip.iter().cloned());
+    Ok(())
+}
+
+/// Runs `plan` behind the full-screen display.
+#[cfg(feature = "tui")]
+fn run_tui(plan: &install::Plan, dry_run: bool) -> Result<()> {
+    // Asked before the screen is taken over; the checks step then finds it granted
+    sudo::validate(dry_run)?;
+    let steps: Vec<_> = plan
+        .selected_ids()?
+        .into_iter()
+        .map(|id| {
+            let title = plan.steps().iter().find(|step| step.id() == id).map_or(id, |step| step.title());
+            (id, title)
+        })
+        .collect();
+
+    let tui = caelestia_installer::tui::Tui::start(&steps)?;
+    let result = plan.run();
+    tui.finish(&result);
+    result
+}
+
+fn run(cli: &mut Cli) -> Result<()> {
     ui::print_banner();
 
     // Initialize logging
@@ -38,91 +552,161 @@ fn run(cli: Cli) -> Result<()> {
     ui::info(&format!("Logging to {:?}", log_path));
     log::log("Installation started");
 
+    let config = match cli.config {
+        Some(ref path) => {
+            ui::info(&format!("Using config {:?}", path));
+            log::log(&format!("Config file: {:?}", path));
+            config::load(path)?
+        }
+        None => config::Config::default(),
+    };
+    apply_config(cli, &config)?;
+    cli.dry_run |= cli.emit_script.is_some();
+    if cli.tui && !cfg!(feature = "tui") {
+        anyhow::bail!("This build has no TUI, rebuild it with: cargo build --release --features tui");
+    }
+
     if cli.dry_run {
         ui::warning("DRY RUN MODE - No changes will be made");
     }
 
-    // Confirmation
-    if !cli.noconfirm && !cli.dry_run {
-        if !ui::prompt("This will install Caelestia Hyprland dotfiles. Continue?") {
-            ui::info("Installation cancelled");
-            return Ok(());
-        }
+    if let Some(ref rate) = cli.limit_rate {
+        ui::info(&format!("Limiting download rate to {}/s", rate));
+        log::log(&format!("Download rate limited to {}", rate));
     }
+    net::set_limit_rate(cli.limit_rate.clone());
 
-    let mut progress = ui::Progress::new(13);
-
-    // Step 1: Pre-flight checks
-    progress.step("Running pre-flight checks...");
-    checks::run_all(cli.dry_run)?;
-
-    // Step 2: Add COPR repos
-    progress.step("Adding COPR repositories...");
-    repos::add_all(cli.dry_run)?;
+    if cli.skip_verify {
+        ui::warning("Not verifying the checksums of downloads");
+        log::log("Checksum verification disabled");
+    }
+    download::set_skip_verify(cli.skip_verify);
+    repos::set_key_pins(&config.repos.copr_keys);
 
-    // Step 3: Install packages
-    progress.step("Installing packages...");
-    packages::install_all(cli.dry_run)?;
-    packages::install_starship(cli.dry_run)?;
-    packages::install_rust(cli.dry_run)?;
-    // Step 3b: Install Hyprland Qt utils
-    progress.step("Installing Hyprland Qt utils...");
-    packages::install_hyprland_qt_support(cli.dry_run)?;
-    packages::install_hyprland_qtutils(cli.dry_run)?;
+    if cli.offline {
+        if let Some(ref dir) = cli.cache_dir {
+            offline::set_offline(dir)?;
+            ui::info(&format!("Installing offline from {:?}", dir));
+        }
+    }
+    let timeout = match cli.command_timeout {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(runner::DEFAULT_TIMEOUT),
+    };
+    runner::set_timeout(timeout, !cli.noconfirm);
 
-    // Step 4: Build Quickshell from source
-    progress.step("Building Quickshell...");
-    packages::install_quickshell(cli.dry_run)?;
+    if let Some(jobs) = cli.jobs {
+        ui::info(&format!("Building with {} job(s)", jobs));
+        log::log(&format!("Build jobs set to {}", jobs));
+    }
+    system::set_jobs(cli.jobs);
 
-    // Step 4b: Installing Cava (Wait, let's just make it sequential)
-    progress.step("Installing Cava...");
-    packages::install_cava(cli.dry_run)?;
+    if let Some(ref dir) = cli.build_dir {
+        ui::info(&format!("Building in {}", dir.display()));
+    }
+    system::set_build_root(cli.build_dir.clone());
+    system::set_keep_build_artifacts(cli.keep_build_artifacts);
+    system::check_build_space();
 
-    // Step 5: Install Fonts
-    progress.step("Installing Fonts...");
-    packages::install_fonts(cli.dry_run)?;
-    dotfiles::clone_repos(cli.dry_run)?;
+    if let Some(ref dir) = cli.prefix {
+        ui::info(&format!("Installing built components under {}", dir.display()));
+        log::log(&format!("Install prefix: {}", dir.display()));
+    }
+    if cli.container_build {
+        ui::info(&format!("Building from source in the {} toolbox", toolbox::CONTAINER));
+        log::log("Container build mode");
+    }
+    toolbox::set_container_build(cli.container_build);
+    prefix::set_prefix(cli.prefix.clone());
+    if cli.container_build && !prefix::is_user() {
+        anyhow::bail!("--container-build installs the built components into a prefix in the home directory, such as ~/.local");
+    }
+    if cli.rpm && prefix::is_user() {
+        anyhow::bail!("--rpm installs system packages, it cannot be used with a prefix in the home directory");
+    }
 
-    // Step 6: Install caelestia-cli
-    progress.step("Installing caelestia-cli...");
-    cli::install_cli(cli.dry_run)?;
+    let prebuilt_url = match cli.prebuilt_url.clone() {
+        Some(url) => Some(url.trim_end_matches('/').to_string()),
+        None if cli.prebuilt => Some(prebuilt::DEFAULT_URL.to_string()),
+        None => None,
+    };
+    if let Some(ref url) = prebuilt_url {
+        ui::info(&format!("Using prebuilt components from {} where available", url));
+        log::log(&format!("Prebuilt release: {}", url));
+    }
+    prebuilt::set_release_url(prebuilt_url);
 
-    // Step 7: Symlink configs (before scheme init so paths exist)
-    progress.step("Symlinking configurations...");
-    dotfiles::symlink_configs(cli.dry_run)?;
+    if cli.rpm {
+        log::log("Quickshell and caelestia-shell are packaged as RPMs");
+    }
+    localrpm::set_enabled(cli.rpm);
+    if cli.force_source {
+        log::log("Building Quickshell and Cava from source");
+    }
+    packages::set_force_source(cli.force_source);
+    if cli.cli_rpm {
+        log::log("caelestia-cli is packaged as an RPM");
+    }
+    cli::set_rpm(cli.cli_rpm);
 
-    // Step 8: Initialize color scheme (after symlinks so ~/.config/hypr exists)
-    progress.step("Initializing color scheme...");
-    cli::init_scheme(cli.dry_run)?;
+    if cli.track_latest {
+        ui::warning("Tracking upstream HEAD instead of the pinned revisions");
+        log::log("Tracking latest upstream revisions");
+    }
+    versions::set_track_latest(cli.track_latest);
 
-    // Step 9: Build shell widgets
-    progress.step("Building caelestia-shell...");
-    dotfiles::build_shell(cli.dry_run)?;
+    let low_priority = match cli.build_priority {
+        BuildPriority::Auto => !cli.noconfirm,
+        BuildPriority::Low => true,
+        BuildPriority::Normal => false,
+    };
+    log::log(&format!("Low build priority: {}", low_priority));
+    system::set_low_priority(low_priority);
+    if cli.memory_guard {
+        ui::info("Capping the memory of builds below what is available");
+    }
+    system::set_memory_guard(cli.memory_guard);
 
-    // Step 10: Set up shell (fish)
-    progress.step("Setting up Fish shell...");
-    shell::setup_all(cli.dry_run)?;
+    // Confirmation
+    if !cli.noconfirm
+        && !cli.dry_run
+        && !ui::prompt("This will install Caelestia Hyprland dotfiles. Continue?")
+    {
+        ui::info("Installation cancelled");
+        return Ok(());
+    }
 
-    // Step 11: Set up keybinds
-    progress.step("Setting up Hyprland keybinds...");
-    keybinds::setup_keybinds(cli.dry_run)?;
+    let plan = build_plan(cli, &config, !cli.noconfirm)?;
+    #[cfg(feature = "tui")]
+    let result = if cli.tui { run_tui(&plan, cli.dry_run) } else { plan.run() };
+    #[cfg(not(feature = "tui"))]
+    let result = plan.run();
+    result?;
 
-    // Step 12: Set up greetd (optional, may need confirmation)
-    if cli.noconfirm || ui::prompt("Set up greetd/tuigreet as display manager?") {
-        greetd::setup_all(cli.dry_run)?;
+    if let Some(ref path) = cli.plan {
+        preview::save(path)?;
+        ui::info(&format!("Change plan written to {}", path.display()));
+    }
+    if let Some(ref path) = cli.emit_script {
+        preview::write_script(path)?;
+        ui::success(&format!("Install script written to {}", path.display()));
     }
 
-    log::log("Installation completed successfully");
     ui::print_completion();
+    summary::print();
+    if !cli.dry_run {
+        match summary::write_report() {
+            Ok(path) => ui::info(&format!("Report saved to {}", path.display())),
+            Err(e) => ui::warning(&format!("Could not save the report: {}", e)),
+        }
+    }
 
     // Offer to reboot
     if !cli.dry_run && !cli.noconfirm {
         if ui::prompt("Reboot now to apply changes?") {
             ui::info("Rebooting...");
-            std::process::Command::new("sudo")
-                .args(["reboot"])
-                .status()
-                .ok();
+            let _ = privileged::run(&["reboot"]);
         } else {
             ui::info("Please reboot to apply all changes");
         }
@@ -130,3 +714,244 @@ fn run(cli: Cli) -> Result<()> {
 
     Ok(())
 }
+
+/// Runs the check of every selected step and prints what is in place and
+/// what is not. Returns whether everything checkable is in place.
+fn check(cli: Cli) -> Result<bool> {
+    let plan = inspect_plan(cli)?;
+
+    if !ui::json() {
+        ui::block(&format!("{}", format!("{:<18} {:<12} {}", "Step", "State", "Description").bold()));
+    }
+    let (mut in_place, mut missing, mut unchecked) = (0, 0, 0);
+    for step in plan.list()? {
+        let state = if step.left_out {
+            "not selected"
+        } else if !step.checked {
+            unchecked += 1;
+            "not checked"
+        } else if step.done {
+            in_place += 1;
+            "in place"
+        } else {
+            missing += 1;
+            "missing"
+        };
+        let description = step.title.trim_end_matches("...");
+        if ui::json() {
+            ui::print_json(serde_json::json!({ "id": step.id, "description": description, "state": state }));
+            continue;
+        }
+        let padded = format!("{:<12}", state);
+        let marker = match state {
+            "in place" => padded.green(),
+            "missing" => padded.red(),
+            _ => padded.dimmed(),
+        };
+        ui::block(&format!("{:<18} {} {}", step.id, marker, description));
+    }
+
+    let counts = format!("{} in place, {} missing, {} without a check", in_place, missing, unchecked);
+    if missing == 0 {
+        ui::success(&counts);
+    } else {
+        ui::warning(&counts);
+        ui::info("Run the installer with --only and the missing steps to restore them");
+    }
+    Ok(missing == 0)
+}
+
+/// Prints every step in dependency order for the given options, marking the
+/// ones left out and the ones whose check finds them already done.
+fn list_steps(cli: Cli) -> Result<()> {
+    let plan = inspect_plan(cli)?;
+
+    let mut total = Duration::ZERO;
+    for step in plan.list()? {
+        let status = if step.left_out {
+            "skip"
+        } else if step.done {
+            "done"
+        } else {
+            "run"
+        };
+        let description = step.title.trim_end_matches("...");
+        if status == "run" {
+            total += step.estimate;
+        }
+        if ui::json() {
+            ui::print_json(serde_json::json!({
+                "id": step.id,
+                "description": description,
+                "after": step.after,
+                "status": status,
+                "estimate_secs": step.estimate.as_secs(),
+            }));
+            continue;
+        }
+        let padded = format!("{:<4}", status);
+        let marker = match status {
+            "skip" => padded.dimmed(),
+            "done" => padded.green(),
+            _ => padded.cyan(),
+        };
+        let after = if step.after.is_empty() {
+            String::new()
+        } else {
+            format!(" (after {})", step.after.join(", "))
+        };
+        let estimate = if status == "run" {
+            format!("~{}", ui::format_duration(step.estimate))
+        } else {
+            String::new()
+        };
+        ui::block(&format!(
+            "{}  {} {} {}{}",
+            marker,
+            format!("{:<18}", step.id).bold(),
+            format!("{:>8}", estimate).dimmed(),
+            description,
+            after.dimmed()
+        ));
+    }
+    if !ui::json() {
+        ui::block(&format!("\nEstimated time on this machine: ~{}", ui::format_duration(total)));
+    }
+    Ok(())
+}
+
+/// The plan an install with these options and config would run, for
+/// looking at without running it.
+fn inspect_plan(mut cli: Cli) -> Result<install::Plan> {
+    let config = match cli.config {
+        Some(ref path) => config::load(path)?,
+        None => config::Config::default(),
+    };
+    apply_config(&mut cli, &config)?;
+    prefix::set_prefix(cli.prefix.clone());
+    let plan = build_plan(&cli, &config, false)?;
+    Ok(plan)
+}
+
+/// Builds the plan the options describe: the package groups, the components
+/// (asking for them with `ask` unless the config lists them) and the step
+/// selection flags.
+fn build_plan(cli: &Cli, config: &config::Config, ask: bool) -> Result<install::Plan> {
+    let groups = packages::select_groups(cli.profile.unwrap_or_default(), &cli.with, &cli.without)?;
+    log::log(&format!("Package groups: {}", groups.join(", ")));
+
+    let mut options = install::Options {
+        dry_run: cli.dry_run,
+        noconfirm: cli.noconfirm,
+        continue_on_error: cli.continue_on_error,
+        rollback_on_failure: cli.rollback_on_failure,
+        shell: cli.shell.unwrap_or_default(),
+        login_shell: if cli.login_shell { Some(true) } else { config.login_shell },
+        packages: packages::group_packages(&groups),
+        rpmfusion: if cli.enable_rpmfusion { Some(true) } else { config.rpmfusion },
+        display_manager: cli.display_manager.unwrap_or_default(),
+        display_manager_wanted: config.greetd,
+        dotfiles_repo: cli.dotfiles_repo.clone().or_else(|| config.repos.dotfiles_repo.clone()),
+        shell_repo: cli.shell_repo.clone().or_else(|| config.repos.shell_repo.clone()),
+        dotfiles_branch: cli.dotfiles_branch.clone().or_else(|| config.repos.dotfiles_branch.clone()),
+        shell_branch: cli.shell_branch.clone().or_else(|| config.repos.shell_branch.clone()),
+        keybinds: keybinds::Choices {
+            main_mod: config.keybinds.main_mod.clone(),
+            terminal: config.keybinds.terminal.clone(),
+            browser: config.keybinds.browser.clone(),
+            file_manager: config.keybinds.file_manager.clone(),
+            launcher: config.keybinds.launcher.clone(),
+            import: cli.import_keybinds.clone(),
+        },
+        starship_script: cli.starship_script,
+        remove_de_portals: cli.remove_de_portals,
+        remove_build_deps: cli.remove_build_deps,
+        quickshell_copr: cli.quickshell_copr.clone().or_else(|| config.repos.quickshell_copr.clone()),
+        rust_toolchain: cli.rust_toolchain.clone().or_else(|| config.rust_toolchain.clone()),
+        fish_plugins: config.fish.plugins.clone(),
+        default_apps: mime::Defaults {
+            file_manager: config.default_apps.file_manager.clone(),
+            browser: config.default_apps.browser.clone(),
+            image_viewer: config.default_apps.image_viewer.clone(),
+            terminal: config.default_apps.terminal.clone(),
+        },
+        keyboard_layout: cli.keyboard_layout.clone(),
+        keyboard_variant: cli.keyboard_variant.clone(),
+        natural_scroll: config.input.natural_scroll,
+        lock_after: cli.lock_after.unwrap_or(idle::DEFAULT_LOCK_AFTER),
+        wallpapers_dir: cli.wallpapers_dir.clone(),
+        flatpak_apps: config.flatpak.apps.clone(),
+        hooks: config.hooks.clone(),
+        ..Default::default()
+    };
+    for package in &cli.extra_packages {
+        if !options.packages.contains(package) {
+            options.packages.push(package.clone());
+        }
+    }
+    options.exclude_packages = cli.exclude_packages.clone();
+    if let Some(ref components) = config.components {
+        if let Some(unknown) = components
+            .iter()
+            .find(|id| *id != "greetd" && !install::COMPONENTS.iter().any(|c| c.id == id.as_str()))
+        {
+            anyhow::bail!("Unknown component '{}' in config", unknown);
+        }
+        // The display manager component used to be greetd only
+        options.components = components
+            .iter()
+            .map(|id| if id == "greetd" { "display-manager".to_string() } else { id.clone() })
+            .collect();
+    }
+
+    // A config that lists the components has already made the choice
+    if ask && config.components.is_none() {
+        let items: Vec<(&str, bool)> = install::COMPONENTS
+            .iter()
+            .map(|c| (c.label, options.components.iter().any(|id| id == c.id)))
+            .collect();
+        if let Some(selected) = ui::multi_select("Components to install", &items) {
+            options.components = install::COMPONENTS
+                .iter()
+                .zip(&selected)
+                .filter(|(_, selected)| **selected)
+                .map(|(c, _)| c.id.to_string())
+                .collect();
+            // Already answered here, so the display manager step need not ask again
+            options.display_manager_wanted = Some(options.components.iter().any(|id| id == "display-manager"));
+            log::log(&format!("Components: {}", options.components.join(", ")));
+        }
+    }
+    // Discover is of little use without Flathub, and listed apps need it
+    let wants_flatpak = !options.flatpak_apps.is_empty() || options.components.iter().any(|id| id == "discover");
+    if wants_flatpak && !options.components.iter().any(|id| id == "flatpak") {
+        options.components.push("flatpak".to_string());
+    }
+    if cli.no_wallpapers {
+        options.components.retain(|id| id != "wallpapers");
+    }
+    if cli.without_rust {
+        options.components.retain(|id| id != "rust");
+    } else if (cli.with_rust || options.rust_toolchain.is_some()) && !options.components.iter().any(|id| id == "rust") {
+        options.components.push("rust".to_string());
+    }
+    let mut plan = install::Plan::new(options);
+    plan.add_custom_steps(&config.custom_steps)?;
+    plan.check_hooks()?;
+    if !cli.only.is_empty() {
+        plan.only(&cli.only)?;
+    }
+    if !cli.skip.is_empty() {
+        plan.skip(&cli.skip)?;
+    }
+    if let Some(ref first) = cli.from {
+        plan.from(first)?;
+    }
+    if let Some(ref last) = cli.until {
+        plan.until(last)?;
+    }
+    if cli.resume {
+        plan.resume();
+    }
+    Ok(plan)
+}
@@ -0,0 +1,236 @@
+//! What the installer linked into and added to the user's configs, kept in
+//! `~/.local/state/caelestia-installer/manifest.json`. `verify` compares it
+//! with the files as they are now, finding links that broke or were replaced
+//! since, and `repair` puts them back.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{backup, dotfiles, journal, keybinds, log, ui};
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Manifest {
+    pub links: Vec<Link>,
+    pub lines: Vec<Line>,
+}
+
+/// A symlink at `path` pointing to `target`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Link {
+    pub path: PathBuf,
+    pub target: PathBuf,
+}
+
+/// A line appended to `file` below a `# comment` heading.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Line {
+    pub file: PathBuf,
+    pub comment: String,
+    pub line: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Problem {
+    /// Nothing is at the link's path.
+    Missing,
+    /// The link is there but its target is gone.
+    Broken,
+    /// Something else took the link's place.
+    Overwritten,
+    /// The line is no longer in the file.
+    LineMissing,
+}
+
+impl Problem {
+    fn describe(self) -> &'static str {
+        match self {
+            Problem::Missing => "missing",
+            Problem::Broken => "broken, its target is gone",
+            Problem::Overwritten => "replaced by something else",
+            Problem::LineMissing => "line removed",
+        }
+    }
+}
+
+pub fn manifest_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer/manifest.json")
+}
+
+pub fn load() -> Manifest {
+    fs::read_to_string(manifest_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(manifest: &Manifest) {
+    let path = manifest_path();
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, serde_json::to_string_pretty(manifest).unwrap_or_default()));
+    if let Err(e) = written {
+        log::log(&format!("Could not save the manifest: {}", e));
+    }
+}
+
+/// Records the symlink at `path`, replacing an earlier entry for it.
+pub fn record_link(path: &Path, target: &Path) {
+    let mut manifest = load();
+    manifest.links.retain(|link| link.path != path);
+    manifest.links.push(Link {
+        path: path.to_path_buf(),
+        target: target.to_path_buf(),
+    });
+    save(&manifest);
+}
+
+/// Records a line added to `file`.
+pub fn record_line(file: &Path, comment: &str, line: &str) {
+    let entry = Line {
+        file: file.to_path_buf(),
+        comment: comment.to_string(),
+        line: line.to_string(),
+    };
+    let mut manifest = load();
+    if !manifest.lines.contains(&entry) {
+        manifest.lines.push(entry);
+        save(&manifest);
+    }
+}
+
+/// The manifest, or for installs from before it was written, what the
+/// symlinks step and the keybinds step would have set up.
+fn expected() -> Manifest {
+    let manifest = load();
+    if !manifest.links.is_empty() {
+        return manifest;
+    }
+
+    let dotfiles_dir = dotfiles::dotfiles_dir();
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+    let links = dotfiles::LINKS
+        .iter()
+        .map(|(src, dst)| Link {
+            path: config_dir.join(dst),
+            target: dotfiles_dir.join(src),
+        })
+        .collect();
+    let lines = config_dir
+        .join("hypr/keybinds.conf")
+        .exists()
+        .then(|| keybinds::source_line_entry(&config_dir.join("hypr")))
+        .into_iter()
+        .collect();
+    Manifest { links, lines }
+}
+
+fn check_link(link: &Link) -> Option<Problem> {
+    match fs::read_link(&link.path) {
+        Ok(target) if target != link.target => Some(Problem::Overwritten),
+        Ok(_) if !link.target.exists() => Some(Problem::Broken),
+        Ok(_) => None,
+        Err(_) if link.path.exists() => Some(Problem::Overwritten),
+        Err(_) => Some(Problem::Missing),
+    }
+}
+
+fn check_line(line: &Line) -> Option<Problem> {
+    let content = fs::read_to_string(&line.file).ok()?;
+    (!content.lines().any(|l| l.trim() == line.line)).then_some(Problem::LineMissing)
+}
+
+/// Checks every recorded link and line, printing what is wrong. Returns the
+/// number of problems.
+pub fn verify() -> Result<usize> {
+    let manifest = expected();
+    ui::info(&format!(
+        "Verifying {} links and {} config lines...",
+        manifest.links.len(),
+        manifest.lines.len()
+    ));
+
+    let mut problems = 0;
+    for link in &manifest.links {
+        match check_link(link) {
+            Some(problem) => {
+                problems += 1;
+                ui::error(&format!("{}: {}", link.path.display(), problem.describe()));
+                log::log(&format!("Verify: {:?} {:?}", link.path, problem));
+            }
+            None => ui::success(&format!("{} -> {}", link.path.display(), link.target.display())),
+        }
+    }
+    for line in &manifest.lines {
+        match check_line(line) {
+            Some(problem) => {
+                problems += 1;
+                ui::error(&format!("{}: '{}' {}", line.file.display(), line.line, problem.describe()));
+                log::log(&format!("Verify: {:?} {:?}", line.file, problem));
+            }
+            None => ui::success(&format!("{} has '{}'", line.file.display(), line.line)),
+        }
+    }
+
+    if problems == 0 {
+        ui::success("All links and config lines are in place");
+    }
+    Ok(problems)
+}
+
+/// Recreates the links and lines [`verify`] finds wrong. What took a link's
+/// place is backed up first, and the repairs are journaled for `--rollback`.
+pub fn repair(dry_run: bool) -> Result<()> {
+    let manifest = expected();
+    if !dry_run {
+        journal::begin();
+    }
+
+    let mut repaired = 0;
+    let mut left = 0;
+    for link in &manifest.links {
+        let Some(problem) = check_link(link) else {
+            continue;
+        };
+        if !link.target.exists() {
+            ui::warning(&format!(
+                "Cannot repair {}: {} is gone, reinstall it with 'caelestia-installer --only dotfiles,symlinks'",
+                link.path.display(),
+                link.target.display()
+            ));
+            left += 1;
+            continue;
+        }
+        log::log(&format!("Repairing {:?} ({:?})", link.path, problem));
+        if dotfiles::create_symlink(&link.target, &link.path, dry_run)? {
+            repaired += 1;
+        }
+    }
+    for line in &manifest.lines {
+        if check_line(line).is_none() {
+            continue;
+        }
+        ui::info(&format!("Adding '{}' back to {}...", line.line, line.file.display()));
+        repaired += 1;
+        if dry_run {
+            continue;
+        }
+        let content = fs::read_to_string(&line.file)?;
+        journal::write_file(&line.file, format!("{}\n\n# {}\n{}\n", content, line.comment, line.line))?;
+        log::log(&format!("Restored line in {:?}", line.file));
+    }
+    if !dry_run {
+        backup::finish()?;
+    }
+
+    match (repaired, left) {
+        (0, 0) => ui::success("Nothing to repair"),
+        (_, 0) => ui::success(&format!("Repaired {} links and lines", repaired)),
+        _ => ui::warning(&format!("Repaired {}, {} could not be repaired", repaired, left)),
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//! Updating the dotfiles and shell checkouts after the user edited them.
+//! Uncommitted edits are stashed around the update and put back after it,
+//! and local commits are rebased onto upstream. Files where the two conflict
+//! are resolved one at a time: keep the user's version, take upstream's, or
+//! look at the diff first.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::{Command, Output};
+
+use crate::{log, ui};
+
+const STASH_MESSAGE: &str = "caelestia-installer: local changes before update";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Mine,
+    Upstream,
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<Output> {
+    log::log_command(&format!("git -C {} {}", repo.display(), args.join(" ")));
+    Ok(Command::new("git").arg("-C").arg(repo).args(args).output()?)
+}
+
+fn stderr(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    log::log_error(&stderr);
+    stderr
+}
+
+/// Whether the work tree has changes or files that are not committed.
+pub fn has_local_changes(repo: &Path) -> bool {
+    git(repo, &["status", "--porcelain"]).is_ok_and(|o| o.status.success() && !o.stdout.is_empty())
+}
+
+/// Runs `update` with the uncommitted changes in `repo` set aside, then puts
+/// them back, even when the update failed.
+pub fn around_local_changes(repo: &Path, noconfirm: bool, update: impl FnOnce() -> Result<()>) -> Result<()> {
+    if !has_local_changes(repo) {
+        return update();
+    }
+
+    ui::info(&format!("{:?} has local changes, setting them aside for the update", repo));
+    let output = git(repo, &["stash", "push", "--include-untracked", "-m", STASH_MESSAGE])?;
+    if !output.status.success() {
+        bail!("Could not stash the local changes in {:?}: {}", repo, stderr(&output));
+    }
+
+    let result = update();
+    reapply(repo, noconfirm)?;
+    result
+}
+
+/// Pops the stash [`around_local_changes`] pushed, resolving conflicts.
+fn reapply(repo: &Path, noconfirm: bool) -> Result<()> {
+    let output = git(repo, &["stash", "pop"])?;
+    if output.status.success() {
+        ui::success("Reapplied the local changes");
+        return Ok(());
+    }
+
+    let conflicts = conflicted(repo)?;
+    if conflicts.is_empty() {
+        bail!(
+            "Could not reapply the local changes in {:?}, they are kept in 'git stash list': {}",
+            repo,
+            stderr(&output)
+        );
+    }
+    ui::warning(&format!("The local changes conflict with the update in {} files", conflicts.len()));
+
+    let mut kept_all = true;
+    for file in &conflicts {
+        // Ours is the updated checkout, theirs the stash
+        let side = choose(repo, file, noconfirm)?;
+        kept_all &= side == Side::Mine;
+        take(repo, file, if side == Side::Mine { "--theirs" } else { "--ours" })?;
+    }
+
+    // Leave the result as uncommitted edits, as it was before the update
+    git(repo, &["reset", "-q"])?;
+    if kept_all {
+        git(repo, &["stash", "drop"])?;
+    } else {
+        ui::info("The local changes you replaced stay in 'git stash list'");
+    }
+    ui::success("Reapplied the local changes");
+    Ok(())
+}
+
+/// Pulls `repo`, rebasing local commits onto upstream.
+pub fn pull(repo: &Path, noconfirm: bool) -> Result<()> {
+    let output = git(repo, &["pull", "--rebase"])?;
+    if output.status.success() {
+        return Ok(());
+    }
+    if !rebasing(repo) {
+        bail!("Could not pull {:?}: {}", repo, stderr(&output));
+    }
+
+    ui::warning(&format!("Local commits in {:?} conflict with upstream", repo));
+    while rebasing(repo) {
+        let conflicts = conflicted(repo)?;
+        if conflicts.is_empty() {
+            git(repo, &["rebase", "--abort"])?;
+            bail!("Could not rebase the local commits in {:?} onto upstream", repo);
+        }
+        for file in &conflicts {
+            // During a rebase ours is upstream and theirs the local commit
+            let side = choose(repo, file, noconfirm)?;
+            take(repo, file, if side == Side::Mine { "--theirs" } else { "--ours" })?;
+        }
+
+        let output = git(repo, &["-c", "core.editor=true", "rebase", "--continue"])?;
+        if !output.status.success() && conflicted(repo)?.is_empty() {
+            // Taking upstream's side everywhere leaves the commit empty
+            git(repo, &["rebase", "--skip"])?;
+        }
+    }
+    ui::success("Rebased the local commits onto upstream");
+    Ok(())
+}
+
+fn rebasing(repo: &Path) -> bool {
+    repo.join(".git/rebase-merge").exists() || repo.join(".git/rebase-apply").exists()
+}
+
+fn conflicted(repo: &Path) -> Result<Vec<String>> {
+    let output = git(repo, &["diff", "--name-only", "--diff-filter=U"])?;
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+/// Asks which version of `file` to keep. Without anyone to ask the user's
+/// version is kept.
+fn choose(repo: &Path, file: &str, noconfirm: bool) -> Result<Side> {
+    if !noconfirm {
+        loop {
+            let choice = ui::select(
+                &format!("{} was changed both by you and upstream", file),
+                &["Keep mine", "Take upstream", "Show diff"],
+            );
+            match choice {
+                Some(0) => return Ok(Side::Mine),
+                Some(1) => return Ok(Side::Upstream),
+                Some(_) => {
+                    let diff = git(repo, &["diff", "--", file])?;
+                    ui::block(&String::from_utf8_lossy(&diff.stdout));
+                }
+                None => break,
+            }
+        }
+    }
+    ui::warning(&format!("{}: keeping your version", file));
+    Ok(Side::Mine)
+}
+
+/// Resolves `file` to the `--ours` or `--theirs` side, or removes it when
+/// that side deleted it.
+fn take(repo: &Path, file: &str, side: &str) -> Result<()> {
+    let output = git(repo, &["checkout", side, "--", file])?;
+    let output = if output.status.success() {
+        git(repo, &["add", "--", file])?
+    } else {
+        git(repo, &["rm", "-q", "--", file])?
+    };
+    if !output.status.success() {
+        bail!("Could not resolve {}: {}", file, stderr(&output));
+    }
+    log::log(&format!("Resolved {} with {}", file, side));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{around_local_changes, pull};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn git(repo: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com", "-c", "commit.gpgsign=false"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+
+    /// An empty directory for a test's repos, removed when it starts.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("caelestia-merge-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn commit(repo: &Path, file: &str, content: &str, message: &str) {
+        fs::write(repo.join(file), content).unwrap();
+        git(repo, &["add", file]);
+        git(repo, &["commit", "-q", "-m", message]);
+    }
+
+    /// A clone of an upstream repo with one commit of `file`.
+    fn clone_with(dir: &Path, file: &str, content: &str) -> (PathBuf, PathBuf) {
+        let upstream = dir.join("upstream");
+        let clone = dir.join("clone");
+        fs::create_dir_all(&upstream).unwrap();
+        git(&upstream, &["init", "-q", "-b", "main"]);
+        commit(&upstream, file, content, "initial");
+        git(dir, &["clone", "-q", upstream.to_str().unwrap(), clone.to_str().unwrap()]);
+        // The rebase commits as whoever is configured
+        git(&clone, &["config", "user.name", "Test"]);
+        git(&clone, &["config", "user.email", "test@example.com"]);
+        (upstream, clone)
+    }
+
+    #[test]
+    fn keeps_uncommitted_edits_across_an_update() {
+        let dir = scratch("stash");
+        let (upstream, clone) = clone_with(&dir, "hyprland.conf", "gaps = 5\n");
+        commit(&upstream, "other.conf", "upstream\n", "add other");
+        fs::write(clone.join("hyprland.conf"), "gaps = 10\n").unwrap();
+
+        around_local_changes(&clone, true, || pull(&clone, true)).unwrap();
+
+        assert_eq!(fs::read_to_string(clone.join("hyprland.conf")).unwrap(), "gaps = 10\n");
+        assert_eq!(fs::read_to_string(clone.join("other.conf")).unwrap(), "upstream\n");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn keeps_the_users_side_of_conflicting_edits_without_confirmation() {
+        let dir = scratch("conflict");
+        let (upstream, clone) = clone_with(&dir, "hyprland.conf", "gaps = 5\n");
+        commit(&upstream, "hyprland.conf", "gaps = 0\n", "upstream change");
+        fs::write(clone.join("hyprland.conf"), "gaps = 10\n").unwrap();
+
+        around_local_changes(&clone, true, || pull(&clone, true)).unwrap();
+
+        assert_eq!(fs::read_to_string(clone.join("hyprland.conf")).unwrap(), "gaps = 10\n");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn rebases_local_commits_keeping_the_users_side() {
+        let dir = scratch("rebase");
+        let (upstream, clone) = clone_with(&dir, "hyprland.conf", "gaps = 5\n");
+        commit(&upstream, "hyprland.conf", "gaps = 0\n", "upstream change");
+        commit(&upstream, "other.conf", "upstream\n", "add other");
+        commit(&clone, "hyprland.conf", "gaps = 10\n", "local change");
+
+        pull(&clone, true).unwrap();
+
+        assert_eq!(fs::read_to_string(clone.join("hyprland.conf")).unwrap(), "gaps = 10\n");
+        assert_eq!(fs::read_to_string(clone.join("other.conf")).unwrap(), "upstream\n");
+        assert!(!clone.join(".git/rebase-merge").exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
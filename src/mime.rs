@@ -0,0 +1,204 @@
+//! Default applications, set with xdg-mime and xdg-settings in
+//! `~/.config/mimeapps.list`: the file manager for folders, the browser for
+//! links, an image viewer for the screenshots grim and swappy save, and the
+//! terminal for `x-scheme-handler/terminal`. Each follows the `[default_apps]`
+//! table of the config file, then the keybinds' choices, then what is
+//! installed.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{journal, keybinds, log, summary, ui};
+
+/// Desktop entries chosen in the config file, by name such as
+/// `thunar.desktop`. Unset ones are picked by [`setup`].
+#[derive(Clone, Debug, Default)]
+pub struct Defaults {
+    pub file_manager: Option<String>,
+    pub browser: Option<String>,
+    pub image_viewer: Option<String>,
+    pub terminal: Option<String>,
+}
+
+const FILE_MANAGERS: &[&str] = &["thunar.desktop", "org.gnome.Nautilus.desktop", "org.kde.dolphin.desktop", "nemo.desktop"];
+
+/// Image viewers looked for, in order of preference.
+const IMAGE_VIEWERS: &[&str] = &[
+    "org.gnome.Loupe.desktop",
+    "imv.desktop",
+    "org.gnome.eog.desktop",
+    "org.xfce.ristretto.desktop",
+    "org.kde.gwenview.desktop",
+    "feh.desktop",
+];
+
+const DIRECTORY_TYPES: &[&str] = &["inode/directory"];
+const BROWSER_TYPES: &[&str] = &["x-scheme-handler/http", "x-scheme-handler/https", "text/html"];
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp", "image/bmp"];
+const TERMINAL_TYPES: &[&str] = &["x-scheme-handler/terminal"];
+
+/// Directories desktop entries are installed in.
+fn application_dirs() -> Vec<PathBuf> {
+    let data = dirs::data_dir().unwrap_or_else(|| PathBuf::from("~/.local/share"));
+    vec![
+        data.join("applications"),
+        data.join("flatpak/exports/share/applications"),
+        PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+        PathBuf::from("/usr/local/share/applications"),
+        PathBuf::from("/usr/share/applications"),
+    ]
+}
+
+fn entry_exists(entry: &str) -> bool {
+    application_dirs().iter().any(|dir| dir.join(entry).exists())
+}
+
+/// The desktop entry that runs `command`, such as `firefox.desktop` for
+/// `firefox` or the app id for `flatpak run <id>`.
+fn entry_for_command(command: &str) -> Option<String> {
+    let mut words = command.split_whitespace();
+    let program = words.next()?;
+    if program == "flatpak" {
+        let id = words.find(|word| *word != "run" && !word.starts_with('-'))?;
+        let entry = format!("{}.desktop", id);
+        return entry_exists(&entry).then_some(entry);
+    }
+    let program = Path::new(program).file_name()?.to_string_lossy().into_owned();
+    let named = format!("{}.desktop", program);
+    if entry_exists(&named) {
+        return Some(named);
+    }
+    application_dirs()
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .find(|entry| {
+            fs::read_to_string(entry.path()).is_ok_and(|content| {
+                content
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("Exec="))
+                    .filter_map(|exec| exec.split_whitespace().next())
+                    .any(|exec| Path::new(exec).file_name().is_some_and(|name| name.to_string_lossy() == program))
+            })
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+/// The entry configured for a slot, with `.desktop` added when left out.
+/// A configured entry that is not installed is reported and left unset.
+fn configured(entry: &Option<String>, what: &str) -> Option<Option<String>> {
+    let entry = entry.as_ref()?;
+    let entry = if entry.ends_with(".desktop") { entry.clone() } else { format!("{}.desktop", entry) };
+    if entry_exists(&entry) {
+        Some(Some(entry))
+    } else {
+        ui::warning(&format!("{} for the {} is not installed", entry, what));
+        Some(None)
+    }
+}
+
+/// Picks the entries for the slots `defaults` leaves open and sets each
+/// one that is not the default already.
+pub fn setup(defaults: &Defaults, choices: &keybinds::Choices, dry_run: bool) -> Result<()> {
+    if which::which("xdg-mime").is_err() {
+        if dry_run {
+            ui::success("Would set the default applications with xdg-mime (dry-run)");
+            return Ok(());
+        }
+        bail!("xdg-mime not found, install xdg-utils");
+    }
+
+    let file_manager = configured(&defaults.file_manager, "file manager").unwrap_or_else(|| {
+        choices
+            .file_manager
+            .as_deref()
+            .and_then(entry_for_command)
+            .or_else(|| FILE_MANAGERS.iter().find(|entry| entry_exists(entry)).map(|entry| entry.to_string()))
+    });
+    let browser = configured(&defaults.browser, "browser").unwrap_or_else(|| {
+        choices
+            .browser
+            .clone()
+            .or_else(keybinds::detect_browser)
+            .as_deref()
+            .and_then(entry_for_command)
+    });
+    let image_viewer = configured(&defaults.image_viewer, "image viewer").unwrap_or_else(|| {
+        IMAGE_VIEWERS.iter().find(|entry| entry_exists(entry)).map(|entry| entry.to_string())
+    });
+    let terminal = configured(&defaults.terminal, "terminal")
+        .unwrap_or_else(|| entry_for_command(choices.terminal.as_deref().unwrap_or("foot")));
+
+    // Journals mimeapps.list as it is, so a rollback undoes what xdg-mime
+    // changes in it below
+    let mimeapps = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("mimeapps.list");
+    if !dry_run {
+        if let Some(parent) = mimeapps.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        journal::write_file(&mimeapps, fs::read(&mimeapps).unwrap_or_default())?;
+    }
+
+    let mut set = 0;
+    for (what, entry, types) in [
+        ("file manager", file_manager, DIRECTORY_TYPES),
+        ("browser", browser, BROWSER_TYPES),
+        ("image viewer", image_viewer, IMAGE_TYPES),
+        ("terminal", terminal, TERMINAL_TYPES),
+    ] {
+        let Some(entry) = entry else {
+            ui::info(&format!("No {} found, leaving its default alone", what));
+            continue;
+        };
+        if set_default(what, &entry, types, dry_run)? {
+            set += 1;
+        }
+    }
+    if set > 0 && !dry_run {
+        summary::artifact(&format!("{} defaults", set));
+    }
+    Ok(())
+}
+
+/// The entry xdg-mime opens `mime_type` with.
+fn current(mime_type: &str) -> Option<String> {
+    let output = Command::new("xdg-mime").args(["query", "default", mime_type]).output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|entry| !entry.is_empty())
+}
+
+/// Makes `entry` the default for `types`, returning whether anything
+/// changed. The browser also becomes xdg-settings' default web browser.
+fn set_default(what: &str, entry: &str, types: &[&str], dry_run: bool) -> Result<bool> {
+    if types.iter().all(|mime_type| current(mime_type).as_deref() == Some(entry)) {
+        ui::success(&format!("{} is already the default {}", entry, what));
+        return Ok(false);
+    }
+
+    let mut commands = vec![[vec!["xdg-mime", "default", entry], types.to_vec()].concat()];
+    if what == "browser" {
+        commands.push(vec!["xdg-settings", "set", "default-web-browser", entry]);
+    }
+    for args in commands {
+        log::log_command(&args.join(" "));
+        if dry_run {
+            continue;
+        }
+        let output = Command::new(args[0]).args(&args[1..]).output()?;
+        if !output.status.success() {
+            log::log_error(&String::from_utf8_lossy(&output.stderr));
+            ui::warning(&format!("Could not set {} as the default {}", entry, what));
+            return Ok(false);
+        }
+    }
+
+    if dry_run {
+        ui::success(&format!("Would make {} the default {} (dry-run)", entry, what));
+    } else {
+        ui::success(&format!("{} is the default {}", entry, what));
+    }
+    Ok(true)
+}
@@ -0,0 +1,84 @@
+use anyhow::{bail, Result};
+use std::sync::Mutex;
+
+use crate::offline;
+
+static LIMIT_RATE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Validates a rate such as `500K`, `2M` or `1G` (bytes per second).
+/// The same syntax is understood by dnf's `throttle` option, and the
+/// installer's own downloads follow it through [`limit_bytes`].
+pub fn parse_rate(rate: &str) -> Result<String> {
+    let digits = rate.trim_end_matches(['k', 'K', 'm', 'M', 'g', 'G']);
+    let suffix = &rate[digits.len()..];
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) || suffix.len() > 1 {
+        bail!("invalid rate {:?}, expected e.g. 500K, 2M or 1G", rate);
+    }
+
+    Ok(rate.to_string())
+}
+
+pub fn set_limit_rate(rate: Option<String>) {
+    *LIMIT_RATE.lock().unwrap() = rate;
+}
+
+pub fn limit_rate() -> Option<String> {
+    LIMIT_RATE.lock().unwrap().clone()
+}
+
+/// The configured rate limit in bytes per second, for downloads made by the
+/// installer itself.
+pub fn limit_bytes() -> Option<u64> {
+    rate_bytes(&limit_rate()?)
+}
+
+/// A rate [`parse_rate`] accepted, in bytes per second.
+fn rate_bytes(rate: &str) -> Option<u64> {
+    let digits = rate.trim_end_matches(['k', 'K', 'm', 'M', 'g', 'G']);
+    let multiplier = match rate[digits.len()..].to_ascii_lowercase().as_str() {
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => 1,
+    };
+    digits.parse::<u64>().ok().map(|n| n * multiplier)
+}
+
+/// Extra dnf arguments applying the configured rate limit, or installing
+/// from the offline cache.
+pub fn dnf_args() -> Vec<String> {
+    let mut args = offline::dnf_args();
+    if let Some(rate) = limit_rate() {
+        args.push(format!("--setopt=throttle={}", rate));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rate, rate_bytes};
+
+    #[test]
+    fn accepts_rates_with_a_unit() {
+        for rate in ["500", "500K", "2M", "2m", "1G"] {
+            assert_eq!(parse_rate(rate).unwrap(), rate);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_rates() {
+        for rate in ["", "M", "2MB", "2MM", "1.5M", "-2M", "2 M", "fast"] {
+            assert!(parse_rate(rate).is_err(), "{:?} was accepted", rate);
+        }
+    }
+
+    #[test]
+    fn converts_rates_to_bytes() {
+        assert_eq!(rate_bytes("500"), Some(500));
+        assert_eq!(rate_bytes("500K"), Some(500 * 1024));
+        assert_eq!(rate_bytes("2m"), Some(2 * 1024 * 1024));
+        assert_eq!(rate_bytes("1G"), Some(1024 * 1024 * 1024));
+        assert_eq!(rate_bytes("M"), None);
+    }
+}
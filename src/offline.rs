@@ -0,0 +1,325 @@
+//! `--offline`: installs from a cache filled by `caelestia-installer
+//! prefetch` on a machine with network access, for air-gapped machines
+//! running the same Fedora release. The cache holds
+//!
+//! - `repos/<name>.git`: mirrors of the git repositories, which git is
+//!   pointed at instead of the upstream URLs
+//! - `downloads/`: fonts and release assets, named after their URLs
+//! - `rpms/`: the packages and all their dependencies, as a dnf repository
+//! - `keys/`: the signing keys of the COPR repositories
+//! - `wheels/`: the Python packages caelestia-cli is installed with
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::install::COMPONENTS;
+use crate::packages::{self, Backend};
+use crate::shell::Shell;
+use crate::{checks, checksums, cli, display_manager, dotfiles, download, log, repos, system, ui, versions, wallpapers};
+
+/// Name of the dnf repository made from `rpms/`.
+const REPO_ID: &str = "caelestia-offline";
+
+/// Installed from the cache instead of through rustup, which needs the network.
+pub const RUST_PACKAGES: &[&str] = &["rust", "cargo"];
+
+/// Build dependencies of caelestia-cli, which pip would otherwise fetch.
+const BUILD_WHEELS: &[&str] = &["hatchling", "hatch-vcs"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// `prefetch`: downloads are copied into the cache.
+    Prefetch,
+    /// `--offline`: downloads are taken from the cache.
+    Offline,
+}
+
+static CACHE: Mutex<Option<(Mode, PathBuf)>> = Mutex::new(None);
+
+/// The git repositories the installer clones, by cache name.
+fn repositories() -> [(&'static str, &'static str); 8] {
+    [
+        ("quickshell", packages::QUICKSHELL_REPO),
+        ("caelestia", dotfiles::DOTFILES_REPO),
+        ("shell", dotfiles::SHELL_REPO),
+        ("cli", cli::CLI_REPO),
+        ("cava", packages::CAVA_REPO),
+        ("hyprland-qt-support", packages::QT_SUPPORT_REPO),
+        ("hyprland-qtutils", packages::QTUTILS_REPO),
+        ("wallpapers", wallpapers::WALLPAPERS_REPO),
+    ]
+}
+
+fn cache(mode: Mode) -> Option<PathBuf> {
+    match &*CACHE.lock().unwrap() {
+        Some((current, dir)) if *current == mode => Some(dir.clone()),
+        _ => None,
+    }
+}
+
+pub fn enabled() -> bool {
+    cache_dir().is_some()
+}
+
+/// The cache installed from with `--offline`.
+pub fn cache_dir() -> Option<PathBuf> {
+    cache(Mode::Offline)
+}
+
+/// Switches to installing from the cache in `dir`. Git and pip are pointed
+/// at it through their environment variables, so this must run before any
+/// threads are started.
+pub fn set_offline(dir: &Path) -> Result<()> {
+    if packages::backend() == Backend::RpmOstree {
+        bail!("--offline is not supported on image-based Fedora");
+    }
+    for sub in ["repos", "downloads", "rpms/repodata", "wheels"] {
+        if !dir.join(sub).exists() {
+            bail!(
+                "{:?} is not a complete cache ({} is missing), fill it with 'caelestia-installer prefetch --cache-dir {}'",
+                dir,
+                sub,
+                dir.display()
+            );
+        }
+    }
+    let dir = fs::canonicalize(dir)?;
+
+    let repositories = repositories();
+    std::env::set_var("GIT_CONFIG_COUNT", repositories.len().to_string());
+    for (i, (name, url)) in repositories.iter().enumerate() {
+        let mirror = dir.join("repos").join(format!("{}.git", name));
+        std::env::set_var(format!("GIT_CONFIG_KEY_{}", i), format!("url.file://{}.insteadOf", mirror.display()));
+        std::env::set_var(format!("GIT_CONFIG_VALUE_{}", i), url);
+    }
+    std::env::set_var("PIP_NO_INDEX", "1");
+    std::env::set_var("PIP_FIND_LINKS", dir.join("wheels"));
+
+    log::log(&format!("Installing offline from {:?}", dir));
+    *CACHE.lock().unwrap() = Some((Mode::Offline, dir));
+    Ok(())
+}
+
+/// Where the download of `url` is kept in the cache.
+fn download_path(dir: &Path, url: &str) -> PathBuf {
+    let hash: String = Sha256::digest(url.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let name = url.rsplit('/').next().unwrap_or("download");
+    dir.join("downloads").join(format!("{}-{}", hash, name))
+}
+
+/// The cached copy of `url` when installing offline; missing ones fail, as
+/// there is no network to fall back to.
+pub fn cached(url: &str) -> Result<Option<PathBuf>> {
+    let Some(dir) = cache(Mode::Offline) else {
+        return Ok(None);
+    };
+    let path = download_path(&dir, url);
+    if !path.exists() {
+        bail!("{} is not in the offline cache {:?}", url, dir);
+    }
+    Ok(Some(path))
+}
+
+/// Keeps a copy of the download of `url` when prefetching.
+pub fn store(url: &str, path: &Path) -> Result<()> {
+    if let Some(dir) = cache(Mode::Prefetch) {
+        let target = download_path(&dir, url);
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(path, &target)?;
+        log::log(&format!("Cached {} as {:?}", url, target));
+    }
+    Ok(())
+}
+
+/// Extra dnf arguments installing from the cached repository only, checking
+/// packages against the Fedora key and the cached COPR keys.
+pub fn dnf_args() -> Vec<String> {
+    let Some(dir) = cache(Mode::Offline) else {
+        return Vec::new();
+    };
+    let mut keys = vec![format!(
+        "file:///etc/pki/rpm-gpg/RPM-GPG-KEY-fedora-{}-{}",
+        checks::fedora_version().unwrap_or_default(),
+        std::env::consts::ARCH
+    )];
+    if let Ok(entries) = fs::read_dir(dir.join("keys")) {
+        keys.extend(entries.flatten().map(|entry| format!("file://{}", entry.path().display())));
+    }
+    vec![
+        format!("--repofrompath={},{}", REPO_ID, dir.join("rpms").display()),
+        format!("--repo={}", REPO_ID),
+        format!("--setopt={}.gpgkey={}", REPO_ID, keys.join(",")),
+    ]
+}
+
+/// Every package an installation can ask for, whatever the profile,
+/// components and shell chosen.
+fn all_packages() -> Vec<String> {
+    let ids: Vec<&str> = packages::GROUPS.iter().map(|group| group.id).collect();
+    let mut packages = packages::group_packages(&ids);
+    let extra = COMPONENTS
+        .iter()
+        .flat_map(|component| component.packages.iter())
+        .chain([Shell::Fish, Shell::Zsh, Shell::Bash].iter().flat_map(|shell| shell.packages().iter()))
+        .chain(display_manager::ALL.iter().flat_map(|dm| dm.packages().iter()))
+        .chain(RUST_PACKAGES.iter());
+    for package in extra {
+        if !packages.iter().any(|p| p == package) {
+            packages.push(package.to_string());
+        }
+    }
+    packages
+}
+
+/// `caelestia-installer prefetch`: fills the cache in `dir` with everything
+/// an offline installation needs.
+pub fn prefetch(dir: &Path, dry_run: bool) -> Result<()> {
+    ui::info(&format!("Prefetching into {:?}...", dir));
+    if dry_run {
+        ui::success(&format!("Would fill {:?} with the repositories, downloads and packages (dry-run)", dir));
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    let dir = fs::canonicalize(dir)?;
+    *CACHE.lock().unwrap() = Some((Mode::Prefetch, dir.clone()));
+
+    ui::info("Mirroring git repositories...");
+    for (name, url) in repositories() {
+        mirror(&dir.join("repos").join(format!("{}.git", name)), url)?;
+    }
+
+    ui::info("Downloading fonts and release assets...");
+    let scratch = system::build_root().join("prefetch");
+    for url in packages::download_urls() {
+        let path = scratch.join(url.rsplit('/').next().unwrap_or("download"));
+        download::download(&url, &path, checksums::sha256(&url)?.as_deref())?;
+        fs::remove_file(&path).ok();
+    }
+
+    ui::info("Downloading packages and their dependencies...");
+    download_rpms(&dir)?;
+
+    ui::info("Downloading Python packages for caelestia-cli...");
+    download_wheels(&dir, &scratch)?;
+    fs::remove_dir_all(&scratch).ok();
+
+    ui::success(&format!(
+        "Cache ready, install with: caelestia-installer --offline --cache-dir {}",
+        dir.display()
+    ));
+    Ok(())
+}
+
+/// Clones a bare mirror of `url`, or updates the one already there.
+fn mirror(path: &Path, url: &str) -> Result<()> {
+    let path_str = path.to_string_lossy();
+    let args: Vec<&str> = if path.exists() {
+        vec!["-C", &path_str, "remote", "update", "--prune"]
+    } else {
+        vec!["clone", "--mirror", url, &path_str]
+    };
+    git(&args)?;
+    ui::success(&format!("Mirrored {}", url));
+    Ok(())
+}
+
+fn git(args: &[&str]) -> Result<()> {
+    log::log_command(&format!("git {}", args.join(" ")));
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("git {} failed: {}", args.join(" "), stderr.trim());
+    }
+    Ok(())
+}
+
+/// Downloads the packages with all their dependencies, from the system's
+/// repositories and the COPRs, and turns them into a repository.
+fn download_rpms(dir: &Path) -> Result<()> {
+    if which::which("createrepo_c").is_err() {
+        bail!("createrepo_c is needed to prefetch packages: sudo dnf install createrepo_c");
+    }
+    let rpms = dir.join("rpms");
+    let keys = dir.join("keys");
+    fs::create_dir_all(&keys)?;
+
+    let mut args = vec![
+        "download".to_string(),
+        "--resolve".to_string(),
+        "--alldeps".to_string(),
+        format!("--destdir={}", rpms.display()),
+    ];
+    for (i, repo) in repos::COPR_REPOS.iter().enumerate() {
+        args.push(format!("--repofrompath=prefetch-copr-{},{}", i, repos::copr_baseurl(repo)));
+
+        let key = keys.join(format!("{}.gpg", repo.replace('/', "-")));
+        download::download(&repos::copr_pubkey_url(repo), &key, None)?;
+    }
+    args.extend(all_packages());
+
+    log::log_command(&format!("dnf {}", args.join(" ")));
+    let output = Command::new("dnf").args(&args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to download the packages");
+    }
+
+    let rpms_str = rpms.to_string_lossy();
+    log::log_command(&format!("createrepo_c --update {}", rpms_str));
+    let output = Command::new("createrepo_c").args(["--update", &rpms_str]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to create the package repository in {:?}", rpms);
+    }
+
+    let count = fs::read_dir(&rpms)?
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rpm"))
+        .count();
+    ui::success(&format!("{} packages in {:?}", count, rpms));
+    Ok(())
+}
+
+/// Downloads caelestia-cli's dependencies and build dependencies as wheels
+/// and source archives, for pip to install from without an index.
+fn download_wheels(dir: &Path, scratch: &Path) -> Result<()> {
+    let checkout = scratch.join("caelestia-cli");
+    if checkout.exists() {
+        fs::remove_dir_all(&checkout)?;
+    }
+    let mirror = dir.join("repos/cli.git");
+    git(&["clone", &mirror.to_string_lossy(), &checkout.to_string_lossy()])?;
+    if let Some(revision) = versions::pinned("cli")? {
+        versions::checkout(&checkout, &revision)?;
+    }
+
+    let wheels = dir.join("wheels");
+    let mut args = vec![
+        "download".to_string(),
+        "--dest".to_string(),
+        wheels.to_string_lossy().into_owned(),
+    ];
+    args.extend(BUILD_WHEELS.iter().map(|wheel| wheel.to_string()));
+    args.push(checkout.to_string_lossy().into_owned());
+
+    log::log_command(&format!("pip3 {}", args.join(" ")));
+    let output = Command::new("pip3").args(&args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to download the Python packages");
+    }
+    ui::success(&format!("Python packages in {:?}", wheels));
+    Ok(())
+}
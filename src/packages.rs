@@ -1,7 +1,10 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 
-use crate::{log, ui};
+use crate::{checks, checksums, dnf, download, localrpm, log, offline, prebuilt, preview, privileged, repos, runner, sudo, summary, toolbox, ui, versions};
 
 // Critical Qt packages required for building Quickshell
 const CRITICAL_QT_PACKAGES: &[&str] = &[
@@ -14,195 +17,686 @@ const CRITICAL_QT_PACKAGES: &[&str] = &[
     "qt6-qtconnectivity-devel",  // For Bluetooth (required by Quickshell)
 ];
 
-const PACKAGES: &[&str] = &[
-    // Hyprland and Wayland
-    "hyprland",
-    // "hyprland-qtutils",
-    "xdg-desktop-portal-hyprland",
-    "xdg-desktop-portal-gtk",
-    "hyprutils-devel",
-    "hyprlang-devel",
-    // Terminal
-    "foot",
-    // Shell and tools
-    "fish",
-    // Greetd
-    "greetd",
-    "tuigreet",
-    // Qt6 (for building quickshell)
-    "qt6-qtbase-devel",
-    "qt6-qtbase-private-devel",     // For Qt6 private APIs (QuickPrivate)
-    "qt6-qtdeclarative-devel",
-    "qt6-qtdeclarative-static",
-    "qt6-qtbase-static",
-    "qt6-qtwayland-devel",
-    "qt6-qtsvg-devel",
-    "qt6-qtshadertools-devel",
-    "qt6-qtconnectivity-devel",     // For Bluetooth (required by Quickshell)
-    "spirv-tools",
-    "cli11-devel",
-    "jemalloc-devel",
-    // Wayland
-    "wayland-devel",
-    "wayland-protocols-devel",
-    "libdrm-devel",
-    "mesa-libgbm-devel",
-    "pipewire-devel",
-    // Quickshell optional deps
-    "polkit-devel",
-    "pam-devel",
-    "pkgconf-pkg-config",
-    "libqalculate-devel",
-    "aubio-devel",
-    // Cava build deps
-    "alsa-lib-devel",
-    "fftw-devel",
-    "pulseaudio-libs-devel",
-    "autoconf-archive",
-    "iniparser-devel",
-    "libtool",
-    // Build tools
+/// A set of packages installed together, chosen with `--profile`,
+/// `--with` and `--without`.
+pub struct Group {
+    pub id: &'static str,
+    pub label: &'static str,
+    pub packages: &'static [&'static str],
+}
+
+pub const GROUPS: &[Group] = &[
+    Group {
+        id: "core",
+        label: "Hyprland, the terminal and what the shell and keybinds run",
+        packages: &[
+            // Hyprland and Wayland
+            "hyprland",
+            // "hyprland-qtutils",
+            "xdg-desktop-portal-hyprland",
+            "xdg-desktop-portal-gtk",
+            // Terminal
+            "foot",
+            // Shell and tools
+            "fish",
+            "git",
+            "curl",
+            "tar",
+            "unzip",
+            "pciutils",
+            // caelestia-cli dependencies
+            "libnotify",
+            "fuzzel",
+            // Fonts
+            "google-noto-fonts-common",
+            "google-noto-sans-fonts",
+            "google-rubik-fonts",
+            "fontawesome-fonts",
+            // Screenshots, media keys and the session
+            "wl-clipboard",
+            "grim",
+            "slurp",
+            "swappy",
+            "brightnessctl",
+            "playerctl",
+            "pamixer",
+            "NetworkManager",
+            "lxpolkit",
+        ],
+    },
+    Group {
+        id: "build-deps",
+        label: "Compilers and libraries to build Quickshell, the shell, cava and the CLI",
+        packages: &[
+            "hyprutils-devel",
+            "hyprlang-devel",
+            // Qt6 (for building quickshell)
+            "qt6-qtbase-devel",
+            "qt6-qtbase-private-devel",     // For Qt6 private APIs (QuickPrivate)
+            "qt6-qtdeclarative-devel",
+            "qt6-qtdeclarative-static",
+            "qt6-qtbase-static",
+            "qt6-qtwayland-devel",
+            "qt6-qtsvg-devel",
+            "qt6-qtshadertools-devel",
+            "qt6-qtconnectivity-devel",     // For Bluetooth (required by Quickshell)
+            "spirv-tools",
+            "cli11-devel",
+            "jemalloc-devel",
+            // Wayland
+            "wayland-devel",
+            "wayland-protocols-devel",
+            "libdrm-devel",
+            "mesa-libgbm-devel",
+            "pipewire-devel",
+            // Quickshell optional deps
+            "polkit-devel",
+            "pam-devel",
+            "pkgconf-pkg-config",
+            "libqalculate-devel",
+            "aubio-devel",
+            // Cava build deps
+            "alsa-lib-devel",
+            "fftw-devel",
+            "pulseaudio-libs-devel",
+            "autoconf-archive",
+            "iniparser-devel",
+            "libtool",
+            // Build tools
+            "cmake",
+            "ninja-build",
+            "gcc-c++",
+            // Python build tools for caelestia-cli
+            "python3-devel",
+            "python3-build",
+            "python3-hatchling",
+            "python3-pip",
+            "glib2-devel",
+        ],
+    },
+    Group {
+        id: "theming",
+        label: "GTK and icon themes",
+        packages: &["adw-gtk3-theme", "papirus-icon-theme"],
+    },
+    Group {
+        id: "utilities",
+        label: "Command-line tools the dotfiles configure (eza, fastfetch, btop)",
+        packages: &["eza", "fastfetch", "btop"],
+    },
+    Group {
+        id: "apps",
+        label: "Desktop apps: Firefox, volume and network settings",
+        packages: &["firefox", "pavucontrol", "nm-connection-editor"],
+    },
+];
+
+/// A preset selection of [`GROUPS`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Profile {
+    /// Only what the desktop needs to run and be built.
+    Minimal,
+    #[default]
+    Default,
+    /// Every group, apps included.
+    Full,
+}
+
+impl Profile {
+    pub fn groups(self) -> &'static [&'static str] {
+        match self {
+            Profile::Minimal => &["core", "build-deps"],
+            Profile::Default => &["core", "build-deps", "theming", "utilities"],
+            Profile::Full => &["core", "build-deps", "theming", "utilities", "apps"],
+        }
+    }
+}
+
+pub fn parse_profile(profile: &str) -> Result<Profile> {
+    match profile {
+        "minimal" => Ok(Profile::Minimal),
+        "default" => Ok(Profile::Default),
+        "full" => Ok(Profile::Full),
+        _ => bail!("unknown profile {:?}, expected minimal, default or full", profile),
+    }
+}
+
+pub fn parse_group(id: &str) -> Result<String> {
+    match GROUPS.iter().find(|group| group.id == id) {
+        Some(group) => Ok(group.id.to_string()),
+        None => {
+            let ids: Vec<&str> = GROUPS.iter().map(|group| group.id).collect();
+            bail!("unknown package group {:?}, expected one of {}", id, ids.join(", "))
+        }
+    }
+}
+
+/// The groups of `profile`, plus `with` and minus `without`. The core group
+/// cannot be left out.
+pub fn select_groups(profile: Profile, with: &[String], without: &[String]) -> Result<Vec<&'static str>> {
+    if without.iter().any(|id| id == "core") {
+        bail!("the core package group cannot be left out");
+    }
+    Ok(GROUPS
+        .iter()
+        .map(|group| group.id)
+        .filter(|id| profile.groups().contains(id) || with.iter().any(|w| w == id))
+        .filter(|id| !without.iter().any(|w| w == id))
+        .collect())
+}
+
+/// The packages of `groups`, in the order of [`GROUPS`].
+pub fn group_packages(groups: &[&str]) -> Vec<String> {
+    let packages: Vec<&str> = GROUPS
+        .iter()
+        .filter(|group| groups.contains(&group.id))
+        .flat_map(|group| group.packages.iter().copied())
+        .collect();
+    for_release(&packages, checks::release())
+}
+
+/// A package whose name differs between Fedora releases.
+struct Adjustment {
+    package: &'static str,
+    /// First release the adjustment applies to.
+    since: u32,
+    /// Its name from that release on, or `None` when it is no longer
+    /// packaged and is left out.
+    replacement: Option<&'static str>,
+}
+
+const ADJUSTMENTS: &[Adjustment] = &[
+    // Mesa 25 split GBM into its own source package
+    Adjustment { package: "mesa-libgbm-devel", since: 42, replacement: Some("libgbm-devel") },
+];
+
+/// Renames or drops `packages` for `release`, so dnf is not asked for
+/// names the release does not have. An unknown release keeps the names.
+pub fn for_release(packages: &[&str], release: Option<checks::Release>) -> Vec<String> {
+    let mut adjusted = Vec::with_capacity(packages.len());
+    for package in packages {
+        let adjustment = ADJUSTMENTS
+            .iter()
+            .find(|a| a.package == *package && release.is_some_and(|r| r.at_least(a.since)));
+        match adjustment {
+            Some(Adjustment { replacement: Some(name), .. }) => {
+                log::log(&format!("{} is {} on this release", package, name));
+                adjusted.push(name.to_string());
+            }
+            Some(Adjustment { replacement: None, .. }) => {
+                log::log(&format!("{} is not packaged for this release, leaving it out", package));
+            }
+            None => adjusted.push(package.to_string()),
+        }
+    }
+    adjusted
+}
+
+/// The packages of the default profile.
+pub fn default_packages() -> Vec<String> {
+    group_packages(Profile::Default.groups())
+}
+
+/// Packages that are only needed to build the source components, which on
+/// rpm-ostree systems stay in the toolbox rather than being layered.
+const BUILD_TOOLS: &[&str] = &[
     "cmake",
     "ninja-build",
     "gcc-c++",
-    "git",
-    "curl",
-    "tar",
-    "unzip",
-    // Python build tools for caelestia-cli
-    "python3-devel",
+    "autoconf-archive",
+    "libtool",
+    "spirv-tools",
+    "pkgconf-pkg-config",
     "python3-build",
     "python3-hatchling",
-    "python3-pip",
-    // caelestia-cli dependencies
-    "libnotify",
-    "fuzzel",
-    "glib2-devel",
-    // Theming
-    "adw-gtk3-theme",
-    "papirus-icon-theme",
-    // Fonts
-    "google-noto-fonts-common",
-    "google-noto-sans-fonts",
-    "google-rubik-fonts",
-    "fontawesome-fonts",
-    // Utilities
-    "eza",
-    "fastfetch",
-    "btop",
-    "wl-clipboard",
-    "grim",
-    "slurp",
-    "swappy",
-    "brightnessctl",
-    "playerctl",
-    "pamixer",
-    "NetworkManager",
-    "lxpolkit",
 ];
 
-pub fn install_all(dry_run: bool) -> Result<()> {
+/// Libraries the components built in the toolbox need on the host, layered
+/// in place of their -devel packages.
+const RUNTIME_LIBRARIES: &[&str] = &[
+    "qt6-qtbase",
+    "qt6-qtdeclarative",
+    "qt6-qtwayland",
+    "qt6-qtsvg",
+    "qt6-qtshadertools",
+    "qt6-qtconnectivity",
+    "jemalloc",
+    "libqalculate",
+    "aubio",
+    "fftw-libs",
+    "iniparser",
+    "pipewire-libs",
+    "hyprutils",
+    "hyprlang",
+];
+
+/// Whether `--force-source` builds Quickshell and Cava even when a package
+/// would do.
+static FORCE_SOURCE: Mutex<bool> = Mutex::new(false);
+
+pub fn set_force_source(force: bool) {
+    *FORCE_SOURCE.lock().unwrap() = force;
+}
+
+fn force_source() -> bool {
+    *FORCE_SOURCE.lock().unwrap()
+}
+
+/// How packages get onto the system.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Dnf,
+    /// Image-based Fedora (Silverblue, Kinoite): packages are layered onto
+    /// the image with rpm-ostree.
+    RpmOstree,
+}
+
+pub fn backend() -> Backend {
+    if std::path::Path::new("/run/ostree-booted").exists() {
+        Backend::RpmOstree
+    } else {
+        Backend::Dnf
+    }
+}
+
+/// Command installing `packages` on the host with the system's backend.
+/// Layered packages are applied live so the rest of the install can use them.
+pub fn install_command<S: AsRef<std::ffi::OsStr>>(packages: &[S]) -> Command {
+    let mut command = sudo::command();
+    match backend() {
+        Backend::Dnf => {
+            command.args(dnf::install_args(false));
+        }
+        Backend::RpmOstree => {
+            command.args(["rpm-ostree", "install", "--idempotent", "--allow-inactive", "--apply-live"]);
+        }
+    }
+    command.args(packages);
+    command
+}
+
+/// [`install_command`] as shown in the log.
+pub fn install_command_line(packages: &str) -> String {
+    match backend() {
+        Backend::Dnf => format!("sudo dnf install -y {}", packages),
+        Backend::RpmOstree => format!(
+            "sudo rpm-ostree install --idempotent --allow-inactive --apply-live {}",
+            packages
+        ),
+    }
+}
+
+pub fn install_all(packages: &[String], dry_run: bool, noconfirm: bool) -> Result<()> {
+    if backend() == Backend::RpmOstree {
+        return install_layered(packages, dry_run, noconfirm);
+    }
+    if toolbox::active() {
+        return install_for_container_build(packages, dry_run);
+    }
+
     ui::info("Installing packages via dnf...");
 
-    let pkg_list = PACKAGES.join(" ");
-    let cmd = format!("sudo dnf install -y --allowerasing {}", pkg_list);
+    let pkg_list = packages.join(" ");
+    let cmd = format!("sudo {} {}", dnf::install_args(true).join(" "), pkg_list);
     log::log_command(&cmd);
 
     if dry_run {
         ui::info("Would install the following packages:");
-        for pkg in PACKAGES {
-            println!("  - {}", pkg);
+        for pkg in packages {
+            ui::block(&format!("  - {}", pkg));
         }
+        preview::transaction("dnf", &missing_packages(packages).unwrap_or_else(|_| packages.to_vec()));
         ui::success("Package installation (dry-run: skipped)");
         return Ok(());
     }
 
+    let not_installed = not_installed(packages);
     // Use --allowerasing to resolve conflicts between COPR and official repos
-    let mut args = vec!["dnf", "install", "-y", "--allowerasing"];
-    args.extend(PACKAGES.iter().copied());
-
-    let output = Command::new("sudo").args(&args).output()?;
-
+    let output = sudo::output(sudo::command().args(dnf::install_args(true)).args(packages))?;
     let stdout = String::from_utf8_lossy(&output.stdout);
     log::log_output(&stdout);
 
-    // Check for skipped packages due to conflicts or broken dependencies
-    if stdout.contains("Skipping packages with conflicts") || stdout.contains("Skipping packages with broken dependencies") {
-        ui::warning("Some packages were skipped due to conflicts or broken dependencies");
-        log::log("WARNING: Some packages were skipped due to conflicts or broken dependencies");
-        
-        // Check if critical Qt packages were skipped by looking for package names
-        // in the "Skipping packages" section of the output
-        let mut skipped_critical = Vec::new();
-        
-        // Look for lines containing both "Skipping" context and package names
-        let lines: Vec<&str> = stdout.lines().collect();
-        let mut in_skipping_section = false;
-        
-        for line in &lines {
-            if line.contains("Skipping packages") {
-                in_skipping_section = true;
-                continue;
-            }
-            // End of skipping section when we hit an empty line or new section
-            if in_skipping_section && (line.trim().is_empty() || line.starts_with("Installing") || line.starts_with("Upgrading")) {
-                in_skipping_section = false;
-            }
-            
-            if in_skipping_section {
-                for pkg in CRITICAL_QT_PACKAGES.iter().take(3) { // Check main Qt packages
-                    if line.contains(pkg) {
-                        skipped_critical.push(*pkg);
-                    }
-                }
-            }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install packages");
+    }
+
+    // dnf skips packages with conflicts or broken dependencies and still
+    // succeeds, so check what actually got installed
+    let skipped = dnf::skipped_packages(&stdout);
+    if !skipped.is_empty() {
+        log::warn(&format!("dnf skipped: {}", skipped.join(", ")));
+    }
+    let missing = missing_packages(packages)?;
+    let missing_critical: Vec<&str> = CRITICAL_QT_PACKAGES
+        .iter()
+        .copied()
+        .filter(|pkg| missing.iter().any(|m| m == pkg))
+        .collect();
+
+    if !missing_critical.is_empty() {
+        ui::error("Critical Qt development packages were skipped:");
+        for pkg in &missing_critical {
+            ui::error(&format!("  - {}", pkg));
         }
-        
-        if !skipped_critical.is_empty() {
-            ui::error("Critical Qt development packages were skipped:");
-            for pkg in &skipped_critical {
-                ui::error(&format!("  - {}", pkg));
-            }
-            ui::info("Attempting to install Qt packages with conflict resolution...");
-            
-            // Try to install Qt packages with allowerasing explicitly
-            let mut qt_args = vec!["dnf", "install", "-y", "--allowerasing"];
-            qt_args.extend(CRITICAL_QT_PACKAGES.iter().copied());
-            
-            let qt_output = Command::new("sudo")
-                .args(&qt_args)
-                .output()?;
-            
-            let qt_stdout = String::from_utf8_lossy(&qt_output.stdout);
-            log::log_output(&qt_stdout);
-            
-            if !qt_output.status.success() || qt_stdout.contains("Skipping packages") {
-                let qt_stderr = String::from_utf8_lossy(&qt_output.stderr);
-                log::log_error(&qt_stderr);
-                bail!("Failed to install critical Qt packages. You may need to manually resolve package conflicts.\n\
-                       Try running: sudo dnf install --allowerasing qt6-qtbase-devel qt6-qtdeclarative-devel qt6-qtwayland-devel");
-            }
-            
-            ui::success("Qt packages installed with conflict resolution");
+        ui::info("Attempting to install Qt packages with conflict resolution...");
+
+        // Try to install Qt packages with allowerasing explicitly
+        let qt_output = sudo::output(
+            sudo::command()
+                .args(dnf::install_args(true))
+                .args(&missing_critical),
+        )?;
+        log::log_output(&String::from_utf8_lossy(&qt_output.stdout));
+
+        if !qt_output.status.success() || !missing_packages(&missing_critical)?.is_empty() {
+            let qt_stderr = String::from_utf8_lossy(&qt_output.stderr);
+            log::log_error(&qt_stderr);
+            bail!("Failed to install critical Qt packages. You may need to manually resolve package conflicts.\n\
+                   Try running: sudo dnf install --allowerasing qt6-qtbase-devel qt6-qtdeclarative-devel qt6-qtwayland-devel");
         }
+
+        ui::success("Qt packages installed with conflict resolution");
     }
 
-    if output.status.success() {
-        ui::success("Package installation complete");
-        log::log("Package installation complete");
-        Ok(())
-    } else {
+    let still_missing: Vec<&String> = missing
+        .iter()
+        .filter(|pkg| !missing_critical.contains(&pkg.as_str()))
+        .collect();
+    if !still_missing.is_empty() {
+        ui::warning(&format!("{} requested packages were not installed:", still_missing.len()));
+        for pkg in &still_missing {
+            ui::warning(&format!("  - {}", pkg));
+        }
+        log::warn(&format!(
+            "Packages missing after the transaction: {}",
+            still_missing.iter().map(|pkg| pkg.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+        summary::artifact(&format!("{} missing", still_missing.len()));
+    }
+
+    let added: Vec<String> = not_installed
+        .into_iter()
+        .filter(|pkg| !missing.contains(pkg) || missing_critical.contains(&pkg.as_str()))
+        .collect();
+    record_build_deps(&added);
+    summary::packages_added(added);
+    ui::success("Package installation complete");
+    log::log("Package installation complete");
+    summary::artifact(&format!("{} packages requested", packages.len()));
+    Ok(())
+}
+
+/// The `packages` that `rpm -q` does not find installed in the build
+/// environment, either by package name or as something a package provides.
+pub fn missing_packages<S: AsRef<str>>(packages: &[S]) -> Result<Vec<String>> {
+    let mut missing = Vec::new();
+    for pkg in packages {
+        let pkg = pkg.as_ref();
+        let installed = toolbox::command("rpm").args(["-q", pkg]).output()?.status.success()
+            || toolbox::command("rpm")
+                .args(["-q", "--whatprovides", pkg])
+                .output()?
+                .status
+                .success();
+        if !installed {
+            missing.push(pkg.to_string());
+        }
+    }
+    Ok(missing)
+}
+
+/// The `packages` nothing installed provides, with a single rpm query, for
+/// telling which ones a transaction added.
+fn not_installed(packages: &[String]) -> Vec<String> {
+    not_installed_by(toolbox::command("rpm"), packages)
+}
+
+/// Like [`not_installed`], querying with the rpm of `rpm`.
+fn not_installed_by(mut rpm: Command, packages: &[String]) -> Vec<String> {
+    let Ok(output) = rpm.args(["-q", "--whatprovides"]).args(packages).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("no package provides "))
+        .map(|pkg| pkg.trim().to_string())
+        .collect()
+}
+
+/// Whether the packages step has nothing left to do: every package is
+/// installed, and so is starship.
+pub fn all_installed(packages: &[String]) -> bool {
+    which::which("starship").is_ok()
+        && missing_packages(packages).is_ok_and(|missing| missing.is_empty())
+}
+
+/// Whether `pkg` is only needed to compile the components built from source.
+fn build_only(pkg: &str) -> bool {
+    pkg.ends_with("-devel") || pkg.ends_with("-static") || BUILD_TOOLS.contains(&pkg)
+}
+
+/// What of `packages` the host needs when the builds happen in the toolbox:
+/// everything but the build dependencies, plus the libraries the built
+/// components link.
+fn runtime_packages(packages: &[String]) -> Vec<String> {
+    let mut runtime: Vec<String> = packages.iter().filter(|pkg| !build_only(pkg)).cloned().collect();
+    for lib in RUNTIME_LIBRARIES {
+        if !runtime.iter().any(|pkg| pkg == lib) {
+            runtime.push(lib.to_string());
+        }
+    }
+    runtime
+}
+
+/// The build dependencies the installer added, which `--remove-build-deps`
+/// takes away again. Packages that were there before are never listed.
+fn build_deps_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer/build-deps")
+}
+
+fn recorded_build_deps() -> Vec<String> {
+    fs::read_to_string(build_deps_path())
+        .map(|content| content.lines().map(str::to_string).filter(|pkg| !pkg.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Adds the build-only packages of `added` to the recorded ones.
+fn record_build_deps(added: &[String]) {
+    let mut recorded = recorded_build_deps();
+    for pkg in added.iter().filter(|pkg| build_only(pkg)) {
+        if !recorded.contains(pkg) {
+            recorded.push(pkg.clone());
+        }
+    }
+    if recorded.is_empty() {
+        return;
+    }
+    let path = build_deps_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    if let Err(e) = fs::write(&path, recorded.join("\n") + "\n") {
+        log::warn(&format!("Could not record the build dependencies in {}: {}", path.display(), e));
+    }
+}
+
+/// Removes the build dependencies the installer added once everything built
+/// from source is installed: right away with `remove`, otherwise after
+/// asking. A later update installs them again when it rebuilds.
+pub fn remove_build_deps(remove: bool, dry_run: bool, noconfirm: bool) -> Result<()> {
+    let recorded = recorded_build_deps();
+    let installed: Vec<String> = match missing_packages(&recorded) {
+        Ok(missing) => recorded.into_iter().filter(|pkg| !missing.contains(pkg)).collect(),
+        Err(_) => recorded,
+    };
+    if installed.is_empty() {
+        ui::success("No build dependencies to remove");
+        return Ok(());
+    }
+    if which::which("quickshell").is_err() || !cava_installed() {
+        ui::warning("Quickshell or Cava is not installed yet, keeping the build dependencies");
+        return Ok(());
+    }
+
+    if !remove {
+        if noconfirm || dry_run {
+            ui::info(&format!(
+                "Keeping the {} packages installed to build from source, --remove-build-deps removes them",
+                installed.len()
+            ));
+            return Ok(());
+        }
+        ui::info(&format!("{} packages were installed only to build from source:", installed.len()));
+        ui::block(&format!("  {}", installed.join(" ")));
+        if !ui::confirm("Remove them now? Updates that rebuild install them again") {
+            ui::info("Keeping the build dependencies");
+            return Ok(());
+        }
+    }
+
+    log::log_command(&format!("sudo dnf remove -y {}", installed.join(" ")));
+    if dry_run {
+        ui::info("Would remove the following build dependencies:");
+        for pkg in &installed {
+            ui::block(&format!("  - {}", pkg));
+        }
+        ui::success("Build dependency removal (dry-run: skipped)");
+        return Ok(());
+    }
+
+    let output = sudo::output(sudo::command().args(["dnf", "remove", "-y"]).args(&installed))?;
+    log::log_output(&String::from_utf8_lossy(&output.stdout));
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to remove the build dependencies");
+    }
+    fs::remove_file(build_deps_path()).ok();
+    ui::success(&format!("Removed {} build dependencies", installed.len()));
+    summary::artifact(&format!("{} build dependencies removed", installed.len()));
+    Ok(())
+}
+
+/// `--container-build` flavour of [`install_all`]: installs what the desktop
+/// needs at runtime on the host with dnf and the build dependencies in the
+/// toolbox only.
+fn install_for_container_build(packages: &[String], dry_run: bool) -> Result<()> {
+    let mut host = runtime_packages(packages);
+    host.push("toolbox".to_string());
+
+    ui::info(&format!(
+        "Container build: installing {} packages on the host, the build dependencies go in the {} toolbox",
+        host.len(),
+        toolbox::CONTAINER
+    ));
+    log::log_command(&format!("sudo {} {}", dnf::install_args(true).join(" "), host.join(" ")));
+
+    if dry_run {
+        ui::info("Would install the following packages on the host:");
+        for pkg in &host {
+            ui::block(&format!("  - {}", pkg));
+        }
+        preview::transaction("dnf", &host);
+        return toolbox::setup(packages, dry_run);
+    }
+
+    let not_installed = not_installed_by(Command::new("rpm"), &host);
+    let output = sudo::output(sudo::command().args(dnf::install_args(true)).args(&host))?;
+    log::log_output(&String::from_utf8_lossy(&output.stdout));
+    if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         log::log_error(&stderr);
         bail!("Failed to install packages");
     }
+    summary::packages_added(not_installed);
+    ui::success("Installed the runtime packages on the host");
+    summary::artifact(&format!("{} packages on the host", host.len()));
+
+    toolbox::setup(packages, dry_run)
+}
+
+/// rpm-ostree flavour of [`install_all`]: layers what the desktop needs at
+/// runtime onto the image and puts the build dependencies in the toolbox.
+fn install_layered(packages: &[String], dry_run: bool, noconfirm: bool) -> Result<()> {
+    let layered = runtime_packages(packages);
+
+    ui::info(&format!("rpm-ostree system: layering {} packages onto the image", layered.len()));
+    let cmd = install_command_line(&layered.join(" "));
+    log::log_command(&cmd);
+
+    if dry_run {
+        ui::info("Would layer the following packages:");
+        for pkg in &layered {
+            ui::block(&format!("  - {}", pkg));
+        }
+        preview::transaction("rpm-ostree", &layered);
+        return toolbox::setup(packages, dry_run);
+    }
+
+    if !noconfirm {
+        for pkg in &layered {
+            ui::block(&format!("  - {}", pkg));
+        }
+        if !ui::prompt("Layer these packages? Layered packages are kept across upgrades but make them slower") {
+            bail!("Layering was declined; the desktop cannot be installed without it");
+        }
+    }
+
+    let output = runner::run(&mut install_command(&layered))?;
+    if !output.status.success() {
+        bail!("Failed to layer packages with rpm-ostree. Check ~/.cache/caelestia-installer/install.log for details.");
+    }
+    ui::success("Layered packages");
+    summary::artifact(&format!("{} layered", layered.len()));
+
+    toolbox::setup(packages, dry_run)
 }
 
-pub fn install_starship(dry_run: bool) -> Result<()> {
+/// Starship release assets, each published with a `.sha256` file.
+const STARSHIP_RELEASE: &str = "https://github.com/starship/starship/releases/latest/download";
+
+/// Packages starship for the Fedora releases that do not.
+const STARSHIP_COPR: &str = "atim/starship";
+
+/// Starship's own installer, only run with `--starship-script`.
+const STARSHIP_SCRIPT: &str = "https://starship.rs/install.sh";
+
+pub const QUICKSHELL_REPO: &str = "https://git.outfoxxed.me/outfoxxed/quickshell.git";
+pub const CAVA_REPO: &str = "https://github.com/karlstav/cava";
+pub const QT_SUPPORT_REPO: &str = "https://github.com/hyprwm/hyprland-qt-support";
+pub const QTUTILS_REPO: &str = "https://github.com/hyprwm/hyprland-qtutils";
+
+const MATERIAL_SYMBOLS_URL: &str = "https://github.com/google/material-design-icons/raw/master/variablefont/MaterialSymbolsRounded%5BFILL,GRAD,opsz,wght%5D.ttf";
+const CASKAYDIA_URL: &str = "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/CascadiaCode.zip";
+const JETBRAINS_URL: &str = "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/JetBrainsMono.zip";
+
+fn starship_url() -> String {
+    format!("{}/starship-{}-unknown-linux-musl.tar.gz", STARSHIP_RELEASE, std::env::consts::ARCH)
+}
+
+/// What the installer downloads itself, for `prefetch`.
+pub fn download_urls() -> Vec<String> {
+    vec![
+        MATERIAL_SYMBOLS_URL.to_string(),
+        CASKAYDIA_URL.to_string(),
+        JETBRAINS_URL.to_string(),
+        starship_url(),
+    ]
+}
+
+/// Installs Starship from Fedora's package, then from its COPR, then as the
+/// release binary checked against its published checksum. Its install script
+/// is only run when all of those failed and `script` allows it.
+pub fn install_starship(script: bool, dry_run: bool, noconfirm: bool) -> Result<()> {
     ui::info("Installing Starship prompt...");
 
     if dry_run {
-        ui::success("Would install Starship (dry-run)");
+        log::log_command(&install_command_line("starship"));
+        ui::success("Would install Starship from Fedora's packages or the release binary (dry-run)");
         return Ok(());
     }
 
@@ -212,18 +706,97 @@ pub fn install_starship(dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
-    let cmd = "curl -sS https://starship.rs/install.sh | sh -s -- -y";
-    log::log_command(cmd);
+    // Layering one more package slows rpm-ostree upgrades, and offline only
+    // the release binary is cached
+    if backend() == Backend::Dnf && !offline::enabled() {
+        if install_starship_package() {
+            return Ok(());
+        }
+        match repos::enable_copr(STARSHIP_COPR, dry_run, noconfirm) {
+            Ok(()) if install_starship_package() => return Ok(()),
+            Ok(()) => {}
+            Err(e) => ui::warning(&format!("Could not add the {} COPR: {}", STARSHIP_COPR, e)),
+        }
+    }
+
+    match install_starship_binary() {
+        Ok(()) => Ok(()),
+        Err(e) if script && !offline::enabled() => {
+            ui::warning(&format!("{}, running Starship's install script instead", e));
+            install_starship_script()
+        }
+        Err(e) => {
+            ui::info("Pass --starship-script to fall back to Starship's install script");
+            Err(e)
+        }
+    }
+}
+
+/// Installs the `starship` package from the enabled repositories, returning
+/// whether it worked.
+fn install_starship_package() -> bool {
+    log::log_command(&install_command_line("starship"));
+    match runner::run(&mut install_command(&["starship"])) {
+        Ok(output) if output.status.success() => {
+            ui::success("Starship installed from the starship package");
+            log::log("Starship installation complete");
+            summary::packages_added(vec!["starship".to_string()]);
+            summary::artifact("starship installed");
+            true
+        }
+        Ok(output) => {
+            log::log(&format!(
+                "No starship package available: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+            false
+        }
+        Err(e) => {
+            log::log_error(&format!("Failed to run dnf for starship: {}", e));
+            false
+        }
+    }
+}
 
-    let output = Command::new("sh")
-        .args(["-c", "curl -sS https://starship.rs/install.sh | sh -s -- -y"])
+fn install_starship_binary() -> Result<()> {
+    let url = starship_url();
+    let name = url.rsplit('/').next().unwrap_or("starship.tar.gz");
+    let build_root = crate::system::build_root();
+    let archive = build_root.join(name);
+    download::download(&url, &archive, checksums::sha256(&url)?.as_deref())?;
+
+    let output = Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&build_root)
+        .arg("starship")
         .output()?;
+    std::fs::remove_file(&archive).ok();
+    if !output.status.success() {
+        log::log_error(&String::from_utf8_lossy(&output.stderr));
+        bail!("Failed to unpack {}", name);
+    }
 
-    log::log_output(&String::from_utf8_lossy(&output.stdout));
+    let binary = build_root.join("starship");
+    let target = crate::prefix::bin_dir().join("starship");
+    log::log_command(&crate::prefix::command_line(&format!(
+        "install -D -m 755 {} {}",
+        binary.display(),
+        target.display()
+    )));
+    let output = crate::prefix::command("install")
+        .args(["-D", "-m", "755"])
+        .arg(&binary)
+        .arg(&target)
+        .output();
+    std::fs::remove_file(&binary).ok();
+    let output = output?;
 
     if output.status.success() {
         ui::success("Starship installed");
         log::log("Starship installation complete");
+        summary::artifact("starship installed");
         Ok(())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -232,7 +805,45 @@ pub fn install_starship(dry_run: bool) -> Result<()> {
     }
 }
 
-pub fn install_quickshell(dry_run: bool) -> Result<()> {
+/// Downloads Starship's install script and runs it with sh, which puts the
+/// binary in the bin directory, using sudo itself when it has to.
+fn install_starship_script() -> Result<()> {
+    let script = crate::system::build_root().join("starship-install.sh");
+    download::download(STARSHIP_SCRIPT, &script, None)?;
+
+    let bin_dir = crate::prefix::bin_dir();
+    log::log_command(&format!("sh {} --yes --bin-dir {}", script.display(), bin_dir.display()));
+    let output = runner::run(Command::new("sh").arg(&script).args(["--yes", "--bin-dir"]).arg(&bin_dir));
+    std::fs::remove_file(&script).ok();
+    let output = output?;
+
+    if output.status.success() {
+        ui::success("Starship installed with its install script");
+        log::log("Starship installation complete");
+        summary::artifact("starship installed");
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Starship's install script failed");
+    }
+}
+
+/// Oldest Quickshell the shell works with, below which a packaged one is
+/// passed over for the source build.
+const QUICKSHELL_MIN_VERSION: &str = "0.2.0";
+
+/// Installs Quickshell from a package when an enabled repository, or the
+/// extra `copr`, has one recent enough, then from a prebuilt artifact, and
+/// otherwise builds it from source.
+pub fn install_quickshell(copr: Option<&str>, dry_run: bool, noconfirm: bool) -> Result<()> {
+    if which::which("quickshell").is_err() && install_quickshell_package(copr, dry_run, noconfirm)? {
+        return Ok(());
+    }
+    if prebuilt::enabled() && which::which("quickshell").is_err() && prebuilt::install("quickshell", dry_run)? {
+        return Ok(());
+    }
+
     ui::info("Installing Quickshell from source...");
 
     if dry_run {
@@ -243,60 +854,42 @@ pub fn install_quickshell(dry_run: bool) -> Result<()> {
     // Check if already installed
     if which::which("quickshell").is_ok() {
         ui::success("Quickshell already installed");
+        summary::skipped();
         return Ok(());
     }
 
     // Verify critical Qt packages are installed
     verify_qt_packages()?;
 
-    let build_dir = std::path::PathBuf::from("/tmp/quickshell");
-
-    // Clone repo
-    if build_dir.exists() {
-        std::fs::remove_dir_all(&build_dir).ok();
-    }
-
-    let cmd = "git clone --depth 1 https://git.outfoxxed.me/outfoxxed/quickshell.git /tmp/quickshell";
-    log::log_command(cmd);
-
-    let output = Command::new("git")
-        .args(["clone", "--depth", "1", "https://git.outfoxxed.me/outfoxxed/quickshell.git", "/tmp/quickshell"])
-        .output()?;
+    let build_dir = crate::system::build_root().join("quickshell");
+    let source = build_dir.to_string_lossy().to_string();
+    let cmake_dir = format!("{}/build", source);
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        log::log_error(&stderr);
-        bail!("Failed to clone Quickshell");
-    }
-
-    ui::success("Cloned Quickshell");
+    ui::info("Fetching Quickshell...");
+    versions::sync_source("quickshell", QUICKSHELL_REPO, &build_dir, true)
+        .map_err(|e| anyhow!("Failed to clone Quickshell: {}", e))?;
 
     // Configure with CMake
     ui::info("Configuring Quickshell...");
-    let cmd = "cmake -B build -S /tmp/quickshell -G Ninja -DCMAKE_BUILD_TYPE=Release -DUSE_JEMALLOC=ON -DX11=OFF";
-    log::log_command(cmd);
-
-    let output = Command::new("cmake")
-        .args([
-            "-B", "/tmp/quickshell/build",
-            "-S", "/tmp/quickshell",
-            "-G", "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
-            "-DUSE_JEMALLOC=ON",
-            "-DX11=OFF",
-            "-DCRASH_REPORTER=OFF",
-            "-DQt6_DIR=/usr/lib64/cmake/Qt6",
-        ])
-        .output()?;
+    let mut args = vec![
+        "-B".to_string(), cmake_dir.clone(),
+        "-S".to_string(), source.clone(),
+        "-G".to_string(), "Ninja".to_string(),
+        "-DCMAKE_BUILD_TYPE=Release".to_string(),
+        "-DUSE_JEMALLOC=ON".to_string(),
+        "-DX11=OFF".to_string(),
+        "-DCRASH_REPORTER=OFF".to_string(),
+        "-DQt6_DIR=/usr/lib64/cmake/Qt6".to_string(),
+    ];
+    if let Some(prefix) = crate::prefix::configured() {
+        args.push(format!("-DCMAKE_INSTALL_PREFIX={}", prefix.display()));
+    }
+    log::log_command(&toolbox::command_line(&format!("cmake {}", args.join(" "))));
+
+    // Output is streamed into the log as it is produced
+    let output = runner::run(toolbox::command("cmake").args(&args))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        // Log both stdout and stderr for CMake configure errors
-        log::log("=== CMAKE CONFIGURE STDOUT ===");
-        log::log_output(&stdout);
-        log::log("=== CMAKE CONFIGURE STDERR ===");
-        log::log_error(&stderr);
         bail!("Failed to configure Quickshell. Check ~/.cache/caelestia-installer/install.log for details.");
     }
 
@@ -304,27 +897,10 @@ pub fn install_quickshell(dry_run: bool) -> Result<()> {
 
     // Build
     ui::info("Building Quickshell (this may take a while)...");
-    let jobs = crate::system::get_ninja_jobs();
-    let mut build_args = vec!["--build", "/tmp/quickshell/build"];
-    let jobs_str;
-    if jobs > 0 {
-        build_args.push("-j");
-        jobs_str = jobs.to_string();
-        build_args.push(&jobs_str);
-    }
-
-    let output = Command::new("cmake")
-        .args(&build_args)
-        .output()?;
+    let output = crate::system::cmake_build(&cmake_dir)?;
 
     let stderr = String::from_utf8_lossy(&output.stderr);
     let stdout = String::from_utf8_lossy(&output.stdout);
-    
-    // Always log both for debugging
-    log::log("=== BUILD STDOUT ===");
-    log::log_output(&stdout);
-    log::log("=== BUILD STDERR ===");
-    log::log_error(&stderr);
 
     if !output.status.success() {
         ui::error("Build failed!");
@@ -332,10 +908,10 @@ pub fn install_quickshell(dry_run: bool) -> Result<()> {
         // Print the last 2000 chars of stdout which likely contains the error
         if !stdout.is_empty() {
             let start = stdout.len().saturating_sub(2000);
-            println!("STDOUT (last 2000 chars):\n{}", &stdout[start..]);
+            ui::block(&format!("STDOUT (last 2000 chars):\n{}", &stdout[start..]));
         }
         if !stderr.is_empty() {
-            println!("STDERR:\n{}", stderr);
+            ui::block(&format!("STDERR:\n{}", stderr));
         }
 
         crate::system::check_oom_event();
@@ -344,13 +920,27 @@ pub fn install_quickshell(dry_run: bool) -> Result<()> {
 
     ui::success("Built Quickshell");
 
+    if localrpm::wanted("quickshell") {
+        localrpm::CmakeProject {
+            name: "quickshell",
+            summary: "Toolkit for building a desktop shell with QtQuick",
+            license: "LGPL-3.0-only",
+            url: "https://quickshell.org",
+            source: &build_dir,
+            build_dir: Path::new(&cmake_dir),
+        }
+        .package()?;
+        summary::artifact("quickshell built");
+        return Ok(());
+    }
+
     // Install
     ui::info("Installing Quickshell...");
-    let cmd = "sudo cmake --install /tmp/quickshell/build";
-    log::log_command(cmd);
+    let cmd = crate::prefix::command_line(&format!("cmake --install {}", cmake_dir));
+    log::log_command(&cmd);
 
-    let output = Command::new("sudo")
-        .args(["cmake", "--install", "/tmp/quickshell/build"])
+    let output = crate::prefix::command("cmake")
+        .args(["--install", &cmake_dir])
         .output()?;
 
     if !output.status.success() {
@@ -361,11 +951,127 @@ pub fn install_quickshell(dry_run: bool) -> Result<()> {
 
     ui::success("Quickshell installed");
     log::log("Quickshell installation complete");
+    summary::artifact("quickshell built");
 
     Ok(())
 }
 
+/// Installs the quickshell package when the repositories carry at least
+/// [`QUICKSHELL_MIN_VERSION`]. Returns false when they do not, or when
+/// versions.lock pins a revision to build.
+fn install_quickshell_package(copr: Option<&str>, dry_run: bool, noconfirm: bool) -> Result<bool> {
+    if force_source() {
+        return Ok(false);
+    }
+    if let Some(revision) = versions::pinned("quickshell")? {
+        log::log(&format!("Quickshell is pinned to {}, not looking for a package", revision));
+        return Ok(false);
+    }
+    if let Some(copr) = copr.filter(|_| !offline::enabled()) {
+        if let Err(e) = repos::enable_copr(copr, dry_run, noconfirm) {
+            ui::warning(&format!("Could not add the {} COPR: {}", copr, e));
+        }
+    }
+
+    ui::info("Looking for a quickshell package...");
+    let Some(version) = available_version("quickshell") else {
+        log::log("No quickshell package in the enabled repositories");
+        return Ok(false);
+    };
+    if !version_at_least(&version, QUICKSHELL_MIN_VERSION) {
+        ui::info(&format!(
+            "The quickshell package is {}, older than the {} the shell needs, building from source",
+            version, QUICKSHELL_MIN_VERSION
+        ));
+        return Ok(false);
+    }
+
+    ui::info(&format!("Installing the quickshell {} package...", version));
+    log::log_command(&install_command_line("quickshell"));
+    if dry_run {
+        ui::success(&format!("Would install the quickshell {} package (dry-run)", version));
+        return Ok(true);
+    }
+
+    let output = runner::run(&mut install_command(&["quickshell"]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        ui::warning("Failed to install the quickshell package, building from source");
+        return Ok(false);
+    }
+    ui::success(&format!("Installed Quickshell {} from its package", version));
+    summary::packages_added(vec!["quickshell".to_string()]);
+    summary::artifact("quickshell (package)");
+    Ok(true)
+}
+
+/// Newest version of `package` the enabled repositories carry.
+fn available_version(package: &str) -> Option<String> {
+    let output = toolbox::command("dnf")
+        .args(["repoquery", "--quiet", "--available", "--latest-limit=1", "--queryformat", "%{version} "])
+        .arg(package)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .max_by(|a, b| compare_versions(a, b))
+        .map(str::to_string)
+}
+
+/// Newest package providing `capability` in the enabled repositories, with
+/// its version.
+fn available_provider(capability: &str) -> Option<(String, String)> {
+    let output = toolbox::command("dnf")
+        .args(["repoquery", "--quiet", "--available", "--latest-limit=1", "--queryformat", "%{name}|%{version} "])
+        .args(["--whatprovides", capability])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .filter_map(|entry| entry.split_once('|'))
+        .max_by(|a, b| compare_versions(a.1, b.1))
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+}
+
+/// Compares the numbers in two versions in order, so `0.10.0` is newer than
+/// `0.9.1` and a `0.2.0^…` snapshot is newer than `0.2.0`.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let numbers = |version: &str| -> Vec<u64> {
+        version
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|part| !part.is_empty())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b))
+}
+
+fn version_at_least(version: &str, minimum: &str) -> bool {
+    compare_versions(version, minimum) != std::cmp::Ordering::Less
+}
+
+/// Oldest cavacore the shell builds against.
+const CAVA_MIN_VERSION: &str = "0.10.3";
+
+/// Whether a cava pkg-config file, with the cavacore library, is installed.
+pub fn cava_installed() -> bool {
+    Path::new("/usr/lib64/pkgconfig/cava.pc").exists()
+        || toolbox::command("pkg-config")
+            .args(["--exists", "cava"])
+            .status()
+            .is_ok_and(|status| status.success())
+}
+
+/// Installs cavacore from a package providing `pkgconfig(cava)` when the
+/// repositories have one recent enough, and otherwise builds it from source.
 pub fn install_cava(dry_run: bool) -> Result<()> {
+    if !force_source() && !cava_installed() && install_cava_package(dry_run)? {
+        return Ok(());
+    }
+
     ui::info("Installing Cava from source...");
 
     if dry_run {
@@ -374,77 +1080,43 @@ pub fn install_cava(dry_run: bool) -> Result<()> {
     }
 
     // Check if already installed via pkg-config check
-    // If /usr/lib64/pkgconfig/cava.pc exists, we assume it's done.
-    if std::path::Path::new("/usr/lib64/pkgconfig/cava.pc").exists() {
+    if cava_installed() {
         ui::success("Cava already installed (checked pkg-config)");
+        summary::skipped();
         return Ok(());
     }
 
-    let build_dir = std::path::PathBuf::from("/tmp/cava-build");
-
-    // Clone repo
-    if build_dir.exists() {
-        std::fs::remove_dir_all(&build_dir).ok();
-    }
-
-    let cmd = "git clone --depth 1 https://github.com/karlstav/cava /tmp/cava-build";
-    log::log_command(cmd);
-
-    let output = Command::new("git")
-        .args(["clone", "--depth", "1", "https://github.com/karlstav/cava", "/tmp/cava-build"])
-        .output()?;
+    let build_dir = crate::system::build_root().join("cava-build");
+    let source = build_dir.to_string_lossy().to_string();
+    let cmake_dir = format!("{}/build", source);
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        log::log_error(&stderr);
-        bail!("Failed to clone Cava");
-    }
-
-    ui::success("Cloned Cava");
+    ui::info("Fetching Cava...");
+    versions::sync_source("cava", CAVA_REPO, &build_dir, true)
+        .map_err(|e| anyhow!("Failed to clone Cava: {}", e))?;
 
     // Configure with CMake (builds cavacore static lib)
     ui::info("Configuring Cava...");
     // CAVACORE.md says to use root CMakeLists
-    let cmd = "cmake -B build -S /tmp/cava-build -G Ninja -DCMAKE_BUILD_TYPE=Release -DCMAKE_POSITION_INDEPENDENT_CODE=ON";
-    log::log_command(cmd);
-
-    let output = Command::new("cmake")
-        .args([
-            "-B", "/tmp/cava-build/build",
-            "-S", "/tmp/cava-build",
-            "-G", "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
-            "-DCMAKE_POSITION_INDEPENDENT_CODE=ON",
-        ])
-        .output()?;
+    let cmd = format!("cmake -B {} -S {} -G Ninja -DCMAKE_BUILD_TYPE=Release -DCMAKE_POSITION_INDEPENDENT_CODE=ON", cmake_dir, source);
+    log::log_command(&toolbox::command_line(&cmd));
+
+    let output = runner::run(toolbox::command("cmake").args([
+        "-B", &cmake_dir,
+        "-S", &source,
+        "-G", "Ninja",
+        "-DCMAKE_BUILD_TYPE=Release",
+        "-DCMAKE_POSITION_INDEPENDENT_CODE=ON",
+    ]))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        log::log_error(&stderr);
         bail!("Failed to configure Cava");
     }
 
     // Build
     ui::info("Building Cava...");
-    let jobs = crate::system::get_ninja_jobs();
-    let mut build_args = vec!["--build", "/tmp/cava-build/build"];
-    let jobs_str;
-    if jobs > 0 {
-        build_args.push("-j");
-        jobs_str = jobs.to_string();
-        build_args.push(&jobs_str);
-    }
-
-    let output = Command::new("cmake")
-        .args(&build_args)
-        .output()?;
+    let output = crate::system::cmake_build(&cmake_dir)?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log("=== Cava BUILD STDOUT ===");
-        log::log_output(&stdout);
-        log::log_error(&stderr);
         crate::system::check_oom_event();
         bail!("Failed to build Cava");
     }
@@ -455,97 +1127,224 @@ pub fn install_cava(dry_run: bool) -> Result<()> {
     ui::info("Installing Cava library and headers...");
 
     // Install header
-    let cmd = "sudo cp /tmp/cava-build/cavacore.h /usr/include/";
-    log::log_command(cmd);
-    Command::new("sudo")
-        .args(["cp", "/tmp/cava-build/cavacore.h", "/usr/include/"])
-        .status()?;
+    let header = format!("{}/cavacore.h", source);
+    privileged::run_in_build_env(&["cp", &header, "/usr/include/"])?;
 
     // Create cava directory and symlink as cavacore.h for compatibility
-    let cmd = "sudo mkdir -p /usr/include/cava";
-    log::log_command(cmd);
-    Command::new("sudo")
-        .args(["mkdir", "-p", "/usr/include/cava"])
-        .status()?;
-
-    let cmd = "sudo ln -sf /usr/include/cavacore.h /usr/include/cava/cavacore.h";
-    log::log_command(cmd);
-    Command::new("sudo")
-        .args(["ln", "-sf", "/usr/include/cavacore.h", "/usr/include/cava/cavacore.h"])
-        .status()?;
+    privileged::run_in_build_env(&["mkdir", "-p", "/usr/include/cava"])?;
+    privileged::run_in_build_env(&["ln", "-sf", "/usr/include/cavacore.h", "/usr/include/cava/cavacore.h"])?;
 
     // Install library
-    let cmd = "sudo cp /tmp/cava-build/build/libcavacore.a /usr/lib64/";
-    log::log_command(cmd);
-    Command::new("sudo")
-        .args(["cp", "/tmp/cava-build/build/libcavacore.a", "/usr/lib64/"])
-        .status()?;
+    let library = format!("{}/libcavacore.a", cmake_dir);
+    privileged::run_in_build_env(&["cp", &library, "/usr/lib64/"])?;
 
     // Create pkg-config file
     ui::info("Creating cava.pc...");
-    let pc_content = r#"prefix=/usr
-exec_prefix=${prefix}
-libdir=${exec_prefix}/lib64
-includedir=${prefix}/include
+    let pc_content = format!(
+        r#"prefix=/usr
+exec_prefix=${{prefix}}
+libdir=${{exec_prefix}}/lib64
+includedir=${{prefix}}/include
 
 Name: cava
 Description: Cava Core Library
-Version: 0.10.3
-Libs: -L${libdir} -lcavacore -lfftw3 -lm -liniparser
-Cflags: -I${includedir}
-"#;
-
-    let pc_path = "/tmp/cava-build/cava.pc";
-    std::fs::write(pc_path, pc_content)?;
-
-    let cmd = "sudo cp /tmp/cava-build/cava.pc /usr/lib64/pkgconfig/";
-    log::log_command(cmd);
-    Command::new("sudo")
-        .args(["cp", pc_path, "/usr/lib64/pkgconfig/"])
-        .status()?;
+Version: {}
+Libs: -L${{libdir}} -lcavacore -lfftw3 -lm -liniparser
+Cflags: -I${{includedir}}
+"#,
+        source_version(&build_dir)
+    );
+
+    let pc_path = format!("{}/cava.pc", source);
+    std::fs::write(&pc_path, pc_content)?;
+
+    privileged::run_in_build_env(&["cp", &pc_path, "/usr/lib64/pkgconfig/"])?;
 
     ui::success("Cava installed");
     log::log("Cava installation complete");
+    summary::artifact("cavacore built");
 
     Ok(())
 }
 
-pub fn install_rust(dry_run: bool) -> Result<()> {
+/// Installs the package providing `pkgconfig(cava)` when it is at least
+/// [`CAVA_MIN_VERSION`]. Returns false when there is none to install.
+fn install_cava_package(dry_run: bool) -> Result<bool> {
+    ui::info("Looking for a cava package with cavacore...");
+    let Some((package, version)) = available_provider("pkgconfig(cava)") else {
+        log::log("No package provides pkgconfig(cava)");
+        return Ok(false);
+    };
+    if !version_at_least(&version, CAVA_MIN_VERSION) {
+        ui::info(&format!(
+            "{} is {}, older than the {} the shell needs, building from source",
+            package, version, CAVA_MIN_VERSION
+        ));
+        return Ok(false);
+    }
+
+    ui::info(&format!("Installing {} {}...", package, version));
+    log::log_command(&install_command_line(&package));
+    if dry_run {
+        ui::success(&format!("Would install {} {} instead of building Cava (dry-run)", package, version));
+        return Ok(true);
+    }
+
+    let output = runner::run(&mut install_command(&[&package]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        ui::warning(&format!("Failed to install {}, building Cava from source", package));
+        return Ok(false);
+    }
+    ui::success(&format!("Installed cavacore from {} {}", package, version));
+    summary::packages_added(vec![package]);
+    summary::artifact("cavacore (package)");
+    Ok(true)
+}
+
+/// The version of the checkout in `source`: its latest tag, or the one in
+/// the `project()` call of its CMakeLists.txt.
+fn source_version(source: &Path) -> String {
+    let tag = Command::new("git")
+        .arg("-C")
+        .arg(source)
+        .args(["describe", "--tags", "--abbrev=0"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().trim_start_matches('v').to_string())
+        .filter(|tag| !tag.is_empty());
+    tag.or_else(|| {
+        let cmake = std::fs::read_to_string(source.join("CMakeLists.txt")).ok()?;
+        cmake
+            .lines()
+            .filter(|line| line.trim_start().to_lowercase().starts_with("project("))
+            .find_map(|line| {
+                let mut words = line.split(|c: char| c.is_whitespace() || c == '(' || c == ')');
+                words.find(|word| *word == "VERSION")?;
+                words.find(|word| !word.is_empty()).map(str::to_string)
+            })
+    })
+    .unwrap_or_else(|| "0".to_string())
+}
+
+/// The `--rust-toolchain` that installs Fedora's rust and cargo packages
+/// instead of rustup.
+pub const FEDORA_RUST: &str = "fedora";
+
+/// Whether rustc and cargo come from Fedora's packages rather than rustup.
+fn fedora_rust() -> bool {
+    Command::new("rpm")
+        .args(["-q", "rust", "cargo"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Whether the Rust toolchain step has nothing left to do.
+pub fn rust_installed() -> bool {
+    which::which("rustc").is_ok() && which::which("cargo").is_ok()
+}
+
+/// Installs `toolchain` (a rustup toolchain such as `stable` or `1.82.0`,
+/// or `fedora` for Fedora's packages), `stable` by default. An existing
+/// Fedora Rust is left alone, and rustup only switches toolchains.
+pub fn install_rust(toolchain: Option<&str>, dry_run: bool) -> Result<()> {
     ui::info("Installing Rust toolchain...");
+    let toolchain = toolchain.unwrap_or("stable");
 
     if dry_run {
-        ui::success("Would install Rust (dry-run)");
+        ui::success(&format!("Would install the {} Rust toolchain (dry-run)", toolchain));
+        return Ok(());
+    }
+
+    if fedora_rust() {
+        if toolchain != FEDORA_RUST {
+            ui::warning(&format!(
+                "Rust is installed from Fedora's packages, leaving it alone rather than adding rustup's {}",
+                toolchain
+            ));
+        }
+        ui::success("Rust already installed from Fedora's packages");
         return Ok(());
     }
 
+    if offline::enabled() || toolchain == FEDORA_RUST {
+        return install_rust_package();
+    }
+
+    if which::which("rustup").is_ok() {
+        return set_rust_toolchain(toolchain);
+    }
+
     // Check if already installed
-    if which::which("rustc").is_ok() && which::which("cargo").is_ok() {
+    if rust_installed() {
         ui::success("Rust already installed");
         return Ok(());
     }
 
-    let cmd = "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y";
-    log::log_command(cmd);
-
-    let output = Command::new("sh")
-        .args(["-c", "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y"])
-        .output()?;
+    let url = format!(
+        "https://static.rust-lang.org/rustup/dist/{}-unknown-linux-gnu/rustup-init",
+        std::env::consts::ARCH
+    );
+    let rustup_init = crate::system::build_root().join("rustup-init");
+    download::download(&url, &rustup_init, checksums::sha256(&url)?.as_deref())?;
+    std::fs::set_permissions(&rustup_init, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+
+    log::log_command(&format!("{} -y --default-toolchain {}", rustup_init.display(), toolchain));
+    let output = Command::new(&rustup_init)
+        .args(["-y", "--default-toolchain", toolchain])
+        .output();
+    std::fs::remove_file(&rustup_init).ok();
+    let output = output?;
 
     log::log_output(&String::from_utf8_lossy(&output.stdout));
 
     if output.status.success() {
-        ui::success("Rust installed");
+        ui::success(&format!("Rust installed ({})", toolchain));
         log::log("Rust installation complete");
+        summary::artifact("rust installed");
         ui::info("Note: You may need to restart your shell or run 'source ~/.cargo/env'");
         Ok(())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
         log::log_error(&stderr);
         bail!("Failed to install Rust");
     }
 }
 
+/// Installs `toolchain` with an existing rustup and makes it the default.
+fn set_rust_toolchain(toolchain: &str) -> Result<()> {
+    for args in [vec!["toolchain", "install", toolchain], vec!["default", toolchain]] {
+        log::log_command(&format!("rustup {}", args.join(" ")));
+        let output = runner::run(Command::new("rustup").args(&args))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Failed to install the {} Rust toolchain: {}", toolchain, stderr.trim());
+        }
+    }
+    ui::success(&format!("Rust {} toolchain set as the default", toolchain));
+    summary::artifact("rust installed");
+    Ok(())
+}
+
+/// Installs Fedora's Rust packages, as rustup cannot work offline.
+fn install_rust_package() -> Result<()> {
+    let source = if offline::enabled() { "the offline cache" } else { "Fedora's packages" };
+    log::log_command(&install_command_line(&offline::RUST_PACKAGES.join(" ")));
+    let output = runner::run(&mut install_command(offline::RUST_PACKAGES))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install Rust from {}", source);
+    }
+    ui::success(&format!("Rust installed from {}", source));
+    summary::packages_added(offline::RUST_PACKAGES.iter().map(|pkg| pkg.to_string()).collect());
+    summary::artifact("rust installed");
+    Ok(())
+}
+
 pub fn install_fonts(dry_run: bool) -> Result<()> {
     ui::info("Installing Fonts...");
 
@@ -564,16 +1363,8 @@ pub fn install_fonts(dry_run: bool) -> Result<()> {
     let mat_target = font_dir.join("MaterialSymbolsRounded.ttf");
     if !mat_target.exists() {
         ui::info("Downloading Material Symbols Rounded...");
-        let url = "https://github.com/google/material-design-icons/raw/master/variablefont/MaterialSymbolsRounded%5BFILL,GRAD,opsz,wght%5D.ttf";
-        let cmd = format!("curl -L -o {:?} {}", mat_target, url);
-        log::log_command(&cmd);
-
-        let output = Command::new("curl")
-            .args(["-L", "-o", mat_target.to_str().unwrap(), url])
-            .output()?;
-
-        if !output.status.success() {
-            ui::warning("Failed to download Material Symbols Rounded");
+        if let Err(e) = download::download(MATERIAL_SYMBOLS_URL, &mat_target, None) {
+            ui::warning(&format!("Failed to download Material Symbols Rounded: {}", e));
         }
     } else {
         ui::success("Material Symbols Rounded already installed");
@@ -583,29 +1374,12 @@ pub fn install_fonts(dry_run: bool) -> Result<()> {
     // Check if one of the files exists as a proxy
     let cas_target = font_dir.join("CaskaydiaCoveNerdFont-Regular.ttf");
     if !cas_target.exists() {
-        ui::info("Downloading Caskaydia Cove Nerd Font...");
-        let url = "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/CascadiaCode.zip";
-        let zip_path = "/tmp/CaskaydiaCove.zip";
-        
-        // Download
-        let output = Command::new("curl")
-            .args(["-L", "-o", zip_path, url])
-            .output()?;
-        
-        if output.status.success() {
-            ui::info("Extracting Caskaydia Cove...");
-            // Unzip content
-            let output = Command::new("unzip")
-                .args(["-o", zip_path, "-d", font_dir.to_str().unwrap(), "CaskaydiaCoveNerdFont*.ttf"])
-                .output()?;
-            
-            if !output.status.success() {
-                 ui::warning("Failed to extract Caskaydia Cove");
-            }
-            std::fs::remove_file(zip_path).ok();
-        } else {
-            ui::warning("Failed to download Caskaydia Cove");
-        }
+        install_nerd_font(
+            "Caskaydia Cove",
+            CASKAYDIA_URL,
+            "CaskaydiaCoveNerdFont*.ttf",
+            &font_dir,
+        )?;
     } else {
         ui::success("Caskaydia Cove Nerd Font already installed");
     }
@@ -613,33 +1387,16 @@ pub fn install_fonts(dry_run: bool) -> Result<()> {
     // 3. JetBrains Mono Nerd Font (required by foot.ini upstream)
     let jb_target = font_dir.join("JetBrainsMonoNerdFont-Regular.ttf");
     if !jb_target.exists() {
-        ui::info("Downloading JetBrains Mono Nerd Font...");
-        let url = "https://github.com/ryanoasis/nerd-fonts/releases/download/v3.3.0/JetBrainsMono.zip";
-        let zip_path = "/tmp/JetBrainsMono.zip";
-        
-        // Download
-        let output = Command::new("curl")
-            .args(["-L", "-o", zip_path, url])
-            .output()?;
-        
-        if output.status.success() {
-            ui::info("Extracting JetBrains Mono...");
-            // Unzip content
-            let output = Command::new("unzip")
-                .args(["-o", zip_path, "-d", font_dir.to_str().unwrap(), "JetBrainsMonoNerdFont*.ttf"])
-                .output()?;
-            
-            if !output.status.success() {
-                 ui::warning("Failed to extract JetBrains Mono");
-            }
-            std::fs::remove_file(zip_path).ok();
-        } else {
-            ui::warning("Failed to download JetBrains Mono");
-        }
+        install_nerd_font(
+            "JetBrains Mono",
+            JETBRAINS_URL,
+            "JetBrainsMonoNerdFont*.ttf",
+            &font_dir,
+        )?;
     } else {
         ui::success("JetBrains Mono Nerd Font already installed");
     }
-    
+
     // Update font cache
     let _ = Command::new("fc-cache").args(["-fv"]).output();
 
@@ -649,6 +1406,32 @@ pub fn install_fonts(dry_run: bool) -> Result<()> {
     Ok(())
 }
 
+/// Downloads a Nerd Fonts release archive and extracts the fonts matching
+/// `pattern`. Failures only warn, as the desktop works without the font,
+/// except for a download not matching its checksum.
+fn install_nerd_font(name: &str, url: &str, pattern: &str, font_dir: &std::path::Path) -> Result<()> {
+    ui::info(&format!("Downloading {} Nerd Font...", name));
+    let zip_path = crate::system::build_root().join(url.rsplit('/').next().unwrap_or("font.zip"));
+
+    let verified = checksums::sha256(url).and_then(|sha256| download::download(url, &zip_path, sha256.as_deref()));
+    if let Err(e) = verified {
+        if e.is::<download::ChecksumMismatch>() {
+            return Err(e);
+        }
+        ui::warning(&format!("Failed to download {}: {}", name, e));
+        return Ok(());
+    }
+
+    ui::info(&format!("Extracting {}...", name));
+    match download::extract(&zip_path, font_dir, pattern) {
+        Ok(0) => ui::warning(&format!("No {} fonts found in the archive", name)),
+        Ok(_) => {}
+        Err(e) => ui::warning(&format!("Failed to extract {}: {}", name, e)),
+    }
+    std::fs::remove_file(&zip_path).ok();
+    Ok(())
+}
+
 pub fn install_hyprland_qt_support(dry_run: bool) -> Result<()> {
     ui::info("Installing hyprland-qt-support...");
     
@@ -657,70 +1440,49 @@ pub fn install_hyprland_qt_support(dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
-    if std::path::Path::new("/usr/lib64/libhyprland-qt-support.so").exists() {
+    if crate::prefix::prefix().join("lib64/libhyprland-qt-support.so").exists() {
         ui::success("hyprland-qt-support already installed");
         return Ok(());
     }
 
-    let tmp_dir = std::path::PathBuf::from("/tmp/hyprland-qt-support");
-    if tmp_dir.exists() {
-        std::fs::remove_dir_all(&tmp_dir).ok();
-    }
+    let tmp_dir = crate::system::build_root().join("hyprland-qt-support");
+    let source = tmp_dir.to_string_lossy().to_string();
+    let cmake_dir = format!("{}/build", source);
 
-    ui::info("Cloning hyprland-qt-support...");
-    Command::new("git")
-        .args(["clone", "https://github.com/hyprwm/hyprland-qt-support", "/tmp/hyprland-qt-support"])
-        .output()?;
+    ui::info("Fetching hyprland-qt-support...");
+    versions::sync_source("hyprland-qt-support", QT_SUPPORT_REPO, &tmp_dir, false)?;
 
     ui::info("Configuring hyprland-qt-support...");
-    let output = Command::new("cmake")
-        .args([
-            "-B", "/tmp/hyprland-qt-support/build",
-            "-S", "/tmp/hyprland-qt-support",
-            "-G", "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
-            "-DCMAKE_INSTALL_PREFIX=/usr",
-            "-DCMAKE_INSTALL_LIBDIR=lib64",
-        ])
-        .output()?;
+    let install_prefix = format!("-DCMAKE_INSTALL_PREFIX={}", crate::prefix::prefix().display());
+    let output = runner::run(toolbox::command("cmake").args([
+        "-B", &cmake_dir,
+        "-S", &source,
+        "-G", "Ninja",
+        "-DCMAKE_BUILD_TYPE=Release",
+        &install_prefix,
+        "-DCMAKE_INSTALL_LIBDIR=lib64",
+    ]))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log_error(&stderr);
         bail!("Failed to configure hyprland-qt-support");
     }
     
     ui::info("Building hyprland-qt-support...");
-    let jobs = crate::system::get_ninja_jobs();
-    let mut build_args = vec!["--build", "/tmp/hyprland-qt-support/build"];
-    let jobs_str;
-    if jobs > 0 {
-        build_args.push("-j");
-        jobs_str = jobs.to_string();
-        build_args.push(&jobs_str);
-    }
-
-    let output = Command::new("cmake")
-        .args(&build_args)
-        .output()?;
+    let output = crate::system::cmake_build(&cmake_dir)?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log("=== hyprland-qt-support BUILD STDOUT ===");
-        log::log_output(&stdout);
-        log::log_error(&stderr);
         crate::system::check_oom_event();
         bail!("Failed to build hyprland-qt-support");
     }
 
     ui::info("Installing hyprland-qt-support...");
-    Command::new("sudo")
-        .args(["cmake", "--install", "/tmp/hyprland-qt-support/build"])
+    log::log_command(&crate::prefix::command_line(&format!("cmake --install {}", cmake_dir)));
+    crate::prefix::command("cmake")
+        .args(["--install", &cmake_dir])
         .status()?;
 
     ui::success("Installed hyprland-qt-support");
+    summary::artifact("hyprland-qt-support built");
     Ok(())
 }
 
@@ -740,65 +1502,44 @@ pub fn install_hyprland_qtutils(dry_run: bool) -> Result<()> {
     // Verify critical Qt packages are installed
     verify_qt_packages()?;
 
-    let tmp_dir = std::path::PathBuf::from("/tmp/hyprland-qtutils");
-    if tmp_dir.exists() {
-        std::fs::remove_dir_all(&tmp_dir).ok();
-    }
+    let tmp_dir = crate::system::build_root().join("hyprland-qtutils");
+    let source = tmp_dir.to_string_lossy().to_string();
+    let cmake_dir = format!("{}/build", source);
 
-    ui::info("Cloning hyprland-qtutils...");
-    Command::new("git")
-        .args(["clone", "https://github.com/hyprwm/hyprland-qtutils", "/tmp/hyprland-qtutils"])
-        .output()?;
+    ui::info("Fetching hyprland-qtutils...");
+    versions::sync_source("hyprland-qtutils", QTUTILS_REPO, &tmp_dir, false)?;
 
     ui::info("Configuring hyprland-qtutils...");
-    let output = Command::new("cmake")
-        .args([
-            "-B", "/tmp/hyprland-qtutils/build",
-            "-S", "/tmp/hyprland-qtutils",
-            "-G", "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
-            "-DCMAKE_INSTALL_PREFIX=/usr",
-            "-DQt6_DIR=/usr/lib64/cmake/Qt6",
-        ])
-        .output()?;
+    let install_prefix = format!("-DCMAKE_INSTALL_PREFIX={}", crate::prefix::prefix().display());
+    let output = runner::run(toolbox::command("cmake").args([
+        "-B", &cmake_dir,
+        "-S", &source,
+        "-G", "Ninja",
+        "-DCMAKE_BUILD_TYPE=Release",
+        &install_prefix,
+        "-DQt6_DIR=/usr/lib64/cmake/Qt6",
+    ]))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log_error(&stderr);
         bail!("Failed to configure hyprland-qtutils");
     }
     
     ui::info("Building hyprland-qtutils...");
-    let jobs = crate::system::get_ninja_jobs();
-    let mut build_args = vec!["--build", "/tmp/hyprland-qtutils/build"];
-    let jobs_str;
-    if jobs > 0 {
-        build_args.push("-j");
-        jobs_str = jobs.to_string();
-        build_args.push(&jobs_str);
-    }
-
-    let output = Command::new("cmake")
-        .args(&build_args)
-        .output()?;
+    let output = crate::system::cmake_build(&cmake_dir)?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log("=== hyprland-qtutils BUILD STDOUT ===");
-        log::log_output(&stdout);
-        log::log_error(&stderr);
         crate::system::check_oom_event();
         bail!("Failed to build hyprland-qtutils");
     }
 
     ui::info("Installing hyprland-qtutils...");
-    Command::new("sudo")
-        .args(["cmake", "--install", "/tmp/hyprland-qtutils/build"])
+    log::log_command(&crate::prefix::command_line(&format!("cmake --install {}", cmake_dir)));
+    crate::prefix::command("cmake")
+        .args(["--install", &cmake_dir])
         .status()?;
 
     ui::success("Installed hyprland-qtutils");
+    summary::artifact("hyprland-qtutils built");
     Ok(())
 }
 
@@ -818,30 +1559,9 @@ fn verify_qt_packages() -> Result<()> {
         "aubio-devel",
     ];
     
-    let mut missing = Vec::new();
-    
-    // Check critical Qt packages
-    for pkg in CRITICAL_QT_PACKAGES {
-        let output = Command::new("rpm")
-            .args(["-q", pkg])
-            .output()?;
-            
-        if !output.status.success() {
-            missing.push(*pkg);
-        }
-    }
-    
-    // Check build tools
-    for pkg in build_tools {
-        let output = Command::new("rpm")
-            .args(["-q", pkg])
-            .output()?;
-            
-        if !output.status.success() {
-            missing.push(*pkg);
-        }
-    }
-    
+    let mut missing = missing_packages(CRITICAL_QT_PACKAGES)?;
+    missing.extend(missing_packages(build_tools)?);
+
     if !missing.is_empty() {
         ui::warning("Missing critical packages:");
         for pkg in &missing {
@@ -849,50 +1569,34 @@ fn verify_qt_packages() -> Result<()> {
         }
         
         ui::info("Installing missing packages with conflict resolution...");
-        let mut args = vec!["dnf", "install", "-y", "--allowerasing"];
-        args.extend(missing.iter().copied());
-        
-        let output = Command::new("sudo").args(&args).output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::log_output(&stdout);
-        
-        // Check if packages were skipped even after using --allowerasing
-        if stdout.contains("Skipping packages") {
-            ui::error("Some packages could not be installed due to unresolvable conflicts.");
-            ui::info("This may be caused by conflicting packages from COPR repositories.");
-            ui::info("Try the following manual steps:");
-            ui::info("  1. sudo dnf remove hyprland-qt-support hyprland-qtutils");
-            ui::info("  2. sudo dnf install --allowerasing qt6-qtbase-devel qt6-qtdeclarative-devel");
-            ui::info("  3. Re-run this installer");
-            log::log_error(&format!("Package conflicts detected, stdout: {}", stdout));
-            bail!("Failed to install Qt packages due to repository conflicts");
-        }
-        
+        let output = toolbox::command("sudo")
+            .args(dnf::install_args(true))
+            .args(&missing)
+            .output()?;
+        log::log_output(&String::from_utf8_lossy(&output.stdout));
+
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             log::log_error(&stderr);
             bail!("Failed to install missing packages");
         }
-        
-        // Verify the packages were actually installed after the install attempt
-        let mut still_missing = Vec::new();
-        for pkg in &missing {
-            let verify_output = Command::new("rpm")
-                .args(["-q", pkg])
-                .output()?;
-            if !verify_output.status.success() {
-                still_missing.push(*pkg);
-            }
-        }
-        
+
+        // Verify the packages were actually installed, as dnf succeeds
+        // when it skips conflicting ones
+        let still_missing = missing_packages(&missing)?;
         if !still_missing.is_empty() {
             ui::error("The following packages are still missing after install attempt:");
             for pkg in &still_missing {
                 ui::error(&format!("  - {}", pkg));
             }
+            ui::info("This may be caused by conflicting packages from COPR repositories.");
+            ui::info("Try the following manual steps:");
+            ui::info("  1. sudo dnf remove hyprland-qt-support hyprland-qtutils");
+            ui::info("  2. sudo dnf install --allowerasing qt6-qtbase-devel qt6-qtdeclarative-devel");
+            ui::info("  3. Re-run this installer");
             bail!("Failed to install required packages. Check for repository conflicts.");
         }
-        
+
         ui::success("Missing packages installed");
     } else {
         ui::success("All critical packages are installed");
@@ -914,3 +1618,22 @@ fn verify_qt_packages() -> Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::for_release;
+    use crate::checks::Release;
+
+    #[test]
+    fn renames_packages_from_the_release_that_renamed_them() {
+        let packages = ["foot", "mesa-libgbm-devel"];
+        assert_eq!(for_release(&packages, Some(Release::Version(41))), ["foot", "mesa-libgbm-devel"]);
+        assert_eq!(for_release(&packages, Some(Release::Version(42))), ["foot", "libgbm-devel"]);
+        assert_eq!(for_release(&packages, Some(Release::Rawhide)), ["foot", "libgbm-devel"]);
+    }
+
+    #[test]
+    fn keeps_the_names_on_an_unknown_release() {
+        assert_eq!(for_release(&["mesa-libgbm-devel"], None), ["mesa-libgbm-devel"]);
+    }
+}
@@ -0,0 +1,194 @@
+//! xdg-desktop-portal for the Hyprland session: a portals.conf preferring
+//! the Hyprland and GTK backends, the portal services restarted to read it,
+//! and a check that screenshots and screen sharing resolve, which otherwise
+//! only shows as an empty share dialog after the reboot.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{journal, log, summary, ui};
+
+/// First line of the portals.conf the installer writes.
+const CONF_MARKER: &str = "# Generated by caelestia-installer";
+
+const CONF: &str = "# Generated by caelestia-installer
+# The Hyprland session only uses the Hyprland and GTK portals, whatever
+# other desktops are installed
+[preferred]
+default=hyprland;gtk
+";
+
+/// Backends the config prefers, in order.
+const BACKENDS: &[&str] = &["hyprland", "gtk"];
+
+/// Where the backends describe the interfaces they implement.
+const BACKENDS_DIR: &str = "/usr/share/xdg-desktop-portal/portals";
+
+/// Interfaces the shell's screenshot and recording tools go through, which
+/// the frontend offers as `org.freedesktop.portal.<name>` when a backend
+/// implements `org.freedesktop.impl.portal.<name>`.
+const INTERFACES: &[&str] = &["Screenshot", "ScreenCast"];
+
+/// User services that read the config when they start, frontend last.
+const SERVICES: &[&str] = &[
+    "xdg-desktop-portal-hyprland.service",
+    "xdg-desktop-portal-gtk.service",
+    "xdg-desktop-portal.service",
+];
+
+/// Where the portal frontend looks for the Hyprland session's config, read
+/// when `XDG_CURRENT_DESKTOP` is `Hyprland`.
+pub fn conf_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("xdg-desktop-portal/hyprland-portals.conf")
+}
+
+/// Whether the config is in place and the backends it prefers provide the
+/// screenshot and screencast interfaces.
+pub fn configured() -> bool {
+    conf_path().exists() && missing_in_backends().is_empty()
+}
+
+/// Writes the config, restarts the portal services when the installer runs
+/// in a Hyprland session and checks that the interfaces resolve.
+pub fn setup(dry_run: bool) -> Result<()> {
+    write_conf(dry_run)?;
+
+    if dry_run {
+        log::log_command(&format!("systemctl --user try-restart {}", SERVICES.join(" ")));
+        ui::success("Would restart the portal services and check Screenshot and ScreenCast (dry-run)");
+        return Ok(());
+    }
+
+    let missing = missing_in_backends();
+    if !missing.is_empty() {
+        bail!(
+            "No portal backend provides {}, check that xdg-desktop-portal-hyprland is installed",
+            missing.join(" and ")
+        );
+    }
+
+    if !in_hyprland_session() {
+        ui::info("The portal services read the config at the next Hyprland login, `caelestia-installer doctor` checks them from there");
+        return Ok(());
+    }
+    restart_services();
+    match verify() {
+        Ok(detail) => ui::success(&detail),
+        Err(problem) => {
+            ui::warning(&problem);
+            log::log_error(&format!("Portals: {}", problem));
+            ui::info("Log out and back in, or check `journalctl --user -u xdg-desktop-portal`");
+        }
+    }
+    Ok(())
+}
+
+/// Writes the config, unless the user has their own.
+fn write_conf(dry_run: bool) -> Result<()> {
+    let path = conf_path();
+    let existing = fs::read_to_string(&path).ok();
+    if existing.as_deref().is_some_and(|content| !content.starts_with(CONF_MARKER)) {
+        ui::success(&format!("Keeping your {}", path.display()));
+        return Ok(());
+    }
+    if existing.as_deref() == Some(CONF) {
+        ui::success(&format!("{} is up to date", path.display()));
+        return Ok(());
+    }
+    if dry_run {
+        ui::success(&format!("Would write {} preferring the Hyprland and GTK portals (dry-run)", path.display()));
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&path, CONF)?;
+    log::log(&format!("Portals config written to {:?}", path));
+    ui::success(&format!("Wrote {}, the Hyprland session uses the Hyprland and GTK portals", path.display()));
+    summary::artifact("hyprland-portals.conf");
+    Ok(())
+}
+
+fn in_hyprland_session() -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP").is_ok_and(|desktops| desktops.split(':').any(|d| d == "Hyprland"))
+}
+
+/// Restarts the portal services that are running, so they read the config.
+/// The ones that are not start with it when first asked for.
+fn restart_services() {
+    log::log_command(&format!("systemctl --user try-restart {}", SERVICES.join(" ")));
+    let restarted = Command::new("systemctl")
+        .args(["--user", "try-restart"])
+        .args(SERVICES)
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !restarted {
+        ui::warning("Could not restart the portal services, they read the config at the next login");
+    }
+}
+
+/// The interfaces none of the preferred backends declares.
+fn missing_in_backends() -> Vec<&'static str> {
+    let declared: Vec<String> = BACKENDS
+        .iter()
+        .filter_map(|backend| fs::read_to_string(Path::new(BACKENDS_DIR).join(format!("{}.portal", backend))).ok())
+        .flat_map(|portal| {
+            portal
+                .lines()
+                .filter_map(|line| line.strip_prefix("Interfaces="))
+                .flat_map(|list| list.split(';').map(|name| name.trim().to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    INTERFACES
+        .iter()
+        .copied()
+        .filter(|interface| {
+            let implemented = format!("org.freedesktop.impl.portal.{}", interface);
+            !declared.contains(&implemented)
+        })
+        .collect()
+}
+
+/// Checks that the Screenshot and ScreenCast interfaces resolve: on the
+/// session bus from inside a Hyprland session, otherwise in the backends'
+/// declarations.
+pub fn verify() -> Result<String, String> {
+    let missing = missing_in_backends();
+    if !missing.is_empty() {
+        return Err(format!("no installed portal backend provides {}", missing.join(" and ")));
+    }
+    if !in_hyprland_session() {
+        return Ok("the Hyprland and GTK backends provide Screenshot and ScreenCast".to_string());
+    }
+
+    let line = "busctl --user introspect org.freedesktop.portal.Desktop /org/freedesktop/portal/desktop";
+    log::log_command(line);
+    let output = Command::new("busctl")
+        .args(["--user", "introspect", "org.freedesktop.portal.Desktop", "/org/freedesktop/portal/desktop"])
+        .output()
+        .map_err(|e| format!("could not run busctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "the portal does not answer on the session bus: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let unresolved: Vec<&str> = INTERFACES
+        .iter()
+        .copied()
+        .filter(|interface| {
+            let offered = format!("org.freedesktop.portal.{}", interface);
+            !stdout.lines().any(|line| line.split_whitespace().next() == Some(offered.as_str()))
+        })
+        .collect();
+    if !unresolved.is_empty() {
+        return Err(format!("the portal does not offer {}", unresolved.join(" and ")));
+    }
+    Ok("Screenshot and ScreenCast resolve on the session bus".to_string())
+}
@@ -0,0 +1,104 @@
+//! `--prebuilt`: installs Quickshell and caelestia-shell from prebuilt
+//! artifacts instead of compiling them, from a release holding
+//! `<component>-fedora<version>-<arch>.tar.gz` archives, laid out relative to
+//! the install prefix, and a `SHA256SUMS` file listing their checksums.
+//! Components without a matching artifact are built from source.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::sync::Mutex;
+
+use crate::{checks, download, log, prefix, summary, system, ui};
+
+/// Where artifacts are downloaded from unless `--prebuilt-url` says otherwise.
+pub const DEFAULT_URL: &str = "https://github.com/ST-2/caelestia-fedora/releases/latest/download";
+
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// Release URL, set when prebuilt mode is on.
+static RELEASE_URL: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn set_release_url(url: Option<String>) {
+    *RELEASE_URL.lock().unwrap() = url;
+}
+
+pub fn release_url() -> Option<String> {
+    RELEASE_URL.lock().unwrap().clone()
+}
+
+pub fn enabled() -> bool {
+    release_url().is_some()
+}
+
+/// The artifact `component` has for this Fedora version and architecture.
+fn artifact_name(component: &str) -> Option<String> {
+    let version = checks::fedora_version()?;
+    Some(format!(
+        "{}-fedora{}-{}.tar.gz",
+        component,
+        version,
+        std::env::consts::ARCH
+    ))
+}
+
+/// Downloads the artifact of `component`, checks it against the release's
+/// checksums and unpacks it into the prefix. Returns false when the release
+/// has no artifact for this system.
+pub fn install(component: &str, dry_run: bool) -> Result<bool> {
+    let Some(url) = release_url() else {
+        return Ok(false);
+    };
+    let Some(name) = artifact_name(component) else {
+        ui::warning("Could not determine the Fedora version, building from source");
+        return Ok(false);
+    };
+
+    ui::info(&format!("Looking for a prebuilt {}...", name));
+    if dry_run {
+        ui::success(&format!("Would install {} from {} if it exists (dry-run)", name, url));
+        return Ok(true);
+    }
+
+    let dir = system::build_root().join("prebuilt");
+    let checksums = dir.join(CHECKSUMS);
+    if let Err(e) = download::download(&format!("{}/{}", url, CHECKSUMS), &checksums, None) {
+        ui::warning(&format!("No prebuilt artifacts at {} ({}), building from source", url, e));
+        return Ok(false);
+    }
+
+    let Some(sha256) = find_checksum(&fs::read_to_string(&checksums)?, &name) else {
+        ui::warning(&format!("No prebuilt {} for this system, building from source", name));
+        return Ok(false);
+    };
+
+    let archive = dir.join(&name);
+    download::download(&format!("{}/{}", url, name), &archive, Some(&sha256))?;
+    ui::success(&format!("Downloaded and verified {}", name));
+
+    let install_prefix = prefix::prefix();
+    let cmd = format!("tar -xzf {} -C {}", archive.display(), install_prefix.display());
+    log::log_command(&prefix::command_line(&cmd));
+    let output = prefix::command("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&install_prefix)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to unpack {}", name);
+    }
+
+    ui::success(&format!("Installed prebuilt {} to {}", component, install_prefix.display()));
+    summary::artifact(&format!("{} (prebuilt)", component));
+    Ok(true)
+}
+
+/// The checksum `SHA256SUMS` lists for `name`, in `sha256sum` format.
+fn find_checksum(checksums: &str, name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (sum, file) = line.split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then(|| sum.to_lowercase())
+    })
+}
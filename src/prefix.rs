@@ -0,0 +1,159 @@
+//! Where the built components (Quickshell, caelestia-shell's QML modules and
+//! the caelestia wrapper) are installed: the system by default, or a prefix
+//! such as ~/.local that the user can write to without sudo.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{hyprconf, journal, log, sudo, summary, toolbox, ui};
+
+static PREFIX: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Marks the lines the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: user prefix";
+
+/// Parses `--prefix`, expanding a leading `~` and making the path absolute.
+pub fn parse_prefix(prefix: &str) -> Result<PathBuf> {
+    let path = match (prefix.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if prefix == "~" => dirs::home_dir().unwrap_or_default(),
+        _ => PathBuf::from(prefix),
+    };
+    Ok(std::path::absolute(path)?)
+}
+
+pub fn set_prefix(prefix: Option<PathBuf>) {
+    *PREFIX.lock().unwrap() = prefix;
+}
+
+/// The prefix given with `--prefix`, if any. rpm-ostree systems default to
+/// ~/.local, as their /usr is read-only.
+pub fn configured() -> Option<PathBuf> {
+    let prefix = PREFIX.lock().unwrap().clone();
+    prefix.or_else(|| {
+        toolbox::active()
+            .then(dirs::home_dir)
+            .flatten()
+            .map(|home| home.join(".local"))
+    })
+}
+
+pub fn prefix() -> PathBuf {
+    configured().unwrap_or_else(|| PathBuf::from("/usr"))
+}
+
+/// Whether installs go to a prefix inside the home directory, which needs no sudo.
+pub fn is_user() -> bool {
+    match (configured(), dirs::home_dir()) {
+        (Some(prefix), Some(home)) => prefix.starts_with(home),
+        _ => false,
+    }
+}
+
+pub fn qml_dir() -> PathBuf {
+    prefix().join("lib64/qt6/qml")
+}
+
+pub fn lib_dir() -> PathBuf {
+    prefix().join("lib64/caelestia")
+}
+
+/// Where the caelestia wrapper script goes.
+pub fn bin_dir() -> PathBuf {
+    if is_user() {
+        prefix().join("bin")
+    } else {
+        PathBuf::from("/usr/local/bin")
+    }
+}
+
+/// Builds a command that writes into the prefix, through sudo unless it is
+/// a user prefix. User prefixes are written from the build environment,
+/// which on rpm-ostree systems is the toolbox sharing the home directory.
+pub fn command(program: &str) -> Command {
+    if is_user() {
+        toolbox::command(program)
+    } else {
+        let mut command = sudo::command();
+        command.arg(program);
+        command
+    }
+}
+
+/// `line` as it would be run by [`command`], for the log.
+pub fn command_line(line: &str) -> String {
+    if is_user() {
+        toolbox::command_line(line)
+    } else {
+        format!("sudo {}", line)
+    }
+}
+
+/// Makes a user prefix visible to the session: adds its bin directory to PATH
+/// and its QML directory to the import path, for systemd user sessions,
+/// Hyprland and fish.
+pub fn setup_environment(dry_run: bool) -> Result<()> {
+    if !is_user() {
+        summary::skipped();
+        return Ok(());
+    }
+
+    let bin = bin_dir();
+    let qml = qml_dir();
+    ui::info(&format!("Adding {} to the session environment...", prefix().display()));
+
+    if dry_run {
+        ui::success("Would add the prefix to PATH and the QML import path (dry-run)");
+        return Ok(());
+    }
+
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+
+    let environment = format!(
+        "PATH={}:${{PATH}}\nQML_IMPORT_PATH={}\nQML2_IMPORT_PATH={}\n",
+        bin.display(),
+        qml.display(),
+        qml.display()
+    );
+    write_config(&config_dir.join("environment.d/caelestia-prefix.conf"), &environment)?;
+
+    // Hyprland starts quickshell, so it needs the import path itself. It does
+    // not expand $PATH, which is left to environment.d and the login shell.
+    let hypr_user = config_dir.join("caelestia/hypr-user.conf");
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if !existing.contains(HYPR_MARKER) {
+        let lines = format!(
+            "\n{}\nenv = QML_IMPORT_PATH,{}\nenv = QML2_IMPORT_PATH,{}\n",
+            HYPR_MARKER,
+            qml.display(),
+            qml.display()
+        );
+        write_config(&hypr_user, &(existing + &lines))?;
+        hyprconf::check(&hypr_user);
+    }
+
+    let fish_conf = config_dir.join("fish/conf.d/caelestia-prefix.fish");
+    let fish = format!(
+        "fish_add_path --global {}\nset -gx QML_IMPORT_PATH {}\nset -gx QML2_IMPORT_PATH {}\n",
+        bin.display(),
+        qml.display(),
+        qml.display()
+    );
+    write_config(&fish_conf, &fish)?;
+
+    ui::success("Added the prefix to the session environment");
+    log::log(&format!("Environment set up for prefix {}", prefix().display()));
+    summary::artifact(&format!("prefix {}", prefix().display()));
+    Ok(())
+}
+
+fn write_config(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(path, content)?;
+    Ok(())
+}
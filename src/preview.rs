@@ -0,0 +1,331 @@
+//! The change plan of a `--dry-run`. Steps report what they would do as they
+//! skip doing it: files with a diff against what is there now, symlinks,
+//! package transactions, service changes and every command. The plan is
+//! printed at the end of the run and can be written out as JSON, or as a
+//! bash script doing the same with `--emit-script`.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use colored::Colorize;
+
+use crate::ui;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Create,
+    Overwrite,
+    Unchanged,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub action: FileAction,
+    /// Unified diff against the current content, when it can be read.
+    pub diff: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SymlinkChange {
+    pub path: PathBuf,
+    pub target: PathBuf,
+    /// Whether a file or directory is there that would be backed up.
+    pub replaces: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Transaction {
+    pub manager: String,
+    pub packages: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ServiceChange {
+    pub unit: String,
+    pub enable: bool,
+}
+
+/// One thing the script does, in the order the installer would.
+#[derive(Clone, Debug)]
+pub enum Action {
+    Command(String),
+    File { path: PathBuf, content: Vec<u8> },
+    Symlink { path: PathBuf, target: PathBuf },
+}
+
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct Plan {
+    pub files: Vec<FileChange>,
+    pub symlinks: Vec<SymlinkChange>,
+    pub transactions: Vec<Transaction>,
+    pub services: Vec<ServiceChange>,
+    pub commands: Vec<String>,
+    /// Everything above in order, with the step it belongs to.
+    #[serde(skip)]
+    pub actions: Vec<(Option<&'static str>, Action)>,
+}
+
+static PLAN: Mutex<Option<Plan>> = Mutex::new(None);
+
+/// Starts collecting the plan; until then nothing is recorded.
+pub fn begin() {
+    *PLAN.lock().unwrap() = Some(Plan::default());
+}
+
+/// The plan collected so far, if collecting.
+pub fn current() -> Option<Plan> {
+    PLAN.lock().unwrap().clone()
+}
+
+fn record(add: impl FnOnce(&mut Plan)) {
+    if let Some(ref mut plan) = *PLAN.lock().unwrap() {
+        add(plan);
+    }
+}
+
+/// A file that would be written with `content`.
+pub fn file(path: &Path, content: &[u8]) {
+    if PLAN.lock().unwrap().is_none() {
+        return;
+    }
+    let (action, diff) = match fs::read(path) {
+        Ok(current) if current == content => (FileAction::Unchanged, None),
+        Ok(_) => (FileAction::Overwrite, diff(path, content)),
+        Err(_) if path.exists() => (FileAction::Overwrite, None),
+        Err(_) => (FileAction::Create, None),
+    };
+    record(|plan| {
+        plan.files.push(FileChange {
+            path: path.to_path_buf(),
+            action,
+            diff,
+        });
+        plan.actions.push((
+            ui::step_label(),
+            Action::File {
+                path: path.to_path_buf(),
+                content: content.to_vec(),
+            },
+        ));
+    });
+}
+
+/// `diff -u` of the file at `path` against `content`.
+fn diff(path: &Path, content: &[u8]) -> Option<String> {
+    let label = path.to_string_lossy();
+    let mut child = Command::new("diff")
+        .args(["-u", "--label", &label, "--label", &label])
+        .arg(path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(content).ok()?;
+    let output = child.wait_with_output().ok()?;
+    // diff exits 1 when the files differ
+    (output.status.code() == Some(1)).then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A symlink at `path` that would point to `target`.
+pub fn symlink(path: &Path, target: &Path) {
+    record(|plan| {
+        plan.symlinks.push(SymlinkChange {
+            path: path.to_path_buf(),
+            target: target.to_path_buf(),
+            replaces: path.exists() && !path.is_symlink(),
+        });
+        plan.actions.push((
+            ui::step_label(),
+            Action::Symlink {
+                path: path.to_path_buf(),
+                target: target.to_path_buf(),
+            },
+        ));
+    });
+}
+
+/// Packages `manager` would install.
+pub fn transaction(manager: &str, packages: &[String]) {
+    if packages.is_empty() {
+        return;
+    }
+    record(|plan| {
+        plan.transactions.push(Transaction {
+            manager: manager.to_string(),
+            packages: packages.to_vec(),
+        })
+    });
+}
+
+pub fn service(unit: &str, enable: bool) {
+    record(|plan| {
+        plan.services.push(ServiceChange {
+            unit: unit.to_string(),
+            enable,
+        })
+    });
+}
+
+/// A command that would run.
+pub fn command(line: &str) {
+    record(|plan| {
+        plan.commands.push(line.to_string());
+        plan.actions.push((ui::step_label(), Action::Command(line.to_string())));
+    });
+}
+
+/// Prints the collected plan, as one JSON object with `--json`.
+pub fn print() {
+    let Some(plan) = current() else {
+        return;
+    };
+    if ui::json() {
+        ui::print_json(serde_json::json!({ "status": "plan", "plan": plan }));
+        return;
+    }
+
+    ui::block(&format!("\n{}", "Change plan:".cyan().bold()));
+    if !plan.transactions.is_empty() {
+        ui::block(&format!("{}", "Packages".bold()));
+        for transaction in &plan.transactions {
+            ui::block(&format!(
+                "  {} would install {}: {}",
+                transaction.manager,
+                transaction.packages.len(),
+                transaction.packages.join(" ")
+            ));
+        }
+    }
+    if !plan.files.is_empty() {
+        ui::block(&format!("{}", "Files".bold()));
+        for file in &plan.files {
+            let action = match file.action {
+                FileAction::Create => format!("{:<9}", "create").green(),
+                FileAction::Overwrite => format!("{:<9}", "overwrite").yellow(),
+                FileAction::Unchanged => format!("{:<9}", "unchanged").dimmed(),
+            };
+            ui::block(&format!("  {} {}", action, file.path.display()));
+            if let Some(ref diff) = file.diff {
+                for line in diff.lines() {
+                    let line = match line.chars().next() {
+                        Some('+') => line.green(),
+                        Some('-') => line.red(),
+                        _ => line.dimmed(),
+                    };
+                    ui::block(&format!("      {}", line));
+                }
+            }
+        }
+    }
+    if !plan.symlinks.is_empty() {
+        ui::block(&format!("{}", "Symlinks".bold()));
+        for link in &plan.symlinks {
+            let replaces = if link.replaces { " (backs up what is there)" } else { "" };
+            ui::block(&format!("  {} -> {}{}", link.path.display(), link.target.display(), replaces));
+        }
+    }
+    if !plan.services.is_empty() {
+        ui::block(&format!("{}", "Services".bold()));
+        for service in &plan.services {
+            let change = if service.enable { "enable" } else { "disable" };
+            ui::block(&format!("  {} {}", change, service.unit));
+        }
+    }
+    if !plan.commands.is_empty() {
+        ui::block(&format!("{}", "Commands".bold()));
+        for command in &plan.commands {
+            ui::block(&format!("  {}", command));
+        }
+    }
+}
+
+/// Writes the collected plan to `path` as JSON.
+pub fn save(path: &Path) -> Result<()> {
+    let plan = current().unwrap_or_default();
+    fs::write(path, serde_json::to_string_pretty(&plan)?)?;
+    Ok(())
+}
+
+/// Writes the collected actions to `path` as a bash script, grouped by step.
+/// Files outside the home directory are written through sudo.
+pub fn write_script(path: &Path) -> Result<()> {
+    let plan = current().unwrap_or_default();
+    let home = dirs::home_dir().unwrap_or_default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut script = String::from("#!/usr/bin/env bash\n");
+    script.push_str(&format!("# Written by: caelestia-installer {}\n", args.join(" ")));
+    script.push_str(
+        "# The commands and files of that install, in order. Choices the installer\n\
+         # makes at run time are written as it would have made them on this machine.\n\
+         set -euo pipefail\n",
+    );
+
+    let mut steps: Vec<Option<&'static str>> = Vec::new();
+    for (step, _) in &plan.actions {
+        if !steps.contains(step) {
+            steps.push(*step);
+        }
+    }
+    let mut made_dirs = HashSet::new();
+    for step in steps {
+        script.push_str(&format!("\n# --- {} ---\n", step.unwrap_or("setup")));
+        for (_, action) in plan.actions.iter().filter(|(s, _)| *s == step) {
+            script.push_str(&script_lines(action, &home, &mut made_dirs));
+        }
+    }
+
+    fs::write(path, script)?;
+    fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+    Ok(())
+}
+
+/// The script lines of `action`. Directories of files in the home directory
+/// are created once; system files come after their own `sudo mkdir -p`.
+fn script_lines(action: &Action, home: &Path, made_dirs: &mut HashSet<PathBuf>) -> String {
+    let mut lines = String::new();
+    let path = match action {
+        Action::Command(line) => return format!("{}\n", line),
+        Action::File { path, .. } | Action::Symlink { path, .. } => path,
+    };
+    let in_home = path.starts_with(home);
+    if let Some(parent) = path.parent().filter(|_| in_home) {
+        if made_dirs.insert(parent.to_path_buf()) {
+            lines.push_str(&format!("mkdir -p {}\n", quote(parent)));
+        }
+    }
+
+    match action {
+        Action::File { content, .. } => {
+            let sudo = if in_home { "" } else { "sudo " };
+            let mut content = String::from_utf8_lossy(content).into_owned();
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            lines.push_str(&format!(
+                "{}tee {} > /dev/null <<'CAELESTIA_EOF'\n{}CAELESTIA_EOF\n",
+                sudo,
+                quote(path),
+                content
+            ));
+        }
+        Action::Symlink { target, .. } => lines.push_str(&format!("ln -sfn {} {}\n", quote(target), quote(path))),
+        Action::Command(_) => {}
+    }
+    lines
+}
+
+/// Single-quotes `path` for the shell.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
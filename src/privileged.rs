@@ -0,0 +1,165 @@
+//! Changes to root-owned files and services, made through one escalation
+//! point. Every operation is logged as the command it runs, skipped with
+//! `--dry-run`, and fails with sudo's error instead of being ignored. Files
+//! and services are journaled so `--rollback` can undo them.
+
+use anyhow::{bail, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+
+use crate::{journal, log, preview, sudo, toolbox};
+
+static DRY_RUN: Mutex<bool> = Mutex::new(false);
+
+/// Only logs the operations from now on.
+pub fn set_dry_run(dry_run: bool) {
+    *DRY_RUN.lock().unwrap() = dry_run;
+}
+
+fn dry_run() -> bool {
+    *DRY_RUN.lock().unwrap()
+}
+
+/// Runs `args` as root, failing with the command's error output.
+pub fn run<S: AsRef<str>>(args: &[S]) -> Result<()> {
+    output(sudo::command(), args, "sudo").map(|_| ())
+}
+
+/// Like [`run`], returning the output of commands whose result is read.
+/// A dry run returns an empty output.
+pub fn run_output<S: AsRef<str>>(args: &[S]) -> Result<Output> {
+    output(sudo::command(), args, "sudo")
+}
+
+/// Runs `args` as root in the build environment, which on rpm-ostree
+/// systems is the toolbox whose `/usr` the source components build against.
+pub fn run_in_build_env<S: AsRef<str>>(args: &[S]) -> Result<()> {
+    output(toolbox::command("sudo"), args, &toolbox::command_line("sudo")).map(|_| ())
+}
+
+fn output<S: AsRef<str>>(mut command: Command, args: &[S], prefix: &str) -> Result<Output> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    let line = format!("{} {}", prefix, args.join(" "));
+    log::log_command(&line);
+    if dry_run() {
+        return Ok(Output {
+            status: Default::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        });
+    }
+
+    let output = sudo::output(command.args(&args))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("{} failed: {}", line, stderr.trim());
+    }
+    Ok(output)
+}
+
+/// Writes a root-owned file, creating its directory. What it replaces is
+/// journaled.
+pub fn write_file(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir(parent)?;
+    }
+    if !dry_run() {
+        journal::before_system_write(path)?;
+    }
+    restore_file(path, content.as_ref())
+}
+
+/// Writes a root-owned file without journaling it, as rollback does.
+pub fn restore_file(path: &Path, content: &[u8]) -> Result<()> {
+    if dry_run() {
+        log::log(&format!("Would write {}", path.display()));
+        preview::file(path, content);
+        return Ok(());
+    }
+    log::log_command(&format!("sudo tee {}", path.display()));
+
+    let mut child = sudo::command()
+        .arg("tee")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(ref mut stdin) = child.stdin {
+        stdin.write_all(content)?;
+    }
+    drop(child.stdin.take());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to write {}: {}", path.display(), stderr.trim());
+    }
+    Ok(())
+}
+
+pub fn create_dir(path: &Path) -> Result<()> {
+    run(&["mkdir", "-p", &path.to_string_lossy()])
+}
+
+pub fn copy(from: &Path, to: &Path) -> Result<()> {
+    run(&["cp", &from.to_string_lossy(), &to.to_string_lossy()])
+}
+
+/// Points `link` at `target`, replacing what is there.
+pub fn symlink(target: &Path, link: &Path) -> Result<()> {
+    run(&["ln", "-sfn", &target.to_string_lossy(), &link.to_string_lossy()])
+}
+
+pub fn remove(path: &Path) -> Result<()> {
+    run(&["rm", "-f", &path.to_string_lossy()])
+}
+
+pub fn set_owner(path: &Path, owner: &str) -> Result<()> {
+    run(&["chown", owner, &path.to_string_lossy()])
+}
+
+pub fn set_mode(path: &Path, mode: &str) -> Result<()> {
+    run(&["chmod", mode, &path.to_string_lossy()])
+}
+
+pub fn enable_service(service: &str) -> Result<()> {
+    set_service(service, true)
+}
+
+pub fn disable_service(service: &str) -> Result<()> {
+    set_service(service, false)
+}
+
+/// Enables or disables `service`, journaling the change when it is one.
+fn set_service(service: &str, enable: bool) -> Result<()> {
+    let was_enabled = journal::service_enabled(service);
+    if dry_run() {
+        preview::service(service, enable);
+    }
+    run(&["systemctl", if enable { "enable" } else { "disable" }, service])?;
+    if was_enabled != enable && !dry_run() {
+        journal::record(journal::Entry::Service {
+            name: service.to_string(),
+            was_enabled,
+        });
+    }
+    Ok(())
+}
+
+/// Boots into `target` from now on, journaling the previous default.
+pub fn set_default_target(target: &str) -> Result<()> {
+    let previous = Command::new("systemctl")
+        .arg("get-default")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+    run(&["systemctl", "set-default", target])?;
+    if !previous.is_empty() && previous != target && !dry_run() {
+        journal::record(journal::Entry::DefaultTarget { previous });
+    }
+    Ok(())
+}
@@ -1,41 +1,352 @@
 use anyhow::{bail, Result};
+use std::fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 
-use crate::{log, ui};
+use crate::packages::{self, Backend};
+use crate::{checks, dnf, download, log, offline, privileged, runner, ui};
 
-const COPR_REPOS: &[&str] = &[
+pub const COPR_REPOS: &[&str] = &[
     "solopasha/hyprland",
 ];
 
-pub fn add_all(dry_run: bool) -> Result<()> {
+const COPR_HOST: &str = "copr.fedorainfracloud.org";
+
+const RPMFUSION_MIRROR: &str = "https://mirrors.rpmfusion.org";
+
+/// Fingerprints pinned in the config file, by repo.
+static CONFIG_PINS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Trusts the COPR keys of the config file's `[repos.copr_keys]`, so an
+/// unattended install can import keys that are not pinned on this machine.
+pub fn set_key_pins(pins: &BTreeMap<String, String>) {
+    *CONFIG_PINS.lock().unwrap() = pins
+        .iter()
+        .map(|(repo, fingerprint)| (repo.clone(), normalize_fingerprint(fingerprint)))
+        .collect();
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.split_whitespace().collect::<String>().to_uppercase()
+}
+
+/// Adds the COPRs, and RPM Fusion when `rpmfusion` says so, asking when it
+/// is `None`.
+pub fn add_all(rpmfusion: Option<bool>, dry_run: bool, noconfirm: bool) -> Result<()> {
+    if offline::enabled() {
+        ui::info("Installing offline, packages come from the cache instead of the COPRs");
+        return Ok(());
+    }
     for repo in COPR_REPOS {
         add_copr(repo, dry_run)?;
+        verify_copr_key(repo, dry_run, noconfirm)?;
+    }
+
+    if rpmfusion_enabled() {
+        ui::success("RPM Fusion already enabled");
+        return Ok(());
+    }
+    let wanted = rpmfusion.unwrap_or_else(|| {
+        !noconfirm && ui::prompt("Enable RPM Fusion free and nonfree (codecs, NVIDIA driver)?")
+    });
+    if wanted {
+        enable_rpmfusion(dry_run)?;
     }
     Ok(())
 }
 
-fn add_copr(repo: &str, dry_run: bool) -> Result<()> {
-    ui::info(&format!("Adding COPR repo: {}", repo));
+fn rpmfusion_enabled() -> bool {
+    Command::new("rpm")
+        .args(["-q", "rpmfusion-free-release", "rpmfusion-nonfree-release"])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
 
-    let cmd = format!("sudo dnf copr enable -y {}", repo);
-    log::log_command(&cmd);
+/// URL of the release package of RPM Fusion's `kind` (free or nonfree)
+/// repository for this Fedora release.
+fn rpmfusion_release_url(kind: &str) -> Result<String> {
+    let Some(release) = checks::release() else {
+        bail!("Could not read the Fedora release from /etc/os-release");
+    };
+    Ok(format!(
+        "{}/{}/fedora/rpmfusion-{}-release-{}.noarch.rpm",
+        RPMFUSION_MIRROR,
+        kind,
+        kind,
+        release.name()
+    ))
+}
+
+/// Enables the RPM Fusion free and nonfree repositories, which carry the
+/// NVIDIA driver and codecs Fedora cannot ship.
+pub fn enable_rpmfusion(dry_run: bool) -> Result<()> {
+    if rpmfusion_enabled() {
+        ui::success("RPM Fusion already enabled");
+        return Ok(());
+    }
+
+    ui::info("Enabling RPM Fusion...");
+    let release_rpms = vec![rpmfusion_release_url("free")?, rpmfusion_release_url("nonfree")?];
+
+    log::log_command(&packages::install_command_line(&release_rpms.join(" ")));
 
     if dry_run {
-        ui::success(&format!("Would add COPR: {} (dry-run)", repo));
+        ui::success("Would enable RPM Fusion (dry-run)");
         return Ok(());
     }
 
-    let output = Command::new("sudo")
-        .args(["dnf", "copr", "enable", "-y", repo])
-        .output()?;
+    let output = runner::run(&mut packages::install_command(&release_rpms))?;
 
     if output.status.success() {
-        ui::success(&format!("Added COPR: {}", repo));
-        log::log(&format!("COPR {} enabled", repo));
+        ui::success("Enabled RPM Fusion");
+        log::log("RPM Fusion enabled");
         Ok(())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
         log::log_error(&stderr);
+        bail!("Failed to enable RPM Fusion");
+    }
+}
+
+/// Adds `repo` and checks its key, for a COPR only one part of the install
+/// falls back to.
+pub fn enable_copr(repo: &str, dry_run: bool, noconfirm: bool) -> Result<()> {
+    add_copr(repo, dry_run)?;
+    verify_copr_key(repo, dry_run, noconfirm)
+}
+
+fn add_copr(repo: &str, dry_run: bool) -> Result<()> {
+    ui::info(&format!("Adding COPR repo: {}", repo));
+
+    if packages::backend() == Backend::RpmOstree {
+        return add_copr_file(repo, dry_run);
+    }
+
+    dnf::ensure_copr_plugin(dry_run)?;
+    if privileged::run(&["dnf", "copr", "enable", "-y", repo]).is_err() {
         bail!("Failed to enable COPR repo: {}", repo);
     }
+
+    if dry_run {
+        ui::success(&format!("Would add COPR: {} (dry-run)", repo));
+    } else {
+        ui::success(&format!("Added COPR: {}", repo));
+        log::log(&format!("COPR {} enabled", repo));
+    }
+    Ok(())
+}
+
+/// rpm-ostree has no `copr` command, so the repo file dnf would write is
+/// downloaded and put in place directly.
+fn add_copr_file(repo: &str, dry_run: bool) -> Result<()> {
+    let (owner, project) = repo.split_once('/').unwrap_or((repo, ""));
+    let version = checks::release().map(|r| r.name()).unwrap_or_else(|| "rawhide".to_string());
+    let url = format!(
+        "https://{}/coprs/{}/{}/repo/fedora-{}/{}-{}-fedora-{}.repo",
+        COPR_HOST, owner, project, version, owner, project, version
+    );
+    let target = copr_repo_file(repo);
+
+    let cmd = format!("sudo install -m 644 <{}> {}", url, target);
+    log::log_command(&cmd);
+
+    if dry_run {
+        ui::success(&format!("Would add COPR: {} (dry-run)", repo));
+        return Ok(());
+    }
+
+    let downloaded = crate::system::build_root().join(format!("copr-{}-{}.repo", owner, project));
+    download::download(&url, &downloaded, None)?;
+
+    let installed = privileged::run(&["install", "-m", "644", downloaded.to_str().unwrap(), &target]);
+    fs::remove_file(&downloaded).ok();
+
+    if installed.is_err() {
+        bail!("Failed to add COPR repo: {}", repo);
+    }
+    ui::success(&format!("Added COPR: {}", repo));
+    log::log(&format!("COPR {} repo file written to {}", repo, target));
+    Ok(())
+}
+
+/// Where `dnf copr enable` puts the repo file of `repo`.
+fn copr_repo_file(repo: &str) -> String {
+    let (owner, project) = repo.split_once('/').unwrap_or((repo, ""));
+    format!("/etc/yum.repos.d/_copr:{}:{}:{}.repo", COPR_HOST, owner, project)
+}
+
+/// Fetches the signing key of an enabled COPR repo and checks its fingerprint
+/// against the pinned one (or asks the user to trust it) before importing it
+/// into rpm, so dnf never imports an unreviewed key on its own.
+fn verify_copr_key(repo: &str, dry_run: bool, noconfirm: bool) -> Result<()> {
+    ui::info(&format!("Verifying GPG key for COPR: {}", repo));
+
+    if dry_run {
+        ui::success(&format!("Would verify GPG key for {} (dry-run)", repo));
+        return Ok(());
+    }
+
+    let key_url = copr_key_url(repo);
+    let key_path = crate::system::build_root().join(format!("copr-{}.gpg", repo.replace('/', "-")));
+
+    if let Err(e) = download::download(&key_url, &key_path, None) {
+        log::log_error(&e.to_string());
+        disable_copr(repo);
+        bail!("Failed to download GPG key for COPR repo: {}", repo);
+    }
+
+    let fingerprint = match key_fingerprint(&key_path)? {
+        Some(fpr) => fpr,
+        None => {
+            disable_copr(repo);
+            bail!("Could not read a fingerprint from the GPG key of COPR repo: {}", repo);
+        }
+    };
+
+    ui::info(&format!("Key fingerprint: {}", fingerprint));
+    log::log(&format!("COPR {} key fingerprint {}", repo, fingerprint));
+
+    match pinned_fingerprint(repo) {
+        Some(pinned) if pinned == fingerprint => {
+            ui::success("Fingerprint matches pinned key");
+        }
+        Some(pinned) => {
+            log::log_error(&format!(
+                "COPR {} key mismatch: pinned {}, got {}",
+                repo, pinned, fingerprint
+            ));
+            ui::error(&format!("Pinned fingerprint: {}", pinned));
+            disable_copr(repo);
+            bail!(
+                "GPG key for COPR repo {} does not match the pinned fingerprint in {:?} or the config file",
+                repo,
+                pins_path()
+            );
+        }
+        None if noconfirm => {
+            disable_copr(repo);
+            bail!(
+                "No pinned fingerprint for COPR repo {}, pin it with \"{}\" = \"{}\" under [repos.copr_keys] in the config file after checking it, or run without --noconfirm to review it",
+                repo,
+                repo,
+                fingerprint
+            );
+        }
+        None => {
+            if !ui::prompt(&format!("Trust this key for {}?", repo)) {
+                disable_copr(repo);
+                bail!("GPG key for COPR repo {} was not trusted", repo);
+            }
+            pin_fingerprint(repo, &fingerprint)?;
+        }
+    }
+
+    let imported = privileged::run(&["rpm", "--import", key_path.to_str().unwrap()]);
+    fs::remove_file(&key_path).ok();
+
+    if imported.is_err() {
+        bail!("Failed to import GPG key for COPR repo: {}", repo);
+    }
+    ui::success(&format!("Imported GPG key for {}", repo));
+    Ok(())
+}
+
+/// Reads the `gpgkey=` entry from the repo file dnf wrote, falling back to the
+/// standard COPR key location.
+fn copr_key_url(repo: &str) -> String {
+    fs::read_to_string(copr_repo_file(repo))
+        .ok()
+        .and_then(|content| {
+            content
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("gpgkey="))
+                .map(|url| url.trim().to_string())
+        })
+        .unwrap_or_else(|| copr_pubkey_url(repo))
+}
+
+/// The standard location of the key a COPR signs its packages with.
+pub fn copr_pubkey_url(repo: &str) -> String {
+    let (owner, project) = repo.split_once('/').unwrap_or((repo, ""));
+    format!("https://download.{}/results/{}/{}/pubkey.gpg", COPR_HOST, owner, project)
+}
+
+/// The packages a COPR builds for this Fedora release and architecture.
+pub fn copr_baseurl(repo: &str) -> String {
+    let (owner, project) = repo.split_once('/').unwrap_or((repo, ""));
+    let version = checks::release().map(|r| r.name()).unwrap_or_else(|| "rawhide".to_string());
+    format!(
+        "https://download.{}/results/{}/{}/fedora-{}-{}/",
+        COPR_HOST,
+        owner,
+        project,
+        version,
+        std::env::consts::ARCH
+    )
+}
+
+fn key_fingerprint(key_path: &Path) -> Result<Option<String>> {
+    let output = Command::new("gpg")
+        .args(["--show-keys", "--with-colons", key_path.to_str().unwrap()])
+        .output()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .find(|line| line.starts_with("fpr:"))
+        .and_then(|line| line.split(':').nth(9))
+        .map(|fpr| fpr.to_uppercase()))
+}
+
+pub fn pins_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia-installer/copr-keys.conf")
+}
+
+/// Pins come from the config file, then from the pins file, which stores
+/// them one per line as `owner/project FINGERPRINT`.
+fn pinned_fingerprint(repo: &str) -> Option<String> {
+    if let Some(pinned) = CONFIG_PINS.lock().unwrap().get(repo) {
+        return Some(pinned.clone());
+    }
+    let content = fs::read_to_string(pins_path()).ok()?;
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .find_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next() == Some(repo) {
+                parts.next().map(str::to_uppercase)
+            } else {
+                None
+            }
+        })
+}
+
+fn pin_fingerprint(repo: &str, fingerprint: &str) -> Result<()> {
+    let path = pins_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut content = fs::read_to_string(&path)
+        .unwrap_or_else(|_| "# Pinned COPR signing key fingerprints\n".to_string());
+    content.push_str(&format!("{} {}\n", repo, fingerprint));
+    fs::write(&path, content)?;
+
+    log::log(&format!("Pinned {} key {} in {:?}", repo, fingerprint, path));
+    Ok(())
+}
+
+fn disable_copr(repo: &str) {
+    if packages::backend() == Backend::RpmOstree {
+        let _ = privileged::remove(Path::new(&copr_repo_file(repo)));
+        return;
+    }
+
+    let _ = privileged::run(&["dnf", "copr", "disable", repo]);
 }
@@ -0,0 +1,214 @@
+//! Runs external commands with their output streamed while they run: every
+//! line goes to the install log as it arrives, and to the terminal with
+//! `-v`, instead of all at once when the command exits. A command
+//! that prints nothing for longer than its budget is taken as hung: the user
+//! is asked whether to keep waiting, or with `--noconfirm` it is stopped.
+
+use anyhow::{bail, Result};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{interrupt, log, ui};
+
+/// How long a command may print nothing before it is taken as hung, unless
+/// `--command-timeout` says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long a command runs before a spinner shows it is still going.
+const SPINNER_DELAY: Duration = Duration::from_secs(3);
+
+const POLL: Duration = Duration::from_millis(100);
+
+static TIMEOUT: Mutex<Option<Duration>> = Mutex::new(Some(DEFAULT_TIMEOUT));
+static ASK_ON_TIMEOUT: Mutex<bool> = Mutex::new(true);
+
+/// Whether command output is echoed to the terminal, with `-v`.
+fn verbose() -> bool {
+    ui::verbosity() >= ui::Verbosity::Verbose
+}
+
+/// Sets how long commands may print nothing, `None` waiting forever, and
+/// whether to ask before stopping one.
+pub fn set_timeout(timeout: Option<Duration>, ask: bool) {
+    *TIMEOUT.lock().unwrap() = timeout;
+    *ASK_ON_TIMEOUT.lock().unwrap() = ask;
+}
+
+/// A spawned command whose output is being streamed.
+pub struct Running {
+    pub child: Child,
+    stdout: JoinHandle<Vec<u8>>,
+    stderr: JoinHandle<Vec<u8>>,
+    /// The command line, shortened, for messages.
+    name: String,
+    last_output: Arc<Mutex<Instant>>,
+}
+
+impl Running {
+    /// Waits for the command to exit and returns everything it printed,
+    /// within the `--command-timeout` budget.
+    pub fn wait(self) -> Result<Output> {
+        let budget = *TIMEOUT.lock().unwrap();
+        self.wait_for(budget)
+    }
+
+    /// Like [`wait`](Self::wait) with `budget` instead of the configured one.
+    pub fn wait_for(mut self, budget: Option<Duration>) -> Result<Output> {
+        let started = Instant::now();
+        let mut spinner = None;
+        let status = loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => {}
+                Err(e) => break Err(e),
+            }
+            thread::sleep(POLL);
+
+            if spinner.is_none() && started.elapsed() >= SPINNER_DELAY && !verbose() {
+                spinner = ui::spinner(&self.name, started.elapsed());
+            }
+            let Some(budget) = budget else {
+                continue;
+            };
+            let quiet = self.last_output.lock().unwrap().elapsed();
+            if quiet < budget {
+                continue;
+            }
+
+            if let Some(bar) = spinner.take() {
+                bar.finish_and_clear();
+            }
+            log::warn(&format!("{} printed nothing for {}", self.name, ui::format_duration(quiet)));
+            let keep_waiting = *ASK_ON_TIMEOUT.lock().unwrap()
+                && ui::prompt(&format!(
+                    "{} has printed nothing for {}. Keep waiting?",
+                    self.name,
+                    ui::format_duration(quiet)
+                ));
+            if keep_waiting {
+                *self.last_output.lock().unwrap() = Instant::now();
+                continue;
+            }
+            self.child.kill().ok();
+            self.child.wait().ok();
+            interrupt::forget_child(self.child.id());
+            bail!("{} printed nothing for {} and was stopped", self.name, ui::format_duration(quiet));
+        };
+        if let Some(bar) = spinner {
+            bar.finish_and_clear();
+        }
+        interrupt::forget_child(self.child.id());
+        let status = status?;
+        Ok(Output {
+            status,
+            stdout: self.stdout.join().unwrap_or_default(),
+            stderr: self.stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+pub fn spawn(command: &mut Command) -> Result<Running> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    interrupt::track_child(child.id());
+
+    let label = ui::step_label();
+    let last_output = Arc::new(Mutex::new(Instant::now()));
+    let stdout = stream(child.stdout.take(), "stdout", label, last_output.clone());
+    let stderr = stream(child.stderr.take(), "stderr", label, last_output.clone());
+
+    Ok(Running {
+        child,
+        stdout,
+        stderr,
+        name: describe(command),
+        last_output,
+    })
+}
+
+/// The start of `command`'s line, enough to tell which one it is.
+fn describe(command: &Command) -> String {
+    let line = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    match line.char_indices().nth(48) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+/// Drop-in replacement for `Command::output` that streams the output.
+pub fn run(command: &mut Command) -> Result<Output> {
+    spawn(command)?.wait()
+}
+
+/// Reads `pipe` line by line on its own thread, logging each line and
+/// returning the whole output. Without `-v` only ninja's progress is
+/// shown: as a bar next to those of the other running builds, or in steps of
+/// 10% when bars cannot be drawn.
+fn stream(
+    pipe: Option<impl Read + Send + 'static>,
+    tag: &'static str,
+    label: Option<&'static str>,
+    last_output: Arc<Mutex<Instant>>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        ui::set_step_label(label);
+
+        let mut collected = Vec::new();
+        let Some(pipe) = pipe else {
+            return collected;
+        };
+
+        let mut reader = BufReader::new(pipe);
+        let mut line = Vec::new();
+        let mut bar = None;
+        let mut shown_tenths = 0;
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            *last_output.lock().unwrap() = Instant::now();
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end();
+            log::log_stream(tag, text);
+
+            if verbose() {
+                ui::output(text);
+            } else if let Some((done, total)) = ninja_progress(text) {
+                match bar.get_or_insert_with(|| ui::build_bar(label.unwrap_or("build"))) {
+                    Some(bar) => {
+                        bar.set_length(total as u64);
+                        bar.set_position(done as u64);
+                    }
+                    None => {
+                        let tenths = done * 10 / total;
+                        if tenths > shown_tenths {
+                            shown_tenths = tenths;
+                            ui::info(&format!("{}% ({}/{})", tenths * 10, done, total));
+                        }
+                    }
+                }
+            }
+
+            collected.extend_from_slice(&line);
+            line.clear();
+        }
+        if let Some(Some(bar)) = bar {
+            bar.finish_and_clear();
+        }
+        collected
+    })
+}
+
+/// Parses ninja's `[done/total] ...` status lines.
+fn ninja_progress(line: &str) -> Option<(usize, usize)> {
+    let status = line.strip_prefix('[')?.split(']').next()?;
+    let (done, total) = status.split_once('/')?;
+    let total: usize = total.parse().ok()?;
+    (total > 0).then_some((done.parse().ok()?, total))
+}
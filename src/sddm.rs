@@ -0,0 +1,93 @@
+//! SDDM as the display manager, running its greeter on Wayland through
+//! weston so no X server is needed.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::display_manager::{disable_others, set_graphical_target};
+use crate::{journal, log, privileged, summary, ui};
+
+pub const PACKAGES: &[&str] = &["sddm", "sddm-wayland-generic", "sddm-themes"];
+
+const CONFIG_PATH: &str = "/etc/sddm.conf.d/caelestia.conf";
+
+const SDDM_CONFIG: &str = "[General]
+DisplayServer=wayland
+
+[Theme]
+Current=maldives
+";
+
+/// Normally shipped by the hyprland package, written when it is missing.
+const SESSION_PATH: &str = "/usr/share/wayland-sessions/hyprland.desktop";
+
+const SESSION_ENTRY: &str = "[Desktop Entry]
+Name=Hyprland
+Comment=An intelligent dynamic tiling Wayland compositor
+Exec=Hyprland
+Type=Application
+DesktopNames=Hyprland
+";
+
+/// Whether SDDM has the Wayland config and is enabled.
+pub fn configured() -> bool {
+    std::fs::read_to_string(CONFIG_PATH).is_ok_and(|config| config == SDDM_CONFIG) && journal::service_enabled("sddm")
+}
+
+pub fn setup_all(dry_run: bool, noconfirm: bool) -> Result<()> {
+    write_config(dry_run)?;
+    write_session(dry_run)?;
+    configure_services(dry_run, noconfirm)?;
+    Ok(())
+}
+
+fn write_config(dry_run: bool) -> Result<()> {
+    ui::info("Writing SDDM configuration...");
+
+    privileged::write_file(Path::new(CONFIG_PATH), SDDM_CONFIG)?;
+    if dry_run {
+        ui::success(&format!("Would write {} (dry-run)", CONFIG_PATH));
+        return Ok(());
+    }
+    ui::success("Wrote SDDM config");
+    log::log("SDDM config written");
+    Ok(())
+}
+
+fn write_session(dry_run: bool) -> Result<()> {
+    if Path::new(SESSION_PATH).exists() {
+        ui::success("Hyprland session entry already exists");
+        return Ok(());
+    }
+    ui::info("Creating the Hyprland session entry...");
+
+    privileged::write_file(Path::new(SESSION_PATH), SESSION_ENTRY)?;
+    if dry_run {
+        ui::success(&format!("Would write {} (dry-run)", SESSION_PATH));
+        return Ok(());
+    }
+    ui::success("Created the Hyprland session entry");
+    log::log("Hyprland session entry written");
+    Ok(())
+}
+
+fn configure_services(dry_run: bool, noconfirm: bool) -> Result<()> {
+    ui::info("Configuring SDDM services...");
+
+    if dry_run {
+        privileged::enable_service("sddm")?;
+        ui::success("Would enable sddm (dry-run)");
+        return Ok(());
+    }
+
+    if !disable_others("sddm", noconfirm)? {
+        summary::skipped();
+        return Ok(());
+    }
+    privileged::enable_service("sddm")?;
+    set_graphical_target()?;
+
+    ui::success("SDDM services configured");
+    log::log("SDDM service configuration complete");
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! SELinux labels of the files the installer puts in system directories.
+//! Files built in /tmp and copied with `cp` keep the `user_tmp_t` label of
+//! their source, which confined services are denied access to, so they are
+//! relabelled with `restorecon` once everything is installed.
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{log, prefix, privileged, summary, ui};
+
+/// The cava files copied by hand, which have no install rule to label them.
+const CAVA_PATHS: &[&str] = &[
+    "/usr/include/cavacore.h",
+    "/usr/include/cava",
+    "/usr/lib64/libcavacore.a",
+    "/usr/lib64/pkgconfig/cava.pc",
+];
+
+/// Config files written through sudo by the system steps.
+const CONFIG_PATHS: &[&str] = &[
+    "/etc/greetd/config.toml",
+    "/etc/sddm.conf.d/caelestia.conf",
+    "/usr/share/wayland-sessions/hyprland.desktop",
+    "/etc/systemd/zram-generator.conf",
+    "/usr/share/fish/vendor_completions.d/caelestia.fish",
+];
+
+/// Programs whose denials are worth showing in diagnostics.
+const PROGRAMS: &[&str] = &["quickshell", "caelestia", "Hyprland", "greetd", "tuigreet", "sddm"];
+
+/// Whether SELinux is enabled, enforcing or permissive.
+pub fn enabled() -> bool {
+    Command::new("selinuxenabled")
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// The system paths the installer may have written, whether or not they
+/// exist. A user prefix is left out, files there get the home label.
+pub fn installed_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = CAVA_PATHS.iter().chain(CONFIG_PATHS).map(PathBuf::from).collect();
+    if !prefix::is_user() {
+        paths.push(prefix::bin_dir().join("caelestia"));
+        paths.push(prefix::bin_dir().join("starship"));
+        paths.push(prefix::qml_dir().join("Caelestia"));
+        paths.push(prefix::lib_dir());
+        paths.push(prefix::prefix().join("bin/quickshell"));
+    }
+    paths
+}
+
+/// Restores the default labels of everything [`installed_paths`] lists that
+/// exists.
+pub fn restore(dry_run: bool) -> Result<()> {
+    if !enabled() {
+        ui::success("SELinux is disabled, nothing to relabel");
+        summary::skipped();
+        return Ok(());
+    }
+
+    let paths: Vec<String> = installed_paths()
+        .into_iter()
+        .filter(|path| path.exists())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if paths.is_empty() {
+        ui::success("No installed system files to relabel");
+        return Ok(());
+    }
+
+    ui::info(&format!("Restoring SELinux labels of {} paths...", paths.len()));
+    let mut args = vec!["restorecon".to_string(), "-R".to_string(), "-v".to_string()];
+    args.extend(paths);
+    let Ok(output) = privileged::run_output(&args) else {
+        ui::warning("restorecon failed, run 'caelestia-installer doctor' to see the wrong labels");
+        return Ok(());
+    };
+    if dry_run {
+        ui::success("Would restore SELinux labels (dry-run)");
+        return Ok(());
+    }
+
+    // restorecon -v prints one line per file it relabelled
+    let relabelled = String::from_utf8_lossy(&output.stdout);
+    log::log(&relabelled);
+    match relabelled.lines().count() {
+        0 => ui::success("SELinux labels already correct"),
+        count => ui::success(&format!("Relabelled {} files", count)),
+    }
+    Ok(())
+}
+
+/// Installed paths whose label differs from the policy default, as
+/// `matchpathcon -V` reports them.
+pub fn mislabelled() -> Vec<String> {
+    installed_paths()
+        .into_iter()
+        .filter(|path| path.exists())
+        .filter_map(|path| {
+            let output = Command::new("matchpathcon").arg("-V").arg(&path).output().ok()?;
+            let report = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!output.status.success() || !report.ends_with("verified.")).then_some(report)
+        })
+        .collect()
+}
+
+/// Recent AVC denials of the desktop's programs. Reading the audit log needs
+/// root, so this is `None` when sudo would have to ask for a password.
+pub fn recent_denials() -> Option<Vec<String>> {
+    let output = Command::new("sudo")
+        .args(["-n", "ausearch", "-m", "AVC", "-ts", "boot", "-i"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        // ausearch exits 1 with "<no matches>" when there are none, and sudo
+        // with 1 too when it needs a password
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return stderr.contains("no matches").then(Vec::new);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(
+        stdout
+            .lines()
+            .filter(|line| line.contains("avc:"))
+            .filter(|line| PROGRAMS.iter().any(|program| line.contains(&format!("comm={}", program))))
+            .map(str::to_string)
+            .collect(),
+    )
+}
@@ -1,34 +1,314 @@
-use anyhow::Result;
+//! The user's interactive shell. Fish is configured by the dotfiles plus a
+//! conf.d snippet for PATH, starship, zoxide and fzf, and gets the plugins
+//! from the config file through fisher. Zsh gets the same tools hooked into
+//! `~/.zshrc`, and bash is left alone. The login shell is only changed when
+//! asked for.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::PathBuf;
 use std::process::Command;
 
-use crate::{log, ui};
+use crate::{journal, log, offline, runner, ui};
+
+/// Marks the line the installer adds to `~/.zshrc`.
+const ZSHRC_MARKER: &str = "# caelestia-installer: zsh";
+
+/// Sourced from `~/.zshrc`; each hook is skipped when its tool is missing.
+const ZSH_CONFIG: &str = r#"# Managed by caelestia-installer
+command -v starship >/dev/null && eval "$(starship init zsh)"
+command -v zoxide >/dev/null && eval "$(zoxide init zsh)"
+command -v fzf >/dev/null && source <(fzf --zsh)
+"#;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Shell {
+    #[default]
+    Fish,
+    Zsh,
+    Bash,
+}
+
+impl Shell {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Fish => "fish",
+            Shell::Zsh => "zsh",
+            Shell::Bash => "bash",
+        }
+    }
+
+    pub fn path(self) -> String {
+        format!("/usr/bin/{}", self.name())
+    }
+
+    /// Packages the shell's setup needs besides the base package list.
+    pub fn packages(self) -> &'static [&'static str] {
+        match self {
+            Shell::Fish => &["zoxide", "fzf"],
+            Shell::Bash => &[],
+            Shell::Zsh => &["zsh", "zoxide", "fzf"],
+        }
+    }
+}
+
+pub fn parse_shell(shell: &str) -> Result<Shell> {
+    match shell {
+        "fish" => Ok(Shell::Fish),
+        "zsh" => Ok(Shell::Zsh),
+        "bash" => Ok(Shell::Bash),
+        _ => bail!("unknown shell {:?}, expected fish, zsh or bash", shell),
+    }
+}
+
+/// Written to `~/.config/fish/conf.d`. Each hook is skipped when its tool is
+/// missing or the dotfiles' config already ran it, and the whole file is
+/// rewritten on every run, so running the installer again adds nothing.
+const FISH_CONFIG: &str = r#"# Managed by caelestia-installer
+fish_add_path --global ~/.local/bin ~/.cargo/bin
+
+status is-interactive; or exit
+
+if type -q starship; and not set -q STARSHIP_SHELL
+    starship init fish | source
+end
+if type -q zoxide; and not functions -q __zoxide_z
+    zoxide init fish | source
+end
+if type -q fzf; and not functions -q fzf_key_bindings
+    fzf --fish | source
+end
+"#;
+
+/// Fisher installs itself, from its own repository.
+const FISHER: &str = "jorgebucaran/fisher";
+
+const FISHER_URL: &str = "https://raw.githubusercontent.com/jorgebucaran/fisher/main/functions/fisher.fish";
+
+/// Keeps fisher and its plugins in `~/.local/share/fisher` rather than in
+/// `~/.config/fish`, which is the dotfiles checkout.
+const FISHER_CONFIG: &str = r#"# Managed by caelestia-installer
+set -g fisher_path ~/.local/share/fisher
+set fish_function_path $fish_function_path[1] $fisher_path/functions $fish_function_path[2..]
+set fish_complete_path $fish_complete_path[1] $fisher_path/completions $fish_complete_path[2..]
+for file in $fisher_path/conf.d/*.fish
+    source $file
+end
+"#;
+
+/// Configures `shell` and makes it the login shell when `login_shell` says
+/// so, asking when it is `None`. Fish also gets `fish_plugins` through
+/// fisher.
+pub fn setup_all(
+    shell: Shell,
+    login_shell: Option<bool>,
+    fish_plugins: &[String],
+    dry_run: bool,
+    noconfirm: bool,
+) -> Result<()> {
+    match shell {
+        Shell::Fish => {
+            setup_fish(dry_run)?;
+            install_fish_plugins(fish_plugins, dry_run)?;
+        }
+        Shell::Zsh => setup_zsh(dry_run)?,
+        Shell::Bash => ui::info("Leaving the bash configuration alone"),
+    }
+
+    if current_shell().as_deref() == Some(shell.path().as_str()) {
+        ui::success(&format!("{} is already the login shell", shell.name()));
+        return Ok(());
+    }
+    let wanted = login_shell.unwrap_or_else(|| {
+        !noconfirm && ui::prompt(&format!("Make {} your login shell?", shell.name()))
+    });
+    if wanted {
+        set_default_shell(shell, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Whether `shell` is configured, with fisher when there are `fish_plugins`,
+/// and, unless `login_shell` declines it, the login shell.
+pub fn configured(shell: Shell, login_shell: Option<bool>, fish_plugins: &[String]) -> bool {
+    let config_written = match shell {
+        Shell::Zsh => dirs::config_dir().is_some_and(|dir| dir.join("caelestia/zshrc").exists()),
+        Shell::Fish => {
+            fish_snippet().is_some_and(|path| fs::read_to_string(path).is_ok_and(|c| c == FISH_CONFIG))
+                && (fish_plugins.is_empty() || fisher_dir().join("functions/fisher.fish").exists())
+        }
+        Shell::Bash => true,
+    };
+    config_written && (login_shell == Some(false) || current_shell().as_deref() == Some(shell.path().as_str()))
+}
+
+fn fish_snippet() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("fish/conf.d/caelestia-env.fish"))
+}
+
+/// Puts `~/.local/bin` and `~/.cargo/bin` on fish's PATH and hooks starship,
+/// zoxide and fzf in, on top of what the dotfiles' config.fish does.
+fn setup_fish(dry_run: bool) -> Result<()> {
+    ui::info("Setting up fish...");
+
+    let Some(snippet) = fish_snippet() else {
+        bail!("Could not find the config directory");
+    };
+    if dry_run {
+        ui::success(&format!("Would write {:?} (dry-run)", snippet));
+        return Ok(());
+    }
+    if fs::read_to_string(&snippet).is_ok_and(|content| content == FISH_CONFIG) {
+        ui::success("Fish environment already set up");
+        return Ok(());
+    }
+
+    if let Some(parent) = snippet.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&snippet, FISH_CONFIG)?;
+
+    ui::success("Set up fish with ~/.local/bin, ~/.cargo/bin, starship, zoxide and fzf");
+    log::log(&format!("fish config written to {:?}", snippet));
+    Ok(())
+}
+
+fn fisher_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.local/share"))
+        .join("fisher")
+}
+
+/// Installs fisher and the `plugins` it does not list yet, such as
+/// `jorgebucaran/autopair.fish`, by running fish.
+fn install_fish_plugins(plugins: &[String], dry_run: bool) -> Result<()> {
+    if plugins.is_empty() {
+        return Ok(());
+    }
+    if let Some(invalid) = plugins
+        .iter()
+        .find(|plugin| !plugin.chars().all(|c| c.is_ascii_alphanumeric() || "-_./@~:".contains(c)))
+    {
+        bail!("Invalid fish plugin '{}': use owner/repo, a URL or a path", invalid);
+    }
+    if offline::enabled() {
+        ui::warning("Skipping the fish plugins: fisher downloads them from GitHub");
+        return Ok(());
+    }
+
+    let Some(snippet) = dirs::config_dir().map(|dir| dir.join("fish/conf.d/caelestia-fisher.fish")) else {
+        bail!("Could not find the config directory");
+    };
+    let mut wanted = vec![FISHER.to_string()];
+    for plugin in plugins {
+        if !wanted.contains(plugin) {
+            wanted.push(plugin.clone());
+        }
+    }
+
+    if !dry_run {
+        if fs::read_to_string(&snippet).map_or(true, |content| content != FISHER_CONFIG) {
+            if let Some(parent) = snippet.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            journal::write_file(&snippet, FISHER_CONFIG)?;
+        }
+        let listed = Command::new("fish")
+            .args(["-c", &format!("source {}; functions -q fisher; and fisher list", snippet.display())])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_lowercase())
+            .unwrap_or_default();
+        wanted.retain(|plugin| !listed.lines().any(|line| line.trim() == plugin.to_lowercase()));
+        if wanted.is_empty() {
+            ui::success("Fish plugins already installed");
+            return Ok(());
+        }
+    }
+
+    ui::info(&format!("Installing fish plugins: {}", wanted.join(", ")));
+    let script = format!(
+        "source {}; if not functions -q fisher; curl -sSL {} | source; or exit 1; end; fisher install {}",
+        snippet.display(),
+        FISHER_URL,
+        wanted.join(" ")
+    );
+    log::log_command(&format!("fish -c {:?}", script));
+    if dry_run {
+        ui::success(&format!("Would install {} with fisher (dry-run)", wanted.join(", ")));
+        return Ok(());
+    }
 
-pub fn setup_all(dry_run: bool) -> Result<()> {
-    set_default_shell(dry_run)?;
+    let output = runner::run(Command::new("fish").args(["-c", &script]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install the fish plugins: {}", stderr.trim());
+    }
+    ui::success(&format!("Installed {} with fisher", wanted.join(", ")));
+    log::log(&format!("Fish plugins installed: {}", wanted.join(", ")));
     Ok(())
 }
 
-fn set_default_shell(dry_run: bool) -> Result<()> {
-    ui::info("Setting fish as default shell...");
+/// Hooks starship, zoxide and fzf into zsh through a file sourced from
+/// `~/.zshrc`.
+fn setup_zsh(dry_run: bool) -> Result<()> {
+    ui::info("Setting up zsh...");
+
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("~"));
+    let config_dir = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+    let snippet = config_dir.join("caelestia/zshrc");
+    let zshrc = home.join(".zshrc");
 
     if dry_run {
-        ui::success("Would set fish as default shell (dry-run)");
+        ui::success(&format!("Would write {:?} and source it from {:?} (dry-run)", snippet, zshrc));
         return Ok(());
     }
 
-    let cmd = "chsh -s /usr/bin/fish";
-    log::log_command(cmd);
+    if let Some(parent) = snippet.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&snippet, ZSH_CONFIG)?;
 
-    let output = Command::new("chsh").args(["-s", "/usr/bin/fish"]).status();
+    let existing = fs::read_to_string(&zshrc).unwrap_or_default();
+    if !existing.contains(ZSHRC_MARKER) {
+        let line = format!("\n{}\nsource {}\n", ZSHRC_MARKER, snippet.display());
+        journal::write_file(&zshrc, existing + &line)?;
+    }
+
+    ui::success("Set up zsh with starship, zoxide and fzf");
+    log::log(&format!("zsh config written to {:?}", snippet));
+    Ok(())
+}
+
+fn set_default_shell(shell: Shell, dry_run: bool) -> Result<()> {
+    let path = shell.path();
+    ui::info(&format!("Setting {} as default shell...", shell.name()));
+
+    if dry_run {
+        ui::success(&format!("Would set {} as default shell (dry-run)", shell.name()));
+        return Ok(());
+    }
+
+    let previous = current_shell();
+    let cmd = format!("chsh -s {}", path);
+    log::log_command(&cmd);
+
+    let output = Command::new("chsh").args(["-s", &path]).status();
 
     match output {
         Ok(s) if s.success() => {
-            ui::success("Set fish as default shell");
-            log::log("Default shell changed to fish");
+            ui::success(&format!("Set {} as default shell", shell.name()));
+            log::log(&format!("Default shell changed to {}", shell.name()));
+            if let Some(previous) = previous.filter(|current| *current != path) {
+                journal::record(journal::Entry::Shell { previous });
+            }
             Ok(())
         }
         Ok(_) => {
-            ui::warning("Could not set default shell (may need to run manually: chsh -s /usr/bin/fish)");
+            ui::warning(&format!(
+                "Could not set default shell (may need to run manually: {})",
+                cmd
+            ));
             Ok(())
         }
         Err(e) => {
@@ -38,3 +318,11 @@ fn set_default_shell(dry_run: bool) -> Result<()> {
         }
     }
 }
+
+/// The login shell of the current user, from the passwd database.
+pub fn current_shell() -> Option<String> {
+    let user = std::env::var("USER").ok()?;
+    let output = Command::new("getent").args(["passwd", &user]).output().ok()?;
+    let entry = String::from_utf8_lossy(&output.stdout);
+    entry.trim().rsplit(':').next().map(str::to_string).filter(|s| !s.is_empty())
+}
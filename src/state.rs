@@ -0,0 +1,102 @@
+//! Checkpoints of the installation, kept in
+//! `~/.local/state/caelestia-installer/state.json` so a failed run can be
+//! resumed with `--resume` instead of starting over.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::log;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct State {
+    /// Steps that finished successfully, in completion order.
+    pub completed: Vec<String>,
+    /// Step that made the last run stop, if it failed.
+    pub failed: Option<String>,
+    /// Seconds each step took the last time it succeeded, which the step
+    /// estimates are made from.
+    #[serde(default)]
+    pub durations: BTreeMap<String, u64>,
+    /// Seconds since the epoch when the state was last written.
+    pub updated: u64,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+pub fn state_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("caelestia-installer/state.json")
+}
+
+/// Reads the state left by the previous run, empty if there is none.
+pub fn load() -> State {
+    fs::read_to_string(state_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Starts recording checkpoints on top of `state`. The durations of earlier
+/// runs are kept, a fresh run only replaces those of the steps it runs.
+pub fn begin(mut state: State) {
+    let mut durations = load().durations;
+    durations.append(&mut state.durations);
+    state.durations = durations;
+    *STATE.lock().unwrap() = Some(state);
+    save();
+}
+
+/// Records that a step finished, successfully or not, and how long it took.
+pub fn record(id: &str, succeeded: bool, took: Option<Duration>) {
+    {
+        let mut guard = STATE.lock().unwrap();
+        let Some(state) = guard.as_mut() else {
+            return;
+        };
+        if succeeded {
+            if let Some(took) = took {
+                state.durations.insert(id.to_string(), took.as_secs());
+            }
+            if !state.completed.iter().any(|done| done == id) {
+                state.completed.push(id.to_string());
+            }
+            if state.failed.as_deref() == Some(id) {
+                state.failed = None;
+            }
+        } else {
+            state.completed.retain(|done| done != id);
+            state.failed = Some(id.to_string());
+        }
+    }
+    save();
+}
+
+fn save() {
+    let mut guard = STATE.lock().unwrap();
+    let Some(state) = guard.as_mut() else {
+        return;
+    };
+    state.updated = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    if let Err(e) = write(state) {
+        log::log(&format!("Could not save install state: {}", e));
+    }
+}
+
+fn write(state: &State) -> Result<()> {
+    let path = state_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
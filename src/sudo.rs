@@ -0,0 +1,128 @@
+//! sudo for the whole run. Access is validated once before anything is
+//! installed and kept alive from a background thread, since builds can take
+//! longer than sudo's 5 minute timestamp. Once validated, privileged
+//! commands run with `sudo -n`, so an expired timestamp fails with a clear
+//! error instead of a password prompt hidden behind the progress display.
+//! The GUI, which may have no terminal to ask in, has sudo ask through an
+//! askpass helper instead.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::{log, runner, ui};
+
+/// How often the keep-alive refreshes the timestamp, well inside sudo's
+/// default timeout.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+static VALIDATED: Mutex<bool> = Mutex::new(false);
+static KEEPALIVE: Mutex<bool> = Mutex::new(false);
+static ASKPASS: Mutex<bool> = Mutex::new(false);
+
+/// Has sudo ask for the password by running `program` with the prompt,
+/// which prints the password, rather than on the terminal.
+pub fn set_askpass(program: &Path) {
+    std::env::set_var("SUDO_ASKPASS", program);
+    *ASKPASS.lock().unwrap() = true;
+}
+
+/// Asks for the password if needed and starts the keep-alive.
+pub fn validate(dry_run: bool) -> Result<()> {
+    ui::info("Checking sudo access...");
+
+    if dry_run {
+        ui::success("Sudo check (dry-run: skipped)");
+        return Ok(());
+    }
+
+    let args: &[&str] = if *ASKPASS.lock().unwrap() { &["-A", "-v"] } else { &["-v"] };
+    log::log_command(&format!("sudo {}", args.join(" ")));
+    let status = Command::new("sudo").args(args).status();
+    match status {
+        Ok(s) if s.success() => {
+            ui::success("Sudo access granted");
+            log::log("Sudo access verified");
+            *VALIDATED.lock().unwrap() = true;
+            start_keepalive();
+            Ok(())
+        }
+        _ => {
+            log::log_error("Sudo access denied");
+            bail!("Could not get sudo access. Please run as a user with sudo privileges.");
+        }
+    }
+}
+
+/// Refreshes the timestamp every [`REFRESH_INTERVAL`] until
+/// [`stop_keepalive`].
+fn start_keepalive() {
+    {
+        let mut running = KEEPALIVE.lock().unwrap();
+        if *running {
+            return;
+        }
+        *running = true;
+    }
+    thread::spawn(|| {
+        let tick = Duration::from_secs(1);
+        let mut waited = Duration::ZERO;
+        while *KEEPALIVE.lock().unwrap() {
+            thread::sleep(tick);
+            waited += tick;
+            if waited < REFRESH_INTERVAL {
+                continue;
+            }
+            waited = Duration::ZERO;
+            let refreshed = Command::new("sudo")
+                .args(["-n", "-v"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false);
+            if !refreshed {
+                log::warn("Could not refresh the sudo timestamp");
+            }
+        }
+    });
+}
+
+pub fn stop_keepalive() {
+    *KEEPALIVE.lock().unwrap() = false;
+}
+
+/// A `sudo` command to add the program and its arguments to.
+pub fn command() -> Command {
+    let mut command = Command::new("sudo");
+    if *VALIDATED.lock().unwrap() {
+        command.arg("-n");
+    }
+    command
+}
+
+/// Whether sudo itself refused to run the command, rather than the command
+/// failing.
+fn refused(output: &Output) -> bool {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    !output.status.success()
+        && ["sudo: a password is required", "sudo: a terminal is required", "incorrect password attempt"]
+            .iter()
+            .any(|message| stderr.contains(message))
+}
+
+/// Runs a command built with [`command`], streaming its output, and fails
+/// when sudo refused it. Failures of the command itself are left to the
+/// caller.
+pub fn output(command: &mut Command) -> Result<Output> {
+    let output = runner::run(command)?;
+    if refused(&output) {
+        log::log_error(&String::from_utf8_lossy(&output.stderr));
+        bail!("sudo no longer accepts the cached password, run 'sudo -v' and resume the install");
+    }
+    Ok(output)
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{cli, dotfiles, journal, log, system, ui};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StepStatus {
+    Done,
+    Skipped,
+    Warned,
+    Failed,
+}
+
+impl StepStatus {
+    fn label(self) -> &'static str {
+        match self {
+            StepStatus::Done => "done",
+            StepStatus::Skipped => "skipped",
+            StepStatus::Warned => "warned",
+            StepStatus::Failed => "failed",
+        }
+    }
+}
+
+struct StepRecord {
+    id: String,
+    status: StepStatus,
+    started: Instant,
+    duration: Option<Duration>,
+    artifacts: Vec<String>,
+}
+
+static RECORDS: Mutex<Vec<StepRecord>> = Mutex::new(Vec::new());
+/// Packages the run installed that were not there before.
+static PACKAGES_ADDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Step running on this thread; steps may run concurrently on separate threads.
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Starts recording a step on the calling thread; results and artifacts noted
+/// from this thread afterwards belong to it.
+pub fn begin(id: &str) {
+    RECORDS.lock().unwrap().push(StepRecord {
+        id: id.to_string(),
+        status: StepStatus::Done,
+        started: Instant::now(),
+        duration: None,
+        artifacts: Vec::new(),
+    });
+    CURRENT.with(|current| *current.borrow_mut() = Some(id.to_string()));
+}
+
+/// Closes the step in progress on the calling thread.
+pub fn finish() {
+    if let Some(record) = current(&mut RECORDS.lock().unwrap()) {
+        record.duration = Some(record.started.elapsed());
+    }
+    CURRENT.with(|current| *current.borrow_mut() = None);
+}
+
+/// Marks the step in progress as failed and closes it.
+pub fn fail() {
+    set_status(StepStatus::Failed);
+    finish();
+}
+
+/// Marks the step in progress as skipped (everything was already in place).
+pub fn skipped() {
+    set_status(StepStatus::Skipped);
+}
+
+/// Called for every warning shown while a step runs.
+pub fn warned() {
+    set_status(StepStatus::Warned);
+}
+
+/// Records a step that was not run at all.
+pub fn skip_step(id: &str) {
+    begin(id);
+    skipped();
+    finish();
+}
+
+/// Notes packages a transaction added, for the report.
+pub fn packages_added(packages: Vec<String>) {
+    PACKAGES_ADDED.lock().unwrap().extend(packages);
+}
+
+/// Notes a key artifact of the step in progress, e.g. "42 packages installed".
+pub fn artifact(text: &str) {
+    if let Some(record) = current(&mut RECORDS.lock().unwrap()) {
+        record.artifacts.push(text.to_string());
+    }
+}
+
+fn current(records: &mut [StepRecord]) -> Option<&mut StepRecord> {
+    let id = CURRENT.with(|current| current.borrow().clone())?;
+    records
+        .iter_mut()
+        .rev()
+        .find(|r| r.id == id && r.duration.is_none())
+}
+
+fn set_status(status: StepStatus) {
+    if let Some(record) = current(&mut RECORDS.lock().unwrap()) {
+        record.status = record.status.max(status);
+    }
+}
+
+/// Steps that started and have not finished.
+pub fn running() -> Vec<String> {
+    RECORDS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|record| record.duration.is_none())
+        .map(|record| record.id.clone())
+        .collect()
+}
+
+/// Prints a table of every recorded step and writes it to the log.
+pub fn print() {
+    let records = RECORDS.lock().unwrap();
+    if records.is_empty() {
+        return;
+    }
+
+    log::log("=== SUMMARY ===");
+    if ui::json() {
+        let steps: Vec<serde_json::Value> = records
+            .iter()
+            .map(|record| {
+                serde_json::json!({
+                    "step": record.id,
+                    "result": record.status.label(),
+                    "seconds": record.duration.map(|d| d.as_secs()),
+                    "details": record.artifacts,
+                })
+            })
+            .collect();
+        ui::print_json(serde_json::json!({ "status": "summary", "steps": steps }));
+        return;
+    }
+
+    println!();
+    println!("{}", "Summary:".cyan().bold());
+
+    let header = format!("  {:<14} {:<8} {:>8}  {}", "Step", "Result", "Time", "Details");
+    println!("{}", header.bold());
+    log::log(&header);
+
+    for record in records.iter() {
+        let time = record.duration.map(ui::format_duration).unwrap_or_default();
+        let line = format!(
+            "  {:<14} {:<8} {:>8}  {}",
+            record.id,
+            record.status.label(),
+            time,
+            record.artifacts.join(", ")
+        );
+        log::log(&line);
+
+        let line = match record.status {
+            StepStatus::Done => line.normal(),
+            StepStatus::Skipped => line.dimmed(),
+            StepStatus::Warned => line.yellow(),
+            StepStatus::Failed => line.red(),
+        };
+        println!("{}", line);
+    }
+
+    let footer = format!("  Total time: {}", ui::format_duration(wall_time(&records)));
+    println!("{}", footer);
+    log::log(&footer);
+}
+
+pub fn report_path() -> PathBuf {
+    log::cache_dir().join("report.txt")
+}
+
+/// Time from the first step starting to the last one finishing.
+fn wall_time(records: &[StepRecord]) -> Duration {
+    let Some(first) = records.iter().map(|r| r.started).min() else {
+        return Duration::ZERO;
+    };
+    records
+        .iter()
+        .filter_map(|r| r.duration.map(|d| r.started + d))
+        .max()
+        .map_or(Duration::ZERO, |last| last - first)
+}
+
+/// First line of what `program` prints, if it runs.
+fn first_line(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next()?.trim();
+    (output.status.success() && !line.is_empty()).then(|| line.to_string())
+}
+
+/// Commit and branch of the checkout at `dir`.
+fn checkout(dir: &Path) -> Option<String> {
+    let dir = dir.to_str()?;
+    let commit = first_line("git", &["-C", dir, "rev-parse", "--short=12", "HEAD"])?;
+    let branch = first_line("git", &["-C", dir, "rev-parse", "--abbrev-ref", "HEAD"]).unwrap_or_default();
+    Some(format!("{} ({})", commit, branch))
+}
+
+/// Appends a titled section listing `lines`, or saying there are none.
+fn section(text: &mut String, title: &str, lines: Vec<String>) {
+    let _ = writeln!(text, "\n{}:", title);
+    if lines.is_empty() {
+        let _ = writeln!(text, "  none");
+    }
+    for line in lines {
+        let _ = writeln!(text, "  {}", line);
+    }
+}
+
+/// What this run installed and changed, as plain text for bug reports and
+/// audits.
+pub fn report() -> String {
+    let mut text = String::new();
+    let records = RECORDS.lock().unwrap();
+    let _ = writeln!(
+        text,
+        "Caelestia installer {} report, {}",
+        env!("CARGO_PKG_VERSION"),
+        log::timestamp()
+    );
+
+    let versions = [
+        ("hyprland", first_line("rpm", &["-q", "--qf", "%{VERSION}-%{RELEASE}", "hyprland"])),
+        (
+            "quickshell",
+            first_line("quickshell", &["--version"]).or_else(|| first_line("rpm", &["-q", "quickshell"])),
+        ),
+        ("caelestia-shell", checkout(&dotfiles::shell_dir())),
+        ("caelestia-cli", cli::installed_commit()),
+    ];
+    let versions = versions
+        .into_iter()
+        .map(|(name, version)| format!("{:<16} {}", name, version.as_deref().unwrap_or("not installed")))
+        .collect();
+    section(&mut text, "Versions", versions);
+
+    let build = system::build_root();
+    let checkouts = [
+        ("dotfiles", dotfiles::dotfiles_dir()),
+        ("shell", dotfiles::shell_dir()),
+        ("quickshell", build.join("quickshell")),
+        ("caelestia-cli", build.join("caelestia-cli")),
+    ];
+    let checkouts = checkouts
+        .into_iter()
+        .filter_map(|(name, dir)| Some(format!("{:<16} {}  {}", name, checkout(&dir)?, dir.display())))
+        .collect();
+    section(&mut text, "Repositories", checkouts);
+
+    let packages = PACKAGES_ADDED.lock().unwrap().clone();
+    section(&mut text, &format!("Packages added ({})", packages.len()), packages);
+
+    let changes = journal::this_run();
+    let files = changes
+        .iter()
+        .filter_map(|entry| match entry {
+            journal::Entry::Symlink { path, .. } => Some(match fs::read_link(path) {
+                Ok(target) => format!("link    {} -> {}", path.display(), target.display()),
+                Err(_) => format!("link    {}", path.display()),
+            }),
+            journal::Entry::File { path, backup } | journal::Entry::SystemFile { path, backup } => {
+                let action = if backup.is_some() { "changed" } else { "created" };
+                Some(format!("{} {}", action, path.display()))
+            }
+            _ => None,
+        })
+        .collect();
+    section(&mut text, "Files and symlinks", files);
+
+    let services = changes
+        .iter()
+        .filter_map(|entry| match entry {
+            journal::Entry::Service { name, was_enabled } => {
+                Some(format!("{} {}", if *was_enabled { "disabled" } else { "enabled" }, name))
+            }
+            journal::Entry::DefaultTarget { previous } => Some(format!("default target changed from {}", previous)),
+            journal::Entry::Shell { previous } => Some(format!("login shell changed from {}", previous)),
+            _ => None,
+        })
+        .collect();
+    section(&mut text, "Services", services);
+
+    let steps = records
+        .iter()
+        .map(|record| {
+            let time = record.duration.map(ui::format_duration).unwrap_or_default();
+            format!(
+                "{:<16} {:<8} {:>8}  {}",
+                record.id,
+                record.status.label(),
+                time,
+                record.artifacts.join(", ")
+            )
+        })
+        .collect();
+    section(&mut text, "Steps", steps);
+    let _ = writeln!(text, "\nTotal time: {}", ui::format_duration(wall_time(&records)));
+    text
+}
+
+/// Prints the [`report`] and saves it to `report.txt` next to the log.
+pub fn write_report() -> Result<PathBuf> {
+    let text = report();
+    if !ui::json() && ui::verbosity() > ui::Verbosity::Quiet {
+        ui::block(&format!("\n{}\n{}", "Report:".cyan().bold(), text.trim_end()));
+    }
+    let path = report_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, text)?;
+    Ok(path)
+}
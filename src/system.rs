@@ -1,26 +1,530 @@
+use anyhow::Result;
 use std::fs;
-use std::process::Command;
-use crate::{log, ui};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::{checks, dotfiles, log, privileged, runner, summary, toolbox, ui};
 
-pub fn get_ninja_jobs() -> usize {
-    if let Ok(mem_info) = fs::read_to_string("/proc/meminfo") {
-        let total_kb = mem_info
-            .lines()
-            .find(|line| line.starts_with("MemTotal:"))
-            .and_then(|line| line.split_whitespace().nth(1))
-            .and_then(|num| num.parse::<usize>().ok())
-            .unwrap_or(8000000); // Default to 8GB
+/// How often memory is sampled while a build runs.
+const MEMORY_POLL: Duration = Duration::from_millis(500);
+/// How long a build stays paused before it is restarted with fewer jobs.
+const PAUSE_LIMIT: Duration = Duration::from_secs(30);
+
+static JOBS_OVERRIDE: Mutex<Option<usize>> = Mutex::new(None);
+static LOW_PRIORITY: Mutex<bool> = Mutex::new(false);
+static MEMORY_GUARD: Mutex<bool> = Mutex::new(false);
+/// Swapfile added for this run, removed when it ends.
+static SWAPFILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+static BUILD_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+static BUILD_SLOTS: Mutex<usize> = Mutex::new(1);
+static KEEP_BUILD_ARTIFACTS: Mutex<bool> = Mutex::new(false);
+
+/// Memory a source build may need at its peak, in GB, when deciding how many
+/// can run side by side.
+const MEMORY_PER_BUILD_GB: usize = 4;
+/// Fewest jobs worth giving a build that runs alongside others.
+const MIN_JOBS_PER_BUILD: usize = 2;
+/// Most builds run at once; the default plan has three that can overlap.
+const MAX_CONCURRENT_BUILDS: usize = 3;
+
+/// Disk space the source builds need together, Quickshell taking most of it.
+const BUILD_SPACE_MB: u64 = 2048;
+
+/// CPU and IO weight of builds run at low priority (the default weight is 100).
+const LOW_PRIORITY_WEIGHT: &str = "20";
+
+/// RAM plus swap below which Quickshell's compile can run out of memory even
+/// with few jobs, as on 4GB virtual machines.
+const BUILD_MEMORY_MB: u64 = 6144;
+/// Size of the temporary swapfile offered to make up for it.
+const SWAPFILE_MB: u64 = 4096;
+const SWAPFILE_PATH: &str = "/var/tmp/caelestia-installer.swap";
+const ZRAM_CONFIG: &str = "/etc/systemd/zram-generator.conf";
+/// Fedora's own zram setup: a compressed swap device up to the size of RAM.
+const ZRAM_DEFAULT_CONFIG: &str = "[zram0]\nzram-size = min(ram, 8192)\ncompression-algorithm = zstd\n";
+
+/// Memory left to the rest of the system when builds are capped.
+const MEMORY_RESERVE_MB: u64 = 1024;
+/// Smallest cap worth building under; one compiler job can need this much.
+const MIN_BUILD_MEMORY_MB: u64 = 768;
+
+/// Forces the number of build jobs, bypassing the memory and load heuristics.
+pub fn set_jobs(jobs: Option<usize>) {
+    *JOBS_OVERRIDE.lock().unwrap() = jobs;
+}
+
+/// Runs builds with reduced CPU and IO priority.
+pub fn set_low_priority(low: bool) {
+    *LOW_PRIORITY.lock().unwrap() = low;
+}
+
+/// Checks RAM plus swap before the builds and, when short, offers to enable
+/// zram or add a swapfile for the duration of the run.
+pub fn ensure_memory(dry_run: bool, noconfirm: bool) -> Result<()> {
+    let ram = meminfo_kb("MemTotal:").unwrap_or(0) / 1024;
+    let swap = meminfo_kb("SwapTotal:").unwrap_or(0) / 1024;
+    log::log(&format!("Memory: {}MB RAM, {}MB swap", ram, swap));
+
+    if ram + swap >= BUILD_MEMORY_MB {
+        ui::success(&format!("{}MB RAM and {}MB swap are enough for the builds", ram, swap));
+        return Ok(());
+    }
+    ui::warning(&format!(
+        "{}MB RAM and {}MB swap may not be enough to compile Quickshell ({}MB recommended)",
+        ram, swap, BUILD_MEMORY_MB
+    ));
+
+    let zram_active = fs::read_to_string("/proc/swaps")
+        .map(|swaps| swaps.contains("/dev/zram"))
+        .unwrap_or(false);
+    let mut choices = Vec::new();
+    if !zram_active {
+        choices.push("Enable zram swap (zram-generator, kept after the install)");
+    }
+    choices.push("Add a temporary 4GB swapfile for this run");
+    choices.push("Continue without more swap (builds use fewer jobs)");
+
+    if noconfirm {
+        ui::info("Continuing without more swap; builds will use fewer jobs");
+        return Ok(());
+    }
+    let choice = ui::select("How should the builds get more memory?", &choices)
+        .map(|index| choices[index])
+        .unwrap_or(choices[choices.len() - 1]);
+
+    if choice.starts_with("Enable zram") {
+        enable_zram(dry_run)
+    } else if choice.starts_with("Add a temporary") {
+        add_swapfile(dry_run)
+    } else {
+        Ok(())
+    }
+}
+
+/// Installs zram-generator, configures a zram device unless a config exists,
+/// and starts it.
+fn enable_zram(dry_run: bool) -> Result<()> {
+    ui::info("Enabling zram swap...");
+    if dry_run {
+        ui::success("Would install zram-generator and start zram0 (dry-run)");
+        return Ok(());
+    }
+
+    let packages = ["zram-generator", "zram-generator-defaults"];
+    log::log_command(&crate::packages::install_command_line(&packages.join(" ")));
+    let output = runner::run(&mut crate::packages::install_command(&packages))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        anyhow::bail!("Failed to install zram-generator");
+    }
+
+    let configured = Path::new(ZRAM_CONFIG).exists()
+        || Path::new("/usr/lib/systemd/zram-generator.conf").exists();
+    if !configured {
+        privileged::write_file(Path::new(ZRAM_CONFIG), ZRAM_DEFAULT_CONFIG)?;
+    }
+
+    privileged::run(&["systemctl", "daemon-reload"])?;
+    privileged::run(&["systemctl", "start", "systemd-zram-setup@zram0.service"])?;
+    let swap = meminfo_kb("SwapTotal:").unwrap_or(0) / 1024;
+    ui::success(&format!("zram swap enabled, {}MB swap in total", swap));
+    summary::artifact("zram swap enabled");
+    Ok(())
+}
+
+/// Creates and enables a swapfile, removed again by [`remove_swapfile`].
+/// Btrfs, Fedora's default, needs its own tool for a usable swapfile.
+fn add_swapfile(dry_run: bool) -> Result<()> {
+    ui::info(&format!("Adding a {}MB swapfile at {}...", SWAPFILE_MB, SWAPFILE_PATH));
+    if dry_run {
+        ui::success("Would add a temporary swapfile (dry-run)");
+        return Ok(());
+    }
+
+    let size = format!("{}M", SWAPFILE_MB);
+    let on_btrfs = filesystem_info(Path::new("/var/tmp")).is_some_and(|(fstype, _)| fstype == "btrfs");
+    if on_btrfs {
+        privileged::run(&["btrfs", "filesystem", "mkswapfile", "--size", &size, SWAPFILE_PATH])?;
+    } else {
+        privileged::run(&["fallocate", "-l", &size, SWAPFILE_PATH])?;
+        privileged::run(&["chmod", "600", SWAPFILE_PATH])?;
+        privileged::run(&["mkswap", SWAPFILE_PATH])?;
+    }
+    *SWAPFILE.lock().unwrap() = Some(PathBuf::from(SWAPFILE_PATH));
+    privileged::run(&["swapon", SWAPFILE_PATH])?;
+
+    ui::success(&format!("Added a {}MB swapfile for this run", SWAPFILE_MB));
+    Ok(())
+}
+
+/// Turns off and deletes the swapfile added for this run, if any.
+pub fn remove_swapfile() {
+    let Some(path) = SWAPFILE.lock().unwrap().take() else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    let removed = privileged::run(&["swapoff", &path]).and_then(|_| privileged::run(&["rm", "-f", &path]));
+    match removed {
+        Ok(()) => ui::info(&format!("Removed the temporary swapfile {}", path)),
+        Err(e) => ui::warning(&format!("Could not remove the swapfile {}: {}", path, e)),
+    }
+}
+
+/// Caps the memory of builds with a systemd scope, for `--memory-guard`.
+pub fn set_memory_guard(guard: bool) {
+    *MEMORY_GUARD.lock().unwrap() = guard;
+}
+
+/// The memory each build may use under `--memory-guard`: what is available
+/// now less a reserve for the desktop, split between concurrent builds.
+fn build_memory_limit_mb() -> Option<u64> {
+    if !*MEMORY_GUARD.lock().unwrap() {
+        return None;
+    }
+    let available = meminfo_kb("MemAvailable:")? / 1024;
+    let slots = *BUILD_SLOTS.lock().unwrap() as u64;
+    let limit = available.saturating_sub(MEMORY_RESERVE_MB) / slots;
+    Some(limit.max(MIN_BUILD_MEMORY_MB))
+}
+
+/// Wraps `program` so it runs at the configured priority and under the
+/// memory cap: in a systemd scope with lowered CPU/IO weights and
+/// MemoryHigh/MemoryMax when the user manager is reachable, otherwise under
+/// nice and ionice without a cap. Both exec the program, keeping its pid.
+fn prioritized(program: &str, args: &[String]) -> (String, Vec<String>) {
+    let low_priority = *LOW_PRIORITY.lock().unwrap();
+    let memory_limit = build_memory_limit_mb();
+    if !low_priority && memory_limit.is_none() {
+        return (program.to_string(), args.to_vec());
+    }
+
+    let user_bus = std::env::var("XDG_RUNTIME_DIR")
+        .map(|dir| Path::new(&dir).join("bus").exists())
+        .unwrap_or(false);
+
+    // Inside the toolbox there is no user manager to create a scope with
+    let mut wrapped: Vec<String> = if user_bus && which::which("systemd-run").is_ok() && !toolbox::active() {
+        let mut properties = Vec::new();
+        if low_priority {
+            properties.push(format!("CPUWeight={}", LOW_PRIORITY_WEIGHT));
+            properties.push(format!("IOWeight={}", LOW_PRIORITY_WEIGHT));
+        }
+        if let Some(limit) = memory_limit {
+            // Reclaim and throttle first, kill only past the hard limit
+            properties.push(format!("MemoryHigh={}M", limit * 9 / 10));
+            properties.push(format!("MemoryMax={}M", limit));
+            log::log(&format!("Build memory capped at {}MB", limit));
+        }
+        let mut scope: Vec<String> = vec!["systemd-run".into(), "--user".into(), "--scope".into(), "--quiet".into()];
+        for property in properties {
+            scope.push("-p".into());
+            scope.push(property);
+        }
+        scope
+    } else {
+        if memory_limit.is_some() {
+            log::warn("No systemd user manager to cap build memory with, building without a cap");
+        }
+        if !low_priority {
+            return (program.to_string(), args.to_vec());
+        }
+        let mut nice = vec!["nice".into(), "-n".into(), "10".into()];
+        if which::which("ionice").is_ok() {
+            nice.extend(["ionice".into(), "-c".into(), "2".into(), "-n".into(), "7".into()]);
+        }
+        nice
+    };
+    wrapped.push(program.to_string());
+    wrapped.extend_from_slice(args);
+
+    let program = wrapped.remove(0);
+    (program, wrapped)
+}
+
+/// Filesystem type and free space in MB of the filesystem holding `path`.
+fn filesystem_info(path: &Path) -> Option<(String, u64)> {
+    let output = Command::new("df")
+        .args(["--output=fstype,avail", "-BM"])
+        .arg(path)
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.lines().nth(1)?.split_whitespace();
+    let fstype = fields.next()?.to_string();
+    let avail = fields.next()?.trim_end_matches('M').parse().ok()?;
+    Some((fstype, avail))
+}
+
+/// Directory the source builds are cloned and compiled in. The checkouts are
+/// kept between runs so sources are only fetched where they changed, the
+/// build trees only with `--keep-build-artifacts`.
+/// Defaults to `~/.cache/caelestia-installer/build`, on disk rather than in
+/// a tmpfs, and in the home directory the toolbox shares with the host.
+pub fn build_root() -> PathBuf {
+    BUILD_ROOT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| log::cache_dir().join("build"))
+        .clone()
+}
+
+/// Builds in `dir` instead of the default build directory, for `--build-dir`.
+pub fn set_build_root(dir: Option<PathBuf>) {
+    *BUILD_ROOT.lock().unwrap() = dir;
+}
+
+/// Warns when the filesystem holding the build directory is short of space.
+pub fn check_build_space() {
+    let root = build_root();
+    if let Err(e) = fs::create_dir_all(&root) {
+        ui::warning(&format!("Could not create {}: {}", root.display(), e));
+        return;
+    }
+    let Some((fstype, free)) = filesystem_info(&root) else {
+        return;
+    };
+    log::log(&format!("Build directory {} on {}, {}MB free", root.display(), fstype, free));
+    if free < BUILD_SPACE_MB {
+        ui::warning(&format!(
+            "Builds need about {}MB, but {} has {}MB free",
+            BUILD_SPACE_MB,
+            root.display(),
+            free
+        ));
+    }
+}
+
+/// `caelestia-installer clean`: removes the build directory with the
+/// sources and build trees kept in it, and the shell's build tree.
+pub fn clean(dry_run: bool) -> Result<()> {
+    let targets: Vec<PathBuf> = [build_root(), dotfiles::shell_dir().join("build")]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if targets.is_empty() {
+        ui::success(&format!("{} is already clean", build_root().display()));
+        return Ok(());
+    }
+    remove_all(&targets, dry_run)
+}
+
+/// Keeps the build trees after an install, so the next rebuild only
+/// compiles what changed.
+pub fn set_keep_build_artifacts(keep: bool) {
+    *KEEP_BUILD_ARTIFACTS.lock().unwrap() = keep;
+}
+
+/// The last step of an install: removes what the builds left in the build
+/// directory, the CMake build trees, packaging trees and downloads, keeping
+/// the checkouts for updates.
+pub fn clean_build_artifacts(dry_run: bool) -> Result<()> {
+    if *KEEP_BUILD_ARTIFACTS.lock().unwrap() {
+        ui::info(&format!("Keeping the build trees in {}", build_root().display()));
+        return Ok(());
+    }
+    let artifacts = build_artifacts();
+    if artifacts.is_empty() {
+        ui::success("No build artifacts to remove");
+        return Ok(());
+    }
+    remove_all(&artifacts, dry_run)
+}
+
+/// What in the build directory is not a checkout: the `build` tree inside
+/// each checkout, and every other directory and file. Checksum files are
+/// small and kept.
+fn build_artifacts() -> Vec<PathBuf> {
+    let mut artifacts = Vec::new();
+    let shell_build = dotfiles::shell_dir().join("build");
+    if shell_build.exists() {
+        artifacts.push(shell_build);
+    }
+    let Ok(entries) = fs::read_dir(build_root()) else {
+        return artifacts;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name() == "checksums" {
+            continue;
+        }
+        if path.join(".git").exists() {
+            let tree = path.join("build");
+            if tree.exists() {
+                artifacts.push(tree);
+            }
+        } else {
+            artifacts.push(path);
+        }
+    }
+    artifacts.sort();
+    artifacts
+}
 
-        let total_gb = total_kb / 1024 / 1024;
-        if total_gb < 2 {
-            ui::warning(&format!("Low memory detected ({}GB), limiting build to 1 job", total_gb));
-            return 1;
-        } else if total_gb < 4 {
-            ui::warning(&format!("Moderate memory detected ({}GB), limiting build to 2 jobs", total_gb));
-            return 2;
+/// Removes `paths`, reporting how much space that freed.
+fn remove_all(paths: &[PathBuf], dry_run: bool) -> Result<()> {
+    let mut freed = 0;
+    for path in paths {
+        let size = disk_usage(path);
+        log::log_command(&format!("rm -rf {}", path.display()));
+        if dry_run {
+            ui::block(&format!("  - {} ({})", path.display(), checks::format_bytes(size as f64)));
+        } else {
+            let removed = if path.is_dir() && !path.is_symlink() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            };
+            if let Err(e) = removed {
+                ui::warning(&format!("Could not remove {}: {}", path.display(), e));
+                continue;
+            }
         }
+        freed += size;
+    }
+
+    let freed = checks::format_bytes(freed as f64);
+    if dry_run {
+        ui::success(&format!("Would remove {} paths, freeing {} (dry-run)", paths.len(), freed));
+        return Ok(());
+    }
+    log::log(&format!("Removed {}, freeing {}", paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", "), freed));
+    ui::success(&format!("Freed {}", freed));
+    summary::artifact(&format!("{} freed", freed));
+    Ok(())
+}
+
+/// Bytes the files under `path` take, without following symlinks.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+/// How many source builds can run at once: one per `MEMORY_PER_BUILD_GB` of
+/// RAM, as long as each still gets `MIN_JOBS_PER_BUILD` of the job budget.
+pub fn concurrent_builds() -> usize {
+    let by_memory = total_memory_gb() / MEMORY_PER_BUILD_GB;
+    let by_jobs = effective_build_jobs() / MIN_JOBS_PER_BUILD;
+    by_memory.min(by_jobs).clamp(1, MAX_CONCURRENT_BUILDS)
+}
+
+/// Splits the job budget between this many concurrent builds.
+pub fn set_build_slots(slots: usize) {
+    *BUILD_SLOTS.lock().unwrap() = slots.max(1);
+}
+
+/// Jobs to pass to ninja, 0 meaning ninja's default. Evaluated before each
+/// build, so a machine that got busier since the last one gets fewer jobs.
+/// When builds run concurrently each gets an equal share of the budget.
+pub fn get_ninja_jobs() -> usize {
+    let jobs = total_ninja_jobs();
+    let slots = *BUILD_SLOTS.lock().unwrap();
+    if slots == 1 {
+        return jobs;
+    }
+    let total = if jobs == 0 { cpu_count() } else { jobs };
+    (total / slots).max(1)
+}
+
+fn total_ninja_jobs() -> usize {
+    if let Some(jobs) = *JOBS_OVERRIDE.lock().unwrap() {
+        return jobs;
+    }
+
+    let total_gb = total_memory_gb();
+    let mut jobs = memory_job_limit(total_gb);
+    if jobs == 1 {
+        ui::warning(&format!("Low memory detected ({}GB), limiting build to 1 job", total_gb));
+    } else if jobs == 2 {
+        ui::warning(&format!("Moderate memory detected ({}GB), limiting build to 2 jobs", total_gb));
+    }
+
+    let idle = idle_cores();
+    let limit = if jobs == 0 { cpu_count() } else { jobs };
+    if idle < limit {
+        ui::warning(&format!(
+            "System is busy (load {:.1} on {} cores), limiting build to {} job(s)",
+            load_average().unwrap_or(0.0),
+            cpu_count(),
+            idle
+        ));
+        jobs = idle;
+    }
+    jobs
+}
+
+/// Job limit imposed by the amount of RAM, 0 meaning no limit (all cores).
+fn memory_job_limit(total_gb: usize) -> usize {
+    if total_gb < 2 {
+        1
+    } else if total_gb < 4 {
+        2
+    } else {
+        0
+    }
+}
+
+pub fn total_memory_gb() -> usize {
+    fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|mem_info| {
+            mem_info
+                .lines()
+                .find(|line| line.starts_with("MemTotal:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|num| num.parse::<usize>().ok())
+        })
+        .unwrap_or(8000000) // Default to 8GB
+        / 1024
+        / 1024
+}
+
+/// Memory in use and in total, in MB.
+pub fn memory_usage() -> Option<(u64, u64)> {
+    let total = meminfo_kb("MemTotal:")?;
+    let available = meminfo_kb("MemAvailable:")?;
+    Some((total.saturating_sub(available) / 1024, total / 1024))
+}
+
+/// One-minute load average.
+pub fn load_average() -> Option<f64> {
+    fs::read_to_string("/proc/loadavg")
+        .ok()
+        .and_then(|loadavg| loadavg.split_whitespace().next()?.parse().ok())
+}
+
+/// Cores not taken by other work according to the load average, at least 1.
+fn idle_cores() -> usize {
+    let load = load_average().unwrap_or(0.0).round() as usize;
+    cpu_count().saturating_sub(load).max(1)
+}
+
+pub fn cpu_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Number of compile jobs the builds will effectively run with.
+pub fn effective_build_jobs() -> usize {
+    if let Some(jobs) = *JOBS_OVERRIDE.lock().unwrap() {
+        return jobs;
     }
-    0 // Default (all cores)
+    let limit = match memory_job_limit(total_memory_gb()) {
+        0 => cpu_count(),
+        limit => limit.min(cpu_count()),
+    };
+    limit.min(idle_cores())
 }
 
 pub fn check_oom_event() {
@@ -28,8 +532,150 @@ pub fn check_oom_event() {
         let text = String::from_utf8_lossy(&output.stdout);
         if text.contains("out of memory") || text.contains("OOM-killer") || text.contains("Killed process") {
             ui::error("DETECTED: Build was likely killed by OOM (Out Of Memory) killer!");
-            ui::info("Try increasing VM RAM to at least 4GB.");
+            if *MEMORY_GUARD.lock().unwrap() {
+                ui::info("--memory-guard capped the build's memory; try fewer jobs with --jobs.");
+            } else {
+                ui::info("Try increasing VM RAM to at least 4GB.");
+            }
             log::log("OOM event detected in dmesg");
         }
     }
 }
+
+/// Memory state sampled from /proc while a build runs.
+#[derive(PartialEq, Eq, Debug)]
+enum Pressure {
+    Normal,
+    /// Reclaim is slowing things down; new compile jobs should wait.
+    High,
+    /// The OOM killer is close; running jobs must go.
+    Critical,
+}
+
+fn meminfo_kb(key: &str) -> Option<u64> {
+    fs::read_to_string("/proc/meminfo").ok().and_then(|mem_info| {
+        mem_info
+            .lines()
+            .find(|line| line.starts_with(key))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|num| num.parse().ok())
+    })
+}
+
+/// Share of time tasks stalled on memory over the last 10s, from PSI.
+fn memory_stall_percent() -> Option<f64> {
+    fs::read_to_string("/proc/pressure/memory").ok().and_then(|psi| {
+        psi.lines()
+            .find(|line| line.starts_with("some"))
+            .and_then(|line| line.split_whitespace().find_map(|field| field.strip_prefix("avg10=")))
+            .and_then(|value| value.parse().ok())
+    })
+}
+
+fn memory_pressure() -> Pressure {
+    let (Some(available), Some(total)) = (meminfo_kb("MemAvailable:"), meminfo_kb("MemTotal:")) else {
+        return Pressure::Normal;
+    };
+    let stall = memory_stall_percent().unwrap_or(0.0);
+
+    if available < total / 20 || available < 256 * 1024 {
+        Pressure::Critical
+    } else if available < total * 15 / 100 || stall > 30.0 {
+        Pressure::High
+    } else {
+        Pressure::Normal
+    }
+}
+
+/// Whether a paused build has enough headroom to continue.
+fn memory_recovered() -> bool {
+    let (Some(available), Some(total)) = (meminfo_kb("MemAvailable:"), meminfo_kb("MemTotal:")) else {
+        return true;
+    };
+    available >= total / 4 && memory_stall_percent().unwrap_or(0.0) < 10.0
+}
+
+/// Sends `signal` to every process of the build (cmake, ninja and the compilers).
+fn signal_build(child: &Child, signal: &str) {
+    let _ = Command::new("kill")
+        .args([signal, "--", &format!("-{}", child.id())])
+        .status();
+}
+
+/// Runs `cmake --build` while watching memory. Under pressure the build is
+/// paused until memory frees up; if it stays paused or memory runs critically
+/// low it is restarted with half the jobs, ninja picking up where it left off.
+pub fn cmake_build(build_dir: &str) -> Result<Output> {
+    let mut jobs = match get_ninja_jobs() {
+        0 => None,
+        jobs => Some(jobs),
+    };
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    loop {
+        let mut args = vec!["--build".to_string(), build_dir.to_string()];
+        if let Some(jobs) = jobs {
+            args.push("-j".to_string());
+            args.push(jobs.to_string());
+        }
+        let (program, args) = prioritized("cmake", &args);
+        log::log_command(&format!("{} {}", program, args.join(" ")));
+
+        // Own process group so the whole build can be paused at once
+        let mut running = runner::spawn(toolbox::command(&program).args(&args).process_group(0))?;
+        let child = &mut running.child;
+
+        let mut paused: Option<Instant> = None;
+        let mut restart = false;
+        loop {
+            if child.try_wait()?.is_some() {
+                break;
+            }
+            thread::sleep(MEMORY_POLL);
+
+            let current = jobs.unwrap_or_else(cpu_count);
+            let pressure = memory_pressure();
+            let stuck = paused.is_some_and(|since| since.elapsed() > PAUSE_LIMIT);
+
+            if current > 1 && (pressure == Pressure::Critical || stuck) {
+                jobs = Some((current / 2).max(1));
+                ui::warning(&format!(
+                    "Memory is running low, restarting the build with {} job(s)",
+                    jobs.unwrap()
+                ));
+                signal_build(child, "-TERM");
+                signal_build(child, "-CONT");
+                restart = true;
+                break;
+            }
+
+            match paused {
+                None if pressure != Pressure::Normal => {
+                    ui::warning("Memory pressure is high, pausing the build until memory frees up");
+                    signal_build(child, "-STOP");
+                    paused = Some(Instant::now());
+                }
+                Some(_) if memory_recovered() || (stuck && current == 1) => {
+                    ui::info("Resuming the build");
+                    signal_build(child, "-CONT");
+                    paused = None;
+                }
+                _ => {}
+            }
+        }
+
+        let output = running.wait()?;
+        stdout.extend(output.stdout);
+        stderr.extend(output.stderr);
+
+        if !restart {
+            return Ok(Output {
+                status: output.status,
+                stdout,
+                stderr,
+            });
+        }
+        log::log(&format!("Build restarted with {} job(s) due to memory pressure", jobs.unwrap_or(1)));
+    }
+}
@@ -0,0 +1,127 @@
+//! GTK, Qt and cursor theming. The themes are installed with the packages
+//! but nothing selects them, so GTK apps get adw-gtk3-dark and Papirus
+//! through gsettings, Qt apps a dark Kvantum style through qt6ct, and
+//! Hyprland the matching cursor.
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{hyprconf, journal, log, summary, ui};
+
+const GTK_THEME: &str = "adw-gtk3-dark";
+const ICON_THEME: &str = "Papirus-Dark";
+const CURSOR_THEME: &str = "Adwaita";
+const CURSOR_SIZE: u32 = 24;
+const KVANTUM_THEME: &str = "KvArcDark";
+
+/// Marks the lines the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: theming";
+
+/// Keys of `org.gnome.desktop.interface` and their values.
+fn interface_settings() -> Vec<(&'static str, String)> {
+    vec![
+        ("gtk-theme", GTK_THEME.to_string()),
+        ("icon-theme", ICON_THEME.to_string()),
+        ("color-scheme", "prefer-dark".to_string()),
+        ("cursor-theme", CURSOR_THEME.to_string()),
+        ("cursor-size", CURSOR_SIZE.to_string()),
+    ]
+}
+
+pub fn setup(dry_run: bool) -> Result<()> {
+    ui::info("Applying GTK, Qt and cursor themes...");
+
+    if dry_run {
+        ui::success(&format!(
+            "Would set {}, {}, Kvantum {} and the {} cursor (dry-run)",
+            GTK_THEME, ICON_THEME, KVANTUM_THEME, CURSOR_THEME
+        ));
+        return Ok(());
+    }
+
+    apply_gsettings();
+
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+    let qt6ct = format!(
+        "[Appearance]\nstyle=kvantum-dark\nicon_theme={}\ncustom_palette=false\nstandard_dialogs=default\n",
+        ICON_THEME
+    );
+    write_if_missing(&config_dir.join("qt6ct/qt6ct.conf"), &qt6ct)?;
+    write_if_missing(
+        &config_dir.join("Kvantum/kvantum.kvconfig"),
+        &format!("[General]\ntheme={}\n", KVANTUM_THEME),
+    )?;
+
+    write_env(&config_dir)?;
+    summary::artifact("themes");
+    Ok(())
+}
+
+/// Sets the GTK themes through gsettings. Without a session bus, as over
+/// SSH, dconf may refuse the writes, which only costs the theming.
+fn apply_gsettings() {
+    let mut failed = Vec::new();
+    for (key, value) in interface_settings() {
+        let args = ["set", "org.gnome.desktop.interface", key, &value];
+        log::log_command(&format!("gsettings {}", args.join(" ")));
+        let ok = Command::new("gsettings")
+            .args(args)
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+        if !ok {
+            failed.push(key);
+        }
+    }
+
+    if failed.is_empty() {
+        ui::success(&format!("GTK theme {}, icons {}, dark color scheme", GTK_THEME, ICON_THEME));
+    } else {
+        ui::warning(&format!(
+            "Could not set {} with gsettings, set them from a graphical session",
+            failed.join(", ")
+        ));
+    }
+}
+
+/// Writes a theme config unless the user already has one.
+fn write_if_missing(path: &std::path::Path, content: &str) -> Result<()> {
+    if path.exists() {
+        ui::success(&format!("{:?} already exists, keeping it", path));
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(path, content)?;
+    log::log(&format!("Theme config written to {:?}", path));
+    Ok(())
+}
+
+/// Points Qt at qt6ct and sets the cursor in
+/// `~/.config/caelestia/hypr-user.conf`.
+fn write_env(config_dir: &std::path::Path) -> Result<()> {
+    let hypr_user = config_dir.join("caelestia/hypr-user.conf");
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if existing.contains(HYPR_MARKER) {
+        ui::success("Theme environment already set up");
+        return Ok(());
+    }
+
+    let lines = format!(
+        "{marker}\nenv = QT_QPA_PLATFORMTHEME,qt6ct\nenv = XCURSOR_THEME,{cursor}\nenv = XCURSOR_SIZE,{size}\nexec-once = hyprctl setcursor {cursor} {size}\n",
+        marker = HYPR_MARKER,
+        cursor = CURSOR_THEME,
+        size = CURSOR_SIZE
+    );
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, format!("{}\n{}", existing, lines))?;
+    hyprconf::check(&hypr_user);
+
+    ui::success(&format!("Qt apps use qt6ct with Kvantum {}, cursor {}", KVANTUM_THEME, CURSOR_THEME));
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Step duration estimates, from the durations the state file recorded on
+//! previous runs or the bundled defaults.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::{checks, state, system};
+
+/// Core count of the machine the default estimates were measured on.
+const REFERENCE_JOBS: f64 = 8.0;
+
+/// Default durations in seconds on the reference machine, used when a step
+/// has never been timed on this machine. Download-bound steps are refined
+/// from the measured bandwidth and build steps scaled by the job count.
+const DEFAULT_SECS: &[(&str, f64)] = &[
+    ("checks", 20.0),
+    ("repos", 30.0),
+    ("memory", 5.0),
+    ("packages", 600.0),
+    ("qt-utils", 300.0),
+    ("quickshell", 900.0),
+    ("cava", 60.0),
+    ("rust", 90.0),
+    ("fonts", 60.0),
+    ("dotfiles", 30.0),
+    ("cli", 60.0),
+    ("symlinks", 2.0),
+    ("scheme", 2.0),
+    ("gpu", 5.0),
+    ("vm", 20.0),
+    ("input", 2.0),
+    ("idle", 1.0),
+    ("clipboard", 1.0),
+    ("wallpapers", 30.0),
+    ("theming", 2.0),
+    ("flatpak", 60.0),
+    ("environment", 1.0),
+    ("shell-build", 300.0),
+    ("shell-config", 2.0),
+    ("keybinds", 1.0),
+    ("mime", 1.0),
+    ("desktops", 5.0),
+    ("portals", 3.0),
+    ("uwsm", 10.0),
+    ("display-manager", 5.0),
+    ("selinux", 5.0),
+    ("build-deps", 30.0),
+    ("health", 15.0),
+    ("cleanup", 5.0),
+];
+
+/// Steps whose duration is dominated by compilation.
+const BUILD_STEPS: &[&str] = &["qt-utils", "quickshell", "cava", "shell-build"];
+
+/// Approximate download sizes of the download-bound steps, in megabytes.
+const DOWNLOAD_MB: &[(&str, f64)] = &[
+    ("packages", checks::PACKAGES_DOWNLOAD_MB),
+    ("fonts", checks::FONTS_DOWNLOAD_MB),
+];
+
+/// Step durations recorded on previous runs of the installer.
+pub struct Timings {
+    history: BTreeMap<String, u64>,
+}
+
+impl Timings {
+    pub fn load() -> Self {
+        Self::from_durations(state::load().durations)
+    }
+
+    /// Estimates from `durations`, in seconds by step id.
+    pub fn from_durations(durations: BTreeMap<String, u64>) -> Self {
+        Self { history: durations }
+    }
+
+    /// Expected duration of a step on this machine.
+    pub fn estimate(&self, id: &str) -> Duration {
+        if let Some(secs) = self.history.get(id) {
+            return Duration::from_secs(*secs);
+        }
+
+        let mut secs = DEFAULT_SECS
+            .iter()
+            .find(|(step, _)| *step == id)
+            .map(|(_, secs)| *secs)
+            .unwrap_or(0.0);
+
+        if BUILD_STEPS.contains(&id) {
+            let jobs = system::effective_build_jobs() as f64;
+            secs *= (REFERENCE_JOBS / jobs).clamp(0.5, 8.0);
+        }
+
+        if let (Some((_, mb)), Some(bandwidth)) = (
+            DOWNLOAD_MB.iter().find(|(step, _)| *step == id),
+            checks::measured_bandwidth(),
+        ) {
+            secs = secs.max(mb * 1024.0 * 1024.0 / bandwidth);
+        }
+
+        Duration::from_secs_f64(secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timings;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn uses_recorded_durations_first() {
+        let timings = Timings::from_durations(BTreeMap::from([
+            ("quickshell".to_string(), 1800),
+            ("symlinks".to_string(), 7),
+        ]));
+        assert_eq!(timings.estimate("quickshell"), Duration::from_secs(1800));
+        assert_eq!(timings.estimate("symlinks"), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn falls_back_to_the_bundled_defaults() {
+        let timings = Timings::from_durations(BTreeMap::new());
+        assert_eq!(timings.estimate("symlinks"), Duration::from_secs(2));
+        assert_eq!(timings.estimate("not-a-step"), Duration::ZERO);
+    }
+
+    #[test]
+    fn scales_build_defaults_within_bounds() {
+        let timings = Timings::from_durations(BTreeMap::new());
+        let quickshell = timings.estimate("quickshell").as_secs_f64();
+        assert!((450.0..=7200.0).contains(&quickshell), "{}", quickshell);
+    }
+}
@@ -0,0 +1,115 @@
+//! The build environment of rpm-ostree systems (Silverblue, Kinoite), whose
+//! /usr is read-only and where layering every -devel package would bloat the
+//! image, and of dnf systems with `--container-build`, which keeps the
+//! -devel packages off the host. Source components are built inside a
+//! toolbox container holding the build dependencies, and installed into a
+//! prefix in the home directory, which the container shares with the host.
+
+use anyhow::{bail, Result};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::packages::{self, Backend};
+use crate::{dnf, log, repos, runner, ui};
+
+/// Name of the toolbox container the builds run in.
+pub const CONTAINER: &str = "caelestia-build";
+
+/// Whether `--container-build` moves the builds into the toolbox on a dnf
+/// system.
+static CONTAINER_BUILD: Mutex<bool> = Mutex::new(false);
+
+pub fn set_container_build(enabled: bool) {
+    *CONTAINER_BUILD.lock().unwrap() = enabled;
+}
+
+/// Whether build commands run inside the toolbox.
+pub fn active() -> bool {
+    packages::backend() == Backend::RpmOstree || *CONTAINER_BUILD.lock().unwrap()
+}
+
+/// Builds a command for `program` that runs in the build environment: the
+/// toolbox on rpm-ostree systems, the host otherwise.
+pub fn command(program: &str) -> Command {
+    if active() {
+        let mut command = Command::new("toolbox");
+        command.args(["run", "--container", CONTAINER, program]);
+        command
+    } else {
+        Command::new(program)
+    }
+}
+
+/// `line` as it would be run by [`command`], for the log.
+pub fn command_line(line: &str) -> String {
+    if active() {
+        format!("toolbox run --container {} {}", CONTAINER, line)
+    } else {
+        line.to_string()
+    }
+}
+
+fn exists() -> bool {
+    Command::new("podman")
+        .args(["container", "exists", CONTAINER])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Creates the toolbox if needed and installs `packages` (the full package
+/// list, build dependencies included) into it.
+pub fn setup(packages: &[String], dry_run: bool) -> Result<()> {
+    ui::info(&format!("Preparing the {} toolbox for source builds...", CONTAINER));
+
+    let cmd = format!("toolbox create --assumeyes --container {}", CONTAINER);
+    log::log_command(&cmd);
+
+    if dry_run {
+        ui::success(&format!(
+            "Would create the {} toolbox and install {} packages in it (dry-run)",
+            CONTAINER,
+            packages.len()
+        ));
+        return Ok(());
+    }
+
+    if !exists() {
+        let output = Command::new("toolbox")
+            .args(["create", "--assumeyes", "--container", CONTAINER])
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Failed to create the {} toolbox", CONTAINER);
+        }
+        ui::success(&format!("Created the {} toolbox", CONTAINER));
+    }
+
+    dnf::ensure_copr_plugin(false)?;
+    for repo in repos::COPR_REPOS {
+        log::log_command(&command_line(&format!("sudo dnf copr enable -y {}", repo)));
+        let output = command("sudo")
+            .args(["dnf", "copr", "enable", "-y", repo])
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Failed to enable COPR repo {} in the toolbox", repo);
+        }
+    }
+
+    log::log_command(&command_line(&format!(
+        "sudo {} {}",
+        dnf::install_args(true).join(" "),
+        packages.join(" ")
+    )));
+    let output = runner::run(command("sudo").args(dnf::install_args(true)).args(packages))?;
+    if !output.status.success() {
+        bail!("Failed to install the build dependencies in the toolbox");
+    }
+
+    ui::success("Toolbox ready");
+    log::log(&format!("Toolbox {} set up", CONTAINER));
+    Ok(())
+}
@@ -0,0 +1,395 @@
+//! `--tui`: a full-screen front-end for the install. The steps run on the
+//! calling thread as usual while this draws them from the ui events: the
+//! step list with live status, the output of the running commands, memory
+//! use and the time left. Questions are answered in a popup, and steps that
+//! have not started yet can be skipped.
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::timings::Timings;
+use crate::{engine, system, ui};
+
+/// How many output lines are kept for scrolling back.
+const MAX_LINES: usize = 2000;
+
+/// How often the screen is redrawn when no key is pressed.
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Running(Instant),
+    Done(Duration),
+    Failed,
+    Skipped,
+}
+
+struct StepView {
+    id: &'static str,
+    title: &'static str,
+    status: Status,
+    skip_requested: bool,
+}
+
+struct Question {
+    message: String,
+    choices: Vec<String>,
+    selected: usize,
+    answer: Sender<Option<usize>>,
+}
+
+struct State {
+    steps: Vec<StepView>,
+    lines: VecDeque<Line<'static>>,
+    question: Option<Question>,
+    started: Instant,
+    finished: Option<Result<(), String>>,
+}
+
+impl State {
+    fn push(&mut self, line: Line<'static>) {
+        self.lines.push_back(line);
+        if self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    fn step(&mut self, id: &str) -> Option<&mut StepView> {
+        self.steps.iter_mut().find(|step| step.id == id)
+    }
+
+    fn handle(&mut self, event: ui::Event) {
+        match event {
+            ui::Event::Step { id, message, .. } => {
+                if let Some(step) = self.step(id) {
+                    step.status = Status::Running(Instant::now());
+                }
+                self.push(Line::from(message).bold().cyan());
+            }
+            ui::Event::StepFinished { id, succeeded } => {
+                if let Some(step) = self.step(id) {
+                    step.status = match (succeeded, step.status) {
+                        (true, _) if step.skip_requested => Status::Skipped,
+                        (true, Status::Running(started)) => Status::Done(started.elapsed()),
+                        (true, _) => Status::Done(Duration::ZERO),
+                        (false, _) => Status::Failed,
+                    };
+                }
+            }
+            ui::Event::Info(message) => self.push(Line::from(vec!["→ ".blue().bold(), message.into()])),
+            ui::Event::Success(message) => self.push(Line::from(vec!["✓ ".green().bold(), message.into()])),
+            ui::Event::Warning(message) => self.push(Line::from(vec!["! ".yellow().bold(), message.into()])),
+            ui::Event::Error(message) => self.push(Line::from(vec!["✗ ".red().bold(), message.into()])),
+            ui::Event::Output(line) => self.push(Line::from(format!("  {}", line)).dim()),
+        }
+    }
+
+    /// Estimated time until the last step finishes.
+    fn remaining(&self, timings: &Timings) -> Duration {
+        self.steps
+            .iter()
+            .map(|step| match step.status {
+                Status::Pending if !step.skip_requested => timings.estimate(step.id),
+                Status::Running(started) => timings.estimate(step.id).saturating_sub(started.elapsed()),
+                _ => Duration::ZERO,
+            })
+            .sum()
+    }
+}
+
+/// The screen while it is shown; [`Tui::finish`] hands the terminal back.
+pub struct Tui {
+    state: Arc<Mutex<State>>,
+    thread: JoinHandle<()>,
+}
+
+impl Tui {
+    /// Takes over the terminal for `steps` (id and title, in order) and
+    /// routes the ui messages and questions to it.
+    pub fn start(steps: &[(&'static str, &'static str)]) -> Result<Self> {
+        let state = Arc::new(Mutex::new(State {
+            steps: steps
+                .iter()
+                .map(|&(id, title)| StepView {
+                    id,
+                    title,
+                    status: Status::Pending,
+                    skip_requested: false,
+                })
+                .collect(),
+            lines: VecDeque::new(),
+            question: None,
+            started: Instant::now(),
+            finished: None,
+        }));
+
+        // The output pane shows what the commands print
+        if ui::verbosity() < ui::Verbosity::Verbose {
+            ui::set_verbosity(ui::Verbosity::Verbose);
+        }
+        let shared = state.clone();
+        ui::set_event_handler(move |event| shared.lock().unwrap().handle(event));
+        let shared = state.clone();
+        ui::set_prompt_handler(move |question| ask(&shared, question, &["Yes", "No"]) == Some(0));
+        let shared = state.clone();
+        ui::set_select_handler(move |question, choices| ask(&shared, question, choices));
+
+        let terminal = ratatui::try_init()?;
+        let shared = state.clone();
+        let thread = thread::spawn(move || run(terminal, shared));
+        Ok(Self { state, thread })
+    }
+
+    /// Shows how the run ended until a key is pressed, then restores the
+    /// terminal for the summary.
+    pub fn finish(self, result: &Result<()>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.finished = Some(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+            if let Some(question) = state.question.take() {
+                question.answer.send(None).ok();
+            }
+        }
+        self.thread.join().ok();
+        ui::clear_handlers();
+    }
+}
+
+/// Shows `message` with `choices` and waits for the answer, `None` when the
+/// screen is closed first.
+fn ask(state: &Mutex<State>, message: &str, choices: &[&str]) -> Option<usize> {
+    let (answer, answers) = mpsc::channel();
+    state.lock().unwrap().question = Some(Question {
+        message: message.to_string(),
+        choices: choices.iter().map(|choice| choice.to_string()).collect(),
+        selected: 0,
+        answer,
+    });
+    answers.recv().ok().flatten()
+}
+
+fn run(mut terminal: DefaultTerminal, state: Arc<Mutex<State>>) {
+    let timings = Timings::load();
+    let mut list = ListState::default().with_selected(Some(0));
+    // Lines scrolled up from the newest output
+    let mut scroll = 0;
+
+    loop {
+        {
+            let state = state.lock().unwrap();
+            terminal.draw(|frame| draw(frame, &state, &timings, &mut list, scroll)).ok();
+        }
+        if !event::poll(TICK).unwrap_or(false) {
+            continue;
+        }
+        let Ok(Event::Key(key)) = event::read() else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            ratatui::restore();
+            ui::clear_handlers();
+            // Raw mode swallowed the signal; the interrupt handler cleans up and exits
+            // SAFETY: raise only delivers a signal to this process
+            unsafe {
+                libc::raise(libc::SIGINT);
+            }
+            return;
+        }
+
+        let mut state = state.lock().unwrap();
+        if state.finished.is_some() {
+            if matches!(key.code, KeyCode::Char('q') | KeyCode::Enter | KeyCode::Esc) {
+                break;
+            }
+            continue;
+        }
+
+        if let Some(ref mut question) = state.question {
+            let last = question.choices.len().saturating_sub(1);
+            let answer = match key.code {
+                KeyCode::Left | KeyCode::Up | KeyCode::BackTab => {
+                    question.selected = question.selected.saturating_sub(1);
+                    None
+                }
+                KeyCode::Right | KeyCode::Down | KeyCode::Tab => {
+                    question.selected = (question.selected + 1).min(last);
+                    None
+                }
+                KeyCode::Enter => Some(Some(question.selected)),
+                KeyCode::Esc => Some(None),
+                // The first letter of a choice picks it
+                KeyCode::Char(c) => question
+                    .choices
+                    .iter()
+                    .position(|choice| choice.to_lowercase().starts_with(c))
+                    .map(Some),
+                _ => None,
+            };
+            if let Some(answer) = answer {
+                if let Some(question) = state.question.take() {
+                    question.answer.send(answer).ok();
+                }
+            }
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => list.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => list.select_next(),
+            KeyCode::Char('s') => {
+                let Some(step) = list.selected().and_then(|i| state.steps.get_mut(i)) else {
+                    continue;
+                };
+                if step.status == Status::Pending && !step.skip_requested {
+                    step.skip_requested = true;
+                    engine::request_skip(step.id);
+                }
+            }
+            KeyCode::PageUp => scroll += 10,
+            KeyCode::PageDown => scroll = scroll.saturating_sub(10),
+            KeyCode::End => scroll = 0,
+            _ => {}
+        }
+    }
+    ratatui::restore();
+}
+
+fn draw(frame: &mut Frame, state: &State, timings: &Timings, list: &mut ListState, scroll: usize) {
+    let [header, body, gauge, help] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [steps_area, output_area] = Layout::horizontal([Constraint::Length(34), Constraint::Min(0)]).areas(body);
+
+    let mut status = vec![
+        "Caelestia installer".magenta().bold(),
+        format!("  elapsed {}", ui::format_duration(state.started.elapsed())).into(),
+    ];
+    if state.finished.is_none() {
+        status.push(format!("  ~{} left", ui::format_duration(state.remaining(timings))).into());
+    }
+    if let Some((used, total)) = system::memory_usage() {
+        status.push(format!("  memory {:.1}/{:.1} GB", used as f64 / 1024.0, total as f64 / 1024.0).into());
+    }
+    frame.render_widget(Line::from(status), header);
+
+    let items: Vec<ListItem> = state
+        .steps
+        .iter()
+        .map(|step| {
+            let (symbol, style, detail) = match step.status {
+                Status::Pending if step.skip_requested => ("-", Style::new().dim(), " (skip)".to_string()),
+                Status::Pending => ("·", Style::new(), String::new()),
+                Status::Running(started) => (
+                    "▶",
+                    Style::new().yellow(),
+                    format!(" {}", ui::format_duration(started.elapsed())),
+                ),
+                Status::Done(took) => ("✓", Style::new().green(), format!(" {}", ui::format_duration(took))),
+                Status::Failed => ("✗", Style::new().red(), String::new()),
+                Status::Skipped => ("-", Style::new().dim(), " skipped".to_string()),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} {}", symbol, step.id), style),
+                Span::raw(detail).dim(),
+            ]))
+        })
+        .collect();
+    let steps = List::new(items)
+        .block(Block::new().borders(Borders::ALL).title(" Steps "))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(steps, steps_area, list);
+
+    let height = output_area.height.saturating_sub(2) as usize;
+    let end = state.lines.len().saturating_sub(scroll);
+    let start = end.saturating_sub(height);
+    let lines: Vec<Line> = state.lines.range(start..end).cloned().collect();
+    let title = match list.selected().and_then(|i| state.steps.get(i)) {
+        _ if scroll > 0 => format!(" Output (scrolled up {}, End to follow) ", scroll),
+        Some(step) => format!(" Output  [{}] ", step.title.trim_end_matches("...")),
+        None => " Output ".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::new().borders(Borders::ALL).title(title)),
+        output_area,
+    );
+
+    let finished = state
+        .steps
+        .iter()
+        .filter(|step| matches!(step.status, Status::Done(_) | Status::Failed | Status::Skipped))
+        .count();
+    let total = state.steps.len().max(1);
+    frame.render_widget(
+        Gauge::default()
+            .ratio(finished as f64 / total as f64)
+            .label(format!("{}/{} steps", finished, state.steps.len()))
+            .cyan(),
+        gauge,
+    );
+
+    let keys = match state.finished {
+        Some(Ok(())) => "Installation complete, press q to close".green().bold(),
+        Some(Err(ref e)) => format!("Installation failed: {} - press q to close", e).red().bold(),
+        None => "↑↓ select step  s skip it  PgUp/PgDn/End scroll output  Ctrl-C stop".dim(),
+    };
+    frame.render_widget(Line::from(keys), help);
+
+    if let Some(ref question) = state.question {
+        draw_question(frame, question);
+    }
+}
+
+fn draw_question(frame: &mut Frame, question: &Question) {
+    let area = frame.area();
+    let width = (area.width * 3 / 5).max(40).min(area.width);
+    let popup = centered(area, width, 7);
+
+    let mut choices = Vec::new();
+    for (i, choice) in question.choices.iter().enumerate() {
+        let choice = format!(" {} ", choice);
+        choices.push(if i == question.selected {
+            choice.reversed().bold()
+        } else {
+            choice.into()
+        });
+        choices.push("  ".into());
+    }
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(vec![Line::from(question.message.clone()), Line::default(), Line::from(choices)])
+            .wrap(Wrap { trim: true })
+            .block(Block::new().borders(Borders::ALL).title(" Question ").yellow()),
+        popup,
+    );
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
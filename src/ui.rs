@@ -1,5 +1,12 @@
 use colored::Colorize;
-use which;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{keybinds, log, summary};
+use crate::timings::Timings;
 
 const BANNER: &str = r#"
    ______           __          __  _
@@ -10,27 +17,353 @@ const BANNER: &str = r#"
 
 "#;
 
+/// Messages shown to the user, forwarded to front-ends other than the terminal.
+#[derive(Clone, Debug)]
+pub enum Event {
+    Step {
+        id: &'static str,
+        current: usize,
+        total: usize,
+        message: String,
+    },
+    StepFinished {
+        id: &'static str,
+        succeeded: bool,
+    },
+    Info(String),
+    Success(String),
+    Warning(String),
+    Error(String),
+    /// A line printed by a command the installer runs.
+    Output(String),
+}
+
+type EventHandler = Box<dyn Fn(Event) + Send>;
+type PromptHandler = Box<dyn Fn(&str) -> bool + Send>;
+type SelectHandler = Box<dyn Fn(&str, &[&str]) -> Option<usize> + Send>;
+
+static EVENT_HANDLER: Mutex<Option<EventHandler>> = Mutex::new(None);
+static PROMPT_HANDLER: Mutex<Option<PromptHandler>> = Mutex::new(None);
+static SELECT_HANDLER: Mutex<Option<SelectHandler>> = Mutex::new(None);
+
+/// Routes all messages to `handler` instead of printing them.
+pub fn set_event_handler(handler: impl Fn(Event) + Send + 'static) {
+    *EVENT_HANDLER.lock().unwrap() = Some(Box::new(handler));
+}
+
+/// Answers confirmation prompts with `handler` instead of reading stdin.
+pub fn set_prompt_handler(handler: impl Fn(&str) -> bool + Send + 'static) {
+    *PROMPT_HANDLER.lock().unwrap() = Some(Box::new(handler));
+}
+
+/// Lets `handler` pick from the choices of [`select`]; without one, front-ends
+/// with their own prompts get no choice.
+pub fn set_select_handler(handler: impl Fn(&str, &[&str]) -> Option<usize> + Send + 'static) {
+    *SELECT_HANDLER.lock().unwrap() = Some(Box::new(handler));
+}
+
+/// Goes back to printing to the terminal and reading stdin.
+pub fn clear_handlers() {
+    *EVENT_HANDLER.lock().unwrap() = None;
+    *PROMPT_HANDLER.lock().unwrap() = None;
+    *SELECT_HANDLER.lock().unwrap() = None;
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How much is printed to the terminal; front-ends get every message.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Verbosity {
+    /// Step headers, warnings and errors only, with `-q`.
+    Quiet,
+    Normal,
+    /// Also the output of the commands run, with `-v`.
+    Verbose,
+    /// Also every command line before it runs, with `-vv`.
+    Commands,
+}
+
+static VERBOSITY: Mutex<Verbosity> = Mutex::new(Verbosity::Normal);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    *VERBOSITY.lock().unwrap() = verbosity;
+}
+
+pub fn verbosity() -> Verbosity {
+    *VERBOSITY.lock().unwrap()
+}
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Turns color on or off for everything printed, including progress bars
+/// and prompts.
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::SeqCst);
+    colored::control::set_override(enabled);
+}
+
+/// Colors output only on a terminal, and not with `--no-color` or when
+/// `NO_COLOR` is set to anything.
+pub fn init_color(no_color: bool) {
+    use std::io::IsTerminal;
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    set_color(!no_color && !no_color_env && std::io::stdout().is_terminal());
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::SeqCst)
+}
+
+/// The prompt theme, without colors when color is off.
+fn theme() -> Box<dyn dialoguer::theme::Theme> {
+    if color() {
+        Box::new(dialoguer::theme::ColorfulTheme::default())
+    } else {
+        Box::new(dialoguer::theme::SimpleTheme)
+    }
+}
+
+/// Steps finished and steps in total, for the percentages of `--json`.
+static JSON_PROGRESS: Mutex<(usize, usize)> = Mutex::new((0, 0));
+
+/// Switches to `--json` output: one JSON object per line on stdout with the
+/// step id, a status, the message and the overall percentage. Prompts are
+/// sent the same way and answered with a `y` or `n` line on stdin.
+pub fn use_json() {
+    JSON.store(true, Ordering::SeqCst);
+    set_color(false);
+
+    set_event_handler(|event| {
+        let (step, status, message) = match event {
+            Event::Step { id, total, message, .. } => {
+                JSON_PROGRESS.lock().unwrap().1 = total;
+                (Some(id), "started", Some(message))
+            }
+            Event::StepFinished { id, succeeded } => {
+                JSON_PROGRESS.lock().unwrap().0 += 1;
+                (Some(id), if succeeded { "succeeded" } else { "failed" }, None)
+            }
+            Event::Info(message) => (step_label(), "info", Some(message)),
+            Event::Success(message) => (step_label(), "success", Some(message)),
+            Event::Warning(message) => (step_label(), "warning", Some(message)),
+            Event::Error(message) => (step_label(), "error", Some(message)),
+            Event::Output(line) => (step_label(), "output", Some(line)),
+        };
+        print_json(serde_json::json!({
+            "step": step,
+            "status": status,
+            "message": message,
+            "percentage": json_percentage(),
+        }));
+    });
+
+    set_prompt_handler(|question| {
+        print_json(serde_json::json!({
+            "step": step_label(),
+            "status": "prompt",
+            "message": question,
+            "percentage": json_percentage(),
+        }));
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).ok();
+        let input = input.trim().to_lowercase();
+        input.is_empty() || input == "y" || input == "yes"
+    });
+}
+
+/// Whether output is JSON lines.
+pub fn json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+fn json_percentage() -> Option<usize> {
+    let (finished, total) = *JSON_PROGRESS.lock().unwrap();
+    (total > 0).then(|| finished * 100 / total)
+}
+
+/// Writes one line of `--json` output.
+pub fn print_json(value: serde_json::Value) {
+    use std::io::Write;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", value).ok();
+    stdout.flush().ok();
+}
+
+/// Hands the event to the registered handler, returning false if there is none.
+fn emit(event: Event) -> bool {
+    match *EVENT_HANDLER.lock().unwrap() {
+        Some(ref handler) => {
+            handler(event);
+            true
+        }
+        None => false,
+    }
+}
+
 pub struct Progress {
-    current: usize,
-    total: usize,
+    steps: Vec<&'static str>,
+    started: HashMap<&'static str, Instant>,
+    finished: Vec<&'static str>,
+    timings: Timings,
+    /// Overall bar below the step messages, when bars can be drawn.
+    bar: Option<indicatif::ProgressBar>,
 }
 
 impl Progress {
-    pub fn new(total: usize) -> Self {
-        Self { current: 0, total }
+    /// `steps` lists the ids of every step that will be started.
+    pub fn new(steps: &[&'static str]) -> Self {
+        Self {
+            steps: steps.to_vec(),
+            started: HashMap::new(),
+            finished: Vec::new(),
+            timings: Timings::load(),
+            bar: steps_bar(steps.len()),
+        }
     }
 
-    pub fn step(&mut self, message: &str) {
-        self.current += 1;
-        println!(
-            "{} {}",
-            format!("[{}/{}]", self.current, self.total).cyan().bold(),
-            message
-        );
+    /// Shows the running steps and the estimated time left on the bar.
+    fn update_bar(&self) {
+        let Some(ref bar) = self.bar else {
+            return;
+        };
+        let running: Vec<_> = self
+            .steps
+            .iter()
+            .filter(|id| self.started.contains_key(*id) && !self.finished.contains(id))
+            .copied()
+            .collect();
+        let remaining: Duration = self
+            .steps
+            .iter()
+            .filter(|step| !self.finished.contains(step))
+            .map(|step| self.timings.estimate(step))
+            .sum();
+        bar.set_message(format!("~{} left  {}", format_duration(remaining), running.join(", ")));
+    }
+
+    pub fn start(&mut self, id: &'static str, message: &str) {
+        self.started.insert(id, Instant::now());
+        let current = self.started.len();
+
+        let remaining: Duration = self
+            .steps
+            .iter()
+            .filter(|step| !self.finished.contains(step))
+            .map(|step| self.timings.estimate(step))
+            .sum();
+        let eta = self.timings.estimate(id);
+
+        if emit(Event::Step {
+            id,
+            current,
+            total: self.steps.len(),
+            message: message.to_string(),
+        }) {
+            return;
+        }
+
+        print_line(format!(
+            "{} {} {}",
+            format!("[{}/{}]", current, self.steps.len()).cyan().bold(),
+            message,
+            format!(
+                "(~{}, ~{} remaining)",
+                format_duration(eta),
+                format_duration(remaining)
+            )
+            .dimmed()
+        ));
+        self.update_bar();
+    }
+
+    /// Shows how long a finished step took next to its estimate. Returns
+    /// the duration, which the state file records for later estimates.
+    pub fn finish(&mut self, id: &'static str, succeeded: bool) -> Option<Duration> {
+        self.finished.push(id);
+        let took = self.started.get(id).map(|started| started.elapsed());
+        let shown = emit(Event::StepFinished { id, succeeded });
+        if let Some(ref bar) = self.bar {
+            bar.inc(1);
+        }
+        self.update_bar();
+
+        let took = took?;
+        if !shown && succeeded && verbosity() > Verbosity::Quiet {
+            print_line(format!(
+                "{} {} {}",
+                "✓".green(),
+                format!("{} finished in {}", id, format_duration(took)).dimmed(),
+                format!("(estimated ~{})", format_duration(self.timings.estimate(id))).dimmed()
+            ));
+        }
+        Some(took)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// Progress bars of the transfers and builds currently running, drawn
+/// together below the messages.
+static BARS: LazyLock<indicatif::MultiProgress> = LazyLock::new(indicatif::MultiProgress::new);
+
+/// Whether progress bars can be drawn: on a terminal and with no other front-end.
+fn bars_shown() -> bool {
+    use std::io::IsTerminal;
+    EVENT_HANDLER.lock().unwrap().is_none() && std::io::stdout().is_terminal()
+}
+
+/// Prints a line above the progress bars.
+fn print_line(line: String) {
+    BARS.suspend(|| println!("{}", line));
+}
+
+thread_local! {
+    /// Step whose output this thread is printing.
+    static STEP_LABEL: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Number of steps currently running; output gets prefixed with the step id
+/// while more than one is.
+static ACTIVE_STEPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks the calling thread as running step `id`.
+pub fn enter_step(id: &'static str) {
+    STEP_LABEL.with(|label| label.set(Some(id)));
+    ACTIVE_STEPS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn leave_step() {
+    STEP_LABEL.with(|label| label.set(None));
+    ACTIVE_STEPS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Step the calling thread prints for, handed to helper threads.
+pub fn step_label() -> Option<&'static str> {
+    STEP_LABEL.with(|label| label.get())
+}
+
+/// Makes a helper thread print for `id` without counting it as a running step.
+pub fn set_step_label(id: Option<&'static str>) {
+    STEP_LABEL.with(|label| label.set(id));
+}
+
+fn labelled(message: &str) -> String {
+    match STEP_LABEL.with(|label| label.get()) {
+        // JSON output carries the step id in its own field
+        Some(id) if ACTIVE_STEPS.load(Ordering::SeqCst) > 1 && !json() => format!("[{}] {}", id, message),
+        _ => message.to_string(),
     }
 }
 
 pub fn print_banner() {
+    if json() || verbosity() == Verbosity::Quiet {
+        return;
+    }
     println!("{}", BANNER.magenta().bold());
     println!(
         "{}",
@@ -41,47 +374,337 @@ pub fn print_banner() {
     println!();
 }
 
+#[derive(Clone, Copy)]
+enum Kind {
+    Success,
+    Error,
+    Warning,
+    Info,
+}
+
+/// The terminal line of a message, which is plain text when color is off.
+fn format_message(kind: Kind, message: &str) -> String {
+    let symbol = match kind {
+        Kind::Success => "✓".green(),
+        Kind::Error => "✗".red(),
+        Kind::Warning => "!".yellow(),
+        Kind::Info => "→".blue(),
+    };
+    format!("{} {}", symbol.bold(), message)
+}
+
 pub fn success(message: &str) {
-    println!("{} {}", "✓".green().bold(), message);
+    let message = &labelled(message);
+    if emit(Event::Success(message.to_string())) {
+        return;
+    }
+    if verbosity() > Verbosity::Quiet {
+        print_line(format_message(Kind::Success, message));
+    }
 }
 
 pub fn error(message: &str) {
-    println!("{} {}", "✗".red().bold(), message);
+    let message = &labelled(message);
+    if emit(Event::Error(message.to_string())) {
+        return;
+    }
+    print_line(format_message(Kind::Error, message));
 }
 
 pub fn warning(message: &str) {
-    println!("{} {}", "!".yellow().bold(), message);
+    log::warn(message);
+    let message = &labelled(message);
+    summary::warned();
+    if emit(Event::Warning(message.to_string())) {
+        return;
+    }
+    print_line(format_message(Kind::Warning, message));
 }
 
 pub fn info(message: &str) {
-    println!("{} {}", "→".blue().bold(), message);
+    let message = &labelled(message);
+    if emit(Event::Info(message.to_string())) {
+        return;
+    }
+    if verbosity() > Verbosity::Quiet {
+        print_line(format_message(Kind::Info, message));
+    }
+}
+
+/// Prints multi-line text such as a build log as it is. Front-ends get it
+/// line by line as output.
+pub fn block(text: &str) {
+    if EVENT_HANDLER.lock().unwrap().is_none() {
+        print_line(text.to_string());
+        return;
+    }
+    for line in text.lines() {
+        emit(Event::Output(line.to_string()));
+    }
+}
+
+/// Shows a command line about to run, with `-vv`.
+pub fn command(line: &str) {
+    if verbosity() < Verbosity::Commands || EVENT_HANDLER.lock().unwrap().is_some() {
+        return;
+    }
+    print_line(format!("{} {}", "$".dimmed(), labelled(line).dimmed()));
+}
+
+/// Echoes a line of command output.
+pub fn output(line: &str) {
+    let line = &labelled(line);
+    if emit(Event::Output(line.to_string())) {
+        return;
+    }
+    print_line(format!("  {}", line.dimmed()));
+}
+
+/// The overall bar of `total` steps, kept above the bars of transfers and
+/// builds. `None` when bars cannot be drawn.
+fn steps_bar(total: usize) -> Option<indicatif::ProgressBar> {
+    use indicatif::{ProgressBar, ProgressStyle};
+
+    if !bars_shown() || total == 0 {
+        return None;
+    }
+    let template = if color() {
+        "{prefix:.cyan.bold} [{bar:30.cyan}] {pos}/{len} ({elapsed}) {msg:.dim}"
+    } else {
+        "{prefix} [{bar:30}] {pos}/{len} ({elapsed}) {msg}"
+    };
+    let bar = ProgressBar::new(total as u64)
+        .with_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "));
+    let bar = BARS.insert(0, bar).with_prefix("Installing");
+    bar.enable_steady_tick(Duration::from_secs(1));
+    Some(bar)
+}
+
+/// A progress bar for a transfer of `total` bytes (unknown when `None`),
+/// hidden when the output is not a terminal or goes to another front-end.
+pub fn progress_bar(message: &str, total: Option<u64>) -> indicatif::ProgressBar {
+    use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+    let bar = match total {
+        Some(total) => ProgressBar::new(total).with_style(
+            ProgressStyle::with_template(
+                "  {msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("  {spinner} {msg} {bytes} ({bytes_per_sec})").unwrap(),
+        ),
+    };
+
+    if !bars_shown() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        return bar.with_message(labelled(message));
+    }
+    BARS.add(bar).with_message(labelled(message))
 }
 
+/// A progress bar for the build of step `id`, sharing the display with the
+/// other running builds. `None` when bars cannot be drawn.
+pub fn build_bar(id: &str) -> Option<indicatif::ProgressBar> {
+    use indicatif::{ProgressBar, ProgressStyle};
+
+    if !bars_shown() {
+        return None;
+    }
+    let bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("  {msg:14} [{bar:30}] {pos}/{len} ({elapsed})")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    Some(BARS.add(bar).with_message(id.to_string()))
+}
+
+/// A spinner showing how long `message` has been running, `elapsed` so
+/// far. `None` when bars cannot be drawn.
+pub fn spinner(message: &str, elapsed: Duration) -> Option<indicatif::ProgressBar> {
+    use indicatif::{ProgressBar, ProgressStyle};
+
+    if !bars_shown() {
+        return None;
+    }
+    let bar = ProgressBar::new_spinner()
+        .with_style(ProgressStyle::with_template("  {spinner} {msg} ({elapsed})").unwrap())
+        .with_elapsed(elapsed);
+    let bar = BARS.add(bar).with_message(labelled(message));
+    bar.enable_steady_tick(Duration::from_millis(120));
+    Some(bar)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Held while a prompt waits for an answer, so concurrent steps ask one at a time.
+static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
 pub fn prompt(message: &str) -> bool {
     use std::io::{self, Write};
 
-    print!("{} {} [Y/n] ", "?".magenta().bold(), message);
-    io::stdout().flush().unwrap();
+    let _guard = PROMPT_LOCK.lock().unwrap();
+
+    if let Some(ref handler) = *PROMPT_HANDLER.lock().unwrap() {
+        return handler(message);
+    }
+
+    let input = BARS.suspend(|| {
+        print!("{} {} [Y/n] ", "?".magenta().bold(), message);
+        io::stdout().flush().unwrap();
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+        input
+    });
 
     let input = input.trim().to_lowercase();
     input.is_empty() || input == "y" || input == "yes"
 }
 
+/// Asks a yes/no question that defaults to no, for actions that should
+/// only happen when asked for. Without a terminal to ask on, the answer is no.
+pub fn confirm(message: &str) -> bool {
+    use std::io::{self, IsTerminal, Write};
+
+    let _guard = PROMPT_LOCK.lock().unwrap();
+
+    if let Some(ref handler) = *PROMPT_HANDLER.lock().unwrap() {
+        return handler(message);
+    }
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+
+    let input = BARS.suspend(|| {
+        print!("{} {} [y/N] ", "?".magenta().bold(), message);
+        io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+        input
+    });
+
+    let input = input.trim().to_lowercase();
+    input == "y" || input == "yes"
+}
+
+/// Lets the user toggle `items` (label, selected by default) and returns the
+/// new selection, or `None` when nobody could be asked: with front-ends that
+/// have their own prompts and when stdin is not a terminal.
+pub fn multi_select(message: &str, items: &[(&str, bool)]) -> Option<Vec<bool>> {
+    use dialoguer::MultiSelect;
+    use std::io::IsTerminal;
+
+    let defaults: Vec<bool> = items.iter().map(|(_, selected)| *selected).collect();
+
+    let _guard = PROMPT_LOCK.lock().unwrap();
+    if PROMPT_HANDLER.lock().unwrap().is_some() || !std::io::stdin().is_terminal() {
+        return None;
+    }
+
+    let labels: Vec<&str> = items.iter().map(|(label, _)| *label).collect();
+    let chosen = MultiSelect::with_theme(&*theme())
+        .with_prompt(format!("{} (space to toggle, enter to confirm)", message))
+        .items(&labels)
+        .defaults(&defaults)
+        .interact();
+
+    match chosen {
+        Ok(indexes) => Some((0..items.len()).map(|i| indexes.contains(&i)).collect()),
+        Err(_) => Some(defaults),
+    }
+}
+
+/// Lets the user pick one of `items` and returns its index, or `None` when
+/// nobody could be asked, as with [`multi_select`].
+pub fn select(message: &str, items: &[&str]) -> Option<usize> {
+    use dialoguer::Select;
+    use std::io::IsTerminal;
+
+    let _guard = PROMPT_LOCK.lock().unwrap();
+    if let Some(ref handler) = *SELECT_HANDLER.lock().unwrap() {
+        return handler(message, items);
+    }
+    if PROMPT_HANDLER.lock().unwrap().is_some() || !std::io::stdin().is_terminal() {
+        return None;
+    }
+
+    BARS.suspend(|| {
+        Select::with_theme(&*theme())
+            .with_prompt(message)
+            .items(items)
+            .default(0)
+            .interact()
+            .ok()
+    })
+}
+
+/// Asks for a line of text, offering `default`, or returns `None` when nobody
+/// could be asked, as with [`multi_select`].
+pub fn input(message: &str, default: &str) -> Option<String> {
+    use dialoguer::Input;
+    use std::io::IsTerminal;
+
+    let _guard = PROMPT_LOCK.lock().unwrap();
+    if PROMPT_HANDLER.lock().unwrap().is_some() || !std::io::stdin().is_terminal() {
+        return None;
+    }
+
+    BARS.suspend(|| {
+        Input::<String>::with_theme(&*theme())
+            .with_prompt(message)
+            .default(default.to_string())
+            .allow_empty(true)
+            .interact_text()
+            .ok()
+            .map(|text| text.trim().to_string())
+    })
+}
+
+/// Binds shown after an install, the rest are on the cheat sheet.
+const SUMMARY_BINDS: usize = 8;
+
+/// Prints the first binds of the Hyprland config and where to find the rest.
 pub fn print_keybinds_summary() {
+    if json() || verbosity() == Verbosity::Quiet {
+        return;
+    }
+    let binds = keybinds::cheat_sheet();
+    if binds.is_empty() {
+        return;
+    }
     println!();
     println!("{}", "Keybinds Summary:".cyan().bold());
-    println!("  {} - Open terminal (foot)", "Super + Return".white().bold());
-    println!("  {} - Application launcher", "Super + D".white().bold());
-    println!("  {} - Close window", "Super + Q".white().bold());
-    println!("  {} - Switch workspaces", "Super + 1-9".white().bold());
-    println!("  {} - Move window to workspace", "Super + Shift + 1-9".white().bold());
+    for (keys, description) in binds.iter().take(SUMMARY_BINDS) {
+        println!("  {} - {}", keys.white().bold(), description);
+    }
+    if binds.len() > SUMMARY_BINDS {
+        println!(
+            "  ...and {} more in {}",
+            binds.len() - SUMMARY_BINDS,
+            keybinds::cheat_sheet_path().display()
+        );
+    }
     println!();
 }
 
 pub fn print_completion() {
+    if json() {
+        success("Installation complete!");
+        return;
+    }
     println!();
     println!(
         "{}",
@@ -100,55 +723,10 @@ pub fn print_completion() {
     print_keybinds_summary();
 }
 
-pub fn print_diagnostics() {
-    println!();
-    println!("{}", "Diagnostic Information:".cyan().bold());
-    println!();
-    
-    // Check fonts
-    println!("Fonts:");
-    if let Some(home) = dirs::home_dir() {
-        let font_dir = home.join(".local/share/fonts");
-        if font_dir.exists() {
-            println!("  Font directory exists: {}", font_dir.display());
-            if let Ok(entries) = std::fs::read_dir(&font_dir) {
-                let mut count = 0;
-                for entry in entries.flatten() {
-                    if entry.file_name().to_str().unwrap_or("").contains(".ttf") {
-                        count += 1;
-                    }
-                }
-                println!("  TTF files found: {}", count);
-            }
-        } else {
-            println!("  Font directory missing: {}", font_dir.display());
-        }
-    }
-    
-    // Check quickshell
-    println!();
-    println!("Quickshell:");
-    if which::which("quickshell").is_ok() {
-        println!("  ✓ quickshell command found");
-    } else {
-        println!("  ✗ quickshell command not found");
-    }
-    
-    // Check caelestia-shell
-    println!();
-    println!("Caelestia Shell:");
-    let paths = ["/usr/lib64/qt6/qml/Caelestia", "/usr/lib/qt6/qml/Caelestia"];
-    for path in &paths {
-        if std::path::Path::new(path).exists() {
-            println!("  ✓ Caelestia components found at {}", path);
-            break;
-        }
-    }
-    
-    println!();
-}
-
 pub fn print_troubleshooting() {
+    if json() {
+        return;
+    }
     println!();
     println!("{}", "Troubleshooting Tips:".yellow().bold());
     println!("  1. If fonts are missing, run: fc-cache -fv");
@@ -160,5 +738,6 @@ pub fn print_troubleshooting() {
     println!("     sudo cmake --install build");
     println!("  5. If Quickshell build fails due to Qt package conflicts:");
     println!("     sudo dnf install --allowerasing qt6-qtbase-devel qt6-qtdeclarative-devel");
+    println!("  6. To report the failure, attach the archive from: caelestia-installer report-bug");
     println!();
 }
@@ -0,0 +1,168 @@
+//! The `update` subcommand: pulls the dotfiles and shell checkouts, or moves
+//! them to the revisions in `versions.lock`, rebuilds the shell when it
+//! changed, reinstalls caelestia-cli when upstream has new commits and
+//! removes the build artifacts left behind.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::{cli, dotfiles, log, merge, summary, system, ui, versions};
+
+pub fn run(dry_run: bool, noconfirm: bool) -> Result<()> {
+    ui::info("Updating Caelestia...");
+
+    summary::begin("dotfiles");
+    let result = pull(&dotfiles::dotfiles_dir(), "dotfiles", dry_run, noconfirm);
+    close(&result);
+    result?;
+
+    summary::begin("shell");
+    let result = pull(&dotfiles::shell_dir(), "shell", dry_run, noconfirm).and_then(|changed| {
+        if changed || dry_run {
+            dotfiles::build_shell(dry_run)?;
+        }
+        Ok(changed)
+    });
+    close(&result);
+    result?;
+
+    summary::begin("cli");
+    let result = update_cli(dry_run);
+    close(&result);
+    result?;
+
+    summary::begin("cleanup");
+    let result = system::clean_build_artifacts(dry_run).map(|_| true);
+    close(&result);
+    result?;
+
+    ui::success("Update complete");
+    summary::print();
+    Ok(())
+}
+
+/// Ends the summary record of a component; unchanged ones show as skipped.
+fn close(result: &Result<bool>) {
+    match result {
+        Ok(true) => summary::finish(),
+        Ok(false) => {
+            summary::skipped();
+            summary::finish();
+        }
+        Err(_) => summary::fail(),
+    }
+}
+
+fn head(repo: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["-C", repo.to_str()?, "rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Pulls a checkout, or moves it to the revision `name` is locked to,
+/// returning whether it changed. Local edits are kept, see [`merge`].
+fn pull(repo: &Path, name: &str, dry_run: bool, noconfirm: bool) -> Result<bool> {
+    if !repo.exists() {
+        bail!("{:?} does not exist, run the installer first", repo);
+    }
+    let revision = versions::pinned(name)?;
+
+    if dry_run {
+        match revision {
+            Some(revision) => ui::success(&format!("Would check out {} in {:?} (dry-run)", revision, repo)),
+            None => ui::success(&format!("Would pull {:?} (dry-run)", repo)),
+        }
+        return Ok(false);
+    }
+
+    let before = head(repo);
+    merge::around_local_changes(repo, noconfirm, || match revision {
+        Some(revision) => versions::checkout(repo, &revision),
+        None => {
+            versions::reattach(repo)?;
+            merge::pull(repo, noconfirm)
+        }
+    })?;
+
+    let after = head(repo);
+    if before == after {
+        ui::success(&format!("{:?} is up to date", repo));
+        return Ok(false);
+    }
+
+    let (Some(before), Some(after)) = (before, after) else {
+        return Ok(true);
+    };
+    let range = format!("{}..{}", before, after);
+    let count = Command::new("git")
+        .args(["-C", repo.to_str().unwrap(), "rev-list", "--count", &range])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    if count == "0" {
+        // A lock can also move a checkout back to an older revision
+        ui::success(&format!("Moved {:?} to {}", repo, &after[..7]));
+    } else {
+        ui::success(&format!("Updated {:?} ({} new commits)", repo, count));
+    }
+    summary::artifact(&format!("{} → {}, {} commits", &before[..7], &after[..7], count));
+    log::log(&format!("Updated {:?} from {} to {}", repo, before, after));
+    Ok(true)
+}
+
+/// Reinstalls caelestia-cli when the commit it is locked to, or upstream
+/// HEAD, differs from what was installed.
+fn update_cli(dry_run: bool) -> Result<bool> {
+    let revision = versions::pinned("cli")?.unwrap_or_else(|| "HEAD".to_string());
+    let upstream = if is_commit(&revision) {
+        revision
+    } else {
+        let peeled = format!("{}^{{}}", revision);
+        let cmd = format!("git ls-remote {} {} {}", cli::CLI_REPO, revision, peeled);
+        log::log_command(&cmd);
+
+        let output = Command::new("git")
+            .args(["ls-remote", cli::CLI_REPO, &revision, &peeled])
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Could not check caelestia-cli for updates");
+        }
+
+        // An annotated tag is listed twice; the peeled line names its commit
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let refs: Vec<(&str, &str)> = stdout
+            .lines()
+            .filter_map(|line| line.split_once(char::is_whitespace))
+            .collect();
+        match refs.iter().find(|(_, name)| name.ends_with("^{}")).or(refs.first()) {
+            Some((commit, _)) => commit.to_string(),
+            None => bail!("caelestia-cli has no revision {}", revision),
+        }
+    };
+    let installed = cli::installed_commit();
+
+    if installed.as_deref() == Some(upstream.as_str()) {
+        ui::success("caelestia-cli is up to date");
+        return Ok(false);
+    }
+
+    cli::reinstall(dry_run)?;
+    if !dry_run {
+        let from = installed.as_deref().map(|c| &c[..7.min(c.len())]).unwrap_or("unknown");
+        summary::artifact(&format!("{} → {}", from, &upstream[..7.min(upstream.len())]));
+    }
+    Ok(!dry_run)
+}
+
+fn is_commit(revision: &str) -> bool {
+    revision.len() == 40 && revision.chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,83 @@
+//! UWSM, which runs Hyprland as a systemd user session so the apps it starts
+//! get their environment from the dotfiles' `~/.config/uwsm` and are
+//! stopped cleanly at logout. The step installs it from Fedora's repos and
+//! registers the `hyprland-uwsm` session the display managers offer.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::packages::{self, Backend};
+use crate::{log, privileged, runner, summary, ui};
+
+/// Shipped by recent hyprland packages, written when it is missing.
+const SESSION_PATH: &str = "/usr/share/wayland-sessions/hyprland-uwsm.desktop";
+
+const SESSION_ENTRY: &str = "[Desktop Entry]
+Name=Hyprland (uwsm-managed)
+Comment=An intelligent dynamic tiling Wayland compositor
+Exec=uwsm start -- hyprland.desktop
+TryExec=uwsm
+DesktopNames=Hyprland
+Type=Application
+";
+
+/// What greetd starts when no session was remembered.
+pub const START_COMMAND: &str = "uwsm start -- hyprland.desktop";
+
+/// Whether uwsm is installed and its session registered, so display
+/// managers should start it rather than Hyprland directly.
+pub fn available() -> bool {
+    which::which("uwsm").is_ok() && Path::new(SESSION_PATH).exists()
+}
+
+pub fn setup(dry_run: bool) -> Result<()> {
+    install(dry_run)?;
+    write_session(dry_run)
+}
+
+fn install(dry_run: bool) -> Result<()> {
+    if which::which("uwsm").is_ok() {
+        ui::success("uwsm is already installed");
+        return Ok(());
+    }
+    ui::info("Installing uwsm...");
+    log::log_command(&packages::install_command_line("uwsm"));
+    if dry_run {
+        ui::success("Would install uwsm (dry-run)");
+        return Ok(());
+    }
+
+    let output = runner::run(&mut packages::install_command(&["uwsm"]))?;
+    if !output.status.success() {
+        log::log_error(&String::from_utf8_lossy(&output.stderr));
+        bail!("Failed to install uwsm");
+    }
+    ui::success("Installed uwsm");
+    summary::packages_added(vec!["uwsm".to_string()]);
+    Ok(())
+}
+
+fn write_session(dry_run: bool) -> Result<()> {
+    if Path::new(SESSION_PATH).exists() {
+        ui::success("The Hyprland UWSM session entry already exists");
+        return Ok(());
+    }
+    if packages::backend() == Backend::RpmOstree {
+        ui::warning(&format!(
+            "{} is missing and /usr is read-only, update the hyprland package for it",
+            SESSION_PATH
+        ));
+        return Ok(());
+    }
+    ui::info("Creating the Hyprland UWSM session entry...");
+
+    privileged::write_file(Path::new(SESSION_PATH), SESSION_ENTRY)?;
+    if dry_run {
+        ui::success(&format!("Would write {} (dry-run)", SESSION_PATH));
+        return Ok(());
+    }
+    ui::success("Created the Hyprland UWSM session entry");
+    log::log("Hyprland UWSM session entry written");
+    summary::artifact("hyprland-uwsm.desktop");
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Pins the upstream repositories to known-good revisions. `versions.lock`,
+//! embedded in the binary, maps each repository to a tag, branch or commit;
+//! a lock file of the user's overrides its entries, and `--track-latest`
+//! goes back to cloning HEAD. Source checkouts in the build directory are
+//! kept and brought up to date in place.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::{log, ui};
+
+const DEFAULT_LOCK: &str = include_str!("../versions.lock");
+
+/// Lock file given with `--lock-file`.
+static LOCK_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+static TRACK_LATEST: Mutex<bool> = Mutex::new(false);
+
+pub fn set_lock_file(path: Option<PathBuf>) {
+    *LOCK_FILE.lock().unwrap() = path;
+}
+
+pub fn set_track_latest(track_latest: bool) {
+    *TRACK_LATEST.lock().unwrap() = track_latest;
+}
+
+/// The lock file overriding the embedded one, if there is one.
+fn lock_file() -> Option<PathBuf> {
+    if let Some(path) = LOCK_FILE.lock().unwrap().clone() {
+        return Some(path);
+    }
+    let path = dirs::config_dir()?.join("caelestia-installer/versions.lock");
+    path.exists().then_some(path)
+}
+
+fn parse(content: &str, source: &str) -> Result<HashMap<String, String>> {
+    toml::from_str(content).map_err(|e| anyhow!("Invalid lock file {}: {}", source, e))
+}
+
+/// The revision `repo` is pinned to, `None` when it follows HEAD.
+pub fn pinned(repo: &str) -> Result<Option<String>> {
+    if *TRACK_LATEST.lock().unwrap() {
+        return Ok(None);
+    }
+    let mut lock = parse(DEFAULT_LOCK, "embedded in the installer")?;
+    if let Some(path) = lock_file() {
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Could not read {:?}: {}", path, e))?;
+        lock.extend(parse(&content, &format!("{:?}", path))?);
+    }
+    Ok(lock.remove(repo))
+}
+
+/// Fetches `revision` into the checkout at `dir` and detaches HEAD at it.
+/// Shallow clones stay shallow.
+pub fn checkout(dir: &Path, revision: &str) -> Result<()> {
+    let dir_str = dir.to_string_lossy();
+    let mut fetch = vec!["-C", &dir_str, "fetch"];
+    if dir.join(".git/shallow").exists() {
+        fetch.extend(["--depth", "1"]);
+    }
+    fetch.extend(["origin", revision]);
+
+    for args in [fetch, vec!["-C", &dir_str, "checkout", "--detach", "FETCH_HEAD"]] {
+        log::log_command(&format!("git {}", args.join(" ")));
+        let output = Command::new("git").args(&args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::log_error(&stderr);
+            bail!("Could not check out {} in {:?}: {}", revision, dir, stderr.trim());
+        }
+    }
+
+    ui::success(&format!("Checked out {} in {:?}", revision, dir));
+    Ok(())
+}
+
+/// Brings the checkout of `repo` in `dir` to its locked revision or to
+/// upstream HEAD, cloning it the first time and fetching into it after that,
+/// so build trees inside it are reused.
+pub fn sync_source(repo: &str, url: &str, dir: &Path, shallow: bool) -> Result<()> {
+    let revision = pinned(repo)?;
+    let dir_str = dir.to_string_lossy();
+
+    if !dir.join(".git").exists() {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        let mut args = vec!["clone"];
+        if shallow {
+            args.extend(["--depth", "1"]);
+        }
+        args.extend([url, &dir_str]);
+        git(&args)?;
+        ui::success(&format!("Cloned {}", repo));
+        return match revision {
+            Some(revision) => checkout(dir, &revision),
+            None => Ok(()),
+        };
+    }
+
+    git(&["-C", &dir_str, "remote", "set-url", "origin", url])?;
+    checkout(dir, revision.as_deref().unwrap_or("HEAD"))
+}
+
+fn git(args: &[&str]) -> Result<()> {
+    log::log_command(&format!("git {}", args.join(" ")));
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("git {} failed: {}", args.join(" "), stderr.trim());
+    }
+    Ok(())
+}
+
+/// Puts a checkout left detached at a pinned revision back on its default
+/// branch, so it can be pulled again.
+pub fn reattach(dir: &Path) -> Result<()> {
+    let dir_str = dir.to_string_lossy();
+    let git = |args: &[&str]| Command::new("git").args(["-C", &dir_str]).args(args).output();
+
+    if git(&["symbolic-ref", "-q", "HEAD"])?.status.success() {
+        return Ok(());
+    }
+    let output = git(&["rev-parse", "--abbrev-ref", "origin/HEAD"])?;
+    let remote_head = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let Some(branch) = remote_head.strip_prefix("origin/") else {
+        bail!("Could not find the default branch of {:?}", dir);
+    };
+
+    log::log_command(&format!("git -C {} checkout {}", dir_str, branch));
+    let output = git(&["checkout", branch])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Could not switch {:?} back to {}: {}", dir, branch, stderr.trim());
+    }
+    Ok(())
+}
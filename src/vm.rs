@@ -0,0 +1,155 @@
+//! Virtual machine detection and setup. Virtual GPUs have no hardware cursor
+//! planes and render blur and animations in software, which leaves the
+//! default config with an invisible cursor and a crawling desktop, so VMs get
+//! software cursors, no blur or animations, and the hypervisor's guest tools.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{hyprconf, journal, log, packages, runner, summary, ui};
+
+/// Marks the lines the installer adds to the user's Hyprland config.
+const HYPR_MARKER: &str = "# caelestia-installer: vm";
+
+const VM_CONFIG: &str = "env = WLR_NO_HARDWARE_CURSORS,1
+env = WLR_RENDERER_ALLOW_SOFTWARE,1
+
+cursor {
+    no_hardware_cursors = true
+}
+
+decoration {
+    blur {
+        enabled = false
+    }
+}
+
+animations {
+    enabled = false
+}
+";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Hypervisor {
+    Qemu,
+    VirtualBox,
+    Vmware,
+    HyperV,
+    Other,
+}
+
+impl Hypervisor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hypervisor::Qemu => "QEMU/KVM",
+            Hypervisor::VirtualBox => "VirtualBox",
+            Hypervisor::Vmware => "VMware",
+            Hypervisor::HyperV => "Hyper-V",
+            Hypervisor::Other => "an unknown hypervisor",
+        }
+    }
+
+    /// Guest tools for clipboard sharing, display resizing and clean
+    /// shutdowns.
+    fn packages(self) -> &'static [&'static str] {
+        match self {
+            Hypervisor::Qemu => &["qemu-guest-agent", "spice-vdagent"],
+            Hypervisor::VirtualBox => &["virtualbox-guest-additions"],
+            Hypervisor::Vmware => &["open-vm-tools", "open-vm-tools-desktop"],
+            Hypervisor::HyperV => &["hyperv-daemons"],
+            Hypervisor::Other => &[],
+        }
+    }
+}
+
+/// The hypervisor this machine runs under, from `systemd-detect-virt`.
+/// Containers do not count.
+pub fn detect() -> Option<Hypervisor> {
+    let output = Command::new("systemd-detect-virt").arg("--vm").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let hypervisor = match String::from_utf8_lossy(&output.stdout).trim() {
+        "" | "none" => return None,
+        "qemu" | "kvm" => Hypervisor::Qemu,
+        "oracle" => Hypervisor::VirtualBox,
+        "vmware" => Hypervisor::Vmware,
+        "microsoft" => Hypervisor::HyperV,
+        _ => Hypervisor::Other,
+    };
+    Some(hypervisor)
+}
+
+/// Installs the guest tools and writes the VM settings when running in a
+/// virtual machine.
+pub fn setup(dry_run: bool) -> Result<()> {
+    let Some(hypervisor) = detect() else {
+        ui::success("Not running in a virtual machine");
+        summary::skipped();
+        return Ok(());
+    };
+    ui::info(&format!("Running in a virtual machine on {}", hypervisor.name()));
+    log::log(&format!("Hypervisor: {:?}", hypervisor));
+
+    install_guest_tools(hypervisor, dry_run)?;
+    write_config(dry_run)
+}
+
+fn install_guest_tools(hypervisor: Hypervisor, dry_run: bool) -> Result<()> {
+    let tools = hypervisor.packages();
+    if tools.is_empty() {
+        ui::info("No guest tools known for this hypervisor");
+        return Ok(());
+    }
+    ui::info("Installing guest tools...");
+
+    log::log_command(&packages::install_command_line(&tools.join(" ")));
+
+    if dry_run {
+        ui::success(&format!("Would install {} (dry-run)", tools.join(", ")));
+        return Ok(());
+    }
+
+    let output = runner::run(&mut packages::install_command(tools))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::log_error(&stderr);
+        bail!("Failed to install the guest tools");
+    }
+
+    ui::success(&format!("Installed {}", tools.join(", ")));
+    summary::artifact("guest tools");
+    Ok(())
+}
+
+/// Adds the VM settings to `~/.config/caelestia/hypr-user.conf`.
+fn write_config(dry_run: bool) -> Result<()> {
+    let hypr_user = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("caelestia/hypr-user.conf");
+
+    if dry_run {
+        ui::success(&format!("Would add the VM settings to {:?} (dry-run)", hypr_user));
+        return Ok(());
+    }
+
+    let existing = fs::read_to_string(&hypr_user).unwrap_or_default();
+    if existing.contains(HYPR_MARKER) {
+        ui::success("VM settings already set up");
+        return Ok(());
+    }
+
+    if let Some(parent) = hypr_user.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    journal::write_file(&hypr_user, format!("{}\n{}\n{}", existing, HYPR_MARKER, VM_CONFIG))?;
+    hyprconf::check(&hypr_user);
+
+    ui::success("Added software cursors and disabled blur and animations for the VM");
+    log::log(&format!("VM settings written to {:?}", hypr_user));
+    summary::artifact("vm config");
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! The wallpaper collection the shell picks from, cloned into
+//! `~/Pictures/Wallpapers` unless another directory is given, and the
+//! default wallpaper set through the caelestia CLI.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{dotfiles, log, summary, ui, versions};
+
+pub const WALLPAPERS_REPO: &str = "https://github.com/caelestia-dots/wallpapers.git";
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+/// Where the shell looks for wallpapers by default.
+pub fn default_dir() -> PathBuf {
+    let pictures = dirs::picture_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join("Pictures")))
+        .unwrap_or_else(|| PathBuf::from("~/Pictures"));
+    pictures.join("Wallpapers")
+}
+
+/// Clones the collection into `dir`, or a `caelestia` directory inside it
+/// when it already holds the user's own wallpapers, and sets a default one.
+pub fn setup(dir: Option<&Path>, dry_run: bool, noconfirm: bool) -> Result<()> {
+    let dir = dir.map(Path::to_path_buf).unwrap_or_else(default_dir);
+    let own_wallpapers = dir.exists()
+        && !dir.join(".git").exists()
+        && fs::read_dir(&dir).map(|mut entries| entries.next().is_some()).unwrap_or(false);
+    let dest = if own_wallpapers { dir.join("caelestia") } else { dir.clone() };
+
+    dotfiles::clone_repo(WALLPAPERS_REPO, &dest, None, versions::pinned("wallpapers")?, dry_run, noconfirm)?;
+    if dry_run {
+        ui::success("Would set a default wallpaper (dry-run)");
+        return Ok(());
+    }
+    summary::artifact("wallpapers");
+    set_default(&dest)
+}
+
+/// Where the caelestia CLI keeps the current wallpaper.
+fn current_wallpaper() -> PathBuf {
+    let state = dirs::state_dir().unwrap_or_else(|| PathBuf::from("~/.local/state"));
+    state.join("caelestia/wallpaper/path.txt")
+}
+
+/// Sets the first wallpaper of the collection, unless one is already set.
+fn set_default(dir: &Path) -> Result<()> {
+    if current_wallpaper().exists() {
+        ui::success("A wallpaper is already set, keeping it");
+        return Ok(());
+    }
+
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        })
+        .collect();
+    images.sort();
+    let Some(wallpaper) = images.first() else {
+        ui::warning(&format!("No images found in {:?}, not setting a wallpaper", dir));
+        return Ok(());
+    };
+
+    let path = wallpaper.to_string_lossy();
+    log::log_command(&format!("caelestia wallpaper -f {}", path));
+    let output = Command::new("caelestia").args(["wallpaper", "-f", &path]).output();
+    match output {
+        Ok(o) if o.status.success() => {
+            ui::success(&format!("Set {:?} as the wallpaper", wallpaper));
+        }
+        Ok(o) => {
+            log::log_error(&String::from_utf8_lossy(&o.stderr));
+            ui::warning(&format!("Could not set the wallpaper, run: caelestia wallpaper -f {}", path));
+        }
+        Err(e) => {
+            log::log_error(&format!("caelestia failed: {}", e));
+            ui::warning("The caelestia CLI is not available, set a wallpaper later from the shell");
+        }
+    }
+    Ok(())
+}